@@ -5,9 +5,12 @@ use std::{
     process::Command,
 };
 
+const DATA_IMG_SIZE: u64 = 16 * 1024 * 1024;
+
 fn main() {
     let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
     println!("cargo:rerun-if-env-changed=FORCE_MKFS");
+    println!("cargo:rerun-if-env-changed=ROOT_PASSWORD");
 
     // build user programs
     let (uprogs_src_path, uprogs) = build_uprogs(&out_dir);
@@ -27,13 +30,22 @@ fn main() {
     if !force_mkfs && fs_img.exists() {
         println!("cargo:warning=mkfs: keeping existing fs.img (set FORCE_MKFS=1 to rebuild)");
     } else {
+        let manifest = out_dir.join("fs.manifest");
+        write_manifest(&manifest, &readme, uprogs).expect("write fs.manifest");
         let mut mkfs_cmd = Command::new(&mkfs_path);
         mkfs_cmd.current_dir(&out_dir);
-        mkfs_cmd.arg(fs_img).arg(&readme).args(uprogs);
+        mkfs_cmd.arg("-m").arg(&manifest).arg(&fs_img);
         let status = mkfs_cmd.status().expect("mkfs fs.img failed to run");
         assert!(status.success(), "mkfs fs.img failed: {status}");
     }
 
+    // blank second disk, formatted from inside the OS with newfs
+    let data_img = fs_img.with_file_name("data.img");
+    if !data_img.exists() {
+        let file = fs::File::create(&data_img).expect("create data.img");
+        file.set_len(DATA_IMG_SIZE).expect("size data.img");
+    }
+
     // linker script for kernel
     println!("cargo:rustc-link-arg-bin=web-os=--script=crates/kernel/kernel.ld");
 }
@@ -92,6 +104,38 @@ fn build_uprogs(out_dir: &Path) -> (PathBuf, Vec<PathBuf>) {
     }
 }
 
+// The fs.img layout: mount points, README.md, init in /, programs in
+// /bin and the rest in /etc and /lib. The build names user files with a
+// leading _ so the host never runs them in place of its own rm, cat...
+fn write_manifest(path: &Path, readme: &Path, mut uprogs: Vec<PathBuf>) -> Result<()> {
+    let mut manifest = String::new();
+    for dir in ["dev", "bin", "lib", "etc", "dfs", "tmp", "proc"] {
+        manifest += &format!("/{dir} dir 755\n");
+    }
+    manifest += &format!("/README.md file 644 {}\n", readme.display());
+    uprogs.sort();
+    for src in uprogs {
+        let dir = src
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str());
+        let name = src.file_name().and_then(|n| n.to_str()).unwrap();
+        let name = name.trim_start_matches('_');
+        let (path, mode) = match dir {
+            Some("bin") if name.contains("init") => (format!("/{name}"), "755"),
+            // su runs as root to switch users
+            Some("bin") if name == "su" => (format!("/bin/{name}"), "4755"),
+            Some("bin") => (format!("/bin/{name}"), "755"),
+            // password hashes, for login and su only
+            Some("etc") if name == "passwd" => (format!("/etc/{name}"), "600"),
+            Some(dir) => (format!("/{dir}/{name}"), "644"),
+            None => (format!("/{name}"), "644"),
+        };
+        manifest += &format!("{path} file {mode} {}\n", src.display());
+    }
+    fs::write(path, manifest)
+}
+
 fn build_mkfs(out_dir: &Path) -> PathBuf {
     let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
     let mut cmd = Command::new(cargo);
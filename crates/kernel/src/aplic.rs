@@ -1,10 +1,12 @@
 use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::memlayout::{
     APLIC_M, APLIC_S, IMSIC_M, IMSIC_S, UART0_HART, UART0_IRQ, VIRTIO0_HART, VIRTIO0_IRQ,
     VIRTIO1_HART, VIRTIO1_IRQ, VIRTIO2_HART, VIRTIO2_IRQ, VIRTIO3_HART, VIRTIO3_IRQ, VIRTIO4_HART,
-    VIRTIO4_IRQ,
+    VIRTIO4_IRQ, VIRTIO5_IRQ, VIRTIO6_IRQ, VIRTIO7_IRQ,
 };
+use crate::param::NCPU;
 
 // Register offsets
 const DOMAINCFG: usize = 0x0000;
@@ -21,6 +23,35 @@ const CLRIE_BASE: usize = 0x1F00; // clrie[idx]
 
 const TARGET_BASE: usize = 0x3004; // target[irq-1]
 
+// Wired sources and the devices behind them.
+pub const SOURCES: [(u32, &str); 9] = [
+    (UART0_IRQ, "uart"),
+    (VIRTIO0_IRQ, "virtio-disk"),
+    (VIRTIO1_IRQ, "virtio-net"),
+    (VIRTIO2_IRQ, "virtio-gpu"),
+    (VIRTIO3_IRQ, "virtio-keyboard"),
+    (VIRTIO4_IRQ, "virtio-mouse"),
+    (VIRTIO5_IRQ, "virtio-disk"),
+    (VIRTIO6_IRQ, "virtio-disk"),
+    (VIRTIO7_IRQ, "virtio-disk"),
+];
+
+// Interrupts taken, by source (in SOURCES order) and hart.
+static COUNTS: [[AtomicUsize; NCPU]; SOURCES.len()] =
+    [const { [const { AtomicUsize::new(0) }; NCPU] }; SOURCES.len()];
+
+// Count an interrupt from source irq taken on hart.
+pub fn count(irq: u32, hart: usize) {
+    if let Some(i) = SOURCES.iter().position(|&(src, _)| src == irq) {
+        COUNTS[i][hart].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Interrupts taken from the i-th of SOURCES, by hart.
+pub fn counts(i: usize) -> [usize; NCPU] {
+    core::array::from_fn(|hart| COUNTS[i][hart].load(Ordering::Relaxed))
+}
+
 #[repr(u32)]
 #[derive(Clone, Copy)]
 pub enum SourceMode {
@@ -148,14 +179,7 @@ pub fn init() {
 
     // Root delegates wired sources to the supervisor (child) domain.
     // Without this, the child domain won't see the device IRQs.
-    for irq in [
-        UART0_IRQ,
-        VIRTIO0_IRQ,
-        VIRTIO1_IRQ,
-        VIRTIO2_IRQ,
-        VIRTIO3_IRQ,
-        VIRTIO4_IRQ,
-    ] {
+    for (irq, _) in SOURCES {
         root.sourcecfg_delegate(irq, 0);
     }
 
@@ -163,14 +187,7 @@ pub fn init() {
     // Configure sources to deliver MSIs with EIID == irq.
     // Route all device MSIs to hart0 so early boot doesn't depend on other harts
     // having IMSIC/trap fully initialized yet.
-    for irq in [
-        UART0_IRQ,
-        VIRTIO0_IRQ,
-        VIRTIO1_IRQ,
-        VIRTIO2_IRQ,
-        VIRTIO3_IRQ,
-        VIRTIO4_IRQ,
-    ] {
+    for (irq, _) in SOURCES {
         let guest = match irq {
             UART0_IRQ => UART0_HART as u32,
             VIRTIO0_IRQ => VIRTIO0_HART as u32,
@@ -178,7 +195,7 @@ pub fn init() {
             VIRTIO2_IRQ => VIRTIO2_HART as u32,
             VIRTIO3_IRQ => VIRTIO3_HART as u32,
             VIRTIO4_IRQ => VIRTIO4_HART as u32,
-            _ => 0,
+            _ => VIRTIO0_HART as u32, // spare slots hold disks
         };
         sup.set_target_msi(irq, UART0_HART as u32, guest, irq);
         sup.set_sourcecfg(irq, SourceMode::LevelHigh);
@@ -11,34 +11,59 @@
 // * When done with the buffer, drop(BufGuard) with relse.
 // * Only one process at a time can use a buffer, so do not keep them longer
 //   than necessary.
+// * BufGuard.write_back marks the buffer dirty instead of writing it; a
+//   kernel task writes dirty buffers every WRITEBACK_TICKS, and
+//   BCACHE.flush() writes them all at once. The log never uses it.
+// * BCACHE.read_ahead reads a block together with the blocks expected
+//   next, in one round trip to the disk.
+//
+// The buffers are allocated at boot from a share of free memory, and
+// cached blocks are found through a hash table on (dev, blockno).
 #![allow(clippy::redundant_allocation)]
 use alloc::{
-    collections::BTreeMap,
+    boxed::Box,
     rc::{Rc, Weak},
     sync::Arc,
+    vec::Vec,
 };
 use core::{
     cell::RefCell,
     ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use crate::{
-    array,
     fs::BSIZE,
-    param::NBUF,
+    kalloc,
+    memlayout::VIRTIO0_HART,
+    param::{BCACHE_SHARE, MAXBUF, NBUF, WRITEBACK_TICKS},
+    riscv::PGSIZE,
     sleeplock::{SleepLock, SleepLockGuard},
     spinlock::Mutex,
-    virtio_disk::DISK,
+    task::{Task, sleep_ticks, spawn_on},
+    virtio_disk::disk,
 };
 
 pub static BCACHE: BCache = BCache::new();
 
+// Most dirty buffers the writeback task starts at once.
+const WRITEBACK_BATCH: usize = 8;
+
 #[derive(Debug)]
 pub struct BCache {
-    buf: [SleepLock<Data>; NBUF],
     // Linked list of all buffers, sorted by how recently the
     // buffer was used.
     lru: Mutex<Lru>,
+    stats: Stats,
+}
+
+// Counters for /proc/bcache.
+#[derive(Debug)]
+pub struct Stats {
+    pub hits: AtomicUsize,      // reads found valid in the cache
+    pub misses: AtomicUsize,    // reads that went to the disk
+    pub readahead: AtomicUsize, // blocks read ahead of need
+    pub writeback: AtomicUsize, // dirty blocks written by the task
 }
 
 #[derive(Debug)]
@@ -54,9 +79,18 @@ pub struct Data {
 pub struct Lru {
     head: Option<Rc<Buf>>,
     tail: Option<Weak<Buf>>,
-    index: BTreeMap<(u32, u32), Weak<Buf>>,
+    // Hash chains on (dev, blockno), a power of two of them.
+    table: Vec<Vec<Weak<Buf>>>,
     n: usize,
 }
+
+// How get() can satisfy a request.
+enum Slot {
+    Hit(Rc<Buf>),
+    Recycle(Rc<Buf>),
+    // No clean buffer is free: write this dirty one out first.
+    Flush(Rc<Buf>),
+}
 unsafe impl Send for Lru {}
 
 #[derive(Debug)]
@@ -69,6 +103,7 @@ pub struct Buf {
 struct Meta {
     dev: u32,
     blockno: u32,
+    dirty: bool, // written with write_back, not yet on disk
     next: Option<Rc<Buf>>,
     prev: Option<Weak<Buf>>,
 }
@@ -78,7 +113,7 @@ impl Lru {
         Self {
             head: None,
             tail: None,
-            index: BTreeMap::new(),
+            table: Vec::new(),
             n: 0,
         }
     }
@@ -99,33 +134,54 @@ impl Lru {
         self.n += 1;
     }
 
-    fn _get(&mut self, dev: u32, blockno: u32) -> (bool, Rc<Buf>) {
-        if let Some(slot) = self.index.get(&(dev, blockno))
-            && let Some(buf) = slot.upgrade()
-        {
-            let hit = {
-                let meta = buf.meta.borrow();
+    fn chain(&self, dev: u32, blockno: u32) -> usize {
+        let h = (dev as usize).wrapping_mul(0x9e37_79b9) ^ blockno as usize;
+        h & (self.table.len() - 1)
+    }
+
+    fn find(&self, dev: u32, blockno: u32) -> Option<Rc<Buf>> {
+        self.table[self.chain(dev, blockno)]
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|b| {
+                let meta = b.meta.borrow();
                 meta.dev == dev && meta.blockno == blockno
-            };
-            if hit {
-                return (false, buf);
-            }
-        }
-        if let Some(slot) = self.index.get(&(dev, blockno))
-            && slot.upgrade().is_none()
-        {
-            self.index.remove(&(dev, blockno));
+            })
+    }
+
+    // Move buf to the chain of (dev, blockno).
+    fn rehash(&mut self, buf: &Rc<Buf>, dev: u32, blockno: u32) {
+        let (odev, oblockno) = {
+            let meta = buf.meta.borrow();
+            (meta.dev, meta.blockno)
+        };
+        let old = self.chain(odev, oblockno);
+        self.table[old].retain(|b| b.as_ptr() != Rc::as_ptr(buf));
+        let mut meta = buf.meta.borrow_mut();
+        meta.dev = dev;
+        meta.blockno = blockno;
+        drop(meta);
+        let new = self.chain(dev, blockno);
+        self.table[new].push(Rc::downgrade(buf));
+    }
+
+    fn _get(&mut self, dev: u32, blockno: u32) -> Slot {
+        if let Some(buf) = self.find(dev, blockno) {
+            return Slot::Hit(buf);
         }
 
         // Not cached
-        // Recycle the least recently used LRU unused buffer
-        for (i, b) in self.iter().rev().enumerate() {
-            assert!(i < 30, "iter could be an infinite loop.");
+        // Recycle the least recently used clean unused buffer.
+        let mut dirty = None;
+        for b in self.iter().rev() {
             if Arc::strong_count(&b.data) == 1 {
-                return (true, b);
+                if !b.meta.borrow().dirty {
+                    return Slot::Recycle(b);
+                }
+                dirty.get_or_insert(b);
             }
         }
-        panic!("no buffers");
+        Slot::Flush(dirty.expect("no buffers"))
     }
 
     fn relse(&mut self, buf: Rc<Buf>) {
@@ -214,6 +270,10 @@ pub struct BufGuard {
     _link: Option<Rc<Buf>>,
 }
 
+// The Rc in _link is only cloned and dropped under the lru lock, so a
+// guard may move to a kernel task on another hart.
+unsafe impl Send for BufGuard {}
+
 impl Deref for BufGuard {
     type Target = SleepLockGuard<'static, Data>;
 
@@ -234,7 +294,22 @@ impl BufGuard {
         if !self.holding() {
             panic!("bwrite");
         }
-        self.data_guard = DISK.rw(self.data_guard.take(), true);
+        self.data_guard = disk(self.dev()).rw(self.data_guard.take(), true);
+        self.set_dirty(false);
+    }
+
+    // Write buf's content to disk later, from the writeback task or
+    // flush. Must be locked.
+    pub fn write_back(&mut self) {
+        if !self.holding() {
+            panic!("bwrite_back");
+        }
+        self.set_dirty(true);
+    }
+
+    fn set_dirty(&self, dirty: bool) {
+        let _lru = BCACHE.lru.lock();
+        self._link.as_ref().unwrap().meta.borrow_mut().dirty = dirty;
     }
 
     pub fn pin(&self) {
@@ -317,53 +392,233 @@ impl DerefMut for Data {
 impl BCache {
     const fn new() -> Self {
         Self {
-            buf: array![SleepLock::new(Data::new(), "buffer"); NBUF],
             lru: Mutex::new(Lru::new(), "bcache"),
+            stats: Stats {
+                hits: AtomicUsize::new(0),
+                misses: AtomicUsize::new(0),
+                readahead: AtomicUsize::new(0),
+                writeback: AtomicUsize::new(0),
+            },
         }
     }
 
-    fn get(&self, dev: u32, blockno: u32) -> BufGuard {
-        let mut lru = self.lru.lock();
-        let (recycle, b) = lru._get(dev, blockno);
-        if recycle {
-            let mut meta = b.meta.borrow_mut();
-            let old = (meta.dev, meta.blockno);
-            if old != (0, 0) {
-                lru.index.remove(&old);
-            }
-            meta.dev = dev;
-            meta.blockno = blockno;
-            lru.index.insert((dev, blockno), Rc::downgrade(&b));
-        }
-        drop(lru);
-
-        let mut sleeplock = b.data.lock();
-        if recycle {
-            sleeplock.valid = false;
-            sleeplock.blockno = blockno;
-            sleeplock.dev = dev;
-        }
-        BufGuard {
-            data_guard: Some(sleeplock),
-            _ref: Some(Arc::clone(&b.data)),
-            _link: Some(b), // Do Not touch outside this function
+    // A buffer for (dev, blockno), locked. With wait false, gives up
+    // instead of sleeping for a buffer someone else holds.
+    fn get(&self, dev: u32, blockno: u32, wait: bool) -> Option<BufGuard> {
+        loop {
+            let mut lru = self.lru.lock();
+            // count the reference while the lru lock still keeps the
+            // buffer from being recycled.
+            let (b, data) = match lru._get(dev, blockno) {
+                Slot::Hit(b) => {
+                    let data = Arc::clone(&b.data);
+                    (b, data)
+                }
+                Slot::Recycle(b) => {
+                    // Unused, so the lock is free: take it before anyone
+                    // can find the buffer under its new block.
+                    lru.rehash(&b, dev, blockno);
+                    let data = Arc::clone(&b.data);
+                    let mut sleeplock = data.try_lock().expect("bget: free buffer locked");
+                    sleeplock.valid = false;
+                    sleeplock.blockno = blockno;
+                    sleeplock.dev = dev;
+                    return Some(BufGuard {
+                        data_guard: Some(sleeplock),
+                        _ref: Some(data),
+                        _link: Some(b), // Do Not touch outside this function
+                    });
+                }
+                Slot::Flush(b) => {
+                    let data = Arc::clone(&b.data);
+                    drop(lru);
+                    let mut old = BufGuard {
+                        data_guard: Some(data.lock()),
+                        _ref: Some(data),
+                        _link: Some(b),
+                    };
+                    old.write();
+                    continue;
+                }
+            };
+            drop(lru);
+
+            let sleeplock = if wait {
+                data.lock()
+            } else if let Some(sleeplock) = data.try_lock() {
+                sleeplock
+            } else {
+                drop(data);
+                self.lru.lock().relse(b);
+                return None;
+            };
+            return Some(BufGuard {
+                data_guard: Some(sleeplock),
+                _ref: Some(data),
+                _link: Some(b), // Do Not touch outside this function
+            });
         }
     }
 
     // Return a locked buf with the contents of the indicated block.
     pub fn read(&self, dev: u32, blockno: u32) -> BufGuard {
-        let mut b = self.get(dev, blockno);
-        if !b.valid {
-            b.data_guard = DISK.rw(b.data_guard.take(), false);
+        let mut b = self.get(dev, blockno, true).unwrap();
+        if b.valid {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats.misses.fetch_add(1, Ordering::Relaxed);
+            b.data_guard = disk(dev).rw(b.data_guard.take(), false);
             b.valid = true;
         }
         b
     }
+
+    // Like read, but also start reading the blocks in ahead that are
+    // not cached, all in one batch. Readahead is best effort: blocks
+    // whose buffers are busy, or that do not fit in the disk queue,
+    // are left for later.
+    pub fn read_ahead(&self, dev: u32, blockno: u32, ahead: &[u32]) -> BufGuard {
+        let mut b = self.get(dev, blockno, true).unwrap();
+        let mut batch = Vec::new();
+        if b.valid {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats.misses.fetch_add(1, Ordering::Relaxed);
+            batch.push(b.data_guard.take().unwrap());
+        }
+        let mut extra = Vec::new();
+        for &bn in ahead {
+            if bn == blockno || self.lru.lock().find(dev, bn).is_some() {
+                continue;
+            }
+            if let Some(mut e) = self.get(dev, bn, false)
+                && !e.valid
+            {
+                batch.push(e.data_guard.take().unwrap());
+                extra.push(e);
+            }
+        }
+        if batch.is_empty() {
+            return b;
+        }
+
+        let mut done = disk(dev).read_batch(batch).into_iter();
+        if b.data_guard.is_none() {
+            let (data, read) = done.next().unwrap();
+            assert!(read, "read_ahead");
+            b.data_guard = Some(data);
+            b.valid = true;
+        }
+        for (mut e, (data, read)) in extra.into_iter().zip(done) {
+            e.data_guard = Some(data);
+            if read {
+                e.valid = true;
+                self.stats.readahead.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        b
+    }
+
+    // Write every dirty buffer to disk now.
+    pub fn flush(&self) {
+        loop {
+            let lru = self.lru.lock();
+            let Some(b) = lru.iter().find(|b| b.meta.borrow().dirty) else {
+                return;
+            };
+            let data = Arc::clone(&b.data);
+            drop(lru);
+            let mut guard = BufGuard {
+                data_guard: Some(data.lock()),
+                _ref: Some(data),
+                _link: Some(b),
+            };
+            guard.write();
+        }
+    }
+
+    // Lock up to WRITEBACK_BATCH dirty buffers nobody is using, oldest
+    // first, and queue their writes. Runs in a kernel task, so it never
+    // sleeps: busy buffers wait for the next round.
+    fn start_writeback(&self) -> Vec<(BufGuard, u32, usize)> {
+        let mut locked = Vec::new();
+        let lru = self.lru.lock();
+        for b in lru.iter().rev() {
+            if locked.len() == WRITEBACK_BATCH {
+                break;
+            }
+            if !b.meta.borrow().dirty || Arc::strong_count(&b.data) != 1 {
+                continue;
+            }
+            let data = Arc::clone(&b.data);
+            if let Some(sleeplock) = data.try_lock() {
+                b.meta.borrow_mut().dirty = false;
+                locked.push(BufGuard {
+                    data_guard: Some(sleeplock),
+                    _ref: Some(data),
+                    _link: Some(b),
+                });
+            }
+        }
+        drop(lru);
+
+        let mut started = Vec::new();
+        for mut b in locked {
+            let dev = b.dev();
+            match disk(dev).start_write(b.data_guard.take().unwrap()) {
+                Ok(id) => started.push((b, dev, id)),
+                Err(data) => {
+                    b.data_guard = Some(data);
+                    b.set_dirty(true);
+                }
+            }
+        }
+        started
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    // Number of buffers.
+    pub fn size(&self) -> usize {
+        self.lru.lock().n
+    }
 }
 
+// Allocate the buffers: BCACHE_SHARE of free memory, at least NBUF and
+// at most MAXBUF of them.
 pub fn init() {
+    let per_buf = core::mem::size_of::<SleepLock<Data>>();
+    let nbuf = (kalloc::free_pages() * PGSIZE / BCACHE_SHARE / per_buf).clamp(NBUF, MAXBUF);
     let mut lru = BCACHE.lru.lock();
-    for b in BCACHE.buf.iter() {
+    lru.table = (0..nbuf.next_power_of_two()).map(|_| Vec::new()).collect();
+    for _ in 0..nbuf {
+        let b: &'static SleepLock<Data> =
+            Box::leak(Box::new(SleepLock::new(Data::new(), "buffer")));
         lru.add(b);
     }
 }
+
+pub fn spawn_tasks() {
+    spawn_on(VIRTIO0_HART, Task::new(writeback_task()));
+}
+
+// Write dirty buffers back every WRITEBACK_TICKS.
+async fn writeback_task() {
+    loop {
+        sleep_ticks(WRITEBACK_TICKS).await;
+        let mut pending = BCACHE.start_writeback();
+        while !pending.is_empty() {
+            sleep_ticks(1).await;
+            pending.retain_mut(|(b, dev, id)| match disk(*dev).finish(*id) {
+                Some(data) => {
+                    b.data_guard = Some(data);
+                    BCACHE.stats.writeback.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+                None => true,
+            });
+        }
+    }
+}
@@ -20,6 +20,7 @@ pub struct BuddyAllocator {
     base: usize,       // memory start address
     end: usize,        // memory end address
     nsize: usize,      // number of entries in self.sizes array
+    total: usize,      // bytes free after init
     sizes: Option<NonNull<[SzInfo]>>,
 }
 unsafe impl Send for BuddyAllocator {}
@@ -126,6 +127,7 @@ impl BuddyAllocator {
             base: 0,
             end: 0,
             nsize: 0,
+            total: 0,
             sizes: None,
         }
     }
@@ -176,6 +178,11 @@ impl BuddyAllocator {
         }
     }
 
+    // Bytes the allocator manages, in use or not.
+    pub fn total_bytes(&self) -> usize {
+        self.total
+    }
+
     pub fn free_bytes(&self) -> usize {
         let Some(sizes_ptr) = self.sizes else {
             return 0;
@@ -373,6 +380,7 @@ impl BuddyAllocator {
                 return Err("allocator bug: free != total - meta - unavailable");
             }
 
+            self.total = free;
             self.initialized = true;
             Ok(())
         }
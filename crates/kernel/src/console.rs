@@ -10,9 +10,9 @@
 use core::num::Wrapping;
 
 use crate::error::{Error::*, Result};
-use crate::file::{DEVSW, Device, Major};
+use crate::file::{DEVSW, Device};
 use crate::framebuffer;
-use crate::proc::{Cpus, dump, either_copyin, either_copyout, kill_pgrp, sleep, wakeup};
+use crate::proc::{self, Cpus, dump, either_copyin, either_copyout, kill_pgrp, sleep, wakeup};
 use crate::signal::{SIGINT, SIGTSTP};
 use crate::spinlock::Mutex;
 use crate::uart;
@@ -219,7 +219,8 @@ impl Device for Mutex<Cons> {
             // wait until interrupt handler has put some
             // input into CONS.buf
             while cons_guard.r == cons_guard.w {
-                if p.inner.lock().killed {
+                // A signal ends the wait unless part of a line was read.
+                if p.inner.lock().killed || (n == target && proc::interrupted()) {
                     return Err(Interrupted);
                 }
                 cons_guard = sleep(&cons_guard.r as *const _ as usize, cons_guard);
@@ -278,8 +279,8 @@ impl Device for Mutex<Cons> {
         Ok(written)
     }
 
-    fn major(&self) -> Major {
-        Major::Console
+    fn is_console(&self) -> bool {
+        true
     }
 }
 
@@ -302,7 +303,7 @@ impl Mutex<Cons> {
                     cons_guard.fg_pgrp
                 };
                 if target != 0 {
-                    let _ = kill_pgrp(target, SIGINT);
+                    let _ = kill_pgrp(target, SIGINT, 0);
                 }
             }
             m if m == ctrl(b'Z') => {
@@ -312,7 +313,7 @@ impl Mutex<Cons> {
                     cons_guard.fg_pgrp
                 };
                 if target != 0 {
-                    let _ = kill_pgrp(target, SIGTSTP);
+                    let _ = kill_pgrp(target, SIGTSTP, 0);
                 }
             }
             // Print process list
@@ -367,7 +368,9 @@ impl Mutex<Cons> {
 
 pub fn init() {
     unsafe { uart::init() }
-    DEVSW.set(Major::Console, &CONS).unwrap();
+    DEVSW.register("console", 0o666, &CONS).unwrap();
+    // the only terminal, so it is everyone's controlling tty.
+    DEVSW.register("tty", 0o666, &CONS).unwrap();
 }
 
 pub fn readable() -> bool {
@@ -413,6 +416,16 @@ pub fn set_fg_pgrp(sid: usize, pgid: usize) -> Result<()> {
     Ok(())
 }
 
+// Called when session leader sid exits, so that the session started
+// after it (the next login) can take over the console.
+pub fn release_session(sid: usize) {
+    let mut guard = CONS.lock();
+    if guard.session == sid {
+        guard.session = 0;
+        guard.fg_pgrp = 0;
+    }
+}
+
 // send one character to the uart + framebuffer.
 // called by printf, and to echo input characters,
 // but not from write().
@@ -0,0 +1,38 @@
+// CRC-32 (IEEE 802.3, the one zlib and ethernet use).
+//
+// The journal checksums its header and every logged block with it, so
+// recovery can tell a complete commit from a torn one. Plain code with
+// no kernel dependencies, so mkfs and fsck can check the log too.
+
+const POLY: u32 = 0xedb8_8320; // reflected 0x04c11db7
+
+static TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { POLY ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+// Continue a checksum over more data; start from 0.
+pub fn update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in data {
+        c = TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    update(0, data)
+}
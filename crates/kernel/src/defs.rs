@@ -26,7 +26,13 @@ pub unsafe fn as_bytes_mut<T: ?Sized>(refs: &mut T) -> &mut [u8] {
 pub use core::mem::{ManuallyDrop, MaybeUninit};
 use core::net::Ipv4Addr;
 
-use crate::{fs::DirEnt, stat::Stat};
+use crate::{
+    fs::DirEnt,
+    signal::{SigAction, SigInfo},
+    stat::{DirRecord, Stat},
+    time::Timespec,
+    tls::Tls,
+};
 
 #[repr(C)]
 pub union _transmuter<T, const N: usize> {
@@ -113,8 +119,14 @@ pub unsafe trait FromBytes: Sized {
 
 // u8, [u8; N], [u8], stats
 unsafe impl AsBytes for Stat {}
+unsafe impl AsBytes for DirRecord {}
+unsafe impl AsBytes for Timespec {}
+unsafe impl AsBytes for SigAction {}
+unsafe impl AsBytes for SigInfo {}
+unsafe impl AsBytes for Tls {}
 unsafe impl AsBytes for str {}
 unsafe impl AsBytes for u8 {}
+unsafe impl AsBytes for u32 {}
 unsafe impl AsBytes for usize {}
 unsafe impl AsBytes for i32 {}
 unsafe impl<T: AsBytes> AsBytes for [T] {}
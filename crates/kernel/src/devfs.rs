@@ -0,0 +1,220 @@
+// Device file system.
+//
+// /dev holds one device node for every driver registered in DEVSW,
+// named as the driver asked, with its major number and minor 0.
+// Nothing is stored: a driver's node appears as soon as its init
+// registers it, and nodes cannot be created, removed or renamed here.
+// Device nodes made with mknod elsewhere still work as before.
+
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use crate::error::{Error::*, Result};
+use crate::fcntl::OMode;
+use crate::file::{DEVSW, Major};
+use crate::fs::ROOTINO;
+use crate::stat::{FileType, Stat};
+use crate::time;
+use crate::vfs::{self, FileSystem, Node, Vnode, check_access, put_dirent};
+use crate::vm::VirtAddr;
+
+pub struct DevFs {
+    dev: u32,
+}
+
+impl DevFs {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            dev: vfs::anon_dev(),
+        })
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn dev(&self) -> u32 {
+        self.dev
+    }
+
+    fn root(self: Arc<Self>) -> Result<Vnode> {
+        Ok(Arc::new(DevNode {
+            fs: self,
+            major: None,
+        }))
+    }
+}
+
+// The root directory, or the node of one registered device.
+pub struct DevNode {
+    fs: Arc<DevFs>,
+    major: Option<Major>,
+}
+
+// Device inode numbers follow the root.
+fn ino_of(major: Option<Major>) -> u32 {
+    match major {
+        None => ROOTINO,
+        Some(Major(m)) => ROOTINO + 1 + m as u32,
+    }
+}
+
+fn ftype_of(major: Option<Major>) -> FileType {
+    match major {
+        None => FileType::Dir,
+        Some(_) => FileType::Device,
+    }
+}
+
+impl DevNode {
+    fn node(&self, major: Option<Major>) -> Vnode {
+        Arc::new(DevNode {
+            fs: Arc::clone(&self.fs),
+            major,
+        })
+    }
+
+    fn perm(&self) -> u32 {
+        match self.major {
+            None => 0o755,
+            Some(m) => DEVSW.entry(m).map_or(0, |e| e.perm as u32),
+        }
+    }
+}
+
+impl Node for DevNode {
+    fn dev(&self) -> u32 {
+        self.fs.dev
+    }
+
+    fn ino(&self) -> u32 {
+        ino_of(self.major)
+    }
+
+    fn stat(&self, st: &mut Stat) -> Result<()> {
+        let now = time::realtime();
+        st.dev = self.fs.dev;
+        st.ino = self.ino();
+        st.ftype = self.file_type();
+        st.nlink = if self.major.is_none() { 2 } else { 1 };
+        st.mode = self.perm();
+        st.uid = 0;
+        st.gid = 0;
+        st.size = 0;
+        st.atime = now;
+        st.mtime = now;
+        st.ctime = now;
+        Ok(())
+    }
+
+    fn file_type(&self) -> FileType {
+        ftype_of(self.major)
+    }
+
+    fn major(&self) -> Major {
+        self.major.unwrap_or_default()
+    }
+
+    fn access(&self, want: u32) -> Result<()> {
+        check_access(self.file_type(), self.perm(), 0, 0, want)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Vnode> {
+        if self.major.is_some() {
+            return Err(NotADirectory);
+        }
+        if name == ".." {
+            return Ok(self.node(None));
+        }
+        let major = DEVSW.lookup(name).ok_or(NotFound)?;
+        Ok(self.node(Some(major)))
+    }
+
+    fn create(
+        &self,
+        _name: &str,
+        _ftype: FileType,
+        _major: u16,
+        _minor: u16,
+        _perm: u32,
+    ) -> Result<Vnode> {
+        Err(PermissionDenied)
+    }
+
+    fn open(self: Arc<Self>, _omode: &OMode) -> Result<Vnode> {
+        Ok(self)
+    }
+
+    // Device nodes are read and written through their driver, never here.
+    fn read(&self, _dst: VirtAddr, _off: u64, _n: usize) -> Result<usize> {
+        Err(if self.major.is_none() {
+            IsADirectory
+        } else {
+            InvalidArgument
+        })
+    }
+
+    fn write(&self, _src: VirtAddr, _off: &mut u64, _n: usize, _append: bool) -> Result<usize> {
+        Err(if self.major.is_none() {
+            IsADirectory
+        } else {
+            InvalidArgument
+        })
+    }
+
+    // The cookie counts records: ".", ".." and then the devices.
+    fn readdir(&self, off: &mut u64, mut dst: VirtAddr, n: usize) -> Result<usize> {
+        if self.major.is_some() {
+            return Err(NotADirectory);
+        }
+        let dots = [(".".to_string(), None), ("..".to_string(), None)];
+        let devices: Vec<_> = DEVSW
+            .entries()
+            .map(|(m, e)| (e.name.to_string(), Some(m)))
+            .collect();
+        let mut tot = 0;
+        for (name, major) in dots.into_iter().chain(devices).skip(*off as usize) {
+            if !put_dirent(&mut dst, &mut tot, n, ino_of(major), ftype_of(major), &name)? {
+                break;
+            }
+            *off += 1;
+        }
+        Ok(tot)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn link(&self, _name: &str, _target: &Vnode) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn symlink(&self, _name: &str, _target: &str) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn fsync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, _name: &str, _ndir: &Vnode, _nname: &str) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn chmod(&self, _perm: u32) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn chown(&self, _uid: u32, _gid: u32) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,10 +1,11 @@
 use crate::defs::{AsBytes, FromBytes};
 
 pub const DFS_MAGIC: u32 = 0x4446_5331; // "DFS1"
-pub const DFS_PREFIX: &str = "/dfs";
-pub const DFS_PREFIX_DIR: &str = "/dfs/";
+pub const DFS_PREFIX: &str = "/dfs"; // where the kernel mounts dfs at boot
 pub const DFS_ADDR: &str = "10.0.2.15:7000";
 pub const DFS_MAX_CHUNK: usize = 512;
+// DfsReq.off of a Read or Write that uses the handle's own offset.
+pub const DFS_OFF_NONE: u64 = u64::MAX;
 
 #[repr(u16)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -19,6 +20,7 @@ pub enum DfsOp {
     Link = 8,
     Symlink = 9,
     Fsync = 10,
+    Readdir = 11,
 }
 
 #[repr(C)]
@@ -31,6 +33,7 @@ pub struct DfsReq {
     pub handle: u32,
     pub len: u32,
     pub aux: u32,
+    pub off: u64, // file offset of a Read or Write
 }
 
 impl DfsReq {
@@ -43,8 +46,14 @@ impl DfsReq {
             handle,
             len,
             aux,
+            off: DFS_OFF_NONE,
         }
     }
+
+    pub fn at(mut self, off: u64) -> Self {
+        self.off = off;
+        self
+    }
 }
 
 unsafe impl AsBytes for DfsReq {}
@@ -75,23 +84,26 @@ unsafe impl FromBytes for DfsResp {}
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
 mod client {
+    use alloc::format;
+    use alloc::string::{String, ToString};
     use alloc::sync::Arc;
     use alloc::vec::Vec;
+    use core::any::Any;
     use core::cmp::min;
     use core::mem::size_of;
 
-    use super::{
-        DFS_ADDR, DFS_MAGIC, DFS_MAX_CHUNK, DFS_PREFIX, DFS_PREFIX_DIR, DfsOp, DfsReq, DfsResp,
-    };
+    use super::{DFS_ADDR, DFS_MAGIC, DFS_MAX_CHUNK, DFS_OFF_NONE, DfsOp, DfsReq, DfsResp};
     use crate::defs::{AsBytes, FromBytes};
     use crate::error::{Error::*, Result};
-    use crate::fs::Path;
+    use crate::fcntl::{OMode, omode};
+    use crate::fs::ROOTINO;
     use crate::proc::{either_copyin, either_copyout};
     use crate::sleeplock::SleepLock;
     use crate::socket::{InetSocket, SOCK_STREAM};
     use crate::spinlock::Mutex;
-    use crate::stat::Stat;
+    use crate::stat::{FileType, Stat};
     use crate::sync::LazyLock;
+    use crate::vfs::{self, FileSystem, Node, Vnode};
     use crate::vm::VirtAddr;
 
     struct ClientState {
@@ -196,22 +208,6 @@ mod client {
         }
     }
 
-    pub fn is_remote_path(path: &Path) -> bool {
-        let s = path.as_str();
-        s == DFS_PREFIX || s.starts_with(DFS_PREFIX_DIR)
-    }
-
-    fn remote_path(path: &Path) -> Option<&str> {
-        let s = path.as_str();
-        if s == DFS_PREFIX {
-            Some("/")
-        } else if s.starts_with(DFS_PREFIX_DIR) {
-            Some(&s[DFS_PREFIX.len()..])
-        } else {
-            None
-        }
-    }
-
     fn check_status(resp: &DfsResp) -> Result<()> {
         if resp.status < 0 {
             return Err(crate::error::Error::from_isize(resp.status as isize));
@@ -219,19 +215,22 @@ mod client {
         Ok(())
     }
 
-    pub fn open(path: &Path, flags: usize) -> Result<u32> {
-        let rpath = remote_path(path).ok_or(InvalidArgument)?;
-        let req = DfsReq::new(DfsOp::Open, flags as u32, 0, rpath.len() as u32, 0);
-        let (resp, _) = call(&req, &[rpath.as_bytes()])?;
+    pub fn open(path: &str, flags: usize) -> Result<u32> {
+        let req = DfsReq::new(DfsOp::Open, flags as u32, 0, path.len() as u32, 0);
+        let (resp, _) = call(&req, &[path.as_bytes()])?;
         check_status(&resp)?;
         Ok(resp.handle)
     }
 
-    pub fn read(handle: u32, dst: VirtAddr, n: usize) -> Result<usize> {
+    // Read n bytes at off, or at the handle's offset for DFS_OFF_NONE.
+    pub fn read(handle: u32, off: u64, dst: VirtAddr, n: usize) -> Result<usize> {
         let mut total = 0usize;
         while total < n {
             let chunk = min(DFS_MAX_CHUNK, n - total);
-            let req = DfsReq::new(DfsOp::Read, 0, handle, chunk as u32, 0);
+            let mut req = DfsReq::new(DfsOp::Read, 0, handle, chunk as u32, 0);
+            if off != DFS_OFF_NONE {
+                req = req.at(off + total as u64);
+            }
             let (resp, data) = call(&req, &[])?;
             check_status(&resp)?;
             let got = resp.status as usize;
@@ -249,14 +248,18 @@ mod client {
         Ok(total)
     }
 
-    pub fn write(handle: u32, src: VirtAddr, n: usize) -> Result<usize> {
+    // Write n bytes at off, or at the handle's offset for DFS_OFF_NONE.
+    pub fn write(handle: u32, off: u64, src: VirtAddr, n: usize) -> Result<usize> {
         let mut total = 0usize;
         while total < n {
             let chunk = min(DFS_MAX_CHUNK, n - total);
             let mut buf = alloc::vec![0u8; chunk];
             let addr = offset_addr(src, total);
             either_copyin(&mut buf[..], addr)?;
-            let req = DfsReq::new(DfsOp::Write, 0, handle, chunk as u32, 0);
+            let mut req = DfsReq::new(DfsOp::Write, 0, handle, chunk as u32, 0);
+            if off != DFS_OFF_NONE {
+                req = req.at(off + total as u64);
+            }
             let (resp, _) = call(&req, &[&buf])?;
             check_status(&resp)?;
             let wrote = resp.status as usize;
@@ -282,7 +285,7 @@ mod client {
         Ok(())
     }
 
-    pub fn stat(handle: u32, dst: VirtAddr) -> Result<()> {
+    pub fn stat(handle: u32) -> Result<Stat> {
         let req = DfsReq::new(DfsOp::Stat, 0, handle, 0, 0);
         let (resp, data) = call(&req, &[])?;
         check_status(&resp)?;
@@ -292,48 +295,267 @@ mod client {
         let mut stat: Stat = Default::default();
         stat.as_bytes_mut()
             .copy_from_slice(&data[..size_of::<Stat>()]);
-        either_copyout(dst, &stat)
+        Ok(stat)
     }
 
-    pub fn mkdir(path: &Path) -> Result<()> {
-        let rpath = remote_path(path).ok_or(InvalidArgument)?;
-        let req = DfsReq::new(DfsOp::Mkdir, 0, 0, rpath.len() as u32, 0);
-        let (resp, _) = call(&req, &[rpath.as_bytes()])?;
+    // Stat a remote path, holding it open just for the call.
+    pub fn stat_path(path: &str) -> Result<Stat> {
+        let handle = open(path, omode::RDONLY)?;
+        let res = stat(handle);
+        close(handle)?;
+        res
+    }
+
+    // Fetch the next directory records of a remote directory, already
+    // in getdents form.
+    pub fn readdir(handle: u32, dst: VirtAddr, n: usize) -> Result<usize> {
+        let req = DfsReq::new(DfsOp::Readdir, 0, handle, min(n, DFS_MAX_CHUNK) as u32, 0);
+        let (resp, data) = call(&req, &[])?;
+        check_status(&resp)?;
+        let len = min(resp.status as usize, data.len());
+        either_copyout(dst, &data[..len])?;
+        Ok(len)
+    }
+
+    pub fn mkdir(path: &str) -> Result<()> {
+        let req = DfsReq::new(DfsOp::Mkdir, 0, 0, path.len() as u32, 0);
+        let (resp, _) = call(&req, &[path.as_bytes()])?;
         check_status(&resp)?;
         Ok(())
     }
 
-    pub fn unlink(path: &Path) -> Result<()> {
-        let rpath = remote_path(path).ok_or(InvalidArgument)?;
-        let req = DfsReq::new(DfsOp::Unlink, 0, 0, rpath.len() as u32, 0);
-        let (resp, _) = call(&req, &[rpath.as_bytes()])?;
+    pub fn unlink(path: &str) -> Result<()> {
+        let req = DfsReq::new(DfsOp::Unlink, 0, 0, path.len() as u32, 0);
+        let (resp, _) = call(&req, &[path.as_bytes()])?;
         check_status(&resp)?;
         Ok(())
     }
 
-    pub fn link(old: &Path, new: &Path) -> Result<()> {
-        let oldp = remote_path(old).ok_or(InvalidArgument)?;
-        let newp = remote_path(new).ok_or(InvalidArgument)?;
-        let req = DfsReq::new(DfsOp::Link, 0, 0, oldp.len() as u32, newp.len() as u32);
-        let (resp, _) = call(&req, &[oldp.as_bytes(), newp.as_bytes()])?;
+    pub fn link(old: &str, new: &str) -> Result<()> {
+        let req = DfsReq::new(DfsOp::Link, 0, 0, old.len() as u32, new.len() as u32);
+        let (resp, _) = call(&req, &[old.as_bytes(), new.as_bytes()])?;
         check_status(&resp)?;
         Ok(())
     }
 
-    pub fn symlink(target: &str, linkpath: &Path) -> Result<()> {
-        let linkp = remote_path(linkpath).ok_or(InvalidArgument)?;
-        let tgt = if target.starts_with(DFS_PREFIX_DIR) {
-            &target[DFS_PREFIX.len()..]
-        } else if target == DFS_PREFIX {
-            "/"
-        } else {
-            target
-        };
-        let req = DfsReq::new(DfsOp::Symlink, 0, 0, tgt.len() as u32, linkp.len() as u32);
-        let (resp, _) = call(&req, &[tgt.as_bytes(), linkp.as_bytes()])?;
+    // Create linkpath as a symlink to target, which the server resolves
+    // in its own tree.
+    pub fn symlink(target: &str, linkpath: &str) -> Result<()> {
+        let req = DfsReq::new(
+            DfsOp::Symlink,
+            0,
+            0,
+            target.len() as u32,
+            linkpath.len() as u32,
+        );
+        let (resp, _) = call(&req, &[target.as_bytes(), linkpath.as_bytes()])?;
         check_status(&resp)?;
         Ok(())
     }
+
+    // dfs as a file system of the vfs: each node names a path on the
+    // server, and nodes opened through open() also hold a handle.
+    pub struct DfsFs {
+        dev: u32,
+    }
+
+    impl DfsFs {
+        pub fn new() -> Arc<Self> {
+            Arc::new(Self {
+                dev: vfs::anon_dev(),
+            })
+        }
+    }
+
+    impl FileSystem for DfsFs {
+        fn name(&self) -> &'static str {
+            "dfs"
+        }
+
+        fn dev(&self) -> u32 {
+            self.dev
+        }
+
+        // The root is not looked up on the server, so mounting works
+        // before it is up.
+        fn root(self: Arc<Self>) -> Result<Vnode> {
+            Ok(Arc::new(DfsNode {
+                fs: self,
+                path: "/".to_string(),
+                ftype: FileType::Dir,
+                ino: ROOTINO,
+                handle: None,
+            }))
+        }
+    }
+
+    pub struct DfsNode {
+        fs: Arc<DfsFs>,
+        path: String,
+        ftype: FileType,
+        ino: u32,
+        handle: Option<u32>,
+    }
+
+    impl DfsNode {
+        // Server path of name in this directory.
+        fn child(&self, name: &str) -> String {
+            match (name, self.path.rsplit_once('/')) {
+                ("..", Some((parent, _))) if !parent.is_empty() => parent.to_string(),
+                ("..", _) => "/".to_string(),
+                _ if self.path == "/" => format!("/{}", name),
+                _ => format!("{}/{}", self.path, name),
+            }
+        }
+
+        // A node for path, which must exist on the server.
+        fn node(&self, path: String) -> Result<Vnode> {
+            let st = stat_path(&path)?;
+            Ok(Arc::new(DfsNode {
+                fs: Arc::clone(&self.fs),
+                ino: if path == "/" { ROOTINO } else { st.ino },
+                ftype: st.ftype,
+                path,
+                handle: None,
+            }))
+        }
+
+        // Call f with the node's handle, or with one opened read-only
+        // for the call if it has none.
+        fn with_handle<T>(&self, f: impl FnOnce(u32) -> Result<T>) -> Result<T> {
+            match self.handle {
+                Some(handle) => f(handle),
+                None => {
+                    let handle = open(&self.path, omode::RDONLY)?;
+                    let res = f(handle);
+                    close(handle)?;
+                    res
+                }
+            }
+        }
+    }
+
+    impl Drop for DfsNode {
+        fn drop(&mut self) {
+            if let Some(handle) = self.handle {
+                let _ = close(handle);
+            }
+        }
+    }
+
+    impl Node for DfsNode {
+        fn dev(&self) -> u32 {
+            self.fs.dev
+        }
+
+        fn ino(&self) -> u32 {
+            self.ino
+        }
+
+        fn stat(&self, st: &mut Stat) -> Result<()> {
+            *st = self.with_handle(stat)?;
+            st.dev = self.fs.dev;
+            st.ino = self.ino;
+            Ok(())
+        }
+
+        fn file_type(&self) -> FileType {
+            self.ftype
+        }
+
+        fn lookup(&self, name: &str) -> Result<Vnode> {
+            if self.ftype != FileType::Dir {
+                return Err(NotADirectory);
+            }
+            self.node(self.child(name))
+        }
+
+        fn create(
+            &self,
+            name: &str,
+            ftype: FileType,
+            _major: u16,
+            _minor: u16,
+            _perm: u32,
+        ) -> Result<Vnode> {
+            let path = self.child(name);
+            match ftype {
+                FileType::File => close(open(&path, omode::CREATE)?)?,
+                FileType::Dir => mkdir(&path)?,
+                _ => return Err(InvalidArgument),
+            }
+            self.node(path)
+        }
+
+        fn open(self: Arc<Self>, omode: &OMode) -> Result<Vnode> {
+            let mut flags = match (omode.is_read(), omode.is_write()) {
+                (true, true) => omode::RDWR,
+                (false, true) => omode::WRONLY,
+                _ => omode::RDONLY,
+            };
+            if omode.is_trunc() {
+                flags |= omode::TRUNC;
+            }
+            if omode.is_append() {
+                flags |= omode::APPEND;
+            }
+            let handle = open(&self.path, flags)?;
+            Ok(Arc::new(DfsNode {
+                fs: Arc::clone(&self.fs),
+                path: self.path.clone(),
+                ftype: self.ftype,
+                ino: self.ino,
+                handle: Some(handle),
+            }))
+        }
+
+        fn read(&self, dst: VirtAddr, off: u64, n: usize) -> Result<usize> {
+            self.with_handle(|handle| read(handle, off, dst, n))
+        }
+
+        fn write(&self, src: VirtAddr, off: &mut u64, n: usize, append: bool) -> Result<usize> {
+            let at = if append { DFS_OFF_NONE } else { *off };
+            let r = self.with_handle(|handle| write(handle, at, src, n))?;
+            *off += r as u64;
+            Ok(r)
+        }
+
+        // The server keeps the position in the directory, off only
+        // counts bytes.
+        fn readdir(&self, off: &mut u64, dst: VirtAddr, n: usize) -> Result<usize> {
+            let r = self.with_handle(|handle| readdir(handle, dst, n))?;
+            *off += r as u64;
+            Ok(r)
+        }
+
+        fn unlink(&self, name: &str) -> Result<()> {
+            unlink(&self.child(name))
+        }
+
+        fn link(&self, name: &str, target: &Vnode) -> Result<()> {
+            match target.as_any().downcast_ref::<DfsNode>() {
+                Some(target) if Arc::ptr_eq(&target.fs, &self.fs) => {
+                    link(&target.path, &self.child(name))
+                }
+                _ => Err(CrossesDevices),
+            }
+        }
+
+        fn symlink(&self, name: &str, target: &str) -> Result<()> {
+            symlink(target, &self.child(name))
+        }
+
+        fn fsync(&self) -> Result<()> {
+            match self.handle {
+                Some(handle) => fsync(handle),
+                None => Ok(()),
+            }
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -15,6 +15,7 @@ pub const ELF64CL: u8 = 2;
 pub const ELFDATA2LSB: u8 = 1;
 
 pub const PT_LOAD: u32 = 1;
+pub const PT_TLS: u32 = 7;
 
 #[derive(Copy, Clone, Default)]
 #[repr(C, packed)]
@@ -35,6 +35,9 @@ pub enum Error {
     Utf8Error = -28,
     WriteZero = -29,
     NotConnected = -30,
+    FileNameTooLong = -31,
+    IllegalSeek = -32,
+    TimedOut = -33,
 }
 
 impl Error {
@@ -70,6 +73,9 @@ impl Error {
             Utf8Error => "slice is not utf8",
             WriteZero => "write zero",
             NotConnected => "not connected",
+            FileNameTooLong => "file name too long",
+            IllegalSeek => "illegal seek",
+            TimedOut => "timed out",
             Uncategorized => "uncategorized error",
         }
     }
@@ -106,6 +112,9 @@ impl Error {
             -28 => Utf8Error,
             -29 => WriteZero,
             -30 => NotConnected,
+            -31 => FileNameTooLong,
+            -32 => IllegalSeek,
+            -33 => TimedOut,
             _ => Uncategorized,
         }
     }
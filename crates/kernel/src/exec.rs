@@ -1,17 +1,20 @@
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec;
 use core::mem::size_of;
 
 use crate::{
     elf::{self, ElfHdr, ProgHdr},
     error::{Error::*, Result},
-    fs::{IData, Path},
-    log::LOG,
+    fs::Path,
     memlayout::{STACK_PAGE_NUM, user_mem_top},
-    param::{MAXARG, NPROC},
+    param::{MAXARG, NOFILE, NPROC},
     proc::{self, AddrSpace, Cpus},
     riscv::{PGSIZE, pgroundup, pteflags},
-    sleeplock::SleepLockGuard,
+    signal::{SIG_IGN, SigAction},
+    stat::{Stat, mode},
+    tls::Tls,
+    vfs::Vnode,
     vm::{Addr, UVAddr, Uvm, VirtAddr},
 };
 
@@ -31,13 +34,7 @@ pub const fn flags2perm(flags: u32) -> usize {
 // and the pages from va to va+sz must already be mapped.
 // Returns Ok(()) on success, Err(_) on failure.
 impl Uvm {
-    pub fn loadseg(
-        &mut self,
-        va: UVAddr,
-        ip_guard: &mut SleepLockGuard<IData>,
-        offset: usize,
-        sz: usize,
-    ) -> Result<()> {
+    pub fn loadseg(&mut self, va: UVAddr, vn: &Vnode, offset: usize, sz: usize) -> Result<()> {
         if !va.is_aligned() {
             panic!("loadseg(): va must be aligned.");
         }
@@ -47,7 +44,7 @@ impl Uvm {
         while i < sz {
             let pa = self.walkaddr(va + i)?;
             let n = if sz - i < PGSIZE { sz - i } else { PGSIZE };
-            ip_guard.read(From::from(pa), (offset + i) as u32, n)?;
+            vn.read(From::from(pa), (offset + i) as u64, n)?;
             i += PGSIZE;
         }
         Ok(())
@@ -78,15 +75,19 @@ pub fn exec(
     let mut elf: ElfHdr = Default::default();
     let mut res;
     let mut sz = 0;
+    let mut setid = (0, 0, 0); // mode, uid and gid of the program
+    let mut tls = Tls::default(); // from PT_TLS, if any
 
     {
-        LOG.begin_op();
         let mut load = || -> Result<usize> {
-            let (_, ip) = path.namei()?;
-            let mut ip_guard = ip.lock();
+            let (_, vn) = path.namei()?;
+            vn.access(mode::X_OK)?;
+            let mut st = Stat::default();
+            vn.stat(&mut st)?;
+            setid = (st.mode, st.uid, st.gid);
 
             // Load & Check ELF header
-            ip_guard.read(
+            vn.read(
                 VirtAddr::Kernel(&mut elf as *mut _ as usize),
                 0,
                 size_of::<ElfHdr>(),
@@ -105,11 +106,26 @@ pub fn exec(
             let mut phdr: ProgHdr = Default::default();
             let mut off = elf.e_phoff;
             for _ in 0..elf.e_phnum {
-                ip_guard.read(
+                vn.read(
                     VirtAddr::Kernel(&mut phdr as *mut _ as usize),
-                    off as u32,
+                    off as u64,
                     size_of::<ProgHdr>(),
                 )?;
+                off += size_of::<ProgHdr>();
+                if phdr.p_type == elf::PT_TLS {
+                    if phdr.p_msize < phdr.p_fsize
+                        || (phdr.p_align != 0 && !phdr.p_align.is_power_of_two())
+                    {
+                        return Err(ExecFileFormatError);
+                    }
+                    tls = Tls {
+                        image: phdr.p_vaddr,
+                        filesz: phdr.p_fsize,
+                        memsz: phdr.p_msize,
+                        align: phdr.p_align,
+                    };
+                    continue;
+                }
                 if phdr.p_type != elf::PT_LOAD {
                     continue;
                 }
@@ -129,16 +145,14 @@ pub fn exec(
                 )?;
                 uvm.as_mut().unwrap().loadseg(
                     From::from(phdr.p_vaddr),
-                    &mut ip_guard,
+                    &vn,
                     phdr.p_offset,
                     phdr.p_fsize,
                 )?;
-                off += size_of::<ProgHdr>();
             }
             Ok(0)
         };
         res = load();
-        LOG.end_op();
     }
 
     let exec = || -> Result<usize> {
@@ -161,9 +175,30 @@ pub fn exec(
         let mut sp: UVAddr = UVAddr::from(sz);
         let stackbase: UVAddr = sp - PGSIZE * STACK_PAGE_NUM;
 
+        // The first thread's TLS area goes at the top of the stack: the
+        // block copied from the PT_TLS template, with its header below.
+        let base = sz
+            .checked_sub(tls.area_size())
+            .filter(|&base| base >= stackbase.into_usize())
+            .ok_or(ExecFileFormatError)?;
+        let tp = tls.tp(base);
+        let mut image = vec![0u8; tls.filesz];
+        uvm.as_mut()
+            .unwrap()
+            .copyin(&mut image[..], tls.image.into())?;
+        uvm.as_mut().unwrap().copyout(tp.into(), &image[..])?;
+        uvm.as_mut()
+            .unwrap()
+            .copyout(Tls::header(tp).into(), &tls)?;
+        sp = UVAddr::from(base);
+        sp -= sp.into_usize() % 16;
+
         // Push argument strings, prepare rest of stack in ustack.
         let mut argc = 0;
+        let mut cmdline = String::new();
         for arg in argv.into_iter().take_while(|e| e.is_some()).flatten() {
+            cmdline.push_str(&arg);
+            cmdline.push('\0');
             sp -= arg.len();
             sp -= sp.into_usize() % 16; // riscv sp must be 16-byte aligned
             if sp < stackbase {
@@ -228,10 +263,39 @@ pub fn exec(
         // main(.., args).
         tf.a1 = if argc > 0 { sp.into_usize() } else { 0 };
 
+        // Caught signals go back to their defaults, since the handlers
+        // were in the old image; ignored ones stay ignored.
+        {
+            let mut guard = p.inner.lock();
+            for act in guard.sig_actions.iter_mut() {
+                if act.handler != SIG_IGN {
+                    *act = SigAction::default();
+                }
+            }
+        }
+        proc_data.sig_active = false;
+
+        let exported = p.exported.lock();
+
         // Save program name for debugging.
         if let Some(name) = path.file_name() {
             proc_data.name = name.to_string();
         }
+        proc_data.cmdline = cmdline;
+
+        // A set-user-id or set-group-id program runs as its owner. The
+        // saved ids remember the effective ones, so it can drop and
+        // regain them with setuid()/setgid().
+        let (fmode, fuid, fgid) = setid;
+        let cred = &mut proc_data.cred;
+        if fmode & mode::ISUID != 0 {
+            cred.euid = fuid;
+        }
+        if fmode & mode::ISGID != 0 {
+            cred.egid = fgid;
+        }
+        cred.suid = cred.euid;
+        cred.sgid = cred.egid;
 
         // Commit to the user image.
         let old_aspace = proc_data
@@ -241,8 +305,11 @@ pub fn exec(
             .as_ref()
             .map(|aspace| aspace.inner.lock().sz)
             .unwrap_or(0);
+        let old_vmas = core::mem::take(&mut proc_data.vmas);
+        drop(exported);
         tf.epc = elf.e_entry; // initial program counter = main
         tf.sp = sp.into_usize(); // initial stack pointer
+        tf.tp = tp; // the first thread's TLS block
         if let Some(old_aspace) = old_aspace {
             let olduvm = {
                 let mut inner = old_aspace.inner.lock();
@@ -250,7 +317,7 @@ pub fn exec(
             };
             if let Some(mut olduvm) = olduvm {
                 // must unmap mmap leaf PTEs before freewalk
-                let writebacks = proc_data.munmap_all(&mut olduvm);
+                let writebacks = proc::munmap_all(old_vmas, &mut olduvm);
                 proc_data.mmap_base = user_mem_top(NPROC);
                 olduvm.proc_uvmfree(oldsz);
                 for wb in writebacks {
@@ -272,12 +339,9 @@ pub fn exec(
         }
         _ => {
             // close on exec
-            for file in p.data_mut().ofile.iter_mut() {
-                match file {
-                    Some(f) if f.is_cloexec() => {
-                        file.take();
-                    }
-                    _ => continue,
+            for fd in 0..NOFILE {
+                if p.data().ofile[fd].as_ref().is_some_and(|f| f.is_cloexec()) {
+                    drop(p.set_ofile(fd, None));
                 }
             }
         }
@@ -24,6 +24,26 @@ pub mod fd {
     pub const CLOEXEC: usize = 0x1;
 }
 
+// lseek whence.
+pub mod seek {
+    pub const SET: usize = 0; // from the start of the file
+    pub const CUR: usize = 1; // from the current offset
+    pub const END: usize = 2; // from the end of the file
+}
+
+// New offset for lseek from the current one and, for seek::END, the
+// file's size. Offsets before the start are invalid.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn seek_to(cur: usize, end: Option<usize>, off: isize, whence: usize) -> Result<usize> {
+    let base = match whence {
+        seek::SET => 0,
+        seek::CUR => cur,
+        seek::END => end.ok_or(InvalidArgument)?,
+        _ => return Err(InvalidArgument),
+    };
+    base.checked_add_signed(off).ok_or(InvalidArgument)
+}
+
 pub mod flock {
     pub const UNLCK: usize = 0;
     pub const RDLCK: usize = 1;
@@ -1,43 +1,41 @@
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use alloc::sync::Arc;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use core::cell::UnsafeCell;
-#[cfg(all(target_os = "none", feature = "kernel"))]
 use core::ops::Deref;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::array;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::console;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::dfs;
-#[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::error::{Error::*, Result};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::fcntl::{self, FcntlCmd, Flock, OMode, fd, flock, omode};
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::fs::{BSIZE, IData, Inode, Path, create};
+use crate::fs::{Inode, Path};
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::log::LOG;
-#[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::param::{MAXOPBLOCKS, NDEV, NFILE};
+use crate::param::{NDEV, NFILE};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::pipe::Pipe;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::poll;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::proc::{Cpus, either_copyin, either_copyout, kill_pgrp};
+use crate::proc::{self, Cpus, either_copyin, either_copyout, kill_pgrp};
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::sleeplock::{SleepLock, SleepLockGuard};
+use crate::sleeplock::SleepLock;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::socket::{self, InetSocket, UnixSocket};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::spinlock::Mutex;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::stat::{FileType, Stat};
+use crate::stat::{FileType, Stat, mode};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::sync::{LazyLock, OnceLock};
 #[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::vfs::{self, Vnode};
+#[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::vm::VirtAddr;
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -63,11 +61,10 @@ pub struct File {
 #[derive(Debug)]
 pub enum VFile {
     Device(DNod),
-    Inode(FNod),
+    Vnode(FNod),
     Pipe(Pipe),
     Socket(Arc<UnixSocket>),
     InetSocket(Arc<InetSocket>),
-    Remote(RemoteFile),
     None,
 }
 
@@ -76,8 +73,8 @@ pub enum VFile {
 #[derive(Debug)]
 pub struct DNod {
     driver: &'static dyn Device,
-    off: UnsafeCell<usize>, // Safety: offset uses per-open file state.
-    ip: Inode,
+    off: SleepLock<usize>,
+    vn: Vnode,
 }
 
 // Device functions, map this trait using dyn
@@ -85,13 +82,24 @@ pub struct DNod {
 pub trait Device: Send + Sync {
     fn read(&self, dst: VirtAddr, n: usize, offset: usize) -> Result<usize>;
     fn write(&self, src: VirtAddr, n: usize, offset: usize) -> Result<usize>;
-    fn major(&self) -> Major;
+    // Size in bytes for lseek from the end, if the device has one.
+    fn size(&self) -> Option<usize> {
+        None
+    }
+    // Write out anything the driver has buffered, for fsync.
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+    // The console, with job control and input readiness for poll.
+    fn is_console(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
 impl core::fmt::Debug for dyn Device {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Device fn {:?}", self.major())
+        write!(f, "Device fn {:p}", self)
     }
 }
 
@@ -104,151 +112,106 @@ impl Deref for DNod {
     }
 }
 
-#[cfg(all(target_os = "none", feature = "kernel"))]
-unsafe impl Send for DNod {}
-#[cfg(all(target_os = "none", feature = "kernel"))]
-unsafe impl Sync for DNod {}
-
 #[cfg(all(target_os = "none", feature = "kernel"))]
 impl DNod {
-    pub fn new(driver: &'static dyn Device, ip: Inode) -> Self {
+    pub fn new(driver: &'static dyn Device, vn: Vnode) -> Self {
         Self {
             driver,
-            off: UnsafeCell::new(0),
-            ip,
+            off: SleepLock::new(0, "devoff"),
+            vn,
         }
     }
 
-    fn read(&self, dst: VirtAddr, n: usize) -> Result<usize> {
-        let off = unsafe { &mut *self.off.get() };
-        let r = self.driver.read(dst, n, *off)?;
+    // Seekable devices, those with a size, hold the offset across the
+    // transfer like files do, so users of a shared fd each get their
+    // own range. Streams like the console ignore the offset, and may
+    // wait a long time for input while others write, so they don't.
+    fn transfer(&self, f: impl FnOnce(usize) -> Result<usize>) -> Result<usize> {
+        if self.driver.size().is_none() {
+            let r = f(*self.off.lock())?;
+            *self.off.lock() += r;
+            return Ok(r);
+        }
+        let mut off = self.off.lock();
+        let r = f(*off)?;
         *off += r;
         Ok(r)
     }
 
-    fn write(&self, src: VirtAddr, n: usize) -> Result<usize> {
-        let off = unsafe { &mut *self.off.get() };
-        let r = self.driver.write(src, n, *off)?;
-        *off += r;
-        Ok(r)
+    fn read(&self, dst: VirtAddr, n: usize) -> Result<usize> {
+        self.transfer(|off| self.driver.read(dst, n, off))
     }
-}
-
-// File & directory Node
-#[cfg(all(target_os = "none", feature = "kernel"))]
-#[derive(Debug)]
-pub struct FNod {
-    off: UnsafeCell<u32>, // Safety: If inode lock is obtained.
-    ip: Inode,
-}
-#[cfg(all(target_os = "none", feature = "kernel"))]
-unsafe impl Send for FNod {}
-#[cfg(all(target_os = "none", feature = "kernel"))]
-unsafe impl Sync for FNod {}
 
-#[cfg(all(target_os = "none", feature = "kernel"))]
-impl FNod {
-    pub fn new(ip: Inode, offset: u32) -> Self {
-        Self {
-            off: UnsafeCell::new(offset),
-            ip,
-        }
+    fn write(&self, src: VirtAddr, n: usize) -> Result<usize> {
+        self.transfer(|off| self.driver.write(src, n, off))
     }
 
-    fn read(&self, dst: VirtAddr, n: usize) -> Result<usize> {
-        LOG.begin_op();
-        let res = {
-            let mut ip = self.ip.lock();
-            let off = unsafe { &mut *self.off.get() };
-
-            let r = ip.read(dst, *off, n)?;
-            if r > 0 {
-                ip.touch_atime();
-            }
-            *off += r as u32;
-            Ok(r)
-        };
-        LOG.end_op();
-        res
+    // pread and pwrite leave the shared offset alone.
+    fn read_at(&self, dst: VirtAddr, n: usize, off: usize) -> Result<usize> {
+        self.driver.read(dst, n, off)
     }
 
-    fn write(&self, src: VirtAddr, n: usize, append: bool) -> Result<usize> {
-        // write a few blocks at a time to avoid exceeding the maximum
-        // log transaction size, including i-node, indirect block,
-        // allocation blocks, and 2 blocks of slop for non-aligned
-        // writes. this really belongs lower down, since inode write()
-        // might be writing a device like the console.
-        let max = ((MAXOPBLOCKS - 1 - 1 - 2) / 2) * BSIZE;
-        let mut ret: Result<usize> = Ok(0);
-        let mut i: usize = 0;
-
-        while i < n {
-            let mut n1 = n - i;
-            if n1 > max {
-                n1 = max
-            }
+    fn write_at(&self, src: VirtAddr, n: usize, off: usize) -> Result<usize> {
+        self.driver.write(src, n, off)
+    }
 
-            LOG.begin_op();
-            {
-                let mut guard = self.ip.lock();
-                let off = unsafe { &mut *self.off.get() };
-                if append {
-                    *off = guard.size();
-                }
-                ret = guard.write(src, *off, n1);
-                match ret {
-                    Ok(r) => {
-                        guard.touch_mtime_ctime();
-                        *off += r as u32;
-                        i += r;
-                        ret = Ok(i);
-                    }
-                    _ => break, // error from inode write
-                }
-            }
-            LOG.end_op();
-        }
-        if ret.is_err() {
-            LOG.end_op();
-        }
-        ret
+    fn seek(&self, off: isize, whence: usize) -> Result<usize> {
+        let mut pos = self.off.lock();
+        *pos = fcntl::seek_to(*pos, self.driver.size(), off, whence)?;
+        Ok(*pos)
     }
 }
 
-// Remote file node
+// File & directory Node, of any file system
 #[cfg(all(target_os = "none", feature = "kernel"))]
 #[derive(Debug)]
-pub struct RemoteFile {
-    handle: u32,
+pub struct FNod {
+    off: SleepLock<u64>,
+    vn: Vnode,
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
-impl RemoteFile {
-    pub fn new(handle: u32) -> Self {
-        Self { handle }
+impl FNod {
+    pub fn new(vn: Vnode, offset: u64) -> Self {
+        Self {
+            off: SleepLock::new(offset, "fileoff"),
+            vn,
+        }
     }
 
     fn read(&self, dst: VirtAddr, n: usize) -> Result<usize> {
-        dfs::read(self.handle, dst, n)
+        let mut off = self.off.lock();
+        let r = self.vn.read(dst, *off, n)?;
+        *off += r as u64;
+        Ok(r)
     }
 
-    fn write(&self, src: VirtAddr, n: usize) -> Result<usize> {
-        dfs::write(self.handle, src, n)
+    // pread and pwrite leave the shared offset alone.
+    fn read_at(&self, dst: VirtAddr, n: usize, off: u64) -> Result<usize> {
+        self.vn.read(dst, off, n)
     }
 
-    fn stat(&self, addr: VirtAddr) -> Result<()> {
-        dfs::stat(self.handle, addr)
+    fn seek(&self, off: isize, whence: usize) -> Result<usize> {
+        let mut pos = self.off.lock();
+        let mut st = Stat::default();
+        self.vn.stat(&mut st)?;
+        let new = fcntl::seek_to(*pos as usize, Some(st.size), off, whence)?;
+        *pos = new as u64;
+        Ok(new)
     }
 
-    fn sync(&self) -> Result<()> {
-        dfs::fsync(self.handle)
+    fn getdents(&self, dst: VirtAddr, n: usize) -> Result<usize> {
+        let mut off = self.off.lock();
+        self.vn.readdir(&mut off, dst, n)
     }
-}
 
-#[cfg(all(target_os = "none", feature = "kernel"))]
-impl Drop for RemoteFile {
-    fn drop(&mut self) {
-        let _ = dfs::close(self.handle);
+    fn write(&self, src: VirtAddr, n: usize, append: bool) -> Result<usize> {
+        let mut off = self.off.lock();
+        self.vn.write(src, &mut off, n, append)
+    }
+
+    fn write_at(&self, src: VirtAddr, n: usize, mut off: u64) -> Result<usize> {
+        self.vn.write(src, &mut off, n, false)
     }
 }
 
@@ -257,11 +220,10 @@ impl VFile {
     fn read(&self, dst: VirtAddr, n: usize) -> Result<usize> {
         match self {
             VFile::Device(d) => d.read(dst, n),
-            VFile::Inode(f) => f.read(dst, n),
+            VFile::Vnode(f) => f.read(dst, n),
             VFile::Pipe(p) => p.read(dst, n),
             VFile::Socket(s) => s.read(dst, n, false),
             VFile::InetSocket(s) => s.read(dst, n, false),
-            VFile::Remote(r) => r.read(dst, n),
             _ => panic!("file read"),
         }
     }
@@ -269,11 +231,10 @@ impl VFile {
     fn write(&self, src: VirtAddr, n: usize, append: bool) -> Result<usize> {
         match self {
             VFile::Device(d) => d.write(src, n),
-            VFile::Inode(f) => f.write(src, n, append),
+            VFile::Vnode(f) => f.write(src, n, append),
             VFile::Pipe(p) => p.write(src, n),
             VFile::Socket(s) => s.write(src, n, false),
             VFile::InetSocket(s) => s.write(src, n, false),
-            VFile::Remote(r) => r.write(src, n),
             _ => panic!("file write"),
         }
     }
@@ -284,10 +245,8 @@ impl VFile {
         let mut stat: Stat = Default::default();
 
         match self {
-            VFile::Device(DNod { driver: _, ip, .. }) | VFile::Inode(FNod { off: _, ip }) => {
-                {
-                    ip.lock().stat(&mut stat);
-                }
+            VFile::Device(DNod { vn, .. }) | VFile::Vnode(FNod { vn, .. }) => {
+                vn.stat(&mut stat)?;
                 either_copyout(addr, &stat)
             }
             VFile::Socket(_) => {
@@ -298,7 +257,6 @@ impl VFile {
                 stat.ftype = FileType::Socket;
                 either_copyout(addr, &stat)
             }
-            VFile::Remote(r) => r.stat(addr),
             _ => Err(BadFileDescriptor),
         }
     }
@@ -313,11 +271,11 @@ impl File {
         }
         match self.f.as_ref().unwrap().as_ref() {
             VFile::Pipe(p) if self.nonblock => p.read_nonblock(dst, n),
-            VFile::Device(d) if d.major() == Major::Console => {
+            VFile::Device(d) if d.is_console() => {
                 let p = Cpus::myproc().unwrap();
                 let pgid = p.inner.lock().pgid;
                 if !console::is_foreground(pgid) {
-                    let _ = kill_pgrp(pgid, crate::signal::SIGTTIN);
+                    let _ = kill_pgrp(pgid, crate::signal::SIGTTIN, 0);
                     return Err(Interrupted);
                 }
                 if self.nonblock && !console::readable() {
@@ -327,36 +285,97 @@ impl File {
             }
             VFile::Socket(s) => s.read(dst, n, self.nonblock),
             VFile::InetSocket(s) => s.read(dst, n, self.nonblock),
-            VFile::Remote(r) => r.read(dst, n),
             _ => self.f.as_ref().unwrap().read(dst, n),
         }
     }
 
+    // Read directory entries as DirRecords.
+    pub fn getdents(&mut self, dst: VirtAddr, n: usize) -> Result<usize> {
+        if !self.readable {
+            return Err(InvalidArgument);
+        }
+        match self.f.as_ref().unwrap().as_ref() {
+            VFile::Vnode(f) => f.getdents(dst, n),
+            _ => Err(NotADirectory),
+        }
+    }
+
+    // Move the offset shared by reads and writes; returns the new one.
+    pub fn lseek(&mut self, off: isize, whence: usize) -> Result<usize> {
+        match self.f.as_ref().unwrap().as_ref() {
+            VFile::Vnode(f) => f.seek(off, whence),
+            VFile::Device(d) => d.seek(off, whence),
+            VFile::None => Err(BadFileDescriptor),
+            _ => Err(IllegalSeek),
+        }
+    }
+
+    // Read at byte off without moving the shared offset.
+    pub fn pread(&mut self, dst: VirtAddr, n: usize, off: usize) -> Result<usize> {
+        if !self.readable {
+            return Err(InvalidArgument);
+        }
+        match self.f.as_ref().unwrap().as_ref() {
+            VFile::Vnode(f) => f.read_at(dst, n, off as u64),
+            VFile::Device(d) => d.read_at(dst, n, off),
+            VFile::None => Err(BadFileDescriptor),
+            _ => Err(IllegalSeek),
+        }
+    }
+
+    // Write at byte off without moving the shared offset.
+    pub fn pwrite(&mut self, src: VirtAddr, n: usize, off: usize) -> Result<usize> {
+        if !self.writable {
+            return Err(InvalidArgument);
+        }
+        match self.f.as_ref().unwrap().as_ref() {
+            VFile::Vnode(f) => f.write_at(src, n, off as u64),
+            VFile::Device(d) => d.write_at(src, n, off),
+            VFile::None => Err(BadFileDescriptor),
+            _ => Err(IllegalSeek),
+        }
+    }
+
+    // Set the length of the regular file behind self to len.
+    pub fn truncate(&mut self, len: usize) -> Result<()> {
+        if !self.writable {
+            return Err(InvalidArgument);
+        }
+        match self.f.as_ref().unwrap().as_ref() {
+            VFile::Vnode(f) => f.vn.truncate(len),
+            VFile::None => Err(BadFileDescriptor),
+            _ => Err(InvalidArgument),
+        }
+    }
+
     // Write to file.
     pub fn write(&mut self, src: VirtAddr, n: usize) -> Result<usize> {
         if !self.writable {
             return Err(InvalidArgument);
         }
-        match self.f.as_ref().unwrap().as_ref() {
+        let res = match self.f.as_ref().unwrap().as_ref() {
             VFile::Pipe(p) if self.nonblock => p.write_nonblock(src, n),
-            VFile::Device(d) if d.major() == Major::Console => d.write(src, n),
+            VFile::Device(d) if d.is_console() => d.write(src, n),
             VFile::Socket(s) => s.write(src, n, self.nonblock),
             VFile::InetSocket(s) => s.write(src, n, self.nonblock),
-            VFile::Remote(r) => r.write(src, n),
             _ => self.f.as_ref().unwrap().write(src, n, self.append),
+        };
+        if let Err(BrokenPipe) = res {
+            proc::raise(crate::signal::SIGPIPE);
         }
+        res
     }
 
     pub fn sync(&self) -> Result<()> {
         match self.f.as_ref().unwrap().as_ref() {
-            VFile::Inode(_) | VFile::Device(_) => {
-                LOG.sync();
-                Ok(())
+            VFile::Vnode(FNod { vn, .. }) => vn.fsync(),
+            VFile::Device(d) => {
+                d.sync()?;
+                d.vn.fsync()
             }
             VFile::Pipe(_) => Err(InvalidArgument),
             VFile::Socket(_) => Err(InvalidArgument),
             VFile::InetSocket(_) => Err(InvalidArgument),
-            VFile::Remote(r) => r.sync(),
             VFile::None => Err(BadFileDescriptor),
         }
     }
@@ -379,28 +398,15 @@ impl File {
                 }
             }
             VFile::Device(d) => {
-                if self.readable && events & poll::IN != 0 {
-                    let ready = match d.major() {
-                        Major::Console => console::readable(),
-                        _ => true,
-                    };
-                    if ready {
-                        revents |= poll::IN;
-                    }
-                }
-                if self.writable && events & poll::OUT != 0 {
-                    revents |= poll::OUT;
-                }
-            }
-            VFile::Inode(_) => {
-                if self.readable && events & poll::IN != 0 {
+                let ready = !d.is_console() || console::readable();
+                if self.readable && events & poll::IN != 0 && ready {
                     revents |= poll::IN;
                 }
                 if self.writable && events & poll::OUT != 0 {
                     revents |= poll::OUT;
                 }
             }
-            VFile::Remote(_) => {
+            VFile::Vnode(_) => {
                 if self.readable && events & poll::IN != 0 {
                     revents |= poll::IN;
                 }
@@ -458,12 +464,7 @@ impl File {
     }
 
     pub fn lock_key(&self) -> Option<(u32, u32)> {
-        match self.f.as_ref()?.as_ref() {
-            VFile::Inode(FNod { off: _, ip }) | VFile::Device(DNod { driver: _, ip, .. }) => {
-                Some((ip.dev(), ip.inum()))
-            }
-            _ => None,
-        }
+        self.vnode().map(|vn| (vn.dev(), vn.ino()))
     }
 
     pub fn is_nonblock(&self) -> bool {
@@ -581,18 +582,20 @@ impl File {
     pub fn is_console(&self) -> bool {
         matches!(
             self.f.as_ref().map(|f| f.as_ref()),
-            Some(VFile::Device(d)) if d.major() == Major::Console
+            Some(VFile::Device(d)) if d.is_console()
         )
     }
 
-    pub fn inode(&self) -> Option<Inode> {
+    pub fn vnode(&self) -> Option<Vnode> {
         match self.f.as_ref()?.as_ref() {
-            VFile::Inode(FNod { off: _, ip }) | VFile::Device(DNod { driver: _, ip, .. }) => {
-                Some(ip.clone())
-            }
+            VFile::Vnode(FNod { vn, .. }) | VFile::Device(DNod { vn, .. }) => Some(Arc::clone(vn)),
             _ => None,
         }
     }
+
+    pub fn inode(&self) -> Option<Inode> {
+        self.vnode()?.inode().cloned()
+    }
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -625,14 +628,9 @@ impl Drop for File {
             }
         }
 
-        // if ref count == 1
-        if let Ok(VFile::Inode(FNod { off: _, ip }) | VFile::Device(DNod { driver: _, ip, .. })) =
-            Arc::try_unwrap(f)
-        {
-            LOG.begin_op();
-            drop(ip);
-            LOG.end_op();
-        }
+        // f goes last, outside the table lock: dropping a vnode may
+        // sleep.
+        drop(f);
     }
 }
 
@@ -643,45 +641,46 @@ pub enum FType<'a> {
     Pipe(Pipe),
     Socket(Arc<UnixSocket>),
     InetSocket(Arc<InetSocket>),
-    Remote(RemoteFile),
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
 impl FTable {
     // Allocate a file structure
-    // Must be called inside transaction if FType == FType::Node.
+    // Must not be called inside a transaction.
     pub fn alloc(&self, opts: OMode, ftype: FType<'_>) -> Result<File> {
         let inner: Arc<VFile> = Arc::new(match ftype {
             FType::Node(path) => {
-                let ip: Inode;
-                let mut ip_guard: SleepLockGuard<'_, IData>;
-
-                if opts.is_create() {
-                    ip = create(path, FileType::File, 0, 0)?;
-                    ip_guard = ip.lock();
+                let vn = if opts.is_create() {
+                    vfs::create(path, FileType::File, 0, 0, mode::FILE)?
                 } else {
-                    (_, ip) = path.namei()?;
-                    ip_guard = ip.lock();
-                    if ip_guard.itype() == FileType::Dir && !opts.is_rdonly() {
+                    let (_, vn) = path.namei()?;
+                    if vn.file_type() == FileType::Dir && !opts.is_rdonly() {
                         return Err(IsADirectory);
                     }
+                    vn
+                };
+                let mut want = 0;
+                if opts.is_read() {
+                    want |= mode::R_OK;
                 }
-                // ?
-                match ip_guard.itype() {
-                    FileType::Device if ip_guard.major() != Major::Invalid => {
-                        let driver = DEVSW.get(ip_guard.major()).unwrap();
-                        SleepLock::unlock(ip_guard);
-                        VFile::Device(DNod::new(driver, ip))
+                if opts.is_write() || opts.is_trunc() {
+                    want |= mode::W_OK;
+                }
+                vn.access(want)?;
+                match vn.file_type() {
+                    FileType::Device => {
+                        let driver = DEVSW.get(vn.major()).ok_or(NoSuchNode)?;
+                        VFile::Device(DNod::new(driver, vn))
                     }
-                    FileType::Dir | FileType::File => {
+                    ftype @ (FileType::Dir | FileType::File) => {
+                        let vn = vn.open(&opts)?;
                         let mut offset = 0;
-                        if opts.is_trunc() && ip_guard.itype() == FileType::File {
-                            ip_guard.trunc();
-                        } else if opts.is_append() && ip_guard.itype() == FileType::File {
-                            offset = ip_guard.size();
+                        if opts.is_append() && ftype == FileType::File {
+                            let mut st = Stat::default();
+                            vn.stat(&mut st)?;
+                            offset = st.size as u64;
                         }
-                        SleepLock::unlock(ip_guard);
-                        VFile::Inode(FNod::new(ip, offset))
+                        VFile::Vnode(FNod::new(vn, offset))
                     }
                     _ => return Err(NoSuchNode),
                 }
@@ -689,7 +688,6 @@ impl FTable {
             FType::Pipe(pi) => VFile::Pipe(pi),
             FType::Socket(sock) => VFile::Socket(sock),
             FType::InetSocket(sock) => VFile::InetSocket(sock),
-            FType::Remote(remote) => VFile::Remote(remote),
         });
 
         let mut guard = self.lock();
@@ -718,9 +716,22 @@ impl FTable {
     }
 }
 
+// Registered device: the name of its node under /dev and its default
+// permission bits.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+#[derive(Debug, Clone, Copy)]
+pub struct DevEntry {
+    pub name: &'static str,
+    pub perm: u16,
+    pub driver: &'static dyn Device,
+}
+
+// Device switch. Drivers register at init and get the next free major
+// number; devfs lists every entry as a node in /dev.
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub struct DevSW {
-    table: [OnceLock<&'static dyn Device>; NDEV],
+    table: [OnceLock<DevEntry>; NDEV],
+    next: AtomicUsize,
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -731,8 +742,8 @@ impl core::fmt::Debug for DevSW {
             if count != 0 {
                 write!(f, ", ")?;
             }
-            if let Some(&v) = v.get() {
-                write!(f, "{:?}", v)?;
+            if let Some(v) = v.get() {
+                write!(f, "{}: {:?}", v.name, v.driver)?;
             } else {
                 write!(f, "None")?;
             }
@@ -753,43 +764,72 @@ impl DevSW {
     pub const fn new() -> Self {
         Self {
             table: array![OnceLock::new(); NDEV],
+            next: AtomicUsize::new(0),
         }
     }
 
-    pub fn set(
+    // Register driver as /dev/name and return its major number.
+    // The same driver may be registered under several names.
+    pub fn register(
         &self,
-        devnum: Major,
-        dev: &'static dyn Device,
-    ) -> core::result::Result<(), &'static (dyn Device + 'static)> {
-        self.table[devnum as usize].set(dev)
+        name: &'static str,
+        perm: u16,
+        driver: &'static dyn Device,
+    ) -> Result<Major> {
+        if self.lookup(name).is_some() {
+            return Err(AlreadyExists);
+        }
+        let devnum = self.next.fetch_add(1, Ordering::Relaxed);
+        if devnum >= NDEV {
+            return Err(OutOfMemory);
+        }
+        let entry = DevEntry { name, perm, driver };
+        self.table[devnum].set(entry).map_err(|_| AlreadyExists)?;
+        Ok(Major(devnum as u16))
     }
 
     pub fn get(&self, devnum: Major) -> Option<&'static dyn Device> {
-        match self.table[devnum as usize].get() {
-            Some(&dev) => Some(dev),
-            None => None,
-        }
+        Some(self.entry(devnum)?.driver)
+    }
+
+    pub fn entry(&self, devnum: Major) -> Option<&DevEntry> {
+        self.table.get(devnum.0 as usize)?.get()
     }
-}
 
-// Device Major Number
-#[repr(u16)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum Major {
-    Null = 0,
-    Console = 1,
-    Disk = 2,
-    #[default]
-    Invalid,
+    pub fn lookup(&self, name: &str) -> Option<Major> {
+        self.entries()
+            .find(|(_, e)| e.name == name)
+            .map(|(devnum, _)| devnum)
+    }
+
+    // Registered devices in major number order.
+    pub fn entries(&self) -> impl Iterator<Item = (Major, &DevEntry)> {
+        self.table
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| Some((Major(i as u16), e.get()?)))
+    }
 }
 
+// Device Major Number, handed out by DevSW::register.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Major(pub u16);
+
 impl Major {
+    pub const INVALID: Major = Major(u16::MAX);
+
     pub fn from_u16(bits: u16) -> Major {
-        match bits {
-            0 => Major::Null,
-            1 => Major::Console,
-            2 => Major::Disk,
-            _ => Major::Invalid,
+        if (bits as usize) < crate::param::NDEV {
+            Major(bits)
+        } else {
+            Major::INVALID
         }
     }
 }
+
+impl Default for Major {
+    fn default() -> Self {
+        Major::INVALID
+    }
+}
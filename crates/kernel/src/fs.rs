@@ -1,25 +1,32 @@
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use alloc::string::String;
-#[cfg(all(target_os = "none", feature = "kernel"))]
 use alloc::sync::Arc;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use alloc::vec::Vec;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use core::mem::size_of;
+use core::any::Any;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use core::mem::{ManuallyDrop, size_of};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use core::ops::Deref;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use core::sync::atomic::{AtomicBool, Ordering};
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::array;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::bio::BCACHE;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::defs::AsBytes;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::error::{Error::*, Result};
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::fcntl::OMode;
 use crate::file::Major;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::log::{LOG, take_recovered};
+use crate::param::{LOGSIZE, MAXOPBLOCKS};
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::param::{MAXPATH, NINODE, ROOTDEV};
+use crate::param::{MAXPATH, NDISK, NINODE, READAHEAD, ROOTDEV};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::proc::{Cpus, either_copyin, either_copyout};
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -28,14 +35,13 @@ use crate::sleeplock::{SleepLock, SleepLockGuard};
 use crate::spinlock::Mutex;
 use crate::stat::FileType;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::stat::Stat;
+use crate::stat::{DirRecord, Stat, mode};
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::time;
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::trap::TICKS;
+use crate::vfs::{FileSystem, Node, Vnode, check_access, cred};
 #[cfg(all(target_os = "none", feature = "kernel"))]
-use crate::{
-    sync::{LazyLock, OnceLock},
-    vm::VirtAddr,
-};
+use crate::{sync::LazyLock, vm::VirtAddr};
 
 // File system implementation. Five layers:
 //   - Blocks: allocator for raw disk blocks.
@@ -51,10 +57,26 @@ use crate::{
 pub const ROOTINO: u32 = 1; // root i-number
 pub const BSIZE: usize = 1024; // block size
 
-// there should be one superblock per disk device, but we run with
-// only one device
+// One superblock per disk, read when its file system is mounted.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+static SB: [Mutex<Option<SuperBlock>>; NDISK] = [const { Mutex::new(None, "sb") }; NDISK];
+
+// Disks whose file system is mounted, or being formatted.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+static BUSY: [AtomicBool; NDISK] = [const { AtomicBool::new(false) }; NDISK];
+
+// Superblock of the file system on disk dev.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-pub static SB: OnceLock<SuperBlock> = OnceLock::new();
+pub fn sb(dev: u32) -> SuperBlock {
+    SB[disk_index(dev)]
+        .lock()
+        .expect("fs: no file system on disk")
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn disk_index(dev: u32) -> usize {
+    (dev - ROOTDEV) as usize
+}
 
 // Disk layout:
 // [ root block | super block | log | inode blocks |
@@ -73,29 +95,71 @@ pub struct SuperBlock {
     pub logstart: u32,   // Block number of first log block
     pub inodestart: u32, // Block number of first inode block
     pub bmapstart: u32,  // Block number of first free map block
+    pub features: u32,   // FEATURE_* flags the image was built with
 }
 
 pub const FSMAGIC: u32 = 0x10203040;
 
+// Superblock feature flags. Images without FEATURE_DIRENT use the old
+// fixed 16-byte directory entries, images without FEATURE_LARGEFILE the
+// old 32-bit inode sizes, images without FEATURE_LOGCRC the old log
+// header without checksums; all are refused at mount.
+pub const FEATURE_DIRENT: u32 = 0x1; // variable-length directory entries
+pub const FEATURE_LARGEFILE: u32 = 0x2; // 64-bit sizes, triple-indirect block
+pub const FEATURE_LOGCRC: u32 = 0x4; // checksummed, sequenced log header
+pub const FEATURES: u32 = FEATURE_DIRENT | FEATURE_LARGEFILE | FEATURE_LOGCRC; // features this kernel supports
+
 pub const NDIRECT: usize = 11;
 pub const NINDIRECT: usize = BSIZE / core::mem::size_of::<u32>();
 pub const NDINDIRECT: usize = NINDIRECT * NINDIRECT;
-pub const MAXFILE: usize = NDIRECT + NINDIRECT + NDINDIRECT;
+pub const NTINDIRECT: usize = NDINDIRECT * NINDIRECT;
+pub const MAXFILE: usize = NDIRECT + NINDIRECT + NDINDIRECT + NTINDIRECT;
 
 // On-disk inode structure
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
-struct DInode {
-    itype: FileType,           // File type
-    major: Major,              // Major Device Number (T_DEVICE only)
-    minor: u16,                // Minor Device Number (T_DEVICE only)
-    nlink: u16,                // Number of links to inode in file system
-    size: u32,                 // Size of data (bytes)
-    addrs: [u32; NDIRECT + 2], // Data block address
-    atime: u64,                // Ticks since boot
-    mtime: u64,                // Ticks since boot
-    ctime: u64,                // Ticks since boot
-    _padding: [u32; 10],       // Pad to 128B
+pub struct DInode {
+    pub itype: FileType,           // File type
+    pub major: Major,              // Major Device Number (T_DEVICE only)
+    pub minor: u16,                // Minor Device Number (T_DEVICE only)
+    pub nlink: u16,                // Number of links to inode in file system
+    pub size: u64,                 // Size of data (bytes)
+    pub addrs: [u32; NDIRECT + 3], // Data block address
+    pub atime: u64,                // Nanoseconds since the epoch
+    pub mtime: u64,                // Nanoseconds since the epoch
+    pub ctime: u64,                // Nanoseconds since the epoch
+    pub mode: u32,                 // Permission bits
+    pub uid: u32,                  // Owner user id
+    pub gid: u32,                  // Owner group id
+    _padding: [u32; 5],            // Pad to 128B
+}
+
+// For the tools that read images: fsck, and mkfs on the host.
+impl DInode {
+    // A fresh inode with one link, owned by root and stamped now.
+    pub fn new(itype: FileType, mode: u32, now: u64) -> Self {
+        Self {
+            itype,
+            nlink: 1,
+            mode,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            ..Default::default()
+        }
+    }
+
+    // Inode idx of an inode block, or None if its type is garbage.
+    pub fn from_block(buf: &[u8; BSIZE], idx: usize) -> Option<Self> {
+        let off = idx * core::mem::size_of::<DInode>();
+        FileType::from_u16(u16::from_le_bytes([buf[off], buf[off + 1]]))?;
+        Some(unsafe { (buf.as_ptr().add(off) as *const DInode).read_unaligned() })
+    }
+
+    pub fn to_block(&self, buf: &mut [u8; BSIZE], idx: usize) {
+        let off = idx * core::mem::size_of::<DInode>();
+        unsafe { (buf.as_mut_ptr().add(off) as *mut DInode).write_unaligned(*self) }
+    }
 }
 
 // Inodes per block
@@ -104,25 +168,138 @@ pub const IPB: usize = BSIZE / core::mem::size_of::<DInode>();
 // Bitmap bits per block
 pub const BPB: u32 = (BSIZE * 8) as u32;
 
-// Directory is a file containing a sequence of dirent structures.
-pub const DIRSIZ: usize = 14;
-#[cfg(all(target_os = "none", feature = "kernel"))]
-const SYMLINK_MAX_DEPTH: usize = 10;
+// Directory is a file of whole blocks, each holding a sequence of
+// variable-length records: a DirEnt header followed by the name,
+// padded to 4 bytes. Records never cross a block and the last one in
+// a block runs to its end, so a free record (inum 0) or the slack
+// after a live record's name is where new entries go.
+pub const DIRSIZ: usize = 255; // longest file name
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct DirEnt {
-    pub inum: u16,
-    pub name: [u8; DIRSIZ],
+    pub inum: u32,    // 0 if the record is free
+    pub reclen: u16,  // Bytes from this record to the next
+    pub namelen: u16, // Bytes of name following the header
+}
+
+impl DirEnt {
+    // Bytes needed by a record holding a name of namelen bytes.
+    pub const fn rec_len(namelen: usize) -> usize {
+        (core::mem::size_of::<DirEnt>() + namelen + 3) & !3
+    }
+
+    // Is this a sane record to find at byte off of a directory?
+    pub fn is_valid(&self, off: usize) -> bool {
+        let reclen = self.reclen as usize;
+        self.namelen as usize <= DIRSIZ
+            && reclen % 4 == 0
+            && reclen >= Self::rec_len(self.namelen as usize)
+            && off % BSIZE + reclen <= BSIZE
+    }
+
+    // The record at byte off of a directory block and its name, or
+    // None if it is not a sane one.
+    pub fn at(buf: &[u8; BSIZE], off: usize) -> Option<(Self, &[u8])> {
+        let start = off + core::mem::size_of::<DirEnt>();
+        if start > BSIZE {
+            return None;
+        }
+        let de = unsafe { (buf.as_ptr().add(off) as *const DirEnt).read_unaligned() };
+        de.is_valid(off)
+            .then(|| (de, &buf[start..start + de.namelen as usize]))
+    }
+
+    // Write this header at byte off of a directory block.
+    pub fn write(&self, buf: &mut [u8; BSIZE], off: usize) {
+        unsafe { (buf.as_mut_ptr().add(off) as *mut DirEnt).write_unaligned(*self) }
+    }
+
+    // Write a record of reclen bytes at off naming inum.
+    pub fn put(buf: &mut [u8; BSIZE], off: usize, reclen: usize, inum: u32, name: &[u8]) {
+        let de = DirEnt {
+            inum,
+            reclen: reclen as u16,
+            namelen: name.len() as u16,
+        };
+        de.write(buf, off);
+        let start = off + core::mem::size_of::<DirEnt>();
+        buf[start..start + name.len()].copy_from_slice(name);
+    }
+
+    // Add a record to a directory block in a free record or the slack
+    // after a live one. Returns its offset, or None if there is no room.
+    pub fn insert(buf: &mut [u8; BSIZE], inum: u32, name: &[u8]) -> Option<usize> {
+        let need = Self::rec_len(name.len());
+        let mut off = 0;
+        while let Some((de, _)) = Self::at(buf, off) {
+            let used = match de.inum {
+                0 => 0,
+                _ => Self::rec_len(de.namelen as usize),
+            };
+            let reclen = de.reclen as usize;
+            if reclen - used >= need {
+                if used > 0 {
+                    let reclen = used as u16;
+                    DirEnt { reclen, ..de }.write(buf, off);
+                }
+                Self::put(buf, off + used, reclen - used, inum, name);
+                return Some(off + used);
+            }
+            off += reclen;
+        }
+        None
+    }
 }
 
 impl SuperBlock {
+    // The layout of a file system of size blocks with ninodes inodes,
+    // or None if they leave no room for data.
+    pub fn new(size: u32, ninodes: u32) -> Option<Self> {
+        let nlog = LOGSIZE as u32 + 1; // header block, then the data blocks
+        let ninodeblocks = ninodes / IPB as u32 + 1;
+        let nbitmap = size / BPB + 1;
+        let nmeta = 2 + nlog + ninodeblocks + nbitmap;
+        if ninodes <= ROOTINO || size <= nmeta + MAXOPBLOCKS as u32 {
+            return None;
+        }
+        Some(SuperBlock {
+            magic: FSMAGIC,
+            size,
+            nblocks: size - nmeta,
+            ninodes,
+            nlog,
+            logstart: 2,
+            inodestart: 2 + nlog,
+            bmapstart: 2 + nlog + ninodeblocks,
+            features: FEATURES,
+        })
+    }
+
     #[cfg(all(target_os = "none", feature = "kernel"))]
     fn read(dev: u32) -> Self {
         let bp = BCACHE.read(dev, 1);
         *bp.align_to::<SuperBlock>().first().unwrap()
     }
 
+    // Why this kernel cannot use the file system, if it cannot.
+    #[cfg(all(target_os = "none", feature = "kernel"))]
+    fn check(&self) -> core::result::Result<(), &'static str> {
+        if self.magic != FSMAGIC {
+            Err("invalid file system")
+        } else if self.features & FEATURE_DIRENT == 0 {
+            Err("old directory format, rebuild fs.img with mkfs")
+        } else if self.features & FEATURE_LARGEFILE == 0 {
+            Err("old inode format, rebuild fs.img with mkfs")
+        } else if self.features & FEATURE_LOGCRC == 0 {
+            Err("old log format, rebuild fs.img with mkfs")
+        } else if self.features & !FEATURES != 0 {
+            Err("unsupported file system features")
+        } else {
+            Ok(())
+        }
+    }
+
     // Block containing inode i
     pub fn iblock(&self, i: u32) -> u32 {
         i / IPB as u32 + self.inodestart
@@ -134,16 +311,20 @@ impl SuperBlock {
     }
 }
 
-// Init fs
+// Init fs: read the superblock of disk dev and recover its log.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-pub fn init(dev: u32) {
-    SB.set(SuperBlock::read(dev)).unwrap();
-    let sb = SB.get().unwrap();
-    assert!(sb.magic == FSMAGIC, "invalid file system");
-    LOG.init();
+fn init(dev: u32) -> Result<()> {
+    let sb = SuperBlock::read(dev);
+    if let Err(why) = sb.check() {
+        println!("fs: dev {}: {}", dev, why);
+        return Err(InvalidArgument);
+    }
+    *SB[disk_index(dev)].lock() = Some(sb);
+    LOG.init(dev);
     if take_recovered() {
         println!("fs: journal recovered");
     }
+    Ok(())
 }
 
 // Zero a block
@@ -159,7 +340,7 @@ fn bzero(dev: u32, bno: u32) {
 // Allocate a zeroed disk block.
 #[cfg(all(target_os = "none", feature = "kernel"))]
 fn balloc(dev: u32) -> u32 {
-    let sb = SB.get().unwrap();
+    let sb = sb(dev);
 
     for b in (0..sb.size).step_by(BPB as usize) {
         let mut bp = BCACHE.read(dev, sb.bblock(b));
@@ -183,7 +364,7 @@ fn balloc(dev: u32) -> u32 {
 // Free a disk block
 #[cfg(all(target_os = "none", feature = "kernel"))]
 fn bfree(dev: u32, b: u32) {
-    let sb = SB.get().unwrap();
+    let sb = sb(dev);
     let mut bp = BCACHE.read(dev, sb.bblock(b));
     let bi = b % BPB;
     let m = 1 << (bi % 8);
@@ -194,6 +375,42 @@ fn bfree(dev: u32, b: u32) {
     LOG.write(bp);
 }
 
+// Free everything below the indirect block addr past its first keep
+// data blocks; depth is 1 for an indirect block, 2 for a double one
+// and 3 for a triple one.
+// Returns what the parent should now hold for addr: 0 if it was freed.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn trunc_indirect(dev: u32, addr: u32, keep: usize, depth: u32) -> u32 {
+    if addr == 0 {
+        return 0;
+    }
+    let span = NINDIRECT.pow(depth - 1);
+    let mut bp = BCACHE.read(dev, addr);
+    let mut dirty = false;
+    for (i, entry) in bp.align_to_mut::<u32>().iter_mut().enumerate() {
+        if *entry == 0 || (i + 1) * span <= keep {
+            continue;
+        }
+        *entry = match depth {
+            1 => {
+                bfree(dev, *entry);
+                0
+            }
+            _ => trunc_indirect(dev, *entry, keep.saturating_sub(i * span), depth - 1),
+        };
+        dirty = true;
+    }
+    if keep == 0 {
+        drop(bp);
+        bfree(dev, addr);
+        return 0;
+    }
+    if dirty {
+        LOG.write(bp);
+    }
+    addr
+}
+
 // Inodes.
 //
 // An inode describes a single unnamed file.
@@ -284,11 +501,15 @@ pub struct IData {
     major: Major,
     minor: u16,
     nlink: u16,
-    size: u32,
-    addrs: [u32; NDIRECT + 2],
+    size: u64,
+    addrs: [u32; NDIRECT + 3],
     atime: u64,
     mtime: u64,
     ctime: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    next_read: u32, // block after the last one read, to spot sequential reads
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -308,12 +529,12 @@ impl IData {
         }
     }
 
-    fn now_ticks() -> u64 {
-        *TICKS.lock() as u64
+    fn now() -> u64 {
+        time::realtime()
     }
 
     pub fn init_times(&mut self) {
-        let now = Self::now_ticks();
+        let now = Self::now();
         self.atime = now;
         self.mtime = now;
         self.ctime = now;
@@ -321,23 +542,23 @@ impl IData {
     }
 
     pub fn touch_atime(&mut self) {
-        self.atime = Self::now_ticks();
+        self.atime = Self::now();
         self.update();
     }
 
     pub fn touch_mtime_ctime(&mut self) {
-        let now = Self::now_ticks();
+        let now = Self::now();
         self.mtime = now;
         self.ctime = now;
         self.update();
     }
 
     pub fn touch_ctime(&mut self) {
-        self.ctime = Self::now_ticks();
+        self.ctime = Self::now();
         self.update();
     }
 
-    pub fn size(&self) -> u32 {
+    pub fn size(&self) -> u64 {
         self.size
     }
 
@@ -349,6 +570,52 @@ impl IData {
         self.major
     }
 
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    // inode is write through, so change about MInode is also must be written into
+    // disk
+    pub fn set_mode(&mut self, mode: u32) {
+        self.mode = mode & mode::ALL;
+        self.touch_ctime();
+    }
+
+    // inode is write through, so change about MInode is also must be written into
+    // disk
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.uid = uid;
+        self.gid = gid;
+        self.touch_ctime();
+    }
+
+    // Set the access and modification times, in nanoseconds since the
+    // epoch; None leaves either unchanged.
+    pub fn set_times(&mut self, atime: Option<u64>, mtime: Option<u64>) {
+        if let Some(atime) = atime {
+            self.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            self.mtime = mtime;
+        }
+        self.touch_ctime();
+    }
+
+    // Check that the current process may access the inode for want,
+    // a mask of mode::R_OK, W_OK and X_OK.
+    // Caller must hold sleeplock.
+    pub fn access(&self, want: u32) -> Result<()> {
+        check_access(self.itype, self.mode, self.uid, self.gid, want)
+    }
+
     // inode is write through, so change about MInode is also must be written into
     // disk
     fn set_type(&mut self, itype: FileType) {
@@ -366,7 +633,7 @@ impl IData {
 
     // inode is write through, so change about MInode is also must be written into
     // disk
-    fn set_size(&mut self, size: u32) {
+    fn set_size(&mut self, size: u64) {
         self.size = size;
         self.update();
     }
@@ -386,7 +653,7 @@ impl IData {
             LinkOp::Minus => self.nlink -= 1,
             LinkOp::Init(num) => self.nlink = num,
         }
-        self.ctime = Self::now_ticks();
+        self.ctime = Self::now();
         self.update();
     }
 
@@ -395,7 +662,7 @@ impl IData {
     // that lives on disk.
     // Caller must hold inode sleeplock.
     fn update(&self) {
-        let sb = SB.get().unwrap();
+        let sb = sb(self.dev);
         let mut bp = BCACHE.read(self.dev, sb.iblock(self.inum));
         let dip = bp
             .align_to_mut::<DInode>()
@@ -410,57 +677,62 @@ impl IData {
         dip.atime = self.atime;
         dip.mtime = self.mtime;
         dip.ctime = self.ctime;
+        dip.mode = self.mode;
+        dip.uid = self.uid;
+        dip.gid = self.gid;
         LOG.write(bp);
     }
 
     // Truncate inode (discard contents).
     // Caller must hold inode sleeplock.
     pub fn trunc(&mut self) {
-        for addr in self.addrs.iter_mut().take(NDIRECT) {
+        self.trunc_to(0);
+    }
+
+    // Shrink inode to len bytes: free the blocks past len, including
+    // partial indirect blocks, and zero the rest of the last block so
+    // that a later extension reads zeros.
+    // Caller must hold inode sleeplock and be inside a transaction.
+    pub fn trunc_to(&mut self, len: u64) {
+        assert!(len <= self.size, "trunc_to: growing");
+        let keep = (len as usize).div_ceil(BSIZE);
+
+        for addr in self.addrs.iter_mut().take(NDIRECT).skip(keep) {
             if *addr > 0 {
                 bfree(self.dev, *addr);
                 *addr = 0;
             }
         }
-
-        let naddr = self.addrs.get_mut(NDIRECT).unwrap();
-        if *naddr > 0 {
-            let bp = BCACHE.read(self.dev, *naddr);
-            let a = bp.align_to::<u32>();
-            for &addr in a.iter() {
-                // 0 .. NINDIRECT = BISIZE / u32
-                if addr > 0 {
-                    bfree(self.dev, addr);
-                }
-            }
-            drop(bp);
-            bfree(self.dev, *naddr);
-            *naddr = 0;
-        }
-
-        let ndaddr = self.addrs.get_mut(NDIRECT + 1).unwrap();
-        if *ndaddr > 0 {
-            let bp = BCACHE.read(self.dev, *ndaddr);
-            let a = bp.align_to::<u32>();
-            for &a_addr in a.iter() {
-                if a_addr > 0 {
-                    let cp = BCACHE.read(self.dev, a_addr);
-                    let b = cp.align_to::<u32>();
-                    for &b_addr in b.iter() {
-                        if b_addr > 0 {
-                            bfree(self.dev, b_addr);
-                        }
-                    }
-                    drop(cp);
-                    bfree(self.dev, a_addr);
-                }
-            }
-            drop(bp);
-            bfree(self.dev, *ndaddr);
-            *ndaddr = 0;
+        self.addrs[NDIRECT] = trunc_indirect(
+            self.dev,
+            self.addrs[NDIRECT],
+            keep.saturating_sub(NDIRECT),
+            1,
+        );
+        self.addrs[NDIRECT + 1] = trunc_indirect(
+            self.dev,
+            self.addrs[NDIRECT + 1],
+            keep.saturating_sub(NDIRECT + NINDIRECT),
+            2,
+        );
+        self.addrs[NDIRECT + 2] = trunc_indirect(
+            self.dev,
+            self.addrs[NDIRECT + 2],
+            keep.saturating_sub(NDIRECT + NINDIRECT + NDINDIRECT),
+            3,
+        );
+
+        let tail = len as usize % BSIZE;
+        if tail > 0
+            && let Ok(addr) = self.bmap((len / BSIZE as u64) as u32, false)
+        {
+            let mut bp = BCACHE.read(self.dev, addr);
+            bp[tail..].fill(0);
+            LOG.write(bp);
         }
-        self.size = 0;
-        let now = Self::now_ticks();
+
+        self.size = len;
+        let now = Self::now();
         self.mtime = now;
         self.ctime = now;
         // update is needed, because size and addrs are updated.
@@ -474,7 +746,9 @@ impl IData {
     // are listed in idata.addrs[]. The next NINDIRECT blocks
     // are listed in block idata.addrs[NDIRECT].
     // The next NDINDIRECT blocks are listed in block
-    // idata.addrs[NDIRECT + 1].
+    // idata.addrs[NDIRECT + 1], through a second layer of
+    // indirect blocks, and the last NTINDIRECT blocks in block
+    // idata.addrs[NDIRECT + 2], through two more layers.
     //
     // Return the disk block address of the nth block in inode ip.
     // If there is no such block, bmap allocates one.
@@ -492,58 +766,41 @@ impl IData {
         }
         bn -= NDIRECT;
 
-        if bn < NINDIRECT {
-            // Load indirect block, allocating if necessary.
-            addr = self.addrs[NDIRECT];
-            if addr == 0 && alloc {
-                addr = balloc(self.dev);
-                self.set_addrs(NDIRECT, addr);
-            }
-            let mut bp = BCACHE.read(self.dev, addr);
-            let a = bp.align_to_mut::<u32>();
-            addr = a[bn];
-            if addr == 0 && alloc {
-                addr = balloc(self.dev);
-                a[bn] = addr;
-                LOG.write(bp);
+        // Find the tree of indirect blocks holding bn: depth layers
+        // of them below idata.addrs[NDIRECT + depth - 1].
+        let mut depth = 1;
+        while bn >= NINDIRECT.pow(depth) {
+            bn -= NINDIRECT.pow(depth);
+            depth += 1;
+            if depth > 3 {
+                return Err(StorageFull);
             }
-            return Ok(addr);
         }
-        bn -= NINDIRECT;
 
-        if bn < NDINDIRECT {
-            // Load double indirect block, allocating if necessary.
-            addr = self.addrs[NDIRECT + 1];
-            if addr == 0 && alloc {
-                addr = balloc(self.dev);
-                self.set_addrs(NDIRECT + 1, addr);
-            }
-            // Load 2nd layer block.
-            {
-                let mut bp = BCACHE.read(self.dev, addr);
-                let a = bp.align_to_mut::<u32>();
-                let dbn = bn / NINDIRECT;
-                addr = a[dbn];
-                if addr == 0 && alloc {
-                    addr = balloc(self.dev);
-                    a[dbn] = addr;
-                    LOG.write(bp);
-                }
+        // Load top indirect block, allocating if necessary.
+        let top = NDIRECT + depth as usize - 1;
+        addr = self.addrs[top];
+        if addr == 0 && alloc {
+            addr = balloc(self.dev);
+            self.set_addrs(top, addr);
+        }
+
+        // Walk down a layer at a time to the disk block.
+        for level in (0..depth).rev() {
+            if addr == 0 {
+                break;
             }
-            // now find disk block
             let mut bp = BCACHE.read(self.dev, addr);
             let a = bp.align_to_mut::<u32>();
-            let bn = bn % NINDIRECT;
-            addr = a[bn];
+            let idx = bn / NINDIRECT.pow(level) % NINDIRECT;
+            addr = a[idx];
             if addr == 0 && alloc {
                 addr = balloc(self.dev);
-                a[bn] = addr;
+                a[idx] = addr;
                 LOG.write(bp);
             }
-            return Ok(addr);
         }
-
-        Err(StorageFull)
+        Ok(addr)
     }
 
     // Copy stat information from inode.
@@ -553,6 +810,9 @@ impl IData {
         st.ino = self.inum;
         st.ftype = self.itype;
         st.nlink = self.nlink;
+        st.mode = self.mode;
+        st.uid = self.uid;
+        st.gid = self.gid;
         st.size = self.size as usize;
         st.atime = self.atime;
         st.mtime = self.mtime;
@@ -561,7 +821,7 @@ impl IData {
 
     // Read data from inode.
     // dst is UVAddr or KVAddr
-    pub fn read(&mut self, mut dst: VirtAddr, off: u32, mut n: usize) -> Result<usize> {
+    pub fn read(&mut self, mut dst: VirtAddr, off: u64, mut n: usize) -> Result<usize> {
         let mut tot = 0;
         let mut off = off as usize;
 
@@ -573,7 +833,17 @@ impl IData {
         }
 
         while tot < n {
-            let bp = BCACHE.read(self.dev, self.bmap((off / BSIZE) as u32, false)?);
+            let bn = (off / BSIZE) as u32;
+            let addr = self.bmap(bn, false)?;
+            // Reading on from where the last read stopped: fetch the
+            // next blocks of the file along with this one.
+            let bp = if bn == self.next_read && addr != 0 {
+                let ahead = self.ahead(bn + 1, (self.size as usize).div_ceil(BSIZE))?;
+                BCACHE.read_ahead(self.dev, addr, &ahead)
+            } else {
+                BCACHE.read(self.dev, addr)
+            };
+            self.next_read = bn + 1;
             let m = core::cmp::min(n - tot, BSIZE - off % BSIZE);
             either_copyout(dst, &bp[(off % BSIZE)..(off % BSIZE + m)])?;
             tot += m;
@@ -583,13 +853,27 @@ impl IData {
         Ok(tot)
     }
 
+    // Disk addresses of up to READAHEAD allocated blocks from bn on,
+    // stopping at nblocks or at a hole, for read_ahead.
+    fn ahead(&mut self, bn: u32, nblocks: usize) -> Result<Vec<u32>> {
+        let mut addrs = Vec::new();
+        for b in bn..(nblocks as u32).min(bn + READAHEAD as u32) {
+            let addr = self.bmap(b, false)?;
+            if addr == 0 {
+                break;
+            }
+            addrs.push(addr);
+        }
+        Ok(addrs)
+    }
+
     // Write data to inode.
     // Caller must hold sleeplock.
     // dst is UVAddr or KVAddr
     // Returns the number of bytes successfully written.
     // If the return value is less then the requested n,
     // there was an error of some kind.
-    pub fn write(&mut self, mut src: VirtAddr, off: u32, n: usize) -> Result<usize> {
+    pub fn write(&mut self, mut src: VirtAddr, off: u64, n: usize) -> Result<usize> {
         let mut tot = 0;
         let mut off = off as usize;
 
@@ -608,7 +892,7 @@ impl IData {
         }
 
         if off > self.size as usize {
-            self.set_size(off as u32);
+            self.set_size(off as u64);
         }
 
         Ok(tot)
@@ -616,41 +900,73 @@ impl IData {
 
     // Directories
 
+    // Read the directory record at byte off, its name into name. A
+    // record that does not fit its block means a damaged directory.
+    fn dirent_at(&mut self, off: u64, name: &mut [u8; DIRSIZ]) -> Result<DirEnt> {
+        let mut de: DirEnt = Default::default();
+        self.read(
+            VirtAddr::Kernel(&mut de as *mut _ as usize),
+            off,
+            size_of::<DirEnt>(),
+        )?;
+        if !de.is_valid(off as usize) {
+            return Err(InvalidArgument);
+        }
+        self.read(
+            VirtAddr::Kernel(name.as_mut_ptr() as usize),
+            off + size_of::<DirEnt>() as u64,
+            de.namelen as usize,
+        )?;
+        Ok(de)
+    }
+
+    // Write the record header de at byte off, followed by name.
+    fn write_dirent(&mut self, off: u64, de: &DirEnt, name: &[u8]) -> Result<()> {
+        self.write(
+            VirtAddr::Kernel(de as *const _ as usize),
+            off,
+            size_of::<DirEnt>(),
+        )?;
+        self.write(
+            VirtAddr::Kernel(name.as_ptr() as usize),
+            off + size_of::<DirEnt>() as u64,
+            name.len(),
+        )?;
+        Ok(())
+    }
+
     // Look for a directory entry in a directory.
     // If found, set *poff to byte offset of entry.
-    pub fn dirlookup(&mut self, name: &str, poff: Option<&mut u32>) -> Result<Inode> {
-        let mut de: DirEnt = Default::default();
+    pub fn dirlookup(&mut self, name: &str, poff: Option<&mut u64>) -> Result<Inode> {
+        let mut buf = [0u8; DIRSIZ];
         if self.itype != FileType::Dir {
             return Err(NotADirectory);
         }
 
-        for off in (0..self.size).step_by(size_of::<DirEnt>()) {
-            self.read(
-                VirtAddr::Kernel(&mut de as *mut _ as usize),
-                off,
-                size_of::<DirEnt>(),
-            )?;
-            if de.inum == 0 {
-                continue;
-            }
-            if name
-                == core::str::from_utf8(&de.name)
-                    .unwrap()
-                    .trim_matches(char::from(0))
-            {
+        let mut off = 0;
+        while off < self.size {
+            let de = self.dirent_at(off, &mut buf)?;
+            if de.inum != 0 && &buf[..de.namelen as usize] == name.as_bytes() {
                 // entry matches path element
                 if let Some(poff) = poff {
                     *poff = off;
                 }
-                return ITABLE.get(self.dev, de.inum as u32);
+                return ITABLE.get(self.dev, de.inum);
             }
+            off += de.reclen as u64;
         }
         Err(NotFound)
     }
 
     // Write a new directory entry (name, inum) into the directory dp.
     pub fn dirlink(&mut self, name: &str, inum: u32) -> Result<()> {
-        let mut de: DirEnt = Default::default();
+        let mut buf = [0u8; DIRSIZ];
+        if name.is_empty() {
+            return Err(InvalidArgument);
+        }
+        if name.len() > DIRSIZ {
+            return Err(FileNameTooLong);
+        }
 
         // check that name is not present.
         self.dirlookup(name, None).map_or_else(
@@ -658,52 +974,139 @@ impl IData {
             |_| Err(AlreadyExists),
         )?;
 
-        // Look for an empty dirent
+        // Look for a free record or a live one with enough slack to split.
+        let need = DirEnt::rec_len(name.len()) as u16;
         let mut off = 0;
         while off < self.size {
-            self.read(
-                VirtAddr::Kernel(&mut de as *mut _ as usize),
-                off,
-                size_of::<DirEnt>(),
-            )?;
-            if de.inum == 0 {
-                break;
+            let mut de = self.dirent_at(off, &mut buf)?;
+            let used = match de.inum {
+                0 => 0,
+                _ => DirEnt::rec_len(de.namelen as usize) as u16,
+            };
+            if de.reclen - used >= need {
+                let new = DirEnt {
+                    inum,
+                    reclen: de.reclen - used,
+                    namelen: name.len() as u16,
+                };
+                if used > 0 {
+                    de.reclen = used;
+                    self.write_dirent(off, &de, &[])?;
+                }
+                self.write_dirent(off + used as u64, &new, name.as_bytes())?;
+                self.touch_mtime_ctime();
+                return Ok(());
             }
-            off += size_of::<DirEnt>() as u32;
+            off += de.reclen as u64;
         }
 
-        let len = core::cmp::min(name.len(), DIRSIZ);
-        de.name.copy_from_slice(&[0; DIRSIZ]);
-        de.name[0..len].copy_from_slice(&name.as_bytes()[0..len]);
-        de.inum = inum as u16;
-        self.write(
-            VirtAddr::Kernel(&mut de as *mut _ as usize),
-            off,
-            size_of::<DirEnt>(),
-        )?;
+        // Directory is full: add a block holding one record that spans it.
+        let de = DirEnt {
+            inum,
+            reclen: BSIZE as u16,
+            namelen: name.len() as u16,
+        };
+        self.write_dirent(off, &de, name.as_bytes())?;
+        self.set_size(off + BSIZE as u64);
         self.touch_mtime_ctime();
         Ok(())
     }
 
-    // Is the directory dp empty except for "." and ".." ?
-    pub fn is_dir_empty(&mut self) -> bool {
-        let mut de: DirEnt = Default::default();
-        for off in ((2 * size_of::<DirEnt>() as u32)..self.size).step_by(size_of::<DirEnt>()) {
-            if self
-                .read(
-                    VirtAddr::Kernel(&mut de as *mut _ as usize),
-                    off,
-                    size_of::<DirEnt>(),
-                )
-                .is_err()
-            {
-                panic!("isdirempty: inode read");
+    // Remove the directory entry at byte off, handing its space to the
+    // record before it in the same block, if any.
+    pub fn dirunlink(&mut self, off: u64) -> Result<()> {
+        let mut buf = [0u8; DIRSIZ];
+        let mut de = self.dirent_at(off, &mut buf)?;
+        let mut prev = None;
+        let mut cur = off - off % BSIZE as u64;
+        while cur < off {
+            prev = Some(cur);
+            cur += self.dirent_at(cur, &mut buf)?.reclen as u64;
+        }
+        match prev {
+            Some(poff) => {
+                let mut pde = self.dirent_at(poff, &mut buf)?;
+                pde.reclen += de.reclen;
+                self.write_dirent(poff, &pde, &[])
+            }
+            None => {
+                de.inum = 0;
+                self.write_dirent(off, &de, &[])
             }
+        }
+    }
+
+    // Point the directory entry at byte off to inum.
+    pub fn dirretarget(&mut self, off: u64, inum: u32) -> Result<()> {
+        let mut buf = [0u8; DIRSIZ];
+        let mut de = self.dirent_at(off, &mut buf)?;
+        de.inum = inum;
+        self.write_dirent(off, &de, &[])
+    }
+
+    // Copy the entries from byte *off on out to dst as DirRecords, as
+    // many as fit in n bytes, and advance *off past them. Returns the
+    // bytes copied, 0 at the end of the directory.
+    // Must be called inside a transaction since it gets and puts the
+    // inode of each entry to learn its type.
+    pub fn getdents(&mut self, off: &mut u64, mut dst: VirtAddr, n: usize) -> Result<usize> {
+        let mut name = [0u8; DIRSIZ];
+        let mut rec = [0u8; DirRecord::rec_len(DIRSIZ)];
+        let hdr = size_of::<DirRecord>();
+        if self.itype != FileType::Dir {
+            return Err(NotADirectory);
+        }
+
+        let mut tot = 0;
+        while *off < self.size {
+            let de = self.dirent_at(*off, &mut name)?;
             if de.inum != 0 {
-                return false;
+                let name = &name[..de.namelen as usize];
+                let reclen = DirRecord::rec_len(name.len());
+                if tot + reclen > n {
+                    if tot == 0 {
+                        return Err(InvalidArgument);
+                    }
+                    break;
+                }
+                // "." and ".." are already locked or above us.
+                let ftype = if de.inum == self.inum || name == b".." {
+                    FileType::Dir
+                } else {
+                    ITABLE.get(self.dev, de.inum)?.lock().itype
+                };
+                let dr = DirRecord {
+                    ino: de.inum,
+                    ftype,
+                    namelen: de.namelen,
+                    reclen: reclen as u16,
+                    _pad: 0,
+                };
+                rec[..hdr].copy_from_slice(dr.as_bytes());
+                rec[hdr..hdr + name.len()].copy_from_slice(name);
+                rec[hdr + name.len()..reclen].fill(0);
+                either_copyout(dst, &rec[..reclen])?;
+                dst += reclen;
+                tot += reclen;
             }
+            *off += de.reclen as u64;
         }
-        true
+        Ok(tot)
+    }
+
+    // Is the directory dp empty except for "." and ".." ?
+    pub fn is_dir_empty(&mut self) -> Result<bool> {
+        let mut buf = [0u8; DIRSIZ];
+        let mut off = 0;
+        while off < self.size {
+            let de = self.dirent_at(off, &mut buf)?;
+            let name = &buf[..de.namelen as usize];
+            if de.inum != 0 && name != b"." && name != b".." {
+                return Ok(false);
+            }
+            off += de.reclen as u64;
+        }
+        Ok(true)
     }
 }
 
@@ -723,7 +1126,7 @@ impl MInode {
     // Lock the inode
     // Reads the inode from disk if necessary.
     pub fn lock(&self) -> SleepLockGuard<'_, IData> {
-        let sb = SB.get().unwrap();
+        let sb = sb(self.dev);
         let mut guard = self.data.lock();
         if !guard.valid {
             let bp = BCACHE.read(self.dev, sb.iblock(self.inum));
@@ -740,6 +1143,9 @@ impl MInode {
             guard.atime = dip.atime;
             guard.mtime = dip.mtime;
             guard.ctime = dip.ctime;
+            guard.mode = dip.mode;
+            guard.uid = dip.uid;
+            guard.gid = dip.gid;
             guard.valid = true;
             guard.dev = self.dev;
             guard.inum = self.inum;
@@ -808,7 +1214,7 @@ impl ITable {
     // Mark it as allocated by giving it type.
     // Returns an unlocked but allocated and referenced inode.
     fn alloc(&self, dev: u32, itype: FileType) -> Result<Inode> {
-        let sb = SB.get().unwrap();
+        let sb = sb(dev);
         for inum in 1..sb.ninodes {
             let mut bp = BCACHE.read(dev, sb.iblock(inum));
             let dip = bp
@@ -894,23 +1300,22 @@ impl ITable {
     }
 }
 
-// Create the path new as a link to the same inode as old.
+// Add name in directory dp as a link to ip.
+// Must be called inside a transaction.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-pub fn link(old: &Path, new: &Path) -> Result<()> {
-    let (_, ip) = old.namei()?;
+pub fn link(dp: &Inode, name: &str, ip: &Inode) -> Result<()> {
     {
         let ip_guard = ip.lock();
         if ip_guard.itype == FileType::Dir {
             return Err(IsADirectory);
         }
     }
-    // ?
 
-    let (name, dp) = new.nameiparent()?;
     let mut dp_guard = dp.lock();
     if dp.dev != ip.dev {
         return Err(CrossesDevices);
     }
+    dp_guard.access(mode::W_OK | mode::X_OK)?;
     dp_guard.dirlink(name, ip.inum)?;
 
     {
@@ -921,31 +1326,172 @@ pub fn link(old: &Path, new: &Path) -> Result<()> {
     Ok(())
 }
 
+// Set the permission bits of ip. Only its owner or root may.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-pub fn symlink(target: &Path, linkpath: &Path) -> Result<()> {
-    let ip = create(linkpath, FileType::Symlink, 0, 0)?;
+pub fn chmod(ip: &Inode, perm: u32) -> Result<()> {
     let mut ip_guard = ip.lock();
-    let bytes = target.inner.as_bytes();
+    let cred = cred();
+    if !cred.is_root() && cred.euid != ip_guard.uid {
+        return Err(PermissionDenied);
+    }
+    ip_guard.set_mode(perm);
+    Ok(())
+}
+
+// Change the owner and group of ip; u32::MAX leaves either unchanged.
+// Only root may give a file away, its owner may only change the group
+// to one they belong to.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn chown(ip: &Inode, uid: u32, gid: u32) -> Result<()> {
+    let mut ip_guard = ip.lock();
+    let uid = if uid == u32::MAX { ip_guard.uid } else { uid };
+    let gid = if gid == u32::MAX { ip_guard.gid } else { gid };
+    let cred = cred();
+    if !cred.is_root()
+        && (cred.euid != ip_guard.uid
+            || uid != ip_guard.uid
+            || (gid != ip_guard.gid && !cred.in_group(gid)))
+    {
+        return Err(PermissionDenied);
+    }
+    ip_guard.set_owner(uid, gid);
+    Ok(())
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+static ZEROS: [u8; BSIZE] = [0; BSIZE];
+
+// The most bytes one transaction writes to a file: what fits the log
+// with the i-node, an indirect block, allocation blocks, and 2 blocks
+// of slop for non-aligned writes.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+const MAXWRITE: usize = ((MAXOPBLOCKS - 1 - 1 - 2) / 2) * BSIZE;
+
+// Write zeros into ip from its end up to byte to.
+// Caller must hold ip's lock, inside a transaction.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn write_zeros(ip_guard: &mut IData, to: u64) -> Result<()> {
+    while ip_guard.size() < to {
+        let size = ip_guard.size();
+        let n = core::cmp::min(to - size, BSIZE as u64) as usize;
+        ip_guard.write(VirtAddr::Kernel(ZEROS.as_ptr() as usize), size, n)?;
+    }
+    ip_guard.touch_mtime_ctime();
+    Ok(())
+}
+
+// Files have no holes, so writing past the end first fills the gap up
+// to byte to with zeros, a few blocks per transaction. A crash part way
+// leaves the file partly extended, as with a write split the same way.
+// Caller must not be inside a transaction.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn zero_fill(ip: &Inode, to: u64) -> Result<()> {
+    if to as usize > MAXFILE * BSIZE {
+        return Err(FileTooLarge);
+    }
+    loop {
+        LOG.begin_op(ip.dev);
+        let res = {
+            let mut ip_guard = ip.lock();
+            let size = ip_guard.size();
+            match to.checked_sub(size) {
+                Some(gap) if gap > 0 => {
+                    let n = core::cmp::min(gap, MAXWRITE as u64);
+                    write_zeros(&mut ip_guard, size + n).map(|_| n)
+                }
+                _ => Ok(0),
+            }
+        };
+        LOG.end_op(ip.dev);
+        if res? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+// Set the length of regular file ip to len. Shrinking happens in a
+// single transaction; growing appends zeros through zero_fill, a few
+// blocks per transaction, like a write past the end.
+// Caller must not be inside a transaction.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn truncate(ip: &Inode, len: usize) -> Result<()> {
+    if len > MAXFILE * BSIZE {
+        return Err(FileTooLarge);
+    }
+    let len = len as u64;
+
+    op(ip.dev, || {
+        let mut ip_guard = ip.lock();
+        match ip_guard.itype() {
+            FileType::File if len <= ip_guard.size() => {
+                ip_guard.trunc_to(len);
+                Ok(())
+            }
+            FileType::File => Ok(()),
+            FileType::Dir => Err(IsADirectory),
+            _ => Err(InvalidArgument),
+        }
+    })?;
+    zero_fill(ip, len)
+}
+
+// Set the access and modification times of ip; None leaves either
+// unchanged. Its owner or root may set any time, anyone with write
+// access only the current one, which now says is all that is asked.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn utimens(ip: &Inode, atime: Option<u64>, mtime: Option<u64>, now: bool) -> Result<()> {
+    let mut ip_guard = ip.lock();
+    let cred = cred();
+    if !cred.is_root() && cred.euid != ip_guard.uid {
+        if !now {
+            return Err(PermissionDenied);
+        }
+        ip_guard.access(mode::W_OK)?;
+    }
+    ip_guard.set_times(atime, mtime);
+    Ok(())
+}
+
+// Create name in directory dp as a symlink to target.
+// Must be called inside a transaction.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn symlink(dp: &Inode, name: &str, target: &str) -> Result<()> {
+    let bytes = target.as_bytes();
     if bytes.is_empty() || bytes.len() > MAXPATH {
         return Err(InvalidArgument);
     }
+    let ip = create(dp, name, FileType::Symlink, 0, 0, 0o777)?;
+    let mut ip_guard = ip.lock();
     ip_guard.write(VirtAddr::Kernel(bytes.as_ptr() as usize), 0, bytes.len())?;
     ip_guard.touch_mtime_ctime();
     Ok(())
 }
 
+// Copy the target of symlink ip into dst, at most n bytes.
+// Returns the number of bytes copied, which are not NUL-terminated.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn readlink(ip: &Inode, dst: VirtAddr, n: usize) -> Result<usize> {
+    let mut ip_guard = ip.lock();
+    if ip_guard.itype != FileType::Symlink {
+        return Err(InvalidArgument);
+    }
+    let n = core::cmp::min(n, ip_guard.size as usize);
+    ip_guard.read(dst, 0, n)
+}
+
+// Remove name from directory dp.
+// Must be called inside a transaction.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-pub fn unlink(path: &Path) -> Result<()> {
-    let de: DirEnt = Default::default();
-    let mut off: u32 = 0;
+pub fn unlink(dp: &Inode, name: &str) -> Result<()> {
+    let mut off: u64 = 0;
 
-    let (name, dp) = path.nameiparent()?;
     let mut dp_guard = dp.lock();
 
     // Cannot unlink "." or ".."
     if name == "." || name == ".." {
         return Err(PermissionDenied);
     }
+    dp_guard.access(mode::W_OK | mode::X_OK)?;
 
     let ip = dp_guard.dirlookup(name, Some(&mut off))?;
     let mut ip_guard = ip.lock();
@@ -953,15 +1499,11 @@ pub fn unlink(path: &Path) -> Result<()> {
     if ip_guard.nlink < 1 {
         panic!("unlink: nlink < 1");
     }
-    if ip_guard.itype == FileType::Dir && !ip_guard.is_dir_empty() {
+    if ip_guard.itype == FileType::Dir && !ip_guard.is_dir_empty()? {
         return Err(DirectoryNotEmpty);
     }
 
-    dp_guard.write(
-        VirtAddr::Kernel(&de as *const _ as usize),
-        off,
-        size_of::<DirEnt>(),
-    )?;
+    dp_guard.dirunlink(off)?;
     if ip_guard.itype == FileType::Dir {
         dp_guard.set_nlink(LinkOp::Minus);
     }
@@ -972,10 +1514,160 @@ pub fn unlink(path: &Path) -> Result<()> {
     Ok(())
 }
 
+// Serializes renames so that directory parents cannot change while
+// one rename walks up the tree and orders its directory locks.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+static RENAME_LOCK: SleepLock<()> = SleepLock::new((), "rename");
+
+// Inode numbers of dp and each directory above it, up to the root.
+// Caller must hold RENAME_LOCK and no inode locks.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn ancestors(dp: &Inode) -> Result<Vec<u32>> {
+    let mut chain = Vec::new();
+    let mut ip = dp.dup();
+    loop {
+        chain.push(ip.inum);
+        if ip.inum == ROOTINO {
+            return Ok(chain);
+        }
+        let parent = ip.lock().dirlookup("..", None)?;
+        if chain.contains(&parent.inum) {
+            return Err(TooManyLinks);
+        }
+        ip = parent;
+    }
+}
+
+// Atomically move oname in odp to nname in ndp, replacing it if it
+// exists.
+// Must be called inside a transaction so the unlink of the old name,
+// the link of the new name and any ".." update commit together.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-pub fn create(path: &Path, type_: FileType, major: u16, minor: u16) -> Result<Inode> {
-    let (name, dp) = path.nameiparent()?;
+pub fn rename(odp: &Inode, oname: &str, ndp: &Inode, nname: &str) -> Result<()> {
+    let _rename = RENAME_LOCK.lock();
+
+    for name in [oname, nname] {
+        if name == "." || name == ".." || name == "/" {
+            return Err(InvalidArgument);
+        }
+    }
+    if odp.dev != ndp.dev {
+        return Err(CrossesDevices);
+    }
+    let same_dir = odp.inum == ndp.inum;
+    let old_chain = ancestors(odp)?;
+    let new_chain = ancestors(ndp)?;
+
+    // Lock both parents, ancestor first, the same order namex and
+    // create use when they hold a directory and one of its children.
+    let (mut od_guard, mut nd_guard) = if same_dir {
+        (odp.lock(), None)
+    } else if new_chain.contains(&odp.inum) {
+        let od_guard = odp.lock();
+        (od_guard, Some(ndp.lock()))
+    } else {
+        let nd_guard = ndp.lock();
+        (odp.lock(), Some(nd_guard))
+    };
+
+    od_guard.access(mode::W_OK | mode::X_OK)?;
+    if let Some(nd_guard) = nd_guard.as_ref() {
+        nd_guard.access(mode::W_OK | mode::X_OK)?;
+    }
+
+    let mut ooff = 0;
+    let ip = od_guard.dirlookup(oname, Some(&mut ooff))?;
+    let mut noff = 0;
+    let tip = match nd_guard
+        .as_mut()
+        .unwrap_or(&mut od_guard)
+        .dirlookup(nname, Some(&mut noff))
+    {
+        Ok(tip) => Some(tip),
+        Err(NotFound) => None,
+        Err(err) => return Err(err),
+    };
+
+    // Both names already refer to the same file.
+    if tip.as_ref().is_some_and(|tip| tip.inum == ip.inum) {
+        return Ok(());
+    }
+    // Only directories can be ancestors, so this also rejects moving a
+    // directory into its own subtree before ip is locked.
+    if new_chain.contains(&ip.inum) {
+        return Err(InvalidArgument);
+    }
+    // A target above the source still contains it.
+    if tip
+        .as_ref()
+        .is_some_and(|tip| old_chain.contains(&tip.inum))
+    {
+        return Err(DirectoryNotEmpty);
+    }
+
+    let mut ip_guard = ip.lock();
+    let is_dir = ip_guard.itype == FileType::Dir;
+    let mut tip_guard = match tip.as_ref() {
+        Some(tip) => {
+            let mut guard = tip.lock();
+            match (is_dir, guard.itype == FileType::Dir) {
+                (true, false) => return Err(NotADirectory),
+                (false, true) => return Err(IsADirectory),
+                (true, true) if !guard.is_dir_empty()? => return Err(DirectoryNotEmpty),
+                _ => Some(guard),
+            }
+        }
+        None => None,
+    };
 
+    {
+        let nd = nd_guard.as_mut().unwrap_or(&mut od_guard);
+        if nd.nlink == 0 {
+            // new's parent was removed while we were walking.
+            return Err(NotFound);
+        }
+        match tip_guard.as_mut() {
+            Some(tip_guard) => {
+                // Point the existing entry at ip, then drop the target.
+                nd.dirretarget(noff, ip.inum)?;
+                if tip_guard.itype == FileType::Dir {
+                    nd.set_nlink(LinkOp::Minus); // for target's ".."
+                }
+                tip_guard.set_nlink(LinkOp::Minus);
+                nd.touch_mtime_ctime();
+            }
+            None => nd.dirlink(nname, ip.inum)?,
+        }
+    }
+
+    od_guard.dirunlink(ooff)?;
+    od_guard.touch_mtime_ctime();
+
+    if is_dir && !same_dir {
+        let mut doff = 0;
+        drop(ip_guard.dirlookup("..", Some(&mut doff))?);
+        ip_guard.dirretarget(doff, ndp.inum)?;
+        od_guard.set_nlink(LinkOp::Minus);
+        nd_guard.as_mut().unwrap().set_nlink(LinkOp::Plus);
+    }
+    ip_guard.touch_ctime();
+
+    Ok(())
+}
+
+// Create name in directory dp with permission bits perm, less the
+// process umask (symlinks are always 0o777). The new inode belongs to
+// the caller.
+// Must be called inside a transaction.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn create(
+    dp: &Inode,
+    name: &str,
+    type_: FileType,
+    major: u16,
+    minor: u16,
+    perm: u32,
+) -> Result<Inode> {
     let ip: Inode;
     {
         let mut dp_guard = dp.lock();
@@ -993,6 +1685,7 @@ pub fn create(path: &Path, type_: FileType, major: u16, minor: u16) -> Result<In
                 _ => return Err(AlreadyExists),
             }
         }
+        dp_guard.access(mode::W_OK | mode::X_OK)?;
 
         ip = ITABLE.alloc(dp.dev, type_)?;
         let mut ip_guard = ip.lock();
@@ -1013,6 +1706,13 @@ pub fn create(path: &Path, type_: FileType, major: u16, minor: u16) -> Result<In
         }
 
         ip_guard.set_major_minor(Major::from_u16(major), minor);
+        let umask = match type_ {
+            FileType::Symlink => 0,
+            _ => Cpus::myproc().map_or(mode::UMASK, |p| p.data().umask),
+        };
+        ip_guard.set_mode(perm & !umask);
+        let cred = cred();
+        ip_guard.set_owner(cred.euid, cred.egid);
         ip_guard.set_nlink(LinkOp::Init(1));
         ip_guard.init_times();
     }
@@ -1020,8 +1720,323 @@ pub fn create(path: &Path, type_: FileType, major: u16, minor: u16) -> Result<In
     Ok(ip)
 }
 
+// The inode file system of a disk, as mounted in the vfs.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub struct InodeFs {
+    dev: u32,
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+impl InodeFs {
+    // The file system on disk dev, which may be mounted only once.
+    pub fn open(dev: u32) -> Result<Arc<Self>> {
+        let busy = &BUSY[disk_index(dev)];
+        if busy.swap(true, Ordering::AcqRel) {
+            return Err(ResourceBusy);
+        }
+        if let Err(e) = init(dev) {
+            busy.store(false, Ordering::Release);
+            return Err(e);
+        }
+        Ok(Arc::new(Self { dev }))
+    }
+}
+
+// Unmounted: commit what the log still holds.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+impl Drop for InodeFs {
+    fn drop(&mut self) {
+        LOG.sync(self.dev);
+        BUSY[disk_index(self.dev)].store(false, Ordering::Release);
+    }
+}
+
+// Make an empty file system of size blocks on disk dev, with ninodes
+// inodes and a root directory: the layout mkfs builds for fs.img. The
+// disk must not be mounted.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn format(dev: u32, size: u32, ninodes: u32) -> Result<()> {
+    let busy = &BUSY[disk_index(dev)];
+    if busy.swap(true, Ordering::AcqRel) {
+        return Err(ResourceBusy);
+    }
+    let res = mkfs(dev, size, ninodes);
+    busy.store(false, Ordering::Release);
+    res
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn mkfs(dev: u32, size: u32, ninodes: u32) -> Result<()> {
+    let sb = SuperBlock::new(size, ninodes).ok_or(InvalidArgument)?;
+    let nmeta = sb.size - sb.nblocks;
+    let nbitmap = nmeta - sb.bmapstart;
+
+    // An empty log and inode table, a free map with the metadata in
+    // use, then the superblock.
+    for bno in 2..sb.bmapstart {
+        let mut bp = BCACHE.read(dev, bno);
+        bp.fill(0);
+        bp.write();
+    }
+    for i in 0..nbitmap {
+        let mut bp = BCACHE.read(dev, sb.bmapstart + i);
+        bp.fill(0);
+        for b in i * BPB..nmeta.min((i + 1) * BPB) {
+            bp[(b % BPB / 8) as usize] |= 1 << (b % 8);
+        }
+        bp.write();
+    }
+    let mut bp = BCACHE.read(dev, 1);
+    bp.fill(0);
+    *bp.align_to_mut::<SuperBlock>().get_mut(0).unwrap() = sb;
+    bp.write();
+    drop(bp);
+
+    init(dev)?;
+    op(dev, || -> Result<()> {
+        let ip = ITABLE.alloc(dev, FileType::Dir)?;
+        assert!(ip.inum == ROOTINO, "mkfs: root inode");
+        let mut ip_guard = ip.lock();
+        ip_guard.dirlink(".", ROOTINO)?;
+        ip_guard.dirlink("..", ROOTINO)?;
+        ip_guard.set_mode(0o755);
+        ip_guard.set_owner(0, 0);
+        ip_guard.set_nlink(LinkOp::Init(1));
+        ip_guard.init_times();
+        Ok(())
+    })?;
+    LOG.sync(dev);
+    Ok(())
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+impl FileSystem for InodeFs {
+    fn name(&self) -> &'static str {
+        "xv6fs"
+    }
+
+    fn dev(&self) -> u32 {
+        self.dev
+    }
+
+    fn root(self: Arc<Self>) -> Result<Vnode> {
+        let ip = ITABLE.get(self.dev, ROOTINO)?;
+        Ok(InodeNode::vnode(self, ip))
+    }
+}
+
+// Run f as a transaction of its own on disk dev.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn op<T>(dev: u32, f: impl FnOnce() -> T) -> T {
+    LOG.begin_op(dev);
+    let res = f();
+    LOG.end_op(dev);
+    res
+}
+
+// A vnode of the inode file system. Every operation is a transaction
+// of its own, and so is dropping it, since putting the inode may free
+// it.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub struct InodeNode {
+    fs: Arc<InodeFs>,
+    ip: ManuallyDrop<Inode>,
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+impl InodeNode {
+    fn vnode(fs: Arc<InodeFs>, ip: Inode) -> Vnode {
+        Arc::new(Self {
+            fs,
+            ip: ManuallyDrop::new(ip),
+        })
+    }
+
+    fn node(&self, ip: Inode) -> Vnode {
+        Self::vnode(Arc::clone(&self.fs), ip)
+    }
+
+    // ip of another vnode on the same disk.
+    fn peer<'a>(&self, vn: &'a Vnode) -> Result<&'a Inode> {
+        match vn.as_any().downcast_ref::<InodeNode>() {
+            Some(node) if node.ip.dev == self.ip.dev => Ok(&node.ip),
+            _ => Err(CrossesDevices),
+        }
+    }
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+impl Drop for InodeNode {
+    fn drop(&mut self) {
+        let dev = self.ip.dev;
+        LOG.begin_op(dev);
+        unsafe { ManuallyDrop::drop(&mut self.ip) };
+        LOG.end_op(dev);
+    }
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+impl Node for InodeNode {
+    fn dev(&self) -> u32 {
+        self.ip.dev
+    }
+
+    fn ino(&self) -> u32 {
+        self.ip.inum
+    }
+
+    fn stat(&self, st: &mut Stat) -> Result<()> {
+        self.ip.lock().stat(st);
+        Ok(())
+    }
+
+    fn file_type(&self) -> FileType {
+        self.ip.lock().itype
+    }
+
+    fn major(&self) -> Major {
+        self.ip.lock().major
+    }
+
+    fn access(&self, want: u32) -> Result<()> {
+        self.ip.lock().access(want)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Vnode> {
+        let ip = op(self.ip.dev, || {
+            let mut ip_guard = self.ip.lock();
+            if ip_guard.itype != FileType::Dir {
+                return Err(NotADirectory);
+            }
+            ip_guard.dirlookup(name, None)
+        })?;
+        Ok(self.node(ip))
+    }
+
+    fn create(
+        &self,
+        name: &str,
+        ftype: FileType,
+        major: u16,
+        minor: u16,
+        perm: u32,
+    ) -> Result<Vnode> {
+        let ip = op(self.ip.dev, || {
+            create(&self.ip, name, ftype, major, minor, perm)
+        })?;
+        Ok(self.node(ip))
+    }
+
+    fn open(self: Arc<Self>, omode: &OMode) -> Result<Vnode> {
+        if omode.is_trunc() {
+            op(self.ip.dev, || {
+                let mut ip_guard = self.ip.lock();
+                if ip_guard.itype == FileType::File {
+                    ip_guard.trunc();
+                }
+            });
+        }
+        Ok(self)
+    }
+
+    fn read(&self, dst: VirtAddr, off: u64, n: usize) -> Result<usize> {
+        op(self.ip.dev, || {
+            let mut ip_guard = self.ip.lock();
+            let res = ip_guard.read(dst, off, n);
+            if let Ok(r) = res
+                && r > 0
+            {
+                ip_guard.touch_atime();
+            }
+            res
+        })
+    }
+
+    fn write(&self, mut src: VirtAddr, off: &mut u64, n: usize, append: bool) -> Result<usize> {
+        if !append {
+            zero_fill(&self.ip, *off)?;
+        }
+
+        // write a few blocks at a time to avoid exceeding the maximum
+        // log transaction size.
+        let mut i: usize = 0;
+        while i < n {
+            let n1 = core::cmp::min(n - i, MAXWRITE);
+            let r = op(self.ip.dev, || {
+                let mut ip_guard = self.ip.lock();
+                if append {
+                    *off = ip_guard.size();
+                }
+                let r = ip_guard.write(src, *off, n1)?;
+                ip_guard.touch_mtime_ctime();
+                Ok(r)
+            })?;
+            *off += r as u64;
+            src += r;
+            i += r;
+        }
+        Ok(i)
+    }
+
+    fn readdir(&self, off: &mut u64, dst: VirtAddr, n: usize) -> Result<usize> {
+        op(self.ip.dev, || self.ip.lock().getdents(off, dst, n))
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        op(self.ip.dev, || unlink(&self.ip, name))
+    }
+
+    fn link(&self, name: &str, target: &Vnode) -> Result<()> {
+        let ip = self.peer(target)?;
+        op(self.ip.dev, || link(&self.ip, name, ip))
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<()> {
+        op(self.ip.dev, || symlink(&self.ip, name, target))
+    }
+
+    fn fsync(&self) -> Result<()> {
+        LOG.sync(self.ip.dev);
+        Ok(())
+    }
+
+    fn readlink(&self, dst: VirtAddr, n: usize) -> Result<usize> {
+        readlink(&self.ip, dst, n)
+    }
+
+    fn rename(&self, name: &str, ndir: &Vnode, nname: &str) -> Result<()> {
+        let ndp = self.peer(ndir)?;
+        op(self.ip.dev, || rename(&self.ip, name, ndp, nname))
+    }
+
+    fn truncate(&self, len: usize) -> Result<()> {
+        truncate(&self.ip, len)
+    }
+
+    fn chmod(&self, perm: u32) -> Result<()> {
+        op(self.ip.dev, || chmod(&self.ip, perm))
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Result<()> {
+        op(self.ip.dev, || chown(&self.ip, uid, gid))
+    }
+
+    fn utimens(&self, atime: Option<u64>, mtime: Option<u64>, now: bool) -> Result<()> {
+        op(self.ip.dev, || utimens(&self.ip, atime, mtime, now))
+    }
+
+    fn inode(&self) -> Option<&Inode> {
+        Some(&self.ip)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // Paths
-// A slice of a path (akin to str)
+// A slice of a path (akin to str); lookup is in vfs.rs since a path
+// may cross mount points.
 #[cfg(all(target_os = "none", feature = "kernel"))]
 #[derive(Debug)]
 #[repr(C)]
@@ -1064,146 +2079,13 @@ impl Path {
     //   skip_elem("///a//bb") = (Some("a"), Some("/bb")),
     //   skipelem("a") = (Some("a"), None)
     //   skipelem("") = skipelem("////") = (None, None)
-    //   if name: &str > DIRSIZE return (None, None)
     pub fn skip_elem(&self) -> (Option<&str>, Option<&Path>) {
         match self.inner.trim_matches('/').split_once('/') {
-            Some((name, path)) if name.len() <= DIRSIZ => (Some(name), Some(Path::new(path))),
-            None if !self.inner.trim_matches('/').is_empty()
-                && self.inner.trim_matches('/').len() < DIRSIZ =>
-            {
+            Some((name, path)) => (Some(name), Some(Path::new(path))),
+            None if !self.inner.trim_matches('/').is_empty() => {
                 (Some(self.inner.trim_matches('/')), None)
             }
             _ => (None, None),
         }
     }
-
-    // Look up and return the inode for a path name.
-    // If `parent` is true, return the inode for the parent.
-    // Must be called inside a transaction since it calls ITABLE.put()
-    // when dropping an inode.
-    // # Safety:
-    // call inside a transaction.
-    pub fn namex(path: &Path, parent: bool) -> Result<(&str, Inode)> {
-        let trimmed = path.inner.trim_matches('/');
-        let orig_name = if trimmed.is_empty() {
-            "/"
-        } else {
-            match trimmed.rsplit_once('/') {
-                Some((_, name)) => name,
-                None => trimmed,
-            }
-        };
-        let mut ip = match path.inner.get(0..1) {
-            Some("/") => ITABLE.get(ROOTDEV, ROOTINO)?,
-            _ => Cpus::myproc().unwrap().data().cwd.as_ref().unwrap().dup(),
-        };
-
-        let mut path = path;
-        let mut link_paths: Vec<String> = Vec::new();
-        let mut link_depth = 0usize;
-        loop {
-            let mut guard = ip.lock();
-            if guard.itype != FileType::Dir {
-                return Err(NotADirectory);
-            }
-            match path.skip_elem() {
-                (Some(name), Some(npath)) => {
-                    let nip = guard.dirlookup(name, None)?;
-                    SleepLock::unlock(guard);
-                    let mut nip_guard = nip.lock();
-                    if nip_guard.itype == FileType::Symlink {
-                        if link_depth >= SYMLINK_MAX_DEPTH {
-                            return Err(TooManyLinks);
-                        }
-                        link_depth += 1;
-                        let target_len = nip_guard.size() as usize;
-                        if target_len == 0 || target_len > MAXPATH {
-                            return Err(InvalidArgument);
-                        }
-                        let mut target_buf = [0u8; MAXPATH];
-                        let read_len = nip_guard.read(
-                            VirtAddr::Kernel(target_buf.as_mut_ptr() as usize),
-                            0,
-                            target_len,
-                        )?;
-                        SleepLock::unlock(nip_guard);
-                        let target =
-                            core::str::from_utf8(&target_buf[..read_len]).or(Err(Utf8Error))?;
-                        let mut new_path = String::new();
-                        new_path.push_str(target);
-                        if !new_path.ends_with('/') {
-                            new_path.push('/');
-                        }
-                        new_path.push_str(&npath.inner);
-                        link_paths.push(new_path);
-                        let path_str = link_paths.last().unwrap();
-                        if path_str.starts_with('/') {
-                            ip = ITABLE.get(ROOTDEV, ROOTINO)?;
-                        }
-                        path = Path::new(path_str);
-                        continue;
-                    }
-                    SleepLock::unlock(nip_guard);
-                    ip = nip;
-                    path = npath;
-                    continue;
-                }
-                (Some(name), None) if !parent => {
-                    let nip = guard.dirlookup(name, None)?;
-                    SleepLock::unlock(guard);
-                    let mut nip_guard = nip.lock();
-                    if nip_guard.itype == FileType::Symlink {
-                        if link_depth >= SYMLINK_MAX_DEPTH {
-                            return Err(TooManyLinks);
-                        }
-                        link_depth += 1;
-                        let target_len = nip_guard.size() as usize;
-                        if target_len == 0 || target_len > MAXPATH {
-                            return Err(InvalidArgument);
-                        }
-                        let mut target_buf = [0u8; MAXPATH];
-                        let read_len = nip_guard.read(
-                            VirtAddr::Kernel(target_buf.as_mut_ptr() as usize),
-                            0,
-                            target_len,
-                        )?;
-                        SleepLock::unlock(nip_guard);
-                        let target =
-                            core::str::from_utf8(&target_buf[..read_len]).or(Err(Utf8Error))?;
-                        let mut new_path = String::new();
-                        new_path.push_str(target);
-                        link_paths.push(new_path);
-                        let path_str = link_paths.last().unwrap();
-                        if path_str.starts_with('/') {
-                            ip = ITABLE.get(ROOTDEV, ROOTINO)?;
-                        }
-                        path = Path::new(path_str);
-                        continue;
-                    }
-                    SleepLock::unlock(nip_guard);
-                    break Ok((orig_name, nip));
-                }
-                (Some(_name), None) => {
-                    SleepLock::unlock(guard);
-                    break Ok((orig_name, ip));
-                }
-                _ => {
-                    if let Some("/") = path.inner.get(..) {
-                        SleepLock::unlock(guard);
-                        break Ok(("/", ip));
-                    } else {
-                        break Err(NotFound);
-                    }
-                }
-            }
-        }
-    }
-
-    pub fn namei(&self) -> Result<(&str, Inode)> {
-        Self::namex(self, false)
-    }
-
-    pub fn nameiparent(&self) -> Result<(&str, Inode)> {
-        Self::namex(self, true)
-    }
 }
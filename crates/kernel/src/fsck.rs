@@ -0,0 +1,663 @@
+// File system checker.
+//
+// The fsck program runs it against a raw disk device and the host
+// fsimg tool against an image file, so both apply the same rules.
+// Without repair the first problem found is the result; with repair
+// every problem is fixed in place and reported as it is:
+//
+// - inodes with a bad type or a bad or shared block are cleared
+// - bad directory records and entries naming free inodes are dropped
+// - a directory named from two places keeps the first
+// - inodes cut off from the root are linked into /lost+found
+// - sizes, "." and "..", link counts and the free map are rebuilt
+//
+// Plain code with no kernel dependencies, like crc32.
+
+use alloc::{format, vec, vec::Vec};
+use core::fmt;
+
+use crate::error::Result;
+use crate::fs::{
+    BPB, BSIZE, DInode, DirEnt, FEATURE_DIRENT, FEATURE_LARGEFILE, FSMAGIC, IPB, NDIRECT,
+    NINDIRECT, ROOTINO, SuperBlock,
+};
+use crate::stat::FileType;
+
+pub const LOST_FOUND: &str = "lost+found";
+const LOST_FOUND_MODE: u32 = 0o700;
+const NONE: u32 = u32::MAX;
+
+// Why the check stopped: the first problem when only checking, or one
+// that cannot be repaired.
+pub type Check<T> = core::result::Result<T, &'static str>;
+
+// Whatever holds the file system, a block at a time.
+pub trait Disk {
+    fn read(&self, bno: u32, buf: &mut [u8; BSIZE]) -> Result<()>;
+    fn write(&self, bno: u32, buf: &[u8; BSIZE]) -> Result<()>;
+}
+
+fn read<D: Disk>(disk: &D, bno: u32) -> Check<[u8; BSIZE]> {
+    let mut buf = [0; BSIZE];
+    disk.read(bno, &mut buf).or(Err("read err"))?;
+    Ok(buf)
+}
+
+fn write<D: Disk>(disk: &D, bno: u32, buf: &[u8; BSIZE]) -> Check<()> {
+    disk.write(bno, buf).or(Err("write err"))
+}
+
+fn u32_at(buf: &[u8; BSIZE], idx: usize) -> u32 {
+    let off = idx * size_of::<u32>();
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn show(name: &[u8]) -> &str {
+    core::str::from_utf8(name).unwrap_or("?")
+}
+
+// The superblock, if it describes a layout that fits together.
+pub fn superblock<D: Disk>(disk: &D) -> Check<SuperBlock> {
+    let buf = read(disk, 1)?;
+    let sb = unsafe { (buf.as_ptr() as *const SuperBlock).read_unaligned() };
+    if sb.magic != FSMAGIC {
+        return Err("bad super");
+    }
+    if sb.features & FEATURE_DIRENT == 0 {
+        return Err("old directory format");
+    }
+    if sb.features & FEATURE_LARGEFILE == 0 {
+        return Err("old inode format");
+    }
+    let size = sb.size as usize;
+    if size == 0 || sb.ninodes == 0 || sb.nblocks == 0 || sb.nblocks >= sb.size {
+        return Err("bad super");
+    }
+    // The data blocks fill the end of the disk; mkfs may leave a spare
+    // bitmap block before them.
+    let n_inode_blocks = (sb.ninodes as usize).div_ceil(IPB);
+    let n_bitmap_blocks = size.div_ceil(BPB as usize);
+    if sb.logstart < 2
+        || (sb.inodestart as usize) < sb.logstart as usize + sb.nlog as usize
+        || (sb.bmapstart as usize) < sb.inodestart as usize + n_inode_blocks
+        || data_start(&sb) < sb.bmapstart + n_bitmap_blocks as u32
+    {
+        return Err("bad super");
+    }
+    Ok(sb)
+}
+
+pub fn data_start(sb: &SuperBlock) -> u32 {
+    sb.size - sb.nblocks
+}
+
+// Does the log hold a commit not yet installed? Mounting would replay
+// it over any repair. The header starts with seq, then n.
+pub fn log_pending<D: Disk>(disk: &D, sb: &SuperBlock) -> Check<bool> {
+    Ok(u32_at(&read(disk, sb.logstart)?, 1) != 0)
+}
+
+// Check the file system on disk, fixing it if repair is set and
+// telling report about every fix. Returns how many were made.
+pub fn fsck<D: Disk>(
+    disk: &D,
+    repair: bool,
+    now: u64,
+    report: &mut dyn FnMut(fmt::Arguments),
+) -> Check<usize> {
+    let sb = superblock(disk)?;
+    if repair && log_pending(disk, &sb)? {
+        return Err("log holds a commit, mount the disk once to install it");
+    }
+    let mut fsck = Fsck::new(disk, &sb, repair, now, report)?;
+    fsck.scan_inodes()?;
+    if !fsck.is_dir(ROOTINO) {
+        return Err("root bad");
+    }
+    fsck.scan_dirs()?;
+    fsck.reconnect()?;
+    fsck.fix_dots()?;
+    fsck.fix_nlinks()?;
+    fsck.fix_bitmap(sb.bmapstart)?;
+    fsck.write_inodes(sb.inodestart)?;
+    Ok(fsck.fixes)
+}
+
+// Where a directory entry lives on disk.
+#[derive(Clone, Copy)]
+struct Loc {
+    block: u32,
+    off: usize,
+}
+
+// The whole file system as fsck sees it. Inodes are kept in memory and
+// written back at the end; directory blocks are rewritten as they are
+// fixed.
+struct Fsck<'a, D: Disk> {
+    disk: &'a D,
+    repair: bool,
+    now: u64,
+    report: &'a mut dyn FnMut(fmt::Arguments),
+    fixes: usize,
+    size: u32,
+    data_start: u32,
+    inodes: Vec<DInode>,
+    dirty: Vec<bool>,                // per inode block
+    blocks: Vec<Vec<u32>>,           // data blocks of each inode, in order
+    owner: Vec<u32>,                 // inode holding each block, data or indirect
+    refs: Vec<u32>,                  // entries naming each inode, other than "."
+    parent: Vec<u32>,                // directory naming each directory
+    parent_at: Vec<Option<Loc>>,     // and where that entry is
+    dot: Vec<bool>,                  // directory has a "." entry
+    dotdot: Vec<Option<(u32, Loc)>>, // what a directory's ".." names, and where
+    links: Vec<(u32, u32)>,          // (directory, inode) of each named entry
+    lost_found: Option<u32>,
+}
+
+impl<'a, D: Disk> Fsck<'a, D> {
+    fn new(
+        disk: &'a D,
+        sb: &SuperBlock,
+        repair: bool,
+        now: u64,
+        report: &'a mut dyn FnMut(fmt::Arguments),
+    ) -> Check<Self> {
+        let ninodes = sb.ninodes as usize;
+        let mut inodes = Vec::with_capacity(ninodes);
+        let mut bad = Vec::new();
+        for bno in sb.inodestart..sb.inodestart + ninodes.div_ceil(IPB) as u32 {
+            let buf = read(disk, bno)?;
+            for idx in 0..IPB.min(ninodes - inodes.len()) {
+                if let Some(din) = DInode::from_block(&buf, idx) {
+                    inodes.push(din);
+                } else {
+                    bad.push(inodes.len() as u32);
+                    inodes.push(DInode::default());
+                }
+            }
+        }
+        let mut fsck = Self {
+            disk,
+            repair,
+            now,
+            report,
+            fixes: 0,
+            size: sb.size,
+            data_start: data_start(sb),
+            inodes,
+            dirty: vec![false; ninodes.div_ceil(IPB)],
+            blocks: vec![Vec::new(); ninodes],
+            owner: vec![NONE; sb.size as usize],
+            refs: vec![0; ninodes],
+            parent: vec![NONE; ninodes],
+            parent_at: vec![None; ninodes],
+            dot: vec![false; ninodes],
+            dotdot: vec![None; ninodes],
+            links: Vec::new(),
+            lost_found: None,
+        };
+        for inum in bad {
+            fsck.problem("bad inode type", format_args!("cleared inode {}", inum))?;
+            fsck.touch(inum);
+        }
+        Ok(fsck)
+    }
+
+    // An inconsistency: the result when only checking, fixed otherwise.
+    fn problem(&mut self, what: &'static str, fix: fmt::Arguments) -> Check<()> {
+        if !self.repair {
+            return Err(what);
+        }
+        (self.report)(format_args!("{}, {}", what, fix));
+        self.fixes += 1;
+        Ok(())
+    }
+
+    fn ninodes(&self) -> u32 {
+        self.inodes.len() as u32
+    }
+
+    fn ftype(&self, inum: u32) -> FileType {
+        self.inodes[inum as usize].itype
+    }
+
+    fn is_dir(&self, inum: u32) -> bool {
+        self.ftype(inum) == FileType::Dir
+    }
+
+    fn touch(&mut self, inum: u32) {
+        self.dirty[inum as usize / IPB] = true;
+    }
+
+    fn clear(&mut self, inum: u32) {
+        self.inodes[inum as usize] = DInode::default();
+        self.touch(inum);
+    }
+
+    // Claim the blocks of every inode, clearing any whose block tree
+    // is bad.
+    fn scan_inodes(&mut self) -> Check<()> {
+        for inum in 0..self.ninodes() {
+            if self.ftype(inum) == FileType::Empty {
+                continue;
+            }
+            let mut claimed = Vec::new();
+            let mut data = Vec::new();
+            let addrs = self.inodes[inum as usize].addrs;
+            // addrs[NDIRECT + k] heads a tree of k + 1 levels.
+            let res = addrs
+                .iter()
+                .enumerate()
+                .filter(|&(_, &addr)| addr != 0)
+                .try_for_each(|(i, &addr)| {
+                    let depth = (i + 1).saturating_sub(NDIRECT);
+                    self.claim(inum, addr, depth, &mut claimed, &mut data)
+                });
+            if let Err(why) = res {
+                for addr in claimed {
+                    self.owner[addr as usize] = NONE;
+                }
+                self.problem(why, format_args!("cleared inode {}", inum))?;
+                self.clear(inum);
+                continue;
+            }
+            self.blocks[inum as usize] = data;
+            self.check_size(inum)?;
+        }
+        Ok(())
+    }
+
+    // Claim block addr for inum, along with the depth levels of blocks
+    // below it if it is an indirect block.
+    fn claim(
+        &mut self,
+        inum: u32,
+        addr: u32,
+        depth: usize,
+        claimed: &mut Vec<u32>,
+        data: &mut Vec<u32>,
+    ) -> Check<()> {
+        if addr < self.data_start || addr >= self.size {
+            return Err("bad addr");
+        }
+        if self.owner[addr as usize] != NONE {
+            return Err("dup block");
+        }
+        self.owner[addr as usize] = inum;
+        claimed.push(addr);
+        if depth == 0 {
+            data.push(addr);
+            return Ok(());
+        }
+        let buf = read(self.disk, addr)?;
+        for i in 0..NINDIRECT {
+            let next = u32_at(&buf, i);
+            if next != 0 {
+                self.claim(inum, next, depth - 1, claimed, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Files have no holes, so the size ends in the last block; a
+    // directory is always whole blocks.
+    fn check_size(&mut self, inum: u32) -> Check<()> {
+        let blocks = self.blocks[inum as usize].len();
+        let size = self.inodes[inum as usize].size as usize;
+        let ok = match blocks {
+            0 => size == 0,
+            n => size > (n - 1) * BSIZE && size <= n * BSIZE,
+        };
+        if ok {
+            return Ok(());
+        }
+        let fixed = match blocks {
+            0 => 0,
+            n if self.is_dir(inum) => n * BSIZE,
+            n => size.clamp((n - 1) * BSIZE + 1, n * BSIZE),
+        };
+        self.problem(
+            "bad size",
+            format_args!("inode {} size {} -> {}", inum, size, fixed),
+        )?;
+        self.inodes[inum as usize].size = fixed as u64;
+        self.touch(inum);
+        Ok(())
+    }
+
+    // Check the records and entries of every directory, dropping the
+    // ones that are bad.
+    fn scan_dirs(&mut self) -> Check<()> {
+        self.parent[ROOTINO as usize] = ROOTINO;
+        for dir in 0..self.ninodes() {
+            if !self.is_dir(dir) {
+                continue;
+            }
+            for block in self.blocks[dir as usize].clone() {
+                let mut buf = read(self.disk, block)?;
+                if self.scan_block(dir, block, &mut buf)? {
+                    write(self.disk, block, &buf)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns whether buf was changed.
+    fn scan_block(&mut self, dir: u32, block: u32, buf: &mut [u8; BSIZE]) -> Check<bool> {
+        let mut changed = false;
+        let mut prev = None;
+        let mut off = 0;
+        while off < BSIZE {
+            let Some((mut de, name)) = DirEnt::at(buf, off) else {
+                self.problem(
+                    "dir entry bad record",
+                    format_args!("dropped the rest of block {} of dir {}", block, dir),
+                )?;
+                // The previous record, or a free one, runs to the end.
+                let (at, de) = prev.unwrap_or_default();
+                let reclen = (BSIZE - at) as u16;
+                DirEnt { reclen, ..de }.write(buf, at);
+                return Ok(true);
+            };
+            let name = name.to_vec();
+            let at = Loc { block, off };
+            prev = Some((off, de));
+            off += de.reclen as usize;
+            let inum = de.inum;
+            if inum == 0 {
+                continue;
+            }
+
+            let what = if inum >= self.ninodes() {
+                Some("dir entry inum")
+            } else if self.ftype(inum) == FileType::Empty {
+                Some("dir entry free")
+            } else if name != b"." && name != b".." && self.is_dir(inum) {
+                // A directory has one parent, the root none but itself.
+                (self.parent[inum as usize] != NONE).then_some("dir dup parent")
+            } else {
+                None
+            };
+            if let Some(what) = what {
+                self.problem(
+                    what,
+                    format_args!("removed {} from dir {}", show(&name), dir),
+                )?;
+                de.inum = 0;
+                de.write(buf, at.off);
+                changed = true;
+                continue;
+            }
+
+            match &name[..] {
+                b"." => {
+                    if inum != dir {
+                        self.problem("dir fmt", format_args!("fixed . of dir {}", dir))?;
+                        de.inum = dir;
+                        de.write(buf, at.off);
+                        changed = true;
+                    }
+                    self.dot[dir as usize] = true;
+                }
+                b".." => {
+                    self.dotdot[dir as usize] = Some((inum, at));
+                    self.refs[inum as usize] += 1;
+                }
+                _ => {
+                    if self.is_dir(inum) {
+                        self.parent[inum as usize] = dir;
+                        self.parent_at[inum as usize] = Some(at);
+                        if dir == ROOTINO && name == LOST_FOUND.as_bytes() {
+                            self.lost_found = Some(inum);
+                        }
+                    }
+                    self.refs[inum as usize] += 1;
+                    self.links.push((dir, inum));
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    // Which inodes can be reached from the root.
+    fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.inodes.len()];
+        seen[ROOTINO as usize] = true;
+        let mut grew = true;
+        while grew {
+            grew = false;
+            for &(dir, inum) in &self.links {
+                if seen[dir as usize] && !seen[inum as usize] {
+                    seen[inum as usize] = true;
+                    grew = true;
+                }
+            }
+        }
+        seen
+    }
+
+    // Link every inode that cannot be reached from the root into
+    // /lost+found, a whole detached subtree at a time.
+    fn reconnect(&mut self) -> Check<()> {
+        loop {
+            let seen = self.reachable();
+            let Some(inum) = (ROOTINO..self.ninodes())
+                .find(|&i| self.ftype(i) != FileType::Empty && !seen[i as usize])
+            else {
+                return Ok(());
+            };
+            // Climb to the top of the subtree; in a detached cycle of
+            // directories any of them will do.
+            let mut top = self
+                .links
+                .iter()
+                .find(|&&(_, i)| i == inum)
+                .map_or(inum, |&(dir, _)| dir);
+            for _ in 0..self.ninodes() {
+                match self.parent[top as usize] {
+                    NONE => break,
+                    dir => top = dir,
+                }
+            }
+
+            self.problem(
+                "inode unref",
+                format_args!("moved inode {} to /{}", top, LOST_FOUND),
+            )?;
+            let lost_found = self.lost_found()?;
+            if let Some(at) = self.parent_at[top as usize].take() {
+                let old = self.parent[top as usize];
+                self.set_entry(at, 0)?;
+                self.refs[top as usize] -= 1;
+                self.links.retain(|&link| link != (old, top));
+            }
+            let at = self.add_entry(lost_found, &format!("#{}", top), top)?;
+            self.refs[top as usize] += 1;
+            self.links.push((lost_found, top));
+            if self.is_dir(top) {
+                self.parent[top as usize] = lost_found;
+                self.parent_at[top as usize] = Some(at);
+            }
+        }
+    }
+
+    // The inode of /lost+found, made if it is missing.
+    fn lost_found(&mut self) -> Check<u32> {
+        if let Some(inum) = self.lost_found {
+            return Ok(inum);
+        }
+        let inum = (ROOTINO + 1..self.ninodes())
+            .find(|&i| self.ftype(i) == FileType::Empty)
+            .ok_or("no free inode for /lost+found")?;
+        self.inodes[inum as usize] = DInode::new(FileType::Dir, LOST_FOUND_MODE, self.now);
+        self.touch(inum);
+        self.add_entry(inum, ".", inum)?;
+        let at = self.add_entry(inum, "..", ROOTINO)?;
+        self.dot[inum as usize] = true;
+        self.dotdot[inum as usize] = Some((ROOTINO, at));
+        self.refs[ROOTINO as usize] += 1;
+
+        let at = self.add_entry(ROOTINO, LOST_FOUND, inum)?;
+        self.parent[inum as usize] = ROOTINO;
+        self.parent_at[inum as usize] = Some(at);
+        self.refs[inum as usize] += 1;
+        self.links.push((ROOTINO, inum));
+        (self.report)(format_args!("created /{}", LOST_FOUND));
+        self.lost_found = Some(inum);
+        Ok(inum)
+    }
+
+    fn set_entry(&self, at: Loc, inum: u32) -> Check<()> {
+        let mut buf = read(self.disk, at.block)?;
+        let (de, _) = DirEnt::at(&buf, at.off).ok_or("dir entry bad record")?;
+        DirEnt { inum, ..de }.write(&mut buf, at.off);
+        write(self.disk, at.block, &buf)
+    }
+
+    // Add an entry to dir in the first block with room for it, or else
+    // in a new one.
+    fn add_entry(&mut self, dir: u32, name: &str, inum: u32) -> Check<Loc> {
+        for block in self.blocks[dir as usize].clone() {
+            let mut buf = read(self.disk, block)?;
+            if let Some(off) = DirEnt::insert(&mut buf, inum, name.as_bytes()) {
+                write(self.disk, block, &buf)?;
+                return Ok(Loc { block, off });
+            }
+        }
+        let block = self.grow(dir)?;
+        let mut buf = [0; BSIZE];
+        DirEnt::put(&mut buf, 0, BSIZE, inum, name.as_bytes());
+        write(self.disk, block, &buf)?;
+        Ok(Loc { block, off: 0 })
+    }
+
+    // Give dir one more direct block from the free ones.
+    fn grow(&mut self, dir: u32) -> Check<u32> {
+        let n = self.blocks[dir as usize].len();
+        if n >= NDIRECT {
+            return Err("directory too big to grow");
+        }
+        let block = (self.data_start..self.size)
+            .find(|&b| self.owner[b as usize] == NONE)
+            .ok_or("out of blocks")?;
+        self.owner[block as usize] = dir;
+        self.blocks[dir as usize].push(block);
+        let din = &mut self.inodes[dir as usize];
+        din.addrs[n] = block;
+        din.size = ((n + 1) * BSIZE) as u64;
+        self.touch(dir);
+        Ok(block)
+    }
+
+    // Every directory needs a "." and a ".." naming its parent.
+    fn fix_dots(&mut self) -> Check<()> {
+        for dir in 0..self.ninodes() {
+            let parent = self.parent[dir as usize];
+            if !self.is_dir(dir) || parent == NONE {
+                continue;
+            }
+            if !self.dot[dir as usize] {
+                self.problem("dir fmt", format_args!("added . to dir {}", dir))?;
+                self.add_entry(dir, ".", dir)?;
+                self.dot[dir as usize] = true;
+            }
+            match self.dotdot[dir as usize] {
+                Some((inum, _)) if inum == parent => continue,
+                Some((inum, at)) => {
+                    self.problem("dir fmt", format_args!("fixed .. of dir {}", dir))?;
+                    self.set_entry(at, parent)?;
+                    self.refs[inum as usize] -= 1;
+                }
+                None => {
+                    self.problem("dir fmt", format_args!("added .. to dir {}", dir))?;
+                    let at = self.add_entry(dir, "..", parent)?;
+                    self.dotdot[dir as usize] = Some((parent, at));
+                }
+            }
+            self.refs[parent as usize] += 1;
+        }
+        Ok(())
+    }
+
+    // A directory's links are its entry in its parent and the ".." of
+    // each subdirectory; the root's own ".." stands in for the first.
+    fn fix_nlinks(&mut self) -> Check<()> {
+        for inum in ROOTINO..self.ninodes() {
+            if self.ftype(inum) == FileType::Empty {
+                continue;
+            }
+            let nlink = self.inodes[inum as usize].nlink;
+            let refs = self.refs[inum as usize];
+            if nlink as u32 != refs {
+                self.problem(
+                    "bad nlink",
+                    format_args!("inode {} nlink {} -> {}", inum, nlink, refs),
+                )?;
+                self.inodes[inum as usize].nlink = refs as u16;
+                self.touch(inum);
+            }
+        }
+        Ok(())
+    }
+
+    // Rebuild the free map from the blocks the inodes hold.
+    fn fix_bitmap(&mut self, bmapstart: u32) -> Check<()> {
+        let (mut marked, mut freed) = (0, 0);
+        for i in 0..(self.size as usize).div_ceil(BPB as usize) {
+            let bno = bmapstart + i as u32;
+            let mut buf = read(self.disk, bno)?;
+            let mut changed = false;
+            for bit in 0..BPB as usize {
+                let blk = i * BPB as usize + bit;
+                if blk >= self.size as usize {
+                    break;
+                }
+                if blk < self.data_start as usize {
+                    continue;
+                }
+                let used = self.owner[blk] != NONE;
+                let mask = 1u8 << (bit % 8);
+                if used == (buf[bit / 8] & mask != 0) {
+                    continue;
+                }
+                if !self.repair {
+                    return Err(if used {
+                        "addr free in bitmap"
+                    } else {
+                        "bitmap uses free"
+                    });
+                }
+                if used {
+                    marked += 1;
+                } else {
+                    freed += 1;
+                }
+                buf[bit / 8] ^= mask;
+                changed = true;
+            }
+            if changed {
+                write(self.disk, bno, &buf)?;
+            }
+        }
+        if marked + freed > 0 {
+            self.problem(
+                "bad bitmap",
+                format_args!("marked {} blocks used and {} free", marked, freed),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_inodes(&self, inodestart: u32) -> Check<()> {
+        for (i, _) in self.dirty.iter().enumerate().filter(|&(_, &d)| d) {
+            let bno = inodestart + i as u32;
+            let mut buf = read(self.disk, bno)?;
+            for (idx, din) in self.inodes.iter().skip(i * IPB).take(IPB).enumerate() {
+                din.to_block(&mut buf, idx);
+            }
+            write(self.disk, bno, &buf)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+// Fast user-space locking. A thread sleeps on a word of user memory
+// only while the word still holds the value it expects, and whoever
+// changes the word wakes the sleepers. Threads of a process meet on the
+// word's address, and processes sharing a shmattach segment on its
+// physical address (see Key).
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use alloc::{sync::Arc, vec::Vec};
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::{
+    error::{Error::*, Result},
+    proc::{self, Cpus},
+    riscv::{PGSIZE, registers::scause::Exception},
+    spinlock::Mutex,
+    trap::TICKS,
+    vm::{Addr, UVAddr, VAddr},
+};
+
+// Where waiters on a word meet. A shmattach segment is the same frames
+// in every process attached to it, wherever each maps it, so its words
+// are keyed by physical address. Any other word belongs to one address
+// space, and is keyed by its address there: a fork makes its page
+// copy-on-write again, and the next store moves the word to another
+// frame, but not to another address.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Shared(usize),         // physical address
+    Private(usize, usize), // address space, virtual address
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+struct Waiter {
+    key: Key,
+    id: usize,   // the waiter's channel when it has no timeout
+    timed: bool, // sleeps on the clock instead
+}
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+static WAITERS: Mutex<Vec<Waiter>> = Mutex::new(Vec::new(), "futex");
+
+// The physical address of the word at addr as this address space maps
+// it now.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn locate(addr: usize) -> Result<usize> {
+    let p = Cpus::myproc().unwrap();
+    let mut va: UVAddr = addr.into();
+    va.rounddown();
+    let aspace = p.data().aspace.as_ref().unwrap();
+    let mut inner = aspace.inner.lock();
+    let pa = inner.uvm.as_mut().unwrap().walkaddr(va)?;
+    Ok(pa.into_usize() + addr % PGSIZE)
+}
+
+// The key of the word at addr, faulting its page in first.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn key(addr: usize) -> Result<Key> {
+    if addr % 4 != 0 {
+        return Err(InvalidArgument);
+    }
+    if addr >= UVAddr::MAXVA {
+        return Err(BadVirtAddr);
+    }
+    let pa = match locate(addr) {
+        Ok(pa) => pa,
+        Err(_) => {
+            proc::handle_user_page_fault(addr, Exception::LoadPageFault)?;
+            locate(addr)?
+        }
+    };
+    let p = Cpus::myproc().unwrap();
+    let data = p.data();
+    let va: UVAddr = addr.into();
+    if data
+        .vmas
+        .iter()
+        .any(|v| v.shm.is_some() && v.contains_pg(va))
+    {
+        Ok(Key::Shared(pa))
+    } else {
+        let aspace = data.aspace.as_ref().unwrap();
+        Ok(Key::Private(Arc::as_ptr(aspace) as usize, addr))
+    }
+}
+
+// Sleep until woken, as long as the word at addr holds val. A timeout
+// of zero ticks waits for ever.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn wait(addr: usize, val: u32, timeout: usize) -> Result<()> {
+    let key = key(addr)?;
+    // Any address on this kernel stack is ours alone while we wait.
+    let token = 0u8;
+    let id = &token as *const _ as usize;
+    let timed = timeout != 0;
+    let start = *TICKS.lock();
+    {
+        // Wakers take this lock too, so a change to the word made
+        // before a wake is seen here or the wake finds us queued. The
+        // word is looked up again, in case a copy-on-write moved it.
+        let mut waiters = WAITERS.lock();
+        let pa = locate(addr)?;
+        if unsafe { core::ptr::read_volatile(pa as *const u32) } != val {
+            return Err(WouldBlock);
+        }
+        waiters.push(Waiter { key, id, timed });
+    }
+
+    loop {
+        if timed {
+            let ticks = TICKS.lock();
+            if let Some(res) = settle(&mut WAITERS.lock(), id, *ticks - start >= timeout) {
+                return res;
+            }
+            drop(proc::sleep(&(*ticks) as *const _ as usize, ticks));
+        } else {
+            let mut waiters = WAITERS.lock();
+            if let Some(res) = settle(&mut waiters, id, false) {
+                return res;
+            }
+            drop(proc::sleep(id, waiters));
+        }
+    }
+}
+
+// Whether the wait is over: woken, or leaving the queue on a signal or
+// timeout. None to keep sleeping.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn settle(waiters: &mut Vec<Waiter>, id: usize, expired: bool) -> Option<Result<()>> {
+    let Some(i) = waiters.iter().position(|w| w.id == id) else {
+        return Some(Ok(()));
+    };
+    let err = if proc::interrupted() {
+        Interrupted
+    } else if expired {
+        TimedOut
+    } else {
+        return None;
+    };
+    waiters.remove(i);
+    Some(Err(err))
+}
+
+// Wake up to n waiters on the word at addr, and return how many woke.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn wake(addr: usize, n: usize) -> Result<usize> {
+    let key = key(addr)?;
+    let mut woken = Vec::new();
+    {
+        let mut waiters = WAITERS.lock();
+        let mut i = 0;
+        while i < waiters.len() && woken.len() < n {
+            if waiters[i].key == key {
+                woken.push(waiters.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+    }
+    for w in woken.iter().filter(|w| !w.timed) {
+        proc::wakeup(w.id);
+    }
+    if woken.iter().any(|w| w.timed) {
+        let ticks = TICKS.lock();
+        proc::wakeup(&(*ticks) as *const _ as usize);
+    }
+    Ok(woken.len())
+}
@@ -1,10 +1,7 @@
 use core::arch::asm;
 use core::ptr;
 
-use crate::memlayout::{
-    IMSIC_S, IMSIC_STRIDE, UART0_IRQ, VIRTIO0_IRQ, VIRTIO1_IRQ, VIRTIO2_IRQ, VIRTIO3_IRQ,
-    VIRTIO4_IRQ,
-};
+use crate::memlayout::{IMSIC_S, IMSIC_STRIDE, UART0_IRQ, VIRTIO_SLOTS};
 
 // IMSIC CSRs
 const SISELECT: usize = 0x150;
@@ -64,11 +61,9 @@ pub fn init_hart() {
     sireg_write(0);
 
     enable_msg(UART0_IRQ as usize);
-    enable_msg(VIRTIO0_IRQ as usize);
-    enable_msg(VIRTIO1_IRQ as usize);
-    enable_msg(VIRTIO2_IRQ as usize);
-    enable_msg(VIRTIO3_IRQ as usize);
-    enable_msg(VIRTIO4_IRQ as usize);
+    for (_, irq) in VIRTIO_SLOTS {
+        enable_msg(irq as usize);
+    }
 }
 
 // Pop the top pending external interrupt message for S-mode.
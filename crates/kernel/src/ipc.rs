@@ -129,22 +129,25 @@ pub fn shm_attach(id: usize, prot: usize) -> Result<usize> {
     let len = seg.size();
     let start = data.alloc_mmap_va(sz, len)?;
 
-    let aspace = data.aspace.as_ref().unwrap();
-    let mut as_inner = aspace.inner.lock();
-    let uvm = as_inner.uvm.as_mut().unwrap();
-
-    for (idx, &pa) in seg.pages.iter().enumerate() {
-        let va = start + idx * PGSIZE;
-        if let Err(err) = uvm.mappages(va, pa.into(), PGSIZE, perm) {
-            if idx > 0 {
-                uvm.unmap(start, idx, true);
+    {
+        let aspace = data.aspace.as_ref().unwrap();
+        let mut as_inner = aspace.inner.lock();
+        let uvm = as_inner.uvm.as_mut().unwrap();
+
+        for (idx, &pa) in seg.pages.iter().enumerate() {
+            let va = start + idx * PGSIZE;
+            if let Err(err) = uvm.mappages(va, pa.into(), PGSIZE, perm) {
+                if idx > 0 {
+                    uvm.unmap(start, idx, true);
+                }
+                data.mmap_base = prev_base;
+                return Err(err);
             }
-            data.mmap_base = prev_base;
-            return Err(err);
+            kalloc::page_ref_inc(pa);
         }
-        kalloc::page_ref_inc(pa);
     }
 
+    let _exported = p.exported.lock();
     data.vmas.push(Vma {
         start,
         len,
@@ -87,3 +87,7 @@ pub fn page_ref_get(pa: usize) -> u16 {
 pub fn free_pages() -> usize {
     KMEM.0.lock().free_bytes() / PGSIZE
 }
+
+pub fn total_pages() -> usize {
+    KMEM.0.lock().total_bytes() / PGSIZE
+}
@@ -16,7 +16,6 @@
 )]
 #![allow(clippy::missing_safety_doc)]
 
-#[cfg(all(target_os = "none", feature = "kernel"))]
 extern crate alloc;
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -40,6 +39,8 @@ pub mod poll;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod proc;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+pub mod procfs;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod semaphore;
 pub mod signal;
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -60,7 +61,10 @@ pub mod aplic;
 pub mod bio;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod buddy;
+pub mod crc32;
 pub mod defs;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub mod devfs;
 pub mod dfs;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod elf;
@@ -73,6 +77,8 @@ pub mod file;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod framebuffer;
 pub mod fs;
+pub mod fsck;
+pub mod futex;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod imsic;
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -87,9 +93,14 @@ pub mod mmap;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod pipe;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+pub mod random;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod riscv;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+pub mod rtc;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod runq;
+pub mod sha256;
 pub mod stat;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod swtch;
@@ -100,11 +111,17 @@ pub mod syscall;
 pub mod task;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod test;
+pub mod time;
+pub mod tls;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub mod tmpfs;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod trampoline;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod trap;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+pub mod vfs;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod virtio_disk;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 pub mod virtio_gpu;
@@ -144,6 +161,7 @@ pub extern "C" fn test_main_entry() -> ! {
         println!("Running kernel tests...");
         println!("");
         null::init();
+        random::init();
         kalloc::init();
         vm::kinit();
         vm::kinithart();
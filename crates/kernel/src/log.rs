@@ -3,12 +3,15 @@ use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use crate::{
     bio::{BCACHE, BufGuard},
-    fs::{BSIZE, SB},
-    param::{LOGSIZE, MAXOPBLOCKS, ROOTDEV},
+    crc32,
+    defs::AsBytes,
+    fs::{self, BSIZE},
+    param::{COMMIT_TICKS, LOGSIZE, MAXOPBLOCKS, NDISK, ROOTDEV},
     proc,
-    spinlock::Mutex,
-    sync::LazyLock,
+    runq::runq_is_empty,
+    spinlock::{Mutex, MutexGuard},
     test::{QemuExitCode, exit_qemu},
+    trap::TICKS,
 };
 
 // Simple logging that allows concurrent FS system calls.
@@ -25,22 +28,38 @@ use crate::{
 // But if it thinks the log is close to running out, it
 // sleeps until the last outstanding end_op() commits.
 //
+// Commits are grouped: the last end_op() leaves the group open for
+// more system calls to join, and commits only once the group fills
+// half the log, is COMMIT_TICKS old, someone waits for space or
+// nothing else is runnable. A timer interrupt commits a group that
+// went stale, and fsync commits at once.
+//
 // The log is a physical re-do log containing disk blocks.
 // The on-disk log format:
-//   header block, containing block #s for block A, B, C, ...
+//   header block, containing sequence number, block #s for
+//     block A, B, C, ..., the CRC32 of each and of the header
 //   block A
 //   block B
 //   block C
 //   ...
-// Log appends are synchronous.
+// Log appends are synchronous. Recovery installs a logged commit only
+// if the header and every block match their checksums, so a header or
+// block torn by a crash is discarded instead of replayed. The log size
+// comes from the superblock.
+//
+// Every disk has a log of its own, set up when its file system is
+// mounted; callers name the disk of the transaction.
 
-pub static LOG: LazyLock<Mutex<Log>> = LazyLock::new(|| Mutex::new(Log::new(ROOTDEV), "log"));
+pub static LOG: Logs = Logs([const { Mutex::new(Log::new(), "log") }; NDISK]);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(usize)]
 pub enum LogCrashStage {
     None = 0,
-    AfterHeadWrite = 1,
+    AfterHeadWrite = 1, // committed, nothing installed: replayed
+    AfterLogWrite = 2,  // blocks logged, header not written: lost
+    TornHeadWrite = 3,  // header written with a bad checksum: discarded
+    AfterInstall = 4,   // installed, header not cleared: replayed again
 }
 
 impl LogCrashStage {
@@ -48,6 +67,9 @@ impl LogCrashStage {
         match stage {
             0 => Some(Self::None),
             1 => Some(Self::AfterHeadWrite),
+            2 => Some(Self::AfterLogWrite),
+            3 => Some(Self::TornHeadWrite),
+            4 => Some(Self::AfterInstall),
             _ => None,
         }
     }
@@ -64,46 +86,112 @@ pub fn take_recovered() -> bool {
     LOG_RECOVERED.swap(false, Ordering::SeqCst)
 }
 
+fn armed(stage: LogCrashStage) -> bool {
+    LOG_CRASH_STAGE
+        .compare_exchange(
+            stage as usize,
+            LogCrashStage::None as usize,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .is_ok()
+}
+
+fn crash() -> ! {
+    exit_qemu(QemuExitCode::Failed);
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
 fn crash_if_armed(stage: LogCrashStage) {
-    if LOG_CRASH_STAGE.swap(LogCrashStage::None as usize, Ordering::SeqCst) == stage as usize {
-        exit_qemu(QemuExitCode::Failed);
-        #[allow(clippy::empty_loop)]
-        loop {}
+    if armed(stage) {
+        crash();
     }
 }
 
+// Most blocks one header block can describe.
+const LOGMAX: usize = (BSIZE / 4 - 3) / 2;
+const _: () = assert!(LOGSIZE <= LOGMAX, "LOGSIZE too big for the log header");
+
 // Contents of the header block, used for both the on-disk header block
 // and to keep track in memory of logged block# before commit.
 #[repr(C)]
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 struct LogHeader {
-    n: u32,
-    block: [u32; LOGSIZE],
+    seq: u32,             // sequence number of the last commit
+    n: u32,               // blocks in the commit, 0 once installed
+    crc: u32,             // CRC32 of the header with this field 0
+    block: [u32; LOGMAX], // home block numbers
+    sum: [u32; LOGMAX],   // CRC32 of each logged block
+}
+
+unsafe impl AsBytes for LogHeader {}
+
+impl LogHeader {
+    fn checksum(&self) -> u32 {
+        let mut lh = *self;
+        lh.crc = 0;
+        crc32::crc32(lh.as_bytes())
+    }
 }
 
+pub struct Stats {
+    pub commits: AtomicUsize,      // commits since boot
+    pub transactions: AtomicUsize, // system calls those commits held
+}
+
+static STATS: Stats = Stats {
+    commits: AtomicUsize::new(0),
+    transactions: AtomicUsize::new(0),
+};
+
 pub struct Log {
     start: u32,
     size: u32,
     dev: u32,
     outstanding: u32,
     committing: bool,
+    waiting: bool, // a begin_op() sleeps for log space
+    opened: usize, // tick the open group logged its first block
+    ops: usize,    // system calls ended in the open group
     lh: LogHeader,
 }
 
 impl Log {
-    fn new(dev: u32) -> Self {
-        let sb = SB.get().unwrap();
-        let mut log = Self {
-            start: sb.logstart,
-            size: sb.nlog,
-            dev,
+    const fn new() -> Self {
+        Self {
+            start: 0,
+            size: 0,
+            dev: 0,
             outstanding: 0,
             committing: false,
+            waiting: false,
+            opened: 0,
+            ops: 0,
             lh: LogHeader {
+                seq: 0,
                 n: 0,
-                block: [0; LOGSIZE],
+                crc: 0,
+                block: [0; LOGMAX],
+                sum: [0; LOGMAX],
             },
+        }
+    }
+
+    // The log of the file system on disk dev, recovered.
+    fn open(dev: u32) -> Self {
+        let sb = fs::sb(dev);
+        let mut log = Self {
+            start: sb.logstart,
+            // one block holds the header
+            size: sb.nlog.saturating_sub(1).min(LOGMAX as u32),
+            dev,
+            ..Self::new()
         };
+        assert!(
+            log.size as usize >= MAXOPBLOCKS,
+            "initlog: log smaller than one op"
+        );
         log.recover();
         log
     }
@@ -118,19 +206,44 @@ impl Log {
     // Write in-memory log header to disk.
     // This is the true point at which the
     // current transaction commits.
-    fn write_head(&self) {
+    fn write_head(&mut self) {
+        self.lh.crc = self.lh.checksum();
+        if armed(LogCrashStage::TornHeadWrite) {
+            self.lh.crc = !self.lh.crc;
+            self.put_head();
+            crash();
+        }
+        self.put_head();
+    }
+
+    fn put_head(&self) {
         let mut buf = BCACHE.read(self.dev, self.start);
         let hb = buf.align_to_mut::<LogHeader>().get_mut(0).unwrap();
         *hb = self.lh;
         buf.write();
     }
 
+    // Does the header on disk describe a complete commit?
+    fn valid(&self) -> bool {
+        if self.lh.n > self.size || self.lh.crc != self.lh.checksum() {
+            return false;
+        }
+        (0..self.lh.n).all(|tail| {
+            let lbuf = BCACHE.read(self.dev, self.start + tail + 1);
+            crc32::crc32(&lbuf[..]) == self.lh.sum[tail as usize]
+        })
+    }
+
     fn recover(&mut self) {
         self.read_head();
         if self.lh.n > 0 {
-            LOG_RECOVERED.store(true, Ordering::SeqCst);
+            if self.valid() {
+                LOG_RECOVERED.store(true, Ordering::SeqCst);
+                self.install_trans(true); // if committed, copy from log to disk
+            } else {
+                println!("log: discarding torn commit {}", self.lh.seq);
+            }
         }
-        self.install_trans(true); // if committed, copy from log to disk
         self.lh.n = 0;
         self.write_head(); // clear the log
     }
@@ -154,6 +267,7 @@ impl Log {
             let mut to = BCACHE.read(self.dev, self.start + tail + 1); // log block
             let from = BCACHE.read(self.dev, self.lh.block[tail as usize]); // cache block
             to.copy_from_slice(from.deref().deref());
+            self.lh.sum[tail as usize] = crc32::crc32(&to[..]);
             to.write(); // write the log
         }
     }
@@ -161,33 +275,121 @@ impl Log {
     fn commit(&mut self) {
         if self.lh.n > 0 {
             self.write_log(); // Write modified blocks from cache to log
+            crash_if_armed(LogCrashStage::AfterLogWrite);
+            self.lh.seq = self.lh.seq.wrapping_add(1);
             self.write_head(); // Write header to disk -- the real commit
             crash_if_armed(LogCrashStage::AfterHeadWrite);
             self.install_trans(false); // Now install writes to home locations
+            crash_if_armed(LogCrashStage::AfterInstall);
             self.lh.n = 0;
             self.write_head();
+            STATS.commits.fetch_add(1, Ordering::Relaxed);
+            STATS.transactions.fetch_add(self.ops, Ordering::Relaxed);
         }
+        self.ops = 0;
+    }
+
+    // Should the open group commit now that no system call is in it?
+    fn due(&self) -> bool {
+        self.lh.n > 0
+            && (self.waiting
+                || self.lh.n * 2 >= self.size
+                || *TICKS.lock() - self.opened >= COMMIT_TICKS
+                || runq_is_empty())
     }
 }
 
-impl Mutex<Log> {
-    pub fn init(&self) {
-        // SyncLazy initialization
+pub struct Logs([Mutex<Log>; NDISK]);
+
+impl Logs {
+    fn get(&self, dev: u32) -> &Mutex<Log> {
+        &self.0[(dev - ROOTDEV) as usize]
+    }
+
+    // Set up the log of disk dev, replaying what it holds. The disk
+    // is not mounted, so nothing else uses its log.
+    pub fn init(&self, dev: u32) {
         assert!(
-            core::mem::size_of::<LogHeader>() < BSIZE,
+            core::mem::size_of::<LogHeader>() <= BSIZE,
             "initlog: too big log header"
         );
+        let log = Log::open(dev);
+        *self.get(dev).lock() = log;
+    }
+
+    pub fn begin_op(&self, dev: u32) {
+        self.get(dev).begin_op();
+    }
+
+    pub fn end_op(&self, dev: u32) {
+        self.get(dev).end_op();
+    }
+
+    pub fn sync(&self, dev: u32) {
+        self.get(dev).sync();
+    }
+
+    pub fn write(&self, b: BufGuard) {
+        self.get(b.dev()).write(b);
+    }
+
+    // Called on timer interrupts: commit groups nobody ended in time.
+    pub fn commit_stale(&self) {
+        for log in &self.0 {
+            log.commit_stale();
+        }
+    }
+
+    // Usable log blocks of disk dev and the sequence number of its
+    // last commit.
+    pub fn size(&self, dev: u32) -> usize {
+        self.get(dev).lock().size as usize
+    }
+
+    pub fn seq(&self, dev: u32) -> u32 {
+        self.get(dev).lock().lh.seq
+    }
+
+    pub fn stats(&self) -> &'static Stats {
+        &STATS
+    }
+}
+
+impl Mutex<Log> {
+    // Commit the open group. The caller has checked that no system
+    // call is in it; commit runs without the lock, since not allowed
+    // to sleep with locks.
+    fn commit_group<'a>(&'a self, mut guard: MutexGuard<'a, Log>) -> MutexGuard<'a, Log> {
+        guard.committing = true;
+        let log = guard.deref_mut() as *mut Log;
+        drop(guard);
+        unsafe {
+            log.as_mut().unwrap().commit();
+        }
+        let mut guard = self.lock();
+        guard.committing = false;
+        guard.waiting = false;
+        proc::wakeup(guard.deref() as *const _ as usize);
+        guard
     }
 
     // called at the start of each FS system call.
-    pub fn begin_op(&self) {
+    fn begin_op(&self) {
         let mut guard = self.lock();
         loop {
-            if guard.committing
-                || (guard.lh.n as usize + (guard.outstanding + 1) as usize * MAXOPBLOCKS) > LOGSIZE
-            // this op might exhaust log space; wait for commit.
-            {
+            if guard.committing {
                 guard = proc::sleep(guard.deref() as *const _ as usize, guard);
+            } else if guard.lh.n as usize + (guard.outstanding + 1) as usize * MAXOPBLOCKS
+                > guard.size as usize
+            {
+                // this op might exhaust log space; wait for commit.
+                if guard.outstanding == 0 {
+                    // nobody is left to end the open group
+                    guard = self.commit_group(guard);
+                } else {
+                    guard.waiting = true;
+                    guard = proc::sleep(guard.deref() as *const _ as usize, guard);
+                }
             } else {
                 guard.outstanding += 1;
                 break;
@@ -196,41 +398,35 @@ impl Mutex<Log> {
     }
 
     // called at the end of each FS system call.
-    // commits if this was the last outstanding operation.
-    pub fn end_op(&self) {
-        let mut log: Option<*mut Log> = None;
-
-        {
-            let mut guard = self.lock();
-            guard.outstanding -= 1;
-            if guard.committing {
-                panic!("log.committing");
-            }
-            if guard.outstanding == 0 {
-                log.replace(guard.deref_mut() as *mut _);
-                guard.committing = true;
+    // commits if this was the last outstanding operation
+    // and the group is due.
+    fn end_op(&self) {
+        let mut guard = self.lock();
+        guard.outstanding -= 1;
+        if guard.lh.n > 0 {
+            guard.ops += 1;
+        }
+        if guard.committing {
+            panic!("log.committing");
+        }
+        if guard.outstanding == 0 {
+            if guard.due() {
+                self.commit_group(guard);
             } else {
-                // begin_op() may be waiting for log space,
-                // and decrementing log.outstanding has decreased
-                // the amount of reserved space.
+                // sync() may be waiting for running system calls.
                 proc::wakeup(guard.deref() as *const _ as usize);
             }
-        }
-
-        if let Some(log) = log {
-            // call commit w/o holding locks, since not allowed
-            // to sleep with locks.
-            unsafe {
-                log.as_mut().unwrap().commit();
-            }
-            let mut guard = self.lock();
-            guard.committing = false;
+        } else {
+            // begin_op() may be waiting for log space,
+            // and decrementing log.outstanding has decreased
+            // the amount of reserved space.
             proc::wakeup(guard.deref() as *const _ as usize);
         }
     }
 
-    pub fn sync(&self) {
-        let mut log_ptr: Option<*mut Log> = None;
+    // Commit whatever the open group holds, waiting out running
+    // system calls first.
+    fn sync(&self) {
         let mut guard = self.lock();
         loop {
             if guard.committing || guard.outstanding > 0 {
@@ -239,22 +435,20 @@ impl Mutex<Log> {
                 break;
             }
         }
-        if guard.lh.n == 0 {
-            return;
+        if guard.lh.n > 0 {
+            self.commit_group(guard);
         }
-        log_ptr.replace(guard.deref_mut() as *mut _);
-        guard.committing = true;
-        drop(guard);
+    }
 
-        if let Some(log) = log_ptr {
-            unsafe {
-                log.as_mut().unwrap().commit();
-            }
+    fn commit_stale(&self) {
+        let guard = self.lock();
+        if !guard.committing
+            && guard.outstanding == 0
+            && guard.lh.n > 0
+            && *TICKS.lock() - guard.opened >= COMMIT_TICKS
+        {
+            self.commit_group(guard);
         }
-
-        let mut guard = self.lock();
-        guard.committing = false;
-        proc::wakeup(guard.deref() as *const _ as usize);
     }
 
     // Caller has modified b->data and is done with the buffer.
@@ -265,9 +459,9 @@ impl Mutex<Log> {
     // bp = BCACHE.read();
     // modify bp.data[]
     // LOG.write(bp)
-    pub fn write(&self, b: BufGuard) {
+    fn write(&self, b: BufGuard) {
         let mut guard = self.lock();
-        if guard.lh.n as usize >= LOGSIZE || guard.lh.n >= guard.size - 1 {
+        if guard.lh.n >= guard.size {
             panic!("too big a transaction");
         }
         if guard.outstanding < 1 {
@@ -281,6 +475,9 @@ impl Mutex<Log> {
             }
         }
         let n = guard.lh.n as usize;
+        if n == 0 {
+            guard.opened = *TICKS.lock();
+        }
         guard.lh.block[n] = b.blockno();
         b.pin();
         guard.lh.n += 1;
@@ -10,7 +10,7 @@ use kernel::{
     param::NCPU,
     println,
     proc::{self, Cpus, scheduler, user_init},
-    task, trap, uart, virtio_disk, virtio_input, virtio_net, vm,
+    random, task, trap, uart, virtio_disk, virtio_input, virtio_net, vm,
 };
 
 static STARTED: AtomicBool = AtomicBool::new(false);
@@ -27,6 +27,7 @@ extern "C" fn main() -> ! {
         console::init();
         println!("kernel is booting");
         null::init();
+        random::init();
         kalloc::init();
         vm::kinit();
         vm::kinithart();
@@ -44,6 +45,7 @@ extern "C" fn main() -> ! {
         }
         uart::spawn_tasks();
         virtio_disk::spawn_tasks();
+        bio::spawn_tasks();
         virtio_net::spawn_tasks();
         user_init(initcode);
         STARTED.store(true, Ordering::SeqCst);
@@ -6,11 +6,13 @@ use crate::vm::{KVAddr, VAddr};
 // based on qemu's hw/riscv/virt.c:
 //
 // 00001000 -- boot ROM, provided by qemu
+// 00101000 -- goldfish RTC
 // 02000000 -- CLINT
 // 0C000000 -- APLIC
 // 10000000 -- uart0
 // 10001000 -- virtio disk
 // 10002000 -- virtio net
+// 10003000 -- virtio gpu, keyboard, mouse and spare slots up to 10008000
 // 80000000 -- boot ROM jumps here in machine mode
 //             -kernel loads the kernel here
 // unused RAM after 80000000.
@@ -23,6 +25,9 @@ use crate::vm::{KVAddr, VAddr};
 // SiFive Test device, for QEMU exit during tests
 pub const SIFIVE_TEST: usize = 0x10_0000;
 
+// goldfish real-time clock, nanoseconds since the epoch.
+pub const GOLDFISH_RTC: usize = 0x10_1000;
+
 // qemu puts UART registers here in physical memory.
 pub const UART0: usize = 0x1000_0000;
 pub const UART0_IRQ: u32 = 10;
@@ -31,7 +36,7 @@ pub const UART0_HART: usize = 0;
 // virtio mmio interface
 pub const VIRTIO0: usize = 0x1000_1000; // disk
 pub const VIRTIO0_IRQ: u32 = 1;
-pub const VIRTIO0_HART: usize = 0;
+pub const VIRTIO0_HART: usize = 0; // for every disk found
 pub const VIRTIO1: usize = 0x1000_2000; // net
 pub const VIRTIO1_IRQ: u32 = 2;
 pub const VIRTIO1_HART: usize = 0;
@@ -44,6 +49,24 @@ pub const VIRTIO3_HART: usize = 0;
 pub const VIRTIO4: usize = 0x1000_5000; // mouse
 pub const VIRTIO4_IRQ: u32 = 5;
 pub const VIRTIO4_HART: usize = 0;
+pub const VIRTIO5: usize = 0x1000_6000; // spare, probed for disks
+pub const VIRTIO5_IRQ: u32 = 6;
+pub const VIRTIO6: usize = 0x1000_7000;
+pub const VIRTIO6_IRQ: u32 = 7;
+pub const VIRTIO7: usize = 0x1000_8000;
+pub const VIRTIO7_IRQ: u32 = 8;
+
+// Every virtio mmio slot of qemu's virt machine, with its interrupt.
+pub const VIRTIO_SLOTS: [(usize, u32); 8] = [
+    (VIRTIO0, VIRTIO0_IRQ),
+    (VIRTIO1, VIRTIO1_IRQ),
+    (VIRTIO2, VIRTIO2_IRQ),
+    (VIRTIO3, VIRTIO3_IRQ),
+    (VIRTIO4, VIRTIO4_IRQ),
+    (VIRTIO5, VIRTIO5_IRQ),
+    (VIRTIO6, VIRTIO6_IRQ),
+    (VIRTIO7, VIRTIO7_IRQ),
+];
 
 // core local interrupter (CLINT), which contains the timer
 pub const CLINT: usize = 0x2000000;
@@ -51,6 +74,7 @@ pub const fn clint_mtimecmp(hartid: usize) -> usize {
     CLINT + 0x4000 + 8 * hartid
 }
 pub const CLINT_MTIME: usize = CLINT + 0xBFF8; // Cycles since boot.
+pub const TIMEBASE_FREQ: u64 = 10_000_000; // mtime cycles per second in qemu
 
 // qemu puts platform-level interrupt controller (PLIC) here.
 pub const PLIC: usize = 0x0C00_0000;
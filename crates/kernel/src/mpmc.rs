@@ -3,7 +3,8 @@ use core::fmt::Debug;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::condvar::Condvar;
-use crate::error::{Error::NotConnected, Error::WouldBlock, Result};
+use crate::error::{Error::Interrupted, Error::NotConnected, Error::WouldBlock, Result};
+use crate::proc;
 use crate::semaphore::Semaphore;
 use crate::spinlock::Mutex;
 
@@ -89,11 +90,13 @@ impl<T: Debug> Receiver<T> {
                 self.sem.post()?;
                 break Ok(data);
             }
-            if self.scnt.load(Ordering::Relaxed) > 0 {
-                buf = self.cond.wait(buf);
-            } else {
+            if self.scnt.load(Ordering::Relaxed) == 0 {
                 break Err(NotConnected);
             }
+            if proc::interrupted() {
+                break Err(Interrupted);
+            }
+            buf = self.cond.wait(buf);
         }
     }
 
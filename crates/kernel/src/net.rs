@@ -765,9 +765,7 @@ impl TcpSocket {
             if self.state() == TcpState::Closed {
                 return Err(NotConnected);
             }
-            if let Some(p) = proc::Cpus::myproc()
-                && p.inner.lock().killed
-            {
+            if proc::interrupted() {
                 return Err(Interrupted);
             }
             wait_tcp(&mut seq);
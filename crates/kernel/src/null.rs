@@ -1,10 +1,21 @@
-use crate::error::Result;
-use crate::file::{DEVSW, Device, Major};
+use crate::error::{Error::StorageFull, Result};
+use crate::file::{DEVSW, Device};
+use crate::proc::either_copyout;
 use crate::vm::VirtAddr;
 
 pub static NULL: Null = Null;
+pub static ZERO: Zero = Zero;
+pub static FULL: Full = Full;
+
+// Discards writes, reads end of file.
 pub struct Null;
 
+// Discards writes, reads zeros.
+pub struct Zero;
+
+// Reads zeros, every write fails with no space.
+pub struct Full;
+
 impl Device for Null {
     fn read(&self, _dst: VirtAddr, _n: usize, _offset: usize) -> Result<usize> {
         Ok(0)
@@ -13,12 +24,42 @@ impl Device for Null {
     fn write(&self, _src: VirtAddr, n: usize, _offset: usize) -> Result<usize> {
         Ok(n)
     }
+}
+
+impl Device for Zero {
+    fn read(&self, dst: VirtAddr, n: usize, _offset: usize) -> Result<usize> {
+        zero_fill(dst, n)
+    }
+
+    fn write(&self, _src: VirtAddr, n: usize, _offset: usize) -> Result<usize> {
+        Ok(n)
+    }
+}
+
+impl Device for Full {
+    fn read(&self, dst: VirtAddr, n: usize, _offset: usize) -> Result<usize> {
+        zero_fill(dst, n)
+    }
+
+    fn write(&self, _src: VirtAddr, _n: usize, _offset: usize) -> Result<usize> {
+        Err(StorageFull)
+    }
+}
 
-    fn major(&self) -> crate::file::Major {
-        crate::file::Major::Null
+fn zero_fill(mut dst: VirtAddr, n: usize) -> Result<usize> {
+    let zeros = [0u8; 512];
+    let mut done = 0;
+    while done < n {
+        let m = (n - done).min(zeros.len());
+        either_copyout(dst, &zeros[..m])?;
+        done += m;
+        dst += m;
     }
+    Ok(done)
 }
 
 pub fn init() {
-    DEVSW.set(Major::Null, &NULL).unwrap();
+    DEVSW.register("null", 0o666, &NULL).unwrap();
+    DEVSW.register("zero", 0o666, &ZERO).unwrap();
+    DEVSW.register("full", 0o666, &FULL).unwrap();
 }
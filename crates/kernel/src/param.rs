@@ -3,13 +3,21 @@ pub const NPROC: usize = 16; // maximum number of processes
 pub const NOFILE: usize = 16; // open files per process
 pub const NFILE: usize = 100; // open files per system
 pub const NINODE: usize = 50; // maximum number of active i-nodes
-pub const NDEV: usize = 10; // maximum major device number
+pub const NDEV: usize = 16; // maximum major device number
 pub const ROOTDEV: u32 = 1; // device number of file system root disk
+pub const NDISK: usize = 4; // maximum number of disks, numbered from ROOTDEV
 pub const MAXARG: usize = 32; // max exec arguments
 pub const MAXOPBLOCKS: usize = 10; // max # of blocks any FS op writes
-pub const LOGSIZE: usize = MAXOPBLOCKS * 3; // max data blocks in on-disk log
-pub const NBUF: usize = MAXOPBLOCKS * 3; // size of disk block cache
+pub const LOGSIZE: usize = MAXOPBLOCKS * 12; // data blocks mkfs reserves for the log
+pub const COMMIT_TICKS: usize = 2; // longest a log group waits for more transactions
+pub const NBUF: usize = LOGSIZE + MAXOPBLOCKS * 3; // minimum size of disk block cache
+pub const MAXBUF: usize = 4096; // maximum size of disk block cache
+pub const BCACHE_SHARE: usize = 16; // block cache gets 1/16 of free memory at boot
+pub const READAHEAD: usize = 8; // blocks read ahead of a sequential read
+pub const WRITEBACK_TICKS: usize = 30; // ticks between writebacks of dirty blocks
 pub const FSSIZE: usize = 200000; // size of file system in blocks
-pub const MAXPATH: usize = 128; // maximum file path name
+pub const MAXPATH: usize = 512; // maximum file path name
 pub const NSHM: usize = 32; // max shared memory segments
 pub const NSEM: usize = 64; // max IPC semaphores
+pub const NGROUPS: usize = 16; // max supplementary groups per process
+pub const TMPFS_PAGES: usize = 4096; // default tmpfs size cap in pages
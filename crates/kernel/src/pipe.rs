@@ -44,9 +44,13 @@ impl Pipe {
         while i < n {
             let mut ch: u8 = 0;
             either_copyin(&mut ch, src)?;
-            let Ok(_) = tx.send(ch) else {
-                break;
-            };
+            if let Err(e) = tx.send(ch) {
+                if i > 0 {
+                    break;
+                }
+                // Nobody left to read it, or interrupted.
+                return Err(if tx.is_closed() { BrokenPipe } else { e });
+            }
             src += 1;
             i += 1;
         }
@@ -68,6 +72,7 @@ impl Pipe {
                 Err(WouldBlock) => {
                     return if i == 0 { Err(WouldBlock) } else { Ok(i) };
                 }
+                Err(_) if i == 0 && tx.is_closed() => return Err(BrokenPipe),
                 Err(_) => break,
             }
         }
@@ -79,8 +84,10 @@ impl Pipe {
 
         let mut i = 0;
         while i < n {
-            let Ok(ch) = rx.recv() else {
-                break;
+            let ch = match rx.recv() {
+                Ok(ch) => ch,
+                Err(Interrupted) if i == 0 => return Err(Interrupted),
+                Err(_) => break,
             };
             either_copyout(dst, &ch)?;
             dst += 1;
@@ -7,12 +7,13 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 use core::{cell::UnsafeCell, ops::Drop};
 
 use crate::bio::BCACHE;
+use crate::console;
 use crate::defs::AsBytes;
 use crate::elf::{self, ElfHdr, ProgHdr};
 use crate::error::{Error::*, Result};
 use crate::exec::flags2perm;
 use crate::file::File;
-use crate::fs::{self, Inode, Path};
+use crate::fs::{Inode, Path};
 use crate::ipc::ShmSegment;
 use crate::log::LOG;
 use crate::memlayout::{STACK_PAGE_NUM, TRAMPOLINE, kstack, trapframe_va, user_mem_top};
@@ -22,15 +23,19 @@ use crate::riscv::registers::scause::Exception;
 use crate::riscv::{pteflags::*, *};
 use crate::runq::{runq_is_empty, runq_pop, runq_push_cpu};
 use crate::signal::{
-    NSIG, SIG_DFL, SIG_IGN, SIGALRM, SIGCONT, SIGKILL, SigDefaultAction, WCONTINUED, WNOHANG,
-    WUNTRACED, default_action, sig_mask,
+    NSIG, SA_NODEFER, SA_RESETHAND, SA_RESTART, SA_SIGINFO, SIG_BLOCK, SIG_DFL, SIG_IGN,
+    SIG_SETMASK, SIG_UNBLOCK, SIGALRM, SIGCHLD, SIGCONT, SIGHUP, SIGKILL, SigAction,
+    SigDefaultAction, SigInfo, UNBLOCKABLE, WCONTINUED, WNOHANG, WUNTRACED, default_action,
+    sig_mask,
 };
 use crate::spinlock::{Mutex, MutexGuard};
+use crate::stat::mode;
 use crate::swtch::swtch;
 use crate::sync::{LazyLock, OnceLock};
 use crate::task::{ready_is_empty_cpu, run_ready_tasks_cpu};
 use crate::trampoline::trampoline;
 use crate::trap::{TICKS, usertrap_ret};
+use crate::vfs::{self, Vnode};
 use crate::vm::{Addr, KVAddr, KVM, PAddr, Page, PageAllocator, Stack, UVAddr, Uvm, VirtAddr};
 use crate::{array, println};
 
@@ -334,6 +339,10 @@ pub struct Proc {
     pub inner: Mutex<ProcInner>,
     // these are private to the process, so lock need not be held.
     pub data: UnsafeCell<ProcData>,
+    // held to write the parts of data that procfs shows, and by procfs
+    // to read them (see ProcData). Taken after inner and before the
+    // address space lock.
+    pub exported: Mutex<()>,
 }
 unsafe impl Sync for Proc {}
 
@@ -349,7 +358,9 @@ pub struct ProcInner {
     pub sid: usize,       // Session ID
     pub last_cpu: usize,  // Last CPU this process was run on
     pub sig_pending: u32,
-    pub sig_handlers: [usize; NSIG],
+    pub sig_blocked: u32, // held pending until unblocked
+    pub sig_actions: [SigAction; NSIG],
+    pub sig_info: [SigInfo; NSIG], // why each pending signal was sent
     pub sig_alarm_deadline: usize,
     pub sig_alarm_interval: usize,
     pub stop_sig: usize,
@@ -357,7 +368,36 @@ pub struct ProcInner {
     pub cont_pending: bool,
 }
 
-// These are private to the process, so lock need not be held.
+// User and group identity of a process. The effective ids are the
+// ones the file system checks; the saved ids let a set-user-id program
+// switch back and forth. The default is root.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cred {
+    pub uid: u32,  // Real user ID
+    pub euid: u32, // Effective user ID
+    pub suid: u32, // Saved user ID
+    pub gid: u32,  // Real group ID
+    pub egid: u32, // Effective group ID
+    pub sgid: u32, // Saved group ID
+    pub ngroups: usize,
+    pub groups: [u32; NGROUPS], // Supplementary group IDs
+}
+
+impl Cred {
+    pub fn is_root(&self) -> bool {
+        self.euid == 0
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.egid == gid || self.groups[..self.ngroups].contains(&gid)
+    }
+}
+
+// These are private to the process, so lock need not be held to read
+// them. aspace, name, cmdline, is_thread, ofile, cwd, cred, umask and
+// vmas are the exception: procfs reads them from other processes, so
+// they are written holding Proc::exported. A file or vnode replaced
+// under it is dropped after, since dropping one may sleep.
 #[derive(Debug)]
 pub struct ProcData {
     pub kstack: KVAddr,                    // Virtual address of kernel stack
@@ -367,12 +407,17 @@ pub struct ProcData {
     pub context: Context,                  // swtch() here to run process
     pub sig_trapframe: Trapframe,          // saved trapframe during signal
     pub sig_active: bool,                  // currently in signal handler
-    pub sig_restorer: usize,               // user-space restorer for signals
+    pub sig_saved_mask: u32,               // blocked mask sigreturn restores
+    pub sig_suspend_mask: Option<u32>,     // blocked mask before sigsuspend
+    pub restart_a0: Option<usize>,         // a0 to rerun an interrupted syscall with
     pub name: String,                      // Process name (debugging)
+    pub cmdline: String,                   // exec arguments, each ending in NUL
     pub is_thread: bool,                   // created by clone()
     pub ustack: usize,                     // clone()'s stack base
     pub ofile: [Option<File>; NOFILE],     // Open files
-    pub cwd: Option<Inode>,                // Current directory
+    pub cwd: Option<Vnode>,                // Current directory
+    pub cred: Cred,                        // User and group ids
+    pub umask: u32,                        // Bits cleared from new file modes
     pub mmap_base: usize,                  // top-down allocator, starts at user_mem_top(NPROC)
     pub vmas: Vec<Vma>,
 }
@@ -404,7 +449,7 @@ impl Vma {
         self.start + self.len_pg()
     }
 
-    fn contains_pg(&self, va: UVAddr) -> bool {
+    pub(crate) fn contains_pg(&self, va: UVAddr) -> bool {
         va >= self.start && va < self.end_pg()
     }
 
@@ -570,6 +615,7 @@ impl Procs {
                     // An empty user page table.
                     match p.uvmcreate() {
                         Ok(uvm) => {
+                            let _exported = p.exported.lock();
                             data.aspace.replace(Arc::new(AddrSpace::new(uvm, 0)));
                         }
                         Err(err) => {
@@ -605,6 +651,7 @@ impl Proc {
             idx,
             inner: Mutex::new(ProcInner::new(), "proc"),
             data: UnsafeCell::new(ProcData::new()),
+            exported: Mutex::new((), "exported"),
         }
     }
 
@@ -620,8 +667,22 @@ impl Proc {
         unsafe { &mut *(self.data.get()) }
     }
 
+    // Put f in open file slot fd, and hand back what was there for the
+    // caller to drop.
+    pub fn set_ofile(&self, fd: usize, f: Option<File>) -> Option<File> {
+        let _exported = self.exported.lock();
+        core::mem::replace(&mut self.data_mut().ofile[fd], f)
+    }
+
+    // Same for the current directory.
+    pub fn set_cwd(&self, cwd: Option<Vnode>) -> Option<Vnode> {
+        let _exported = self.exported.lock();
+        core::mem::replace(&mut self.data_mut().cwd, cwd)
+    }
+
     fn free(&self, mut guard: MutexGuard<'_, ProcInner>) {
         let data = self.data_mut();
+        let exported = self.exported.lock();
         let aspace = data.aspace.as_ref().map(Arc::clone);
         let mut writebacks = Vec::new();
 
@@ -648,7 +709,7 @@ impl Proc {
                 }
             }
             if let Some(mut uvm) = olduvm {
-                writebacks = data.munmap_all(&mut uvm);
+                writebacks = munmap_all(core::mem::take(&mut data.vmas), &mut uvm);
                 data.mmap_base = user_mem_top(NPROC);
                 uvm.proc_uvmfree(oldsz);
             }
@@ -659,24 +720,30 @@ impl Proc {
         data.mmap_base = user_mem_top(NPROC);
         data.sig_trapframe = Trapframe::default();
         data.sig_active = false;
-        data.sig_restorer = 0;
+        data.sig_saved_mask = 0;
+        data.sig_suspend_mask = None;
+        data.restart_a0 = None;
         guard.pid = PId(0);
         guard.pgid = 0;
         guard.sid = 0;
         data.name.clear();
+        data.cmdline.clear();
         data.is_thread = false;
         data.ustack = 0;
         guard.chan = 0;
         guard.killed = false;
         guard.xstate = 0;
         guard.sig_pending = 0;
-        guard.sig_handlers = [SIG_DFL; NSIG];
+        guard.sig_blocked = 0;
+        guard.sig_actions = [SigAction::default(); NSIG];
+        guard.sig_info = [SigInfo::default(); NSIG];
         guard.sig_alarm_deadline = 0;
         guard.sig_alarm_interval = 0;
         guard.stop_sig = 0;
         guard.stop_reported = false;
         guard.cont_pending = false;
         guard.state = ProcState::UNUSED;
+        drop(exported);
         drop(guard);
         for wb in writebacks {
             let _ = wb.flush();
@@ -835,7 +902,11 @@ pub fn user_init(initcode: &'static [u8]) {
     tf.epc = elf.e_entry; // user program counter
     tf.sp = UVAddr::from(sz).into_usize(); // user stack pointer
 
-    data.name.push_str("initcode");
+    {
+        let _exported = p.exported.lock();
+        data.name.push_str("initcode");
+        data.cmdline.push_str("initcode\0");
+    }
     make_runnable(p.idx, guard);
 }
 
@@ -851,7 +922,9 @@ impl ProcInner {
             sid: 0,
             last_cpu: 0,
             sig_pending: 0,
-            sig_handlers: [SIG_DFL; NSIG],
+            sig_blocked: 0,
+            sig_actions: [SigAction::new(SIG_DFL); NSIG],
+            sig_info: [SigInfo::new(0, 0); NSIG],
             sig_alarm_deadline: 0,
             sig_alarm_interval: 0,
             stop_sig: 0,
@@ -859,6 +932,11 @@ impl ProcInner {
             cont_pending: false,
         }
     }
+
+    // Pending signals that are not blocked.
+    pub fn deliverable(&self) -> u32 {
+        self.sig_pending & !self.sig_blocked
+    }
 }
 
 impl Default for ProcInner {
@@ -877,12 +955,17 @@ impl ProcData {
             context: Context::new(),
             sig_trapframe: Trapframe::default(),
             sig_active: false,
-            sig_restorer: 0,
+            sig_saved_mask: 0,
+            sig_suspend_mask: None,
+            restart_a0: None,
             name: String::new(),
+            cmdline: String::new(),
             is_thread: false,
             ustack: 0,
             ofile: array![None; NOFILE],
             cwd: Default::default(),
+            cred: Cred::default(),
+            umask: mode::UMASK,
             mmap_base: user_mem_top(NPROC),
             vmas: Vec::new(),
         }
@@ -911,15 +994,14 @@ impl ProcData {
         self.mmap_base = base;
         Ok(UVAddr::from(base))
     }
+}
 
-    pub(crate) fn munmap_all(&mut self, uvm: &mut Uvm) -> Vec<Writeback> {
-        let vmas = core::mem::take(&mut self.vmas);
-        let mut writebacks = Vec::new();
-        for v in vmas {
-            let _ = munmap_vma_range(uvm, &v, v.start, v.len_pg(), &mut writebacks);
-        }
-        writebacks
+pub(crate) fn munmap_all(vmas: Vec<Vma>, uvm: &mut Uvm) -> Vec<Writeback> {
+    let mut writebacks = Vec::new();
+    for v in vmas {
+        let _ = munmap_vma_range(uvm, &v, v.start, v.len_pg(), &mut writebacks);
     }
+    writebacks
 }
 
 // A fork child's very first scheduling by scheduler()
@@ -940,9 +1022,10 @@ pub unsafe extern "C" fn fork_ret() -> ! {
         unsafe {
             FIRST = false;
         }
-        fs::init(ROOTDEV);
+        vfs::init();
         // register initproc here, because namei must be called after fs initialization.
-        INITPROC.get().unwrap().data_mut().cwd = Some(Path::new("/").namei().unwrap().1);
+        let root = Path::new("/").namei().unwrap().1;
+        INITPROC.get().unwrap().set_cwd(Some(root));
     }
     unsafe { usertrap_ret() }
 }
@@ -964,6 +1047,36 @@ pub fn dump() {
     }
 }
 
+// The process with pid, if any.
+pub fn find(pid: usize) -> Option<Arc<Proc>> {
+    PROCS
+        .pool
+        .iter()
+        .find(|p| {
+            let inner = p.inner.lock();
+            inner.state != ProcState::UNUSED && inner.pid.0 == pid
+        })
+        .cloned()
+}
+
+// Pids of all processes, in table order.
+pub fn pids() -> Vec<usize> {
+    PROCS
+        .pool
+        .iter()
+        .filter_map(|p| {
+            let inner = p.inner.lock();
+            (inner.state != ProcState::UNUSED).then_some(inner.pid.0)
+        })
+        .collect()
+}
+
+// Pid of p's parent, 0 if it has none.
+pub fn ppid(p: &Proc) -> usize {
+    let parent = PROCS.parents.lock()[p.idx].clone();
+    parent.map_or(0, |pp| pp.pid())
+}
+
 // Per-CPU process scheduler.
 // Each CPU calls scheduler() after setting itself up.
 // Scheduler never returns. It loops, doing:
@@ -1049,6 +1162,21 @@ pub fn yielding() {
     sched(guard, &mut p.data_mut().context);
 }
 
+// The pid of the process a thread belongs to: the first of its
+// creators that is not a thread itself.
+pub fn tgid(p: &Arc<Proc>) -> usize {
+    let parents = PROCS.parents.lock();
+    let mut cur = Arc::clone(p);
+    while cur.data().is_thread {
+        let Some(pp) = parents[cur.idx].as_ref() else {
+            break;
+        };
+        cur = Arc::clone(pp);
+    }
+    drop(parents);
+    cur.pid()
+}
+
 // Kill + reap all child threads of parent.
 
 pub fn reap_threads(parent: &Arc<Proc>) -> Result<()> {
@@ -1103,15 +1231,24 @@ pub fn exit(status: i32) -> ! {
 
     // Close all open files
     let data = p.data_mut();
-    for fd in data.ofile.iter_mut() {
-        let _file = fd.take();
+    for fd in 0..NOFILE {
+        let _file = p.set_ofile(fd, None);
     }
 
-    LOG.begin_op();
-    {
-        let _ip = data.cwd.take();
+    drop(p.set_cwd(None));
+
+    let (pid, sid) = {
+        let guard = p.inner.lock();
+        (guard.pid.0, guard.sid)
+    };
+    if pid == sid {
+        // Hang up on the foreground job of a session that loses its leader.
+        let fg = console::fg_pgrp();
+        if console::session() == sid && fg != 0 {
+            let _ = kill_pgrp(fg, SIGHUP, 0);
+        }
+        console::release_session(sid);
     }
-    LOG.end_op();
 
     let mut proc_guard;
     {
@@ -1126,7 +1263,15 @@ pub fn exit(status: i32) -> ! {
             }
         }
         // Parent might be sleeping in wait().
-        self::wakeup(Arc::as_ptr(parents[p.idx].as_ref().unwrap()) as usize);
+        let parent = parents[p.idx].as_ref().unwrap();
+        self::wakeup(Arc::as_ptr(parent) as usize);
+        if !data.is_thread {
+            let info = SigInfo {
+                status,
+                ..SigInfo::new(SIGCHLD, pid)
+            };
+            post_signal(parent.idx, &mut parent.inner.lock(), info);
+        }
         proc_guard = p.inner.lock();
         proc_guard.xstate = status;
         proc_guard.state = ProcState::ZOMBIE;
@@ -1197,6 +1342,8 @@ pub fn on_tick(now: usize) {
             continue;
         }
         guard.sig_pending |= sig_mask(SIGALRM);
+        guard.sig_info[SIGALRM - 1] = SigInfo::new(SIGALRM, 0);
+        let blocked = guard.sig_blocked & sig_mask(SIGALRM) != 0;
         if guard.sig_alarm_interval == 0 {
             guard.sig_alarm_deadline = 0;
         } else if let Some(next) = now.checked_add(guard.sig_alarm_interval) {
@@ -1204,77 +1351,122 @@ pub fn on_tick(now: usize) {
         } else {
             guard.sig_alarm_deadline = 0;
         }
-        if guard.state == ProcState::SLEEPING {
+        if guard.state == ProcState::SLEEPING && !blocked {
             make_runnable(idx, &mut guard);
         }
     }
 }
 
+// Take one deliverable signal: run its default action, or set up the
+// trap frame to enter its handler on return to user space. A system
+// call the signal interrupted runs again if the action was a default
+// one that let the process carry on, or a handler with SA_RESTART;
+// otherwise it returns Interrupted.
 pub fn deliver_signals(p: &Arc<Proc>) {
     let data = p.data_mut();
+    let restart_a0 = data.restart_a0.take();
+    let mut guard = p.inner.lock();
+    // sigsuspend's mask lasts until a signal is delivered: a handler
+    // runs under it and its sigreturn restores the one from before.
+    let blocked = guard.sig_blocked;
+    if let Some(old) = data.sig_suspend_mask.take() {
+        guard.sig_blocked = old;
+    }
     if data.sig_active {
         return;
     }
-    let mut guard = p.inner.lock();
-    let pending = guard.sig_pending;
+    let pending = guard.sig_pending & !blocked;
     if pending == 0 {
         return;
     }
     let sig = pending.trailing_zeros() as usize + 1;
     let mask = sig_mask(sig);
+    guard.sig_pending &= !mask;
     if sig == SIGKILL {
-        guard.sig_pending &= !mask;
         guard.killed = true;
         return;
     }
     if sig == SIGCONT {
         guard.cont_pending = false;
     }
-    let handler = guard.sig_handlers[sig - 1];
-    if handler == SIG_IGN {
-        guard.sig_pending &= !mask;
-        return;
-    }
-    if handler == SIG_DFL {
-        guard.sig_pending &= !mask;
+    let act = guard.sig_actions[sig - 1];
+    if act.handler == SIG_DFL {
         match default_action(sig) {
             SigDefaultAction::Terminate => {
                 guard.killed = true;
+                return;
             }
             SigDefaultAction::Stop => {
                 guard.stop_sig = sig;
                 guard.stop_reported = false;
                 guard.state = ProcState::STOPPED;
+                let info = SigInfo {
+                    status: stop_status(sig),
+                    ..SigInfo::new(SIGCHLD, guard.pid.0)
+                };
                 let parent = {
                     let parents = PROCS.parents.lock();
                     parents[p.idx].clone()
                 };
                 if let Some(parent) = parent {
                     wakeup(Arc::as_ptr(&parent) as usize);
+                    post_signal(parent.idx, &mut parent.inner.lock(), info);
                 }
                 let _guard = sched(guard, &mut data.context);
-                return;
             }
             SigDefaultAction::Continue => {
                 guard.cont_pending = false;
             }
             SigDefaultAction::Ignore => {}
         }
+        restart_syscall(data.trapframe.as_mut().unwrap(), restart_a0);
+        return;
+    }
+    if act.handler == SIG_IGN {
+        restart_syscall(data.trapframe.as_mut().unwrap(), restart_a0);
         return;
     }
-    let restorer = data.sig_restorer;
-    if restorer == 0 {
-        guard.sig_pending &= !mask;
+    if act.restorer == 0 {
         guard.killed = true;
         return;
     }
     let tf = data.trapframe.as_mut().unwrap();
+    if act.flags & SA_RESTART != 0 {
+        restart_syscall(tf, restart_a0);
+    }
     data.sig_trapframe = **tf;
+    data.sig_saved_mask = guard.sig_blocked;
     data.sig_active = true;
-    guard.sig_pending &= !mask;
-    tf.epc = handler;
+    let defer = if act.flags & SA_NODEFER != 0 { 0 } else { mask };
+    guard.sig_blocked = (blocked | act.mask | defer) & !UNBLOCKABLE;
+    if act.flags & SA_RESETHAND != 0 {
+        guard.sig_actions[sig - 1] = SigAction::default();
+    }
+    let info = guard.sig_info[sig - 1];
+    drop(guard);
+    tf.epc = act.handler;
     tf.a0 = sig;
-    tf.ra = restorer;
+    tf.ra = act.restorer;
+    if act.flags & SA_SIGINFO != 0 {
+        // The SigInfo goes on the user stack, below where it was.
+        let sp = tf.sp.wrapping_sub(size_of::<SigInfo>()) & !0xf;
+        if either_copyout(UVAddr::from(sp).into(), &info).is_err() {
+            p.inner.lock().killed = true;
+            return;
+        }
+        tf.sp = sp;
+        tf.a1 = sp;
+        tf.a2 = 0;
+    }
+}
+
+// Point tf back at the ecall of an interrupted system call, with its
+// first argument back in a0, so it runs again.
+fn restart_syscall(tf: &mut Trapframe, a0: Option<usize>) {
+    if let Some(a0) = a0 {
+        tf.epc -= 4;
+        tf.a0 = a0;
+    }
 }
 
 // Create a new process, copying the parent.
@@ -1284,6 +1476,7 @@ pub fn fork() -> Result<usize> {
     let p_data = p.data();
     let (c, mut c_guard) = PROCS.alloc()?;
     let c_data = c.data_mut();
+    let c_exported = c.exported.lock();
 
     // Copy user memory from parent to child.
     let p_aspace = p_data.aspace.as_ref().unwrap();
@@ -1296,6 +1489,7 @@ pub fn fork() -> Result<usize> {
     c_as_inner.sz = p_sz;
     let c_uvm = c_as_inner.uvm.as_mut().unwrap();
     if let Err(err) = p_uvm.copy(c_uvm, p_sz) {
+        drop(c_exported);
         c.free(c_guard);
         return Err(err);
     }
@@ -1311,16 +1505,22 @@ pub fn fork() -> Result<usize> {
     // increment reference counts on open file descriptors.
     c_data.ofile.clone_from_slice(&p_data.ofile);
     c_data.cwd = p_data.cwd.clone();
+    c_data.cred = p_data.cred;
+    c_data.umask = p_data.umask;
 
     c_data.name.push_str(&p_data.name);
+    c_data.cmdline.push_str(&p_data.cmdline);
     c_data.sig_trapframe = Trapframe::default();
     c_data.sig_active = false;
-    c_data.sig_restorer = p_data.sig_restorer;
+    c_data.sig_saved_mask = 0;
+    c_data.sig_suspend_mask = None;
+    c_data.restart_a0 = None;
 
     {
         let p_inner = p.inner.lock();
-        c_guard.sig_handlers = p_inner.sig_handlers;
+        c_guard.sig_actions = p_inner.sig_actions;
         c_guard.sig_pending = 0;
+        c_guard.sig_blocked = p_inner.sig_blocked;
         c_guard.sig_alarm_deadline = p_inner.sig_alarm_deadline;
         c_guard.sig_alarm_interval = p_inner.sig_alarm_interval;
         c_guard.pgid = p_inner.pgid;
@@ -1365,6 +1565,7 @@ pub fn fork() -> Result<usize> {
         }
     }
 
+    drop(c_exported);
     let pid = c_guard.pid;
 
     let c_inner = Mutex::unlock(c_guard);
@@ -1377,25 +1578,38 @@ pub fn fork() -> Result<usize> {
     Ok(pid.0)
 }
 
-// Create a new thread in the same address space as the caller.
+// Create a new thread in the same address space as the caller, running
+// on the size bytes of stack at stack, with tp set to tls.
 
-pub fn clone(fcn: usize, arg1: usize, arg2: usize, stack: usize) -> Result<usize> {
+pub fn clone(
+    fcn: usize,
+    arg1: usize,
+    arg2: usize,
+    stack: usize,
+    size: usize,
+    tls: usize,
+) -> Result<usize> {
     let p = Cpus::myproc().unwrap();
     let p_data = p.data();
-    let stack_base: UVAddr = stack.into();
-    if stack == 0 || !stack_base.is_aligned() {
+    let Some(sp) = stack.checked_add(size) else {
+        return Err(BadVirtAddr);
+    };
+    if stack == 0 || size == 0 || !sp.is_multiple_of(16) {
         return Err(BadVirtAddr);
     }
     let p_aspace = p_data.aspace.as_ref().unwrap();
     let p_sz = p_aspace.inner.lock().sz;
-    if stack.checked_add(PGSIZE).is_none() || stack + PGSIZE > p_sz {
+    if sp > p_sz {
         return Err(BadVirtAddr);
     }
     let (c, mut c_guard) = PROCS.alloc()?;
     let c_data = c.data_mut();
 
     // Switch child to share parent's address space.
-    let _old = c_data.aspace.replace(Arc::clone(p_aspace));
+    let _old = {
+        let _exported = c.exported.lock();
+        c_data.aspace.replace(Arc::clone(p_aspace))
+    };
 
     // Map child's trapframe into the shared user page table.
     {
@@ -1409,27 +1623,37 @@ pub fn clone(fcn: usize, arg1: usize, arg2: usize, stack: usize) -> Result<usize
         )?;
     }
 
-    // Start thread at fcn(arg1, arg2).
+    // Start thread at fcn(arg1, arg2), with its own TLS block at tp.
     let p_tf = p_data.trapframe.as_ref().unwrap();
     let c_tf = c_data.trapframe.as_mut().unwrap();
     c_tf.clone_from(p_tf);
     c_tf.epc = fcn;
-    c_tf.sp = stack + PGSIZE;
+    c_tf.sp = sp;
+    c_tf.tp = tls;
     c_tf.a0 = arg1;
     c_tf.a1 = arg2;
-    c_data.is_thread = true;
     c_data.ustack = stack;
-    c_data.ofile.clone_from_slice(&p_data.ofile);
-    c_data.cwd = p_data.cwd.clone();
-    c_data.name.push_str(&p_data.name);
+    {
+        let _exported = c.exported.lock();
+        c_data.is_thread = true;
+        c_data.ofile.clone_from_slice(&p_data.ofile);
+        c_data.cwd = p_data.cwd.clone();
+        c_data.cred = p_data.cred;
+        c_data.umask = p_data.umask;
+        c_data.name.push_str(&p_data.name);
+        c_data.cmdline.push_str(&p_data.cmdline);
+    }
     c_data.sig_trapframe = Trapframe::default();
     c_data.sig_active = false;
-    c_data.sig_restorer = p_data.sig_restorer;
+    c_data.sig_saved_mask = 0;
+    c_data.sig_suspend_mask = None;
+    c_data.restart_a0 = None;
 
     {
         let p_inner = p.inner.lock();
-        c_guard.sig_handlers = p_inner.sig_handlers;
+        c_guard.sig_actions = p_inner.sig_actions;
         c_guard.sig_pending = 0;
+        c_guard.sig_blocked = p_inner.sig_blocked;
         c_guard.sig_alarm_deadline = p_inner.sig_alarm_deadline;
         c_guard.sig_alarm_interval = p_inner.sig_alarm_interval;
         c_guard.pgid = p_inner.pgid;
@@ -1450,11 +1674,10 @@ pub fn clone(fcn: usize, arg1: usize, arg2: usize, stack: usize) -> Result<usize
     Ok(pid.0)
 }
 
-// Wait for a child thread to exit; returns child's pid and writes its stack
+// Wait for child thread tid to exit, or any child thread if tid is 0;
+// returns child's pid and writes its stack base into addr.
 
-// base into addr.
-
-pub fn join(addr: UVAddr) -> Result<usize> {
+pub fn join(tid: usize, addr: UVAddr) -> Result<usize> {
     let pid;
     let mut havekids;
     let p = Cpus::myproc().unwrap();
@@ -1469,6 +1692,9 @@ pub fn join(addr: UVAddr) -> Result<usize> {
                         continue;
                     }
                     let c_guard = c.inner.lock();
+                    if tid != 0 && c_guard.pid.0 != tid {
+                        continue;
+                    }
                     havekids = true;
                     if c_guard.state == ProcState::ZOMBIE {
                         pid = c_guard.pid.0;
@@ -1495,62 +1721,119 @@ pub fn join(addr: UVAddr) -> Result<usize> {
     }
 }
 
-// Kill the process with the given pid.
+// Kill the process with the given pid; from is the sender's pid, or 0
+// for the kernel.
 // The victim won't exit until it tries to return
 // to user space (see usertrap in trap.rs)
-pub fn kill(pid: usize, sig: usize) -> Result<()> {
+pub fn kill(pid: usize, sig: usize, from: usize) -> Result<()> {
     if sig == 0 || sig > NSIG {
         return Err(InvalidArgument);
     }
-    let mask = sig_mask(sig);
-    if mask == 0 {
+    if sig_mask(sig) == 0 {
         return Err(InvalidArgument);
     }
     for (idx, p) in PROCS.pool.iter().enumerate() {
         let mut guard = p.inner.lock();
         if guard.pid.0 == pid {
-            if sig == SIGKILL
-                || (guard.sig_handlers[sig - 1] == SIG_DFL
-                    && default_action(sig) == SigDefaultAction::Terminate)
-            {
-                guard.killed = true;
-            }
-            if sig != SIGKILL && guard.sig_handlers[sig - 1] == SIG_IGN {
-                return Ok(());
-            }
-            if sig == SIGCONT {
-                guard.stop_sig = 0;
-                guard.stop_reported = false;
-                guard.cont_pending = true;
-            }
-            guard.sig_pending |= mask;
-            if guard.state == ProcState::STOPPED
-                && (sig == SIGCONT
-                    || sig == SIGKILL
-                    || (guard.sig_handlers[sig - 1] == SIG_DFL
-                        && default_action(sig) == SigDefaultAction::Terminate))
-            {
-                make_runnable(idx, &mut guard);
-            }
-            if guard.state == ProcState::SLEEPING {
-                // Wake process from sleep().
-                make_runnable(idx, &mut guard);
-            }
+            post_signal(idx, &mut guard, SigInfo::new(sig, from));
             return Ok(());
         }
     }
     Err(NoSuchProcess)
 }
 
-pub fn kill_pgrp(pgid: usize, sig: usize) -> Result<()> {
+// Make sig pending for a process. A blocked signal stays pending, and
+// neither terminates nor wakes the process, until it is unblocked;
+// SIGCONT continues a stopped process even so. An unblocked signal
+// whose default is to be ignored, like SIGCHLD, is dropped here so it
+// doesn't cut short a sleep.
+fn post_signal(idx: usize, guard: &mut ProcInner, info: SigInfo) {
+    let sig = info.signo as usize;
+    let mask = sig_mask(sig);
+    let blocked = guard.sig_blocked & mask != 0;
+    let handler = guard.sig_actions[sig - 1].handler;
+    let terminate = sig == SIGKILL
+        || (handler == SIG_DFL && default_action(sig) == SigDefaultAction::Terminate);
+    if terminate && !blocked {
+        guard.killed = true;
+    }
+    if sig != SIGKILL && handler == SIG_IGN {
+        return;
+    }
+    if handler == SIG_DFL && default_action(sig) == SigDefaultAction::Ignore && !blocked {
+        return;
+    }
+    if sig == SIGCONT {
+        guard.stop_sig = 0;
+        guard.stop_reported = false;
+        guard.cont_pending = true;
+    }
+    guard.sig_pending |= mask;
+    guard.sig_info[sig - 1] = info;
+    if guard.state == ProcState::STOPPED && (sig == SIGCONT || (terminate && !blocked)) {
+        make_runnable(idx, guard);
+    }
+    if guard.state == ProcState::SLEEPING && !blocked {
+        // Wake process from sleep().
+        make_runnable(idx, guard);
+    }
+}
+
+// Send sig to the calling process, as for a write to a pipe nobody
+// reads.
+pub fn raise(sig: usize) {
+    if let Some(p) = Cpus::myproc() {
+        let mut guard = p.inner.lock();
+        let info = SigInfo::new(sig, guard.pid.0);
+        post_signal(p.idx, &mut guard, info);
+    }
+}
+
+// Turn a fault the process took at addr into sig. The faulting
+// instruction runs again once the trap returns, so the signal can't be
+// ignored or left pending: with no handler that can run now, the
+// process dies.
+pub fn fault(p: &Arc<Proc>, sig: usize, addr: usize) {
+    let data = p.data();
+    let mut guard = p.inner.lock();
+    let act = guard.sig_actions[sig - 1];
+    if act.handler == SIG_DFL
+        || act.handler == SIG_IGN
+        || guard.sig_blocked & sig_mask(sig) != 0
+        || data.sig_active
+        || act.restorer == 0
+    {
+        guard.killed = true;
+    } else {
+        guard.sig_pending |= sig_mask(sig);
+        guard.sig_info[sig - 1] = SigInfo {
+            addr,
+            ..SigInfo::new(sig, 0)
+        };
+    }
+}
+
+// Whether a system call sleeping for the calling process should give
+// up with Interrupted: it has been killed or has a signal to take.
+// deliver_signals decides whether the call then runs again. While a
+// handler runs no other signal is taken before its sigreturn, so only
+// being killed interrupts calls made from the handler.
+pub fn interrupted() -> bool {
+    Cpus::myproc().is_some_and(|p| {
+        let sig_active = p.data().sig_active;
+        let guard = p.inner.lock();
+        guard.killed || (!sig_active && guard.deliverable() != 0)
+    })
+}
+
+pub fn kill_pgrp(pgid: usize, sig: usize, from: usize) -> Result<()> {
     if pgid == 0 {
         return Err(InvalidArgument);
     }
     if sig == 0 || sig > NSIG {
         return Err(InvalidArgument);
     }
-    let mask = sig_mask(sig);
-    if mask == 0 {
+    if sig_mask(sig) == 0 {
         return Err(InvalidArgument);
     }
     let mut found = false;
@@ -1560,32 +1843,7 @@ pub fn kill_pgrp(pgid: usize, sig: usize) -> Result<()> {
             continue;
         }
         found = true;
-        if sig == SIGKILL
-            || (guard.sig_handlers[sig - 1] == SIG_DFL
-                && default_action(sig) == SigDefaultAction::Terminate)
-        {
-            guard.killed = true;
-        }
-        if sig != SIGKILL && guard.sig_handlers[sig - 1] == SIG_IGN {
-            continue;
-        }
-        if sig == SIGCONT {
-            guard.stop_sig = 0;
-            guard.stop_reported = false;
-            guard.cont_pending = true;
-        }
-        guard.sig_pending |= mask;
-        if guard.state == ProcState::STOPPED
-            && (sig == SIGCONT
-                || sig == SIGKILL
-                || (guard.sig_handlers[sig - 1] == SIG_DFL
-                    && default_action(sig) == SigDefaultAction::Terminate))
-        {
-            make_runnable(idx, &mut guard);
-        }
-        if guard.state == ProcState::SLEEPING {
-            make_runnable(idx, &mut guard);
-        }
+        post_signal(idx, &mut guard, SigInfo::new(sig, from));
     }
     if found { Ok(()) } else { Err(NoSuchProcess) }
 }
@@ -1627,6 +1885,51 @@ pub fn setpgid(pid: usize, pgid: usize) -> Result<()> {
     Err(NoSuchProcess)
 }
 
+// Root sets all three user ids; anyone else may only switch the
+// effective id between the real and saved ones.
+pub fn setuid(uid: u32) -> Result<()> {
+    let p = Cpus::myproc().unwrap();
+    let _exported = p.exported.lock();
+    let cred = &mut p.data_mut().cred;
+    if cred.is_root() {
+        cred.uid = uid;
+        cred.suid = uid;
+    } else if uid != cred.uid && uid != cred.suid {
+        return Err(PermissionDenied);
+    }
+    cred.euid = uid;
+    Ok(())
+}
+
+pub fn setgid(gid: u32) -> Result<()> {
+    let p = Cpus::myproc().unwrap();
+    let _exported = p.exported.lock();
+    let cred = &mut p.data_mut().cred;
+    if cred.is_root() {
+        cred.gid = gid;
+        cred.sgid = gid;
+    } else if gid != cred.gid && gid != cred.sgid {
+        return Err(PermissionDenied);
+    }
+    cred.egid = gid;
+    Ok(())
+}
+
+pub fn setgroups(groups: &[u32]) -> Result<()> {
+    let p = Cpus::myproc().unwrap();
+    let _exported = p.exported.lock();
+    let cred = &mut p.data_mut().cred;
+    if !cred.is_root() {
+        return Err(PermissionDenied);
+    }
+    if groups.len() > NGROUPS {
+        return Err(InvalidArgument);
+    }
+    cred.groups[..groups.len()].copy_from_slice(groups);
+    cred.ngroups = groups.len();
+    Ok(())
+}
+
 pub fn setsid() -> Result<usize> {
     let p = Cpus::myproc().unwrap();
     let mut guard = p.inner.lock();
@@ -1654,29 +1957,31 @@ pub fn pgid_in_session(pgid: usize, sid: usize) -> bool {
     false
 }
 
-pub fn sigaction(sig: usize, handler: usize, restorer: usize) -> Result<usize> {
+// Set the action for sig, if act is given, and return the old one.
+pub fn sigaction(sig: usize, act: Option<SigAction>) -> Result<SigAction> {
     if sig == 0 || sig > NSIG {
         return Err(InvalidArgument);
     }
-    if sig == SIGKILL {
-        return Err(PermissionDenied);
-    }
     let p = Cpus::myproc().unwrap();
     let mut guard = p.inner.lock();
-    let prev = guard.sig_handlers[sig - 1];
-    let prev_ret = match prev {
-        SIG_DFL => 0,
-        SIG_IGN => 1,
-        _ => prev,
-    };
-    if handler != SIG_DFL && handler != SIG_IGN {
-        if restorer == 0 {
+    let old = guard.sig_actions[sig - 1];
+    if let Some(mut act) = act {
+        if sig == SIGKILL {
+            return Err(PermissionDenied);
+        }
+        if act.handler != SIG_DFL && act.handler != SIG_IGN && act.restorer == 0 {
             return Err(InvalidArgument);
         }
-        p.data_mut().sig_restorer = restorer;
+        act.mask &= !UNBLOCKABLE;
+        // A signal that is now ignored is no longer pending either.
+        if act.handler == SIG_IGN
+            || (act.handler == SIG_DFL && default_action(sig) == SigDefaultAction::Ignore)
+        {
+            guard.sig_pending &= !sig_mask(sig);
+        }
+        guard.sig_actions[sig - 1] = act;
     }
-    guard.sig_handlers[sig - 1] = handler;
-    Ok(prev_ret)
+    Ok(old)
 }
 
 pub fn sigreturn() -> Result<()> {
@@ -1689,9 +1994,53 @@ pub fn sigreturn() -> Result<()> {
     let tf = data.trapframe.as_mut().unwrap();
     **tf = saved;
     data.sig_active = false;
+    p.inner.lock().sig_blocked = data.sig_saved_mask;
     Ok(())
 }
 
+// Change the blocked mask as how says, if set is given, and return the
+// old one.
+pub fn sigprocmask(how: usize, set: Option<u32>) -> Result<u32> {
+    let p = Cpus::myproc().unwrap();
+    let mut guard = p.inner.lock();
+    let old = guard.sig_blocked;
+    if let Some(set) = set {
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return Err(InvalidArgument),
+        };
+        guard.sig_blocked = blocked & !UNBLOCKABLE;
+    }
+    Ok(old)
+}
+
+pub fn sigpending() -> u32 {
+    Cpus::myproc().unwrap().inner.lock().sig_pending
+}
+
+// Block mask instead until a signal can be delivered, without a window
+// where one can slip past. Always returns Interrupted; the old mask
+// comes back once the signal is delivered (see deliver_signals).
+pub fn sigsuspend(mask: u32) -> Result<()> {
+    let p = Cpus::myproc().unwrap();
+    let data = p.data_mut();
+    let mut guard = p.inner.lock();
+    data.sig_suspend_mask = Some(guard.sig_blocked);
+    guard.sig_blocked = mask & !UNBLOCKABLE;
+    while guard.deliverable() == 0 && !guard.killed {
+        // post_signal and on_tick wake sleepers, whatever the channel.
+        guard.chan = Arc::as_ptr(&p) as usize;
+        guard.state = ProcState::SLEEPING;
+        guard = sched(guard, &mut data.context);
+        guard.chan = 0;
+    }
+    // Done once a signal arrives, so never run again.
+    data.restart_a0 = None;
+    Err(Interrupted)
+}
+
 pub fn setitimer(initial: usize, interval: usize) -> Result<usize> {
     let p = Cpus::myproc().unwrap();
     let now = *TICKS.lock();
@@ -1798,27 +2147,8 @@ pub fn waitpid(pid: isize, addr: UVAddr, options: usize) -> Result<usize> {
         if !havekids || p.inner.lock().killed {
             break Err(NoChildProcesses);
         }
-        {
-            let mut guard = p.inner.lock();
-            let mut pending = guard.sig_pending;
-            if pending != 0 {
-                for sig in 1..=NSIG {
-                    let mask = sig_mask(sig);
-                    if pending & mask == 0 {
-                        continue;
-                    }
-                    let handler = guard.sig_handlers[sig - 1];
-                    if handler == SIG_IGN
-                        || (handler == SIG_DFL && default_action(sig) == SigDefaultAction::Ignore)
-                    {
-                        pending &= !mask;
-                        guard.sig_pending &= !mask;
-                    }
-                }
-            }
-            if pending != 0 {
-                return Err(Interrupted);
-            }
+        if interrupted() {
+            return Err(Interrupted);
         }
 
         // wait for a child to exit
@@ -1914,6 +2244,7 @@ pub fn mmap(
     };
     let start = data.alloc_mmap_va(sz, len)?;
 
+    let _exported = p.exported.lock();
     data.vmas.push(Vma {
         start,
         len,
@@ -1944,6 +2275,7 @@ pub fn munmap(addr: usize, len: usize) -> Result<()> {
     // may touch multiple vmas
     let mut i = 0;
     {
+        let _exported = p.exported.lock();
         let aspace = data.aspace.as_ref().unwrap();
         let mut as_inner = aspace.inner.lock();
         let uvm = as_inner.uvm.as_mut().unwrap();
@@ -2011,7 +2343,7 @@ pub fn munmap(addr: usize, len: usize) -> Result<()> {
 
 pub(crate) struct Writeback {
     ip: Inode,
-    file_off: u32,
+    file_off: u64,
     data: Box<Page>,
     data_off: usize,
     len: usize,
@@ -2019,15 +2351,15 @@ pub(crate) struct Writeback {
 
 impl Writeback {
     pub(crate) fn flush(self) -> Result<()> {
-        LOG.begin_op();
-        {
+        LOG.begin_op(self.ip.dev());
+        let res = {
             let mut guard = self.ip.lock();
             let base = self.data.as_ref() as *const Page as usize;
             let src = VirtAddr::Kernel(base + self.data_off);
-            let _ = guard.write(src, self.file_off, self.len)?;
-        }
-        LOG.end_op();
-        Ok(())
+            guard.write(src, self.file_off, self.len)
+        };
+        LOG.end_op(self.ip.dev());
+        res.map(|_| ())
     }
 }
 
@@ -2061,10 +2393,6 @@ fn munmap_vma_range(
                     let n = write_end - write_start;
                     let file_off = v.file_off + page_off + (write_start - a.into_usize());
 
-                    if file_off > u32::MAX as usize {
-                        return Err(InvalidArgument);
-                    }
-
                     let mut buf = match Box::<Page>::try_new_zeroed() {
                         Ok(mem) => unsafe { mem.assume_init() },
                         Err(_) => return Err(OutOfMemory),
@@ -2080,7 +2408,7 @@ fn munmap_vma_range(
 
                     writebacks.push(Writeback {
                         ip,
-                        file_off: file_off as u32,
+                        file_off: file_off as u64,
                         data: buf,
                         data_off: write_start - a.into_usize(),
                         len: n,
@@ -2158,14 +2486,12 @@ pub fn handle_user_page_fault(fault_addr: usize, cause: Exception) -> Result<()>
         let page_off = va - v.start;
         let file_off = v.file_off + page_off;
 
-        if file_off <= u32::MAX as usize {
-            let mut guard = ip.lock();
-            if let Err(err) = guard.read(VirtAddr::Kernel(mem as usize), file_off as u32, PGSIZE) {
-                unsafe {
-                    let _pg = Box::from_raw(mem);
-                }
-                return Err(err);
+        let mut guard = ip.lock();
+        if let Err(err) = guard.read(VirtAddr::Kernel(mem as usize), file_off as u64, PGSIZE) {
+            unsafe {
+                let _pg = Box::from_raw(mem);
             }
+            return Err(err);
         }
     }
 
@@ -0,0 +1,618 @@
+// Process file system.
+//
+// Nothing in /proc is stored: every read formats the current state of
+// the process table, the allocator or the interrupt counters as text,
+// and directory listings come from the process table as well.
+//
+//   /proc/<pid>/status    name, state, ids and memory size
+//   /proc/<pid>/cmdline   exec arguments, each ending in NUL
+//   /proc/<pid>/maps      mmap regions
+//   /proc/<pid>/fd/<n>    symlink describing open file n
+//   /proc/<pid>/cwd       symlink to the current directory
+//   /proc/self            symlink to the reader's own <pid>
+//   /proc/meminfo, /proc/interrupts, /proc/mounts, /proc/uptime,
+//   /proc/devices, /proc/bcache, /proc/log
+//
+// The parts of another process's data shown here are copied out under
+// its Proc::exported lock, which it holds to change them, and
+// formatted from the copy. A file is a snapshot that may already be
+// stale.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cmp::min;
+use core::fmt::{self, Write};
+use core::sync::atomic::Ordering;
+
+use crate::aplic;
+use crate::bio::BCACHE;
+use crate::error::{Error::*, Result};
+use crate::fcntl::OMode;
+use crate::file::{DEVSW, File, VFile};
+use crate::fs::ROOTINO;
+use crate::kalloc;
+use crate::log::LOG;
+use crate::mmap::{MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE};
+use crate::param::{NCPU, NOFILE, ROOTDEV};
+use crate::proc::{self, Cpus, Proc, ProcState, either_copyout};
+use crate::riscv::{PGSIZE, pgroundup};
+use crate::stat::{FileType, Stat};
+use crate::time::{self, NSEC_PER_SEC};
+use crate::vfs::{self, FileSystem, Node, Vnode, check_access, put_dirent};
+use crate::vm::{Addr, VirtAddr};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Root,
+    Meminfo,
+    Interrupts,
+    Mounts,
+    Uptime,
+    Devices,
+    Bcache,
+    Log,
+    SelfLink,
+    Pid(usize),
+    Status(usize),
+    Cmdline(usize),
+    Maps(usize),
+    FdDir(usize),
+    Fd(usize, usize),
+    Cwd(usize),
+}
+
+// Entries of /proc besides the pid directories.
+const TOP: [(&str, Kind); 8] = [
+    ("meminfo", Kind::Meminfo),
+    ("interrupts", Kind::Interrupts),
+    ("mounts", Kind::Mounts),
+    ("uptime", Kind::Uptime),
+    ("devices", Kind::Devices),
+    ("bcache", Kind::Bcache),
+    ("log", Kind::Log),
+    ("self", Kind::SelfLink),
+];
+
+// Entries of /proc/<pid>, made from the pid.
+type PidEntry = fn(usize) -> Kind;
+const PER_PID: [(&str, PidEntry); 5] = [
+    ("status", Kind::Status),
+    ("cmdline", Kind::Cmdline),
+    ("maps", Kind::Maps),
+    ("fd", Kind::FdDir),
+    ("cwd", Kind::Cwd),
+];
+
+impl Kind {
+    // Inode numbers: the low byte tells the entries of a pid directory
+    // apart, the rest is the pid. Pids start at 1, so they never clash
+    // with the top-level entries.
+    fn ino(self) -> u32 {
+        let (pid, sub) = match self {
+            Kind::Root => return ROOTINO,
+            Kind::Meminfo => return ROOTINO + 1,
+            Kind::Interrupts => return ROOTINO + 2,
+            Kind::Mounts => return ROOTINO + 3,
+            Kind::Uptime => return ROOTINO + 4,
+            Kind::SelfLink => return ROOTINO + 5,
+            Kind::Devices => return ROOTINO + 6,
+            Kind::Bcache => return ROOTINO + 7,
+            Kind::Log => return ROOTINO + 8,
+            Kind::Pid(pid) => (pid, 0),
+            Kind::Status(pid) => (pid, 1),
+            Kind::Cmdline(pid) => (pid, 2),
+            Kind::Maps(pid) => (pid, 3),
+            Kind::FdDir(pid) => (pid, 4),
+            Kind::Cwd(pid) => (pid, 5),
+            Kind::Fd(pid, fd) => (pid, 16 + fd),
+        };
+        ((pid << 8) | sub) as u32
+    }
+
+    fn ftype(self) -> FileType {
+        match self {
+            Kind::Root | Kind::Pid(_) | Kind::FdDir(_) => FileType::Dir,
+            Kind::SelfLink | Kind::Fd(..) | Kind::Cwd(_) => FileType::Symlink,
+            _ => FileType::File,
+        }
+    }
+
+    fn pid(self) -> Option<usize> {
+        match self {
+            Kind::Pid(pid)
+            | Kind::Status(pid)
+            | Kind::Cmdline(pid)
+            | Kind::Maps(pid)
+            | Kind::FdDir(pid)
+            | Kind::Fd(pid, _)
+            | Kind::Cwd(pid) => Some(pid),
+            _ => None,
+        }
+    }
+}
+
+pub struct ProcFs {
+    dev: u32,
+}
+
+impl ProcFs {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            dev: vfs::anon_dev(),
+        })
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "proc"
+    }
+
+    fn dev(&self) -> u32 {
+        self.dev
+    }
+
+    fn root(self: Arc<Self>) -> Result<Vnode> {
+        Ok(Arc::new(ProcNode {
+            fs: self,
+            kind: Kind::Root,
+        }))
+    }
+}
+
+pub struct ProcNode {
+    fs: Arc<ProcFs>,
+    kind: Kind,
+}
+
+impl ProcNode {
+    fn node(&self, kind: Kind) -> Vnode {
+        Arc::new(ProcNode {
+            fs: Arc::clone(&self.fs),
+            kind,
+        })
+    }
+
+    // The process this node is about; gone once it has been reaped.
+    fn proc(&self) -> Result<Arc<Proc>> {
+        self.kind.pid().and_then(proc::find).ok_or(NotFound)
+    }
+
+    // Entries of this directory other than "." and "..".
+    fn entries(&self) -> Result<Vec<(String, Kind)>> {
+        Ok(match self.kind {
+            Kind::Root => TOP
+                .iter()
+                .map(|&(name, kind)| (name.to_string(), kind))
+                .chain(
+                    proc::pids()
+                        .into_iter()
+                        .map(|pid| (pid.to_string(), Kind::Pid(pid))),
+                )
+                .collect(),
+            Kind::Pid(pid) => {
+                self.proc()?;
+                PER_PID
+                    .iter()
+                    .map(|&(name, kind)| (name.to_string(), kind(pid)))
+                    .collect()
+            }
+            Kind::FdDir(pid) => {
+                let p = self.proc()?;
+                let open: Vec<usize> = {
+                    let _exported = p.exported.lock();
+                    (0..NOFILE)
+                        .filter(|&fd| p.data().ofile[fd].is_some())
+                        .collect()
+                };
+                open.into_iter()
+                    .map(|fd| (fd.to_string(), Kind::Fd(pid, fd)))
+                    .collect()
+            }
+            _ => return Err(NotADirectory),
+        })
+    }
+
+    fn perm(&self) -> u32 {
+        match self.kind.ftype() {
+            FileType::Dir => 0o555,
+            FileType::Symlink => 0o777,
+            _ => 0o444,
+        }
+    }
+
+    fn parent(&self) -> Kind {
+        match self.kind {
+            Kind::FdDir(pid) => Kind::Pid(pid),
+            _ => Kind::Root,
+        }
+    }
+
+    // Text of a regular file.
+    fn content(&self) -> Result<String> {
+        let mut out = String::new();
+        match self.kind {
+            Kind::Meminfo => meminfo(&mut out),
+            Kind::Interrupts => interrupts(&mut out),
+            Kind::Mounts => mounts(&mut out),
+            Kind::Uptime => uptime(&mut out),
+            Kind::Devices => devices(&mut out),
+            Kind::Bcache => bcache(&mut out),
+            Kind::Log => log(&mut out),
+            Kind::Status(_) => status(&mut out, self.proc()?.as_ref()),
+            Kind::Cmdline(_) => {
+                let p = self.proc()?;
+                let _exported = p.exported.lock();
+                out.push_str(&p.data().cmdline);
+                Ok(())
+            }
+            Kind::Maps(_) => maps(&mut out, self.proc()?.as_ref()),
+            _ => return Err(IsADirectory),
+        }
+        .or(Err(OutOfMemory))?;
+        Ok(out)
+    }
+
+    // Target of a symlink.
+    fn target(&self) -> Result<String> {
+        match self.kind {
+            Kind::SelfLink => Cpus::myproc().map(|p| p.pid().to_string()).ok_or(NotFound),
+            Kind::Cwd(_) => {
+                let p = self.proc()?;
+                let cwd = {
+                    let _exported = p.exported.lock();
+                    p.data().cwd.clone()
+                };
+                vfs::path_of(&cwd.ok_or(NotFound)?)
+            }
+            Kind::Fd(_, fd) => {
+                let p = self.proc()?;
+                // A copy of the file, so it is described, and closed if
+                // the owner has closed it meanwhile, without the lock.
+                let f = {
+                    let _exported = p.exported.lock();
+                    p.data().ofile[fd].clone()
+                };
+                describe(&f.ok_or(NotFound)?)
+            }
+            _ => Err(InvalidArgument),
+        }
+    }
+}
+
+fn meminfo(out: &mut String) -> fmt::Result {
+    let total = kalloc::total_pages() * PGSIZE / 1024;
+    let free = kalloc::free_pages() * PGSIZE / 1024;
+    writeln!(out, "MemTotal: {:>10} kB", total)?;
+    writeln!(out, "MemFree:  {:>10} kB", free)?;
+    writeln!(out, "MemUsed:  {:>10} kB", total - free)
+}
+
+// One row per APLIC source, one column per hart.
+fn interrupts(out: &mut String) -> fmt::Result {
+    write!(out, "    ")?;
+    for hart in 0..NCPU {
+        write!(out, "{:>10}", format!("CPU{}", hart))?;
+    }
+    writeln!(out)?;
+    for (i, (irq, name)) in aplic::SOURCES.iter().enumerate() {
+        write!(out, "{:>3}:", irq)?;
+        for count in aplic::counts(i) {
+            write!(out, "{:>10}", count)?;
+        }
+        writeln!(out, "  {}", name)?;
+    }
+    Ok(())
+}
+
+fn mounts(out: &mut String) -> fmt::Result {
+    for (source, target, fstype) in vfs::mounts() {
+        writeln!(out, "{} {} {}", source, target, fstype)?;
+    }
+    Ok(())
+}
+
+// Major numbers of the registered drivers, for mknod.
+fn devices(out: &mut String) -> fmt::Result {
+    for (major, entry) in DEVSW.entries() {
+        writeln!(out, "{:>3} {}", major.0, entry.name)?;
+    }
+    Ok(())
+}
+
+// Buffer cache size and counters since boot.
+fn bcache(out: &mut String) -> fmt::Result {
+    let stats = BCACHE.stats();
+    writeln!(out, "Buffers:   {:>10}", BCACHE.size())?;
+    writeln!(out, "Hits:      {:>10}", stats.hits.load(Ordering::Relaxed))?;
+    writeln!(
+        out,
+        "Misses:    {:>10}",
+        stats.misses.load(Ordering::Relaxed)
+    )?;
+    writeln!(
+        out,
+        "Readahead: {:>10}",
+        stats.readahead.load(Ordering::Relaxed)
+    )?;
+    writeln!(
+        out,
+        "Writeback: {:>10}",
+        stats.writeback.load(Ordering::Relaxed)
+    )
+}
+
+// Root journal size and last commit, group commit counters since boot.
+fn log(out: &mut String) -> fmt::Result {
+    let stats = LOG.stats();
+    writeln!(out, "Size:         {:>10}", LOG.size(ROOTDEV))?;
+    writeln!(out, "Seq:          {:>10}", LOG.seq(ROOTDEV))?;
+    writeln!(
+        out,
+        "Commits:      {:>10}",
+        stats.commits.load(Ordering::Relaxed)
+    )?;
+    writeln!(
+        out,
+        "Transactions: {:>10}",
+        stats.transactions.load(Ordering::Relaxed)
+    )
+}
+
+// Seconds since boot, to a hundredth.
+fn uptime(out: &mut String) -> fmt::Result {
+    let now = time::monotonic();
+    writeln!(
+        out,
+        "{}.{:02}",
+        now / NSEC_PER_SEC,
+        now % NSEC_PER_SEC / (NSEC_PER_SEC / 100)
+    )
+}
+
+fn status(out: &mut String, p: &Proc) -> fmt::Result {
+    let (state, pgid, sid, pending, blocked) = {
+        let inner = p.inner.lock();
+        let state = match inner.state {
+            ProcState::UNUSED | ProcState::USED => "N (new)",
+            ProcState::SLEEPING => "S (sleeping)",
+            ProcState::STOPPED => "T (stopped)",
+            ProcState::RUNNABLE => "R (runnable)",
+            ProcState::RUNNING => "R (running)",
+            ProcState::ZOMBIE => "Z (zombie)",
+        };
+        (
+            state,
+            inner.pgid,
+            inner.sid,
+            inner.sig_pending,
+            inner.sig_blocked,
+        )
+    };
+    let (name, cred, umask, is_thread, size) = {
+        let _exported = p.exported.lock();
+        let data = p.data();
+        let size = data.aspace.as_ref().map_or(0, |a| a.inner.lock().sz);
+        (
+            data.name.clone(),
+            data.cred,
+            data.umask,
+            data.is_thread,
+            size,
+        )
+    };
+    writeln!(out, "Name:\t{}", name)?;
+    writeln!(out, "State:\t{}", state)?;
+    writeln!(out, "Pid:\t{}", p.pid())?;
+    writeln!(out, "PPid:\t{}", proc::ppid(p))?;
+    writeln!(out, "Pgid:\t{}", pgid)?;
+    writeln!(out, "Sid:\t{}", sid)?;
+    writeln!(out, "Uid:\t{}\t{}\t{}", cred.uid, cred.euid, cred.suid)?;
+    writeln!(out, "Gid:\t{}\t{}\t{}", cred.gid, cred.egid, cred.sgid)?;
+    writeln!(out, "Umask:\t{:04o}", umask)?;
+    writeln!(out, "Thread:\t{}", if is_thread { "yes" } else { "no" })?;
+    writeln!(out, "VmSize:\t{} kB", size / 1024)?;
+    writeln!(out, "SigPnd:\t{:08x}", pending)?;
+    writeln!(out, "SigBlk:\t{:08x}", blocked)
+}
+
+// One line per mmap region: range, permissions, file offset and what
+// backs it.
+fn maps(out: &mut String, p: &Proc) -> fmt::Result {
+    let vmas = {
+        let _exported = p.exported.lock();
+        p.data().vmas.clone()
+    };
+    for vma in vmas.iter() {
+        let start = vma.start.into_usize();
+        let flag = |bit, c| if vma.prot & bit != 0 { c } else { '-' };
+        let what = match (&vma.file, &vma.shm) {
+            (Some(f), _) => describe(f).unwrap_or_default(),
+            (None, Some(_)) => "[shm]".to_string(),
+            (None, None) => String::new(),
+        };
+        writeln!(
+            out,
+            "{:08x}-{:08x} {}{}{}{} {:08x} {}",
+            start,
+            start + pgroundup(vma.len),
+            flag(PROT_READ, 'r'),
+            flag(PROT_WRITE, 'w'),
+            flag(PROT_EXEC, 'x'),
+            if vma.flags & MAP_SHARED != 0 {
+                's'
+            } else {
+                'p'
+            },
+            vma.file_off,
+            what
+        )?;
+    }
+    Ok(())
+}
+
+// What an open file refers to: the path of a directory, and otherwise
+// its kind and, for a node, its (dev, ino), since files do not know
+// their names.
+fn describe(f: &File) -> Result<String> {
+    if let Some(vn) = f.vnode() {
+        return match vn.file_type() {
+            FileType::Dir => vfs::path_of(&vn),
+            _ => Ok(format!("inode:[{}:{}]", vn.dev(), vn.ino())),
+        };
+    }
+    Ok(match &***f {
+        VFile::Pipe(_) => "pipe:",
+        VFile::Socket(_) => "socket:[unix]",
+        VFile::InetSocket(_) => "socket:[inet]",
+        _ => "?",
+    }
+    .to_string())
+}
+
+impl Node for ProcNode {
+    fn dev(&self) -> u32 {
+        self.fs.dev
+    }
+
+    fn ino(&self) -> u32 {
+        self.kind.ino()
+    }
+
+    fn stat(&self, st: &mut Stat) -> Result<()> {
+        let ftype = self.kind.ftype();
+        let (uid, gid) = match self.kind.pid() {
+            Some(_) => {
+                let p = self.proc()?;
+                let _exported = p.exported.lock();
+                let cred = p.data().cred;
+                (cred.euid, cred.egid)
+            }
+            None => (0, 0),
+        };
+        let now = time::realtime();
+        st.dev = self.fs.dev;
+        st.ino = self.kind.ino();
+        st.ftype = ftype;
+        st.nlink = if ftype == FileType::Dir { 2 } else { 1 };
+        st.mode = self.perm();
+        st.uid = uid;
+        st.gid = gid;
+        st.size = 0;
+        st.atime = now;
+        st.mtime = now;
+        st.ctime = now;
+        Ok(())
+    }
+
+    fn file_type(&self) -> FileType {
+        self.kind.ftype()
+    }
+
+    fn access(&self, want: u32) -> Result<()> {
+        let mut st = Stat::default();
+        self.stat(&mut st)?;
+        check_access(st.ftype, st.mode, st.uid, st.gid, want)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Vnode> {
+        if name == ".." {
+            return Ok(self.node(self.parent()));
+        }
+        let kind = self
+            .entries()?
+            .into_iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, kind)| kind)
+            .ok_or(NotFound)?;
+        Ok(self.node(kind))
+    }
+
+    fn create(
+        &self,
+        _name: &str,
+        _ftype: FileType,
+        _major: u16,
+        _minor: u16,
+        _perm: u32,
+    ) -> Result<Vnode> {
+        Err(PermissionDenied)
+    }
+
+    fn open(self: Arc<Self>, omode: &OMode) -> Result<Vnode> {
+        if omode.is_write() || omode.is_trunc() {
+            return Err(PermissionDenied);
+        }
+        Ok(self)
+    }
+
+    fn read(&self, dst: VirtAddr, off: u64, n: usize) -> Result<usize> {
+        let text = self.content()?;
+        let off = min(off as usize, text.len());
+        let n = min(n, text.len() - off);
+        either_copyout(dst, &text.as_bytes()[off..off + n])?;
+        Ok(n)
+    }
+
+    fn write(&self, _src: VirtAddr, _off: &mut u64, _n: usize, _append: bool) -> Result<usize> {
+        Err(PermissionDenied)
+    }
+
+    // The cookie counts records: ".", ".." and then entries().
+    fn readdir(&self, off: &mut u64, mut dst: VirtAddr, n: usize) -> Result<usize> {
+        let dots = [
+            (".".to_string(), self.kind),
+            ("..".to_string(), self.parent()),
+        ];
+        let mut tot = 0;
+        for (name, kind) in dots.into_iter().chain(self.entries()?).skip(*off as usize) {
+            if !put_dirent(&mut dst, &mut tot, n, kind.ino(), kind.ftype(), &name)? {
+                break;
+            }
+            *off += 1;
+        }
+        Ok(tot)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn link(&self, _name: &str, _target: &Vnode) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn symlink(&self, _name: &str, _target: &str) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn fsync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn readlink(&self, dst: VirtAddr, n: usize) -> Result<usize> {
+        let target = self.target()?;
+        let n = min(n, target.len());
+        either_copyout(dst, &target.as_bytes()[..n])?;
+        Ok(n)
+    }
+
+    fn rename(&self, _name: &str, _ndir: &Vnode, _nname: &str) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn chmod(&self, _perm: u32) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn chown(&self, _uid: u32, _gid: u32) -> Result<()> {
+        Err(PermissionDenied)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,89 @@
+// /dev/urandom: an xorshift64* generator seeded from the real-time
+// clock and the timer counter on first use. Good for test data and
+// hash seeds, not for keys. Bytes written to it are mixed into the
+// state, so a writer can add its own seed.
+
+use crate::error::Result;
+use crate::file::{DEVSW, Device};
+use crate::proc::{either_copyin, either_copyout};
+use crate::riscv::registers::time;
+use crate::rtc;
+use crate::spinlock::Mutex;
+use crate::vm::VirtAddr;
+
+pub static URANDOM: Urandom = Urandom {
+    state: Mutex::new(0, "urandom"),
+};
+
+pub struct Urandom {
+    state: Mutex<u64>,
+}
+
+fn seed(state: &mut u64) {
+    if *state == 0 {
+        *state = (rtc::read() ^ time::read().rotate_left(32)) | 1;
+    }
+}
+
+impl Urandom {
+    fn fill(&self, buf: &mut [u8]) {
+        let mut state = self.state.lock();
+        seed(&mut state);
+        for chunk in buf.chunks_mut(8) {
+            let mut x = *state;
+            x ^= x >> 12;
+            x ^= x << 25;
+            x ^= x >> 27;
+            *state = x;
+            let bytes = x.wrapping_mul(0x2545_f491_4f6c_dd1d).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn mix(&self, buf: &[u8]) {
+        let mut state = self.state.lock();
+        seed(&mut state);
+        for chunk in buf.chunks(8) {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            let x = (*state ^ u64::from_le_bytes(bytes)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            // xorshift never leaves zero, so keep out of it.
+            *state = x.rotate_left(31) | 1;
+        }
+    }
+}
+
+impl Device for Urandom {
+    fn read(&self, mut dst: VirtAddr, n: usize, _offset: usize) -> Result<usize> {
+        let mut buf = [0u8; 512];
+        let mut done = 0;
+        while done < n {
+            let m = (n - done).min(buf.len());
+            // fill takes the lock for each chunk and drops it before
+            // the copy out, which may fault.
+            self.fill(&mut buf[..m]);
+            either_copyout(dst, &buf[..m])?;
+            done += m;
+            dst += m;
+        }
+        Ok(done)
+    }
+
+    fn write(&self, mut src: VirtAddr, n: usize, _offset: usize) -> Result<usize> {
+        let mut buf = [0u8; 512];
+        let mut done = 0;
+        while done < n {
+            let m = (n - done).min(buf.len());
+            // copy in before taking the lock, it may fault.
+            either_copyin(&mut buf[..m], src)?;
+            self.mix(&buf[..m]);
+            done += m;
+            src += m;
+        }
+        Ok(done)
+    }
+}
+
+pub fn init() {
+    DEVSW.register("urandom", 0o666, &URANDOM).unwrap();
+}
@@ -249,6 +249,34 @@ pub mod registers {
         }
     }
 
+    // Machine Counter-Enable, which counters lower modes may read
+    pub mod mcounteren {
+        use core::arch::asm;
+
+        const TM: usize = 1 << 1;
+
+        #[inline]
+        pub unsafe fn set_time() {
+            unsafe {
+                asm!("csrs mcounteren, {}", in(reg) TM);
+            }
+        }
+    }
+
+    // Real-time counter, the CLINT's mtime as seen by lower modes
+    pub mod time {
+        use core::arch::asm;
+
+        #[inline]
+        pub fn read() -> u64 {
+            let bits: u64;
+            unsafe {
+                asm!("csrr {}, time", out(reg) bits);
+            }
+            bits
+        }
+    }
+
     // supervisor exceptions program counter, holds the
     // instruction address to which a return from
     // exception will go.
@@ -500,6 +528,7 @@ pub mod registers {
             InstructionFault,
             IllegalInstruction,
             Breakpoint,
+            LoadMisaligned,
             LoadFault,
             StoreMisaligned,
             StoreFault,
@@ -533,6 +562,7 @@ pub mod registers {
                     1 => Exception::InstructionFault,
                     2 => Exception::IllegalInstruction,
                     3 => Exception::Breakpoint,
+                    4 => Exception::LoadMisaligned,
                     5 => Exception::LoadFault,
                     6 => Exception::StoreMisaligned,
                     7 => Exception::StoreFault,
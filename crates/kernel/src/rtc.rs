@@ -0,0 +1,25 @@
+// Driver for the goldfish real-time clock on qemu's virt machine.
+//
+// The device counts nanoseconds since the Unix epoch, taken from the
+// host clock when qemu starts.
+
+use core::ptr;
+
+use crate::memlayout::GOLDFISH_RTC;
+
+// goldfish rtc registers, 32 bits each.
+const TIME_LOW: usize = 0x00; // reading latches TIME_HIGH
+const TIME_HIGH: usize = 0x04;
+
+fn reg(r: usize) -> *mut u32 {
+    (GOLDFISH_RTC + r) as *mut u32
+}
+
+// Nanoseconds since the Unix epoch.
+pub fn read() -> u64 {
+    unsafe {
+        let low = ptr::read_volatile(reg(TIME_LOW));
+        let high = ptr::read_volatile(reg(TIME_HIGH));
+        ((high as u64) << 32) | low as u64
+    }
+}
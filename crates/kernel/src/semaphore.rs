@@ -1,7 +1,7 @@
 use crate::{
     condvar::Condvar,
     error::{Error::Interrupted, Error::InvalidArgument, Result},
-    proc::Cpus,
+    proc,
     spinlock::Mutex,
 };
 
@@ -54,9 +54,7 @@ impl Semaphore {
     pub fn wait(&self) -> Result<()> {
         let mut state = self.mutex.lock();
         loop {
-            if let Some(p) = Cpus::myproc()
-                && p.inner.lock().killed
-            {
+            if proc::interrupted() {
                 return Err(Interrupted);
             }
             if state.closed {
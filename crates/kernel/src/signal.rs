@@ -10,23 +10,94 @@ pub const NSIG: usize = 32;
 pub const SIG_DFL: usize = usize::MAX;
 pub const SIG_IGN: usize = usize::MAX - 1;
 
+pub const SIGHUP: usize = 1;
 pub const SIGINT: usize = 2;
+pub const SIGILL: usize = 4;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
 pub const SIGKILL: usize = 9;
 pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
 pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
 pub const SIGALRM: usize = 14;
 pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
 pub const SIGCONT: usize = 18;
 pub const SIGTSTP: usize = 20;
 pub const SIGTTIN: usize = 21;
 pub const SIGTTOU: usize = 22;
 
+// sigprocmask how
+pub const SIG_BLOCK: usize = 0; // add set to the blocked mask
+pub const SIG_UNBLOCK: usize = 1; // remove set from it
+pub const SIG_SETMASK: usize = 2; // replace it with set
+
+// sigaction flags
+pub const SA_SIGINFO: u32 = 0x4; // handler takes a SigInfo as well
+pub const SA_RESTART: u32 = 0x1000_0000; // restart interrupted system calls
+pub const SA_NODEFER: u32 = 0x4000_0000; // don't block the signal in its handler
+pub const SA_RESETHAND: u32 = 0x8000_0000; // back to SIG_DFL once caught
+
+// What to do with a signal. A handler is called as
+// handler(sig), or handler(sig, &SigInfo, 0) with SA_SIGINFO, and
+// returns to restorer, which must call sigreturn.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SigAction {
+    pub handler: usize,  // SIG_DFL, SIG_IGN or a function
+    pub mask: u32,       // blocked as well while the handler runs
+    pub flags: u32,      // SA_*
+    pub restorer: usize, // where the handler returns to
+}
+
+impl SigAction {
+    pub const fn new(handler: usize) -> Self {
+        Self {
+            handler,
+            mask: 0,
+            flags: 0,
+            restorer: 0,
+        }
+    }
+}
+
+impl Default for SigAction {
+    fn default() -> Self {
+        Self::new(SIG_DFL)
+    }
+}
+
+// Why a signal was sent, passed to SA_SIGINFO handlers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SigInfo {
+    pub signo: u32,
+    pub status: i32, // SIGCHLD: the child's wait status
+    pub pid: usize,  // the sender, or the child for SIGCHLD; 0 for the kernel
+    pub addr: usize, // SIGSEGV, SIGBUS, SIGILL: the faulting address
+}
+
+impl SigInfo {
+    pub const fn new(sig: usize, pid: usize) -> Self {
+        Self {
+            signo: sig as u32,
+            status: 0,
+            pid,
+            addr: 0,
+        }
+    }
+}
+
+// Signals that are delivered whatever the blocked mask says.
+pub const UNBLOCKABLE: u32 = sig_mask(SIGKILL);
+
 pub const WNOHANG: usize = 0x1;
 pub const WUNTRACED: usize = 0x2;
 pub const WCONTINUED: usize = 0x4;
 
 #[inline]
-pub fn sig_mask(sig: usize) -> u32 {
+pub const fn sig_mask(sig: usize) -> u32 {
     if sig == 0 || sig > NSIG {
         0
     } else {
@@ -37,9 +108,11 @@ pub fn sig_mask(sig: usize) -> u32 {
 #[inline]
 pub fn default_action(sig: usize) -> SigDefaultAction {
     match sig {
-        SIGKILL | SIGTERM | SIGINT | SIGALRM | SIGUSR1 | SIGUSR2 => SigDefaultAction::Terminate,
+        SIGKILL | SIGTERM | SIGINT | SIGHUP | SIGALRM | SIGUSR1 | SIGUSR2 | SIGPIPE | SIGSEGV
+        | SIGBUS | SIGILL | SIGFPE => SigDefaultAction::Terminate,
         SIGTSTP | SIGTTIN | SIGTTOU => SigDefaultAction::Stop,
         SIGCONT => SigDefaultAction::Continue,
+        SIGCHLD => SigDefaultAction::Ignore,
         _ => SigDefaultAction::Ignore,
     }
 }
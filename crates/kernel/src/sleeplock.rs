@@ -57,9 +57,27 @@ impl<T> SleepLock<T> {
         SleepLockGuard { sleep_lock: self }
     }
 
+    // Lock without sleeping, or None if someone holds it. Kernel tasks
+    // may use this too; they hold the lock as pid 0.
+    pub fn try_lock(&self) -> Option<SleepLockGuard<'_, T>> {
+        let mut lk = self.lk.lock();
+        if lk.locked {
+            return None;
+        }
+        lk.locked = true;
+        lk.pid = holder();
+        Some(SleepLockGuard { sleep_lock: self })
+    }
+
+    // The data, without locking: a unique reference means no one else
+    // can hold the lock.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
     pub fn holding(&self) -> bool {
         let lk = self.lk.lock();
-        lk.locked && lk.pid == Cpus::myproc().unwrap().pid()
+        lk.locked && lk.pid == holder()
     }
 
     pub fn unlock(guard: SleepLockGuard<'_, T>) -> &'_ SleepLock<T> {
@@ -67,6 +85,11 @@ impl<T> SleepLock<T> {
     }
 }
 
+// Pid of the current holder: the running process, or 0 in a kernel task.
+fn holder() -> usize {
+    Cpus::myproc().map_or(0, |p| p.pid())
+}
+
 impl<'a, T: 'a> SleepLockGuard<'a, T> {
     // Returns a reference to the original 'Mutex' object.
     pub fn sleep_lock(&self) -> &'a SleepLock<T> {
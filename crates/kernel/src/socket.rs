@@ -6,7 +6,6 @@ pub const SOCK_DGRAM: usize = 2;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use alloc::{
     collections::BTreeMap,
-    string::{String, ToString},
     sync::{Arc, Weak},
 };
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -15,6 +14,8 @@ use core::net::{Ipv4Addr, SocketAddrV4};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::error::{Error::*, Result};
 #[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::fs::Path;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::mpmc::{Receiver, SyncSender, sync_channel};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::net::{self, TcpListener, TcpSocket, UdpSocket};
@@ -23,8 +24,12 @@ use crate::proc::{either_copyin, either_copyout};
 #[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::spinlock::Mutex;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::stat::{FileType, mode};
+#[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::sync::LazyLock;
 #[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::vfs;
+#[cfg(all(target_os = "none", feature = "kernel"))]
 use crate::vm::VirtAddr;
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -32,8 +37,11 @@ const STREAM_BUF: isize = 512;
 #[cfg(all(target_os = "none", feature = "kernel"))]
 const BACKLOG_MAX: usize = 16;
 
+// Bound sockets by the (dev, ino) of their socket node.
 #[cfg(all(target_os = "none", feature = "kernel"))]
-static UNIX_REGISTRY: LazyLock<Mutex<BTreeMap<String, Weak<UnixSocket>>>> =
+type UnixRegistry = BTreeMap<(u32, u32), Weak<UnixSocket>>;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+static UNIX_REGISTRY: LazyLock<Mutex<UnixRegistry>> =
     LazyLock::new(|| Mutex::new(BTreeMap::new(), "unixsock"));
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -64,6 +72,7 @@ impl UnixStream {
                 dst += 1;
                 i += 1;
             }
+            Err(Interrupted) => return Err(Interrupted),
             Err(_) => return Ok(0),
         }
         while i < n {
@@ -103,9 +112,13 @@ impl UnixStream {
         while i < n {
             let mut ch: u8 = 0;
             either_copyin(&mut ch, src)?;
-            let Ok(()) = self.tx.send(ch) else {
-                break;
-            };
+            if let Err(e) = self.tx.send(ch) {
+                if i > 0 {
+                    break;
+                }
+                // Nobody left to read it, or interrupted.
+                return Err(if self.tx.is_closed() { BrokenPipe } else { e });
+            }
             src += 1;
             i += 1;
         }
@@ -125,6 +138,7 @@ impl UnixStream {
                 Err(WouldBlock) => {
                     return if i == 0 { Err(WouldBlock) } else { Ok(i) };
                 }
+                Err(_) if i == 0 && self.tx.is_closed() => return Err(BrokenPipe),
                 Err(_) => break,
             }
         }
@@ -198,7 +212,7 @@ enum SocketState {
 #[cfg(all(target_os = "none", feature = "kernel"))]
 #[derive(Debug)]
 struct UnixSocketInner {
-    name: Option<String>,
+    name: Option<(u32, u32)>, // socket node bound to
     state: SocketState,
 }
 
@@ -234,24 +248,26 @@ impl UnixSocket {
         })
     }
 
+    // Bind to path by creating a socket node there, which must not exist.
     pub fn bind(self: &Arc<Self>, path: &str) -> Result<()> {
         if path.is_empty() {
             return Err(InvalidArgument);
         }
+        {
+            let inner = self.inner.lock();
+            if inner.name.is_some() || !matches!(inner.state, SocketState::Unbound) {
+                return Err(InvalidArgument);
+            }
+        }
+        let vn = vfs::create(Path::new(path), FileType::Socket, 0, 0, mode::FILE)?;
+        let key = (vn.dev(), vn.ino());
+        drop(vn);
         let mut inner = self.inner.lock();
         if inner.name.is_some() {
             return Err(InvalidArgument);
         }
-        if !matches!(inner.state, SocketState::Unbound) {
-            return Err(InvalidArgument);
-        }
-        let mut reg = UNIX_REGISTRY.lock();
-        if let Some(existing) = reg.get(path).and_then(|weak| weak.upgrade()) {
-            let _ = existing;
-            return Err(AlreadyExists);
-        }
-        reg.insert(path.to_string(), Arc::downgrade(self));
-        inner.name = Some(path.to_string());
+        UNIX_REGISTRY.lock().insert(key, Arc::downgrade(self));
+        inner.name = Some(key);
         Ok(())
     }
 
@@ -293,7 +309,15 @@ impl UnixSocket {
             }
         }
 
-        let listener = lookup_listener(path)?;
+        let (_, vn) = Path::new(path).namei()?;
+        if vn.file_type() != FileType::Socket {
+            return Err(NotConnected);
+        }
+        vn.access(mode::W_OK)?;
+        let key = (vn.dev(), vn.ino());
+        drop(vn);
+
+        let listener = lookup_listener(key)?;
         let (client, server) = UnixStream::pair();
         listener.enqueue(server, nonblock)?;
 
@@ -377,7 +401,11 @@ impl Drop for UnixSocket {
             inner.name.take()
         };
         if let Some(name) = name {
-            UNIX_REGISTRY.lock().remove(&name);
+            let mut reg = UNIX_REGISTRY.lock();
+            // The node may have been removed and its name bound again.
+            if reg.get(&name).is_some_and(|weak| weak.as_ptr() == self) {
+                reg.remove(&name);
+            }
         }
     }
 }
@@ -651,14 +679,14 @@ pub fn validate(domain: usize, stype: usize, _protocol: usize) -> Result<()> {
 }
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
-fn lookup_listener(path: &str) -> Result<Arc<UnixListener>> {
+fn lookup_listener(key: (u32, u32)) -> Result<Arc<UnixListener>> {
     let mut reg = UNIX_REGISTRY.lock();
-    let Some(entry) = reg.get(path).cloned() else {
-        return Err(NotFound);
+    let Some(entry) = reg.get(&key).cloned() else {
+        return Err(NotConnected);
     };
     let Some(sock) = entry.upgrade() else {
-        reg.remove(path);
-        return Err(NotFound);
+        reg.remove(&key);
+        return Err(NotConnected);
     };
     drop(reg);
     let inner = sock.inner.lock();
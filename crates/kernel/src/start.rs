@@ -38,6 +38,9 @@ pub unsafe fn start() -> ! {
         // ask for clock interrupts.
         timerinit();
 
+        // let supervisor mode read the time CSR, for CLOCK_MONOTONIC.
+        mcounteren::set_time();
+
         // keep each CPU's hartid in its tp register, for cpuid().
         let id = mhartid::read();
         asm!("mv tp, {0}", in(reg) id);
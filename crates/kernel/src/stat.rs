@@ -10,6 +10,38 @@ pub enum FileType {
     Socket = 5,
 }
 
+impl FileType {
+    // The type stored as bits on disk, if there is one.
+    pub fn from_u16(bits: u16) -> Option<FileType> {
+        match bits {
+            0 => Some(FileType::Empty),
+            1 => Some(FileType::Dir),
+            2 => Some(FileType::File),
+            3 => Some(FileType::Device),
+            4 => Some(FileType::Symlink),
+            5 => Some(FileType::Socket),
+            _ => None,
+        }
+    }
+}
+
+// Permission bits of an inode's mode.
+pub mod mode {
+    pub const ISUID: u32 = 0o4000; // set user id on execution
+    pub const ISGID: u32 = 0o2000; // set group id on execution
+    pub const ALL: u32 = 0o7777; // bits chmod may change
+
+    // Requested access, as in the lowest rwx triplet.
+    pub const R_OK: u32 = 4;
+    pub const W_OK: u32 = 2;
+    pub const X_OK: u32 = 1;
+
+    // Default modes before the umask is applied.
+    pub const FILE: u32 = 0o666;
+    pub const DIR: u32 = 0o777;
+    pub const UMASK: u32 = 0o022;
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Stat {
@@ -17,10 +49,13 @@ pub struct Stat {
     pub ino: u32,        // Inode number
     pub ftype: FileType, // Type of file
     pub nlink: u16,      // Number of links to file
+    pub mode: u32,       // Permission bits
+    pub uid: u32,        // Owner user id
+    pub gid: u32,        // Owner group id
     pub size: usize,     // Size of file in bytes
-    pub atime: u64,      // Ticks since boot
-    pub mtime: u64,      // Ticks since boot
-    pub ctime: u64,      // Ticks since boot
+    pub atime: u64,      // Nanoseconds since the epoch
+    pub mtime: u64,      // Nanoseconds since the epoch
+    pub ctime: u64,      // Nanoseconds since the epoch
 }
 
 impl Stat {
@@ -28,3 +63,23 @@ impl Stat {
         self.ftype
     }
 }
+
+// A directory entry as returned by getdents, whatever the file system:
+// this header, then namelen bytes of name, padded so that the next
+// record starts reclen bytes later on a 4-byte boundary.
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+pub struct DirRecord {
+    pub ino: u32,        // Inode number
+    pub ftype: FileType, // Type of file
+    pub namelen: u16,    // Bytes of name after the header
+    pub reclen: u16,     // Bytes from this record to the next
+    pub _pad: u16,
+}
+
+impl DirRecord {
+    // Bytes taken by a record holding a name of namelen bytes.
+    pub const fn rec_len(namelen: usize) -> usize {
+        (core::mem::size_of::<DirRecord>() + namelen + 3) & !3
+    }
+}
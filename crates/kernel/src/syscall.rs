@@ -15,21 +15,24 @@ use crate::error::Result;
 use crate::{
     array, console,
     defs::AsBytes,
-    dfs,
     exec::exec,
     fcntl::{self, FcntlCmd, OMode},
-    file::{FTABLE, FType, File, RemoteFile},
+    file::{FTABLE, FType, File},
     fs::{self, Path},
+    futex::{self, FUTEX_WAIT, FUTEX_WAKE},
     ipc,
-    log::{LOG, LogCrashStage, set_crash_stage},
-    param::{MAXARG, MAXPATH, NOFILE},
+    log::{LogCrashStage, set_crash_stage},
+    param::{MAXARG, MAXPATH, NGROUPS, NOFILE},
     pipe::Pipe,
     poll,
     proc::*,
     riscv::PGSIZE,
-    stat::FileType,
+    signal::SigAction,
+    stat::{FileType, Stat, mode},
     task,
+    time::{self, Timespec, UTIME_NOW, UTIME_OMIT},
     trap::TICKS,
+    vfs, virtio_disk,
     vm::{Addr, UVAddr},
 };
 
@@ -98,6 +101,36 @@ pub enum SysCalls {
     Getnprocs = 60,
     Getnprocsconf = 61,
     Killpg = 62,
+    Rename = 63,
+    Chmod = 64,
+    Fchmod = 65,
+    Chown = 66,
+    Umask = 67,
+    Getuid = 68,
+    Setuid = 69,
+    Getgid = 70,
+    Setgid = 71,
+    Getgroups = 72,
+    Setgroups = 73,
+    Stat = 74,
+    Lstat = 75,
+    Readlink = 76,
+    Getdents = 77,
+    Lseek = 78,
+    Pread = 79,
+    Pwrite = 80,
+    Truncate = 81,
+    Ftruncate = 82,
+    ClockGettime = 83,
+    Utimens = 84,
+    Mount = 85,
+    Umount = 86,
+    Mkfs = 87,
+    Sigprocmask = 88,
+    Sigpending = 89,
+    Sigsuspend = 90,
+    Futex = 91,
+    Gettid = 92,
     Invalid = 0,
 }
 
@@ -171,9 +204,9 @@ impl SysCalls {
         (Fn::U(Self::munmap), "(addr: usize, len: usize)"), //
         (
             Fn::I(Self::clone),
-            "(fcn: usize, arg1: usize, arg2: usize, stack: usize)",
+            "(fcn: usize, arg1: usize, arg2: usize, stack: usize, size: usize, tls: usize)",
         ),
-        (Fn::I(Self::join), "(stack: &mut usize)"),
+        (Fn::I(Self::join), "(tid: usize, stack: &mut usize)"),
         (Fn::I(Self::ext_irq_count), "()"),
         (Fn::I(Self::ktaskpolls), "()"),
         (
@@ -189,8 +222,8 @@ impl SysCalls {
             "(pid: isize, xstatus: &mut i32, options: usize)",
         ),
         (
-            Fn::I(Self::sigaction),
-            "(signum: usize, handler: usize, restorer: usize)",
+            Fn::U(Self::sigaction),
+            "(signum: usize, act: &SigAction, oldact: &mut SigAction)",
         ),
         (Fn::U(Self::sigreturn), "()"),
         (Fn::I(Self::setitimer), "(initial: usize, interval: usize)"),
@@ -222,6 +255,55 @@ impl SysCalls {
         (Fn::I(Self::getnprocs), "()"),
         (Fn::I(Self::getnprocsconf), "()"),
         (Fn::U(Self::killpg), "(pgid: usize, sig: usize)"),
+        (Fn::U(Self::rename), "(old: &str, new: &str)"), // Atomically move old to new.
+        (Fn::U(Self::chmod), "(path: &str, mode: usize)"),
+        (Fn::U(Self::fchmod), "(fd: usize, mode: usize)"),
+        (Fn::U(Self::chown), "(path: &str, uid: usize, gid: usize)"),
+        (Fn::I(Self::umask), "(mask: usize)"),
+        (Fn::I(Self::getuid), "()"),
+        (Fn::U(Self::setuid), "(uid: usize)"),
+        (Fn::I(Self::getgid), "()"),
+        (Fn::U(Self::setgid), "(gid: usize)"),
+        (Fn::I(Self::getgroups), "(groups: &mut [u32])"), // Returns the number of groups.
+        (Fn::U(Self::setgroups), "(groups: &[u32])"),
+        (Fn::U(Self::stat), "(path: &str, st: &mut Stat)"), // fstat by name.
+        (Fn::U(Self::lstat), "(path: &str, st: &mut Stat)"), // stat, but not following a final symlink.
+        (Fn::I(Self::readlink), "(path: &str, buf: &mut [u8])"), // Returns the target's length.
+        (Fn::I(Self::getdents), "(fd: usize, buf: &mut [u8])"), /* Read DirRecords from a
+                                                              * directory; 0 at its end. */
+        (Fn::I(Self::lseek), "(fd: usize, off: isize, whence: usize)"), // Returns the new offset.
+        (
+            Fn::I(Self::pread),
+            "(fd: usize, buf: &mut [u8], off: usize)",
+        ), // read at off.
+        (Fn::I(Self::pwrite), "(fd: usize, buf: &[u8], off: usize)"),   // write at off.
+        (Fn::U(Self::truncate), "(path: &str, len: usize)"),            // set file length.
+        (Fn::U(Self::ftruncate), "(fd: usize, len: usize)"),
+        (
+            Fn::U(Self::clockgettime),
+            "(clock: usize, ts: &mut Timespec)",
+        ), // read a clock.
+        (
+            Fn::U(Self::utimens),
+            "(path: &str, atime: &Timespec, mtime: &Timespec)",
+        ), // set file times.
+        (
+            Fn::U(Self::mount),
+            "(source: &str, target: &str, fstype: &str)",
+        ), // attach a file system.
+        (Fn::U(Self::umount), "(target: &str)"), // detach a file system.
+        (Fn::U(Self::mkfs), "(source: &str, ninodes: usize)"), // format a disk.
+        (
+            Fn::U(Self::sigprocmask),
+            "(how: usize, set: &u32, oldset: &mut u32)",
+        ), // change the blocked signals.
+        (Fn::U(Self::sigpending), "(set: &mut u32)"), // get the pending signals.
+        (Fn::U(Self::sigsuspend), "(mask: &u32)"), // wait for a signal under mask.
+        (
+            Fn::I(Self::futex),
+            "(addr: usize, op: usize, val: usize, timeout: usize)",
+        ), // wait on or wake a user memory word.
+        (Fn::I(Self::gettid), "()"),             // Return the calling thread's id.
     ];
 
     pub fn invalid() -> ! {
@@ -247,12 +329,18 @@ pub fn syscall() {
         let _ = SysCalls::sigreturn();
         return;
     }
+    // Kept if the call is interrupted, so deliver_signals can rerun it.
+    // A call that must not start over clears it or sets where to resume.
+    pdata.restart_a0 = Some(tf.a0);
     tf.a0 = match syscall_id {
         SysCalls::Invalid => {
             println!("{} {}: unknown sys call {}", p.pid(), pdata.name, tf.a7);
             -1_isize as usize
         }
         _ => SysCalls::TABLE[syscall_id as usize].0.call() as usize,
+    };
+    if tf.a0 != Interrupted as isize as usize {
+        pdata.restart_a0 = None;
     }
 }
 
@@ -308,6 +396,23 @@ fn fetch_slice<T: AsBytes>(slice_info: Slice, buf: &mut [T]) -> Result<Option<us
     Ok(Some(sbinfo.len))
 }
 
+// Place info about the file at the path in argument 0 into the Stat
+// at argument 1, following a final symlink if follow is set.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+fn path_stat(follow: bool) -> Result<()> {
+    let mut path = [0; MAXPATH];
+    let path = Path::from_arg(0, &mut path)?;
+    let st: UVAddr = argraw(1).into();
+
+    let mut stat: Stat = Default::default();
+    let (_, vn) = match follow {
+        true => path.namei()?,
+        false => path.namei_nofollow()?,
+    };
+    vn.stat(&mut stat)?;
+    either_copyout(st.into(), &stat)
+}
+
 #[cfg(all(target_os = "none", feature = "kernel"))]
 fn poll_check(fds: &mut [poll::PollFd]) -> Result<usize> {
     let p_data = Cpus::myproc().unwrap().data();
@@ -451,19 +556,15 @@ impl Arg for Envp {
 
 #[cfg(all(target_os = "none", feature = "kernel"))]
 fn fdalloc(file: File) -> Result<usize> {
-    for (fd, f) in Cpus::myproc()
-        .unwrap()
-        .data_mut()
+    let p = Cpus::myproc().unwrap();
+    let fd = p
+        .data()
         .ofile
-        .iter_mut()
-        .enumerate()
-    {
-        if f.is_none() {
-            f.replace(file);
-            return Ok(fd);
-        }
-    }
-    Err(FileDescriptorTooLarge)
+        .iter()
+        .position(|f| f.is_none())
+        .ok_or(FileDescriptorTooLarge)?;
+    p.set_ofile(fd, Some(file));
+    Ok(fd)
 }
 
 // Process related system calls
@@ -481,6 +582,15 @@ impl SysCalls {
     }
 
     pub fn getpid() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            Ok(tgid(&Cpus::myproc().unwrap()))
+        }
+    }
+
+    pub fn gettid() -> Result<usize> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(0);
         #[cfg(all(target_os = "none", feature = "kernel"))]
@@ -508,7 +618,9 @@ impl SysCalls {
             let arg1 = argraw(1);
             let arg2 = argraw(2);
             let stack = argraw(3);
-            clone(fcn, arg1, arg2, stack)
+            let size = argraw(4);
+            let tls = argraw(5);
+            clone(fcn, arg1, arg2, stack, size, tls)
         }
     }
 
@@ -539,8 +651,9 @@ impl SysCalls {
         return Ok(0);
         #[cfg(all(target_os = "none", feature = "kernel"))]
         {
-            let addr: UVAddr = argraw(0).into();
-            join(addr)
+            let tid = argraw(0);
+            let addr: UVAddr = argraw(1).into();
+            join(tid, addr)
         }
     }
 
@@ -594,12 +707,9 @@ impl SysCalls {
             let mut ticks = TICKS.lock();
             let ticks0 = *ticks;
             while *ticks - ticks0 < n {
-                if p.inner.lock().killed {
-                    return Err(Interrupted);
-                }
-                let pending =
-                    p.inner.lock().sig_pending & !crate::signal::sig_mask(crate::signal::SIGCONT);
-                if pending != 0 {
+                if interrupted() {
+                    // Run again, only for the time that is left.
+                    p.data_mut().restart_a0 = Some(n - (*ticks - ticks0));
                     return Err(Interrupted);
                 }
                 ticks = sleep(&(*ticks) as *const _ as usize, ticks);
@@ -619,7 +729,7 @@ impl SysCalls {
             if pid == 0 {
                 Err(PermissionDenied)
             } else {
-                kill(pid, sig)
+                kill(pid, sig, Cpus::myproc().unwrap().pid())
             }
         }
     }
@@ -635,20 +745,32 @@ impl SysCalls {
             if pgid == 0 {
                 Err(PermissionDenied)
             } else {
-                kill_pgrp(pgid, sig)
+                kill_pgrp(pgid, sig, Cpus::myproc().unwrap().pid())
             }
         }
     }
 
-    pub fn sigaction() -> Result<usize> {
+    pub fn sigaction() -> Result<()> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
-        return Ok(0);
+        return Ok(());
         #[cfg(all(target_os = "none", feature = "kernel"))]
         {
             let signum = argraw(0);
-            let handler = argraw(1);
-            let restorer = argraw(2);
-            sigaction(signum, handler, restorer)
+            let act: UVAddr = argraw(1).into();
+            let oldact: UVAddr = argraw(2).into();
+            let mut new = SigAction::default();
+            let act = match act.into_usize() {
+                0 => None,
+                _ => {
+                    fetch_addr(act, &mut new)?;
+                    Some(new)
+                }
+            };
+            let old = sigaction(signum, act)?;
+            match oldact.into_usize() {
+                0 => Ok(()),
+                _ => either_copyout(oldact.into(), &old),
+            }
         }
     }
 
@@ -661,6 +783,51 @@ impl SysCalls {
         }
     }
 
+    pub fn sigprocmask() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let how = argraw(0);
+            let set: UVAddr = argraw(1).into();
+            let oldset: UVAddr = argraw(2).into();
+            let mut mask = 0u32;
+            let set = match set.into_usize() {
+                0 => None,
+                _ => {
+                    fetch_addr(set, &mut mask)?;
+                    Some(mask)
+                }
+            };
+            let old = sigprocmask(how, set)?;
+            match oldset.into_usize() {
+                0 => Ok(()),
+                _ => either_copyout(oldset.into(), &old),
+            }
+        }
+    }
+
+    pub fn sigpending() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let set: UVAddr = argraw(0).into();
+            either_copyout(set.into(), &sigpending())
+        }
+    }
+
+    pub fn sigsuspend() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut mask = 0u32;
+            fetch_addr(argraw(0).into(), &mut mask)?;
+            sigsuspend(mask)
+        }
+    }
+
     pub fn setitimer() -> Result<usize> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(0);
@@ -672,6 +839,22 @@ impl SysCalls {
         }
     }
 
+    pub fn futex() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let addr = argraw(0);
+            let val = argraw(2);
+            let timeout = argraw(3);
+            match argraw(1) {
+                FUTEX_WAIT => futex::wait(addr, val as u32, timeout).map(|_| 0),
+                FUTEX_WAKE => futex::wake(addr, val),
+                _ => Err(InvalidArgument),
+            }
+        }
+    }
+
     pub fn shmcreate() -> Result<usize> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(0);
@@ -772,6 +955,98 @@ impl SysCalls {
         }
     }
 
+    pub fn clockgettime() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let ts: UVAddr = argraw(1).into();
+            let now = time::now(argraw(0)).ok_or(InvalidArgument)?;
+            either_copyout(ts.into(), &now)
+        }
+    }
+
+    pub fn utimens() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut path = [0; MAXPATH];
+            let path = Path::from_arg(0, &mut path)?;
+            let mut times: [Timespec; 2] = Default::default();
+            fetch_addr(argraw(1).into(), &mut times[0])?;
+            fetch_addr(argraw(2).into(), &mut times[1])?;
+
+            let now = time::realtime();
+            let mut set = [None; 2];
+            for (set, ts) in set.iter_mut().zip(times.iter()) {
+                *set = match ts.nsec {
+                    UTIME_NOW => Some(now),
+                    UTIME_OMIT => None,
+                    nsec if nsec < time::NSEC_PER_SEC => Some(ts.as_nanos()),
+                    _ => return Err(InvalidArgument),
+                };
+            }
+            let only_now = times.iter().all(|ts| ts.nsec == UTIME_NOW);
+
+            let (_, vn) = path.namei()?;
+            vn.utimens(set[0], set[1], only_now)
+        }
+    }
+
+    pub fn mount() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut source = [0u8; MAXPATH];
+            let mut target = [0u8; MAXPATH];
+            let mut fstype = [0u8; MAXPATH];
+            let source = Path::from_arg(0, &mut source)?;
+            let target = Path::from_arg(1, &mut target)?;
+            let fstype = Path::from_arg(2, &mut fstype)?;
+
+            if !Cpus::myproc().unwrap().data().cred.is_root() {
+                return Err(PermissionDenied);
+            }
+            let fs = vfs::new_fs(fstype.as_str(), source.as_str())?;
+            vfs::mount(fs, source.as_str(), target)
+        }
+    }
+
+    pub fn umount() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut target = [0u8; MAXPATH];
+            let target = Path::from_arg(0, &mut target)?;
+
+            if !Cpus::myproc().unwrap().data().cred.is_root() {
+                return Err(PermissionDenied);
+            }
+            vfs::umount(target)
+        }
+    }
+
+    pub fn mkfs() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut source = [0u8; MAXPATH];
+            let source = Path::from_arg(0, &mut source)?;
+            let ninodes = u32::try_from(argraw(1)).or(Err(InvalidArgument))?;
+
+            if !Cpus::myproc().unwrap().data().cred.is_root() {
+                return Err(PermissionDenied);
+            }
+            let dev = vfs::disk_of(source.as_str())?;
+            let size = virtio_disk::blocks(dev).ok_or(InvalidArgument)?;
+            fs::format(dev, size.min(u32::MAX as usize) as u32, ninodes)
+        }
+    }
+
     pub fn freepages() -> Result<usize> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(0);
@@ -838,7 +1113,7 @@ impl SysCalls {
         return Ok(0);
         #[cfg(all(target_os = "none", feature = "kernel"))]
         {
-            let p = Cpus::myproc().unwrap().data_mut();
+            let p = Cpus::myproc().unwrap();
             let src_fd = argraw(0);
             let dst_fd = argraw(1);
             if src_fd == dst_fd {
@@ -846,6 +1121,7 @@ impl SysCalls {
             }
 
             let src = p
+                .data()
                 .ofile
                 .get(src_fd)
                 .ok_or(FileDescriptorTooLarge)?
@@ -856,10 +1132,10 @@ impl SysCalls {
             let mut dst = src;
             dst.clear_cloexec();
 
-            p.ofile
-                .get_mut(dst_fd)
-                .ok_or(FileDescriptorTooLarge)?
-                .replace(dst);
+            if dst_fd >= NOFILE {
+                return Err(FileDescriptorTooLarge);
+            }
+            drop(p.set_ofile(dst_fd, Some(dst)));
 
             Ok(dst_fd)
         }
@@ -902,7 +1178,7 @@ impl SysCalls {
         {
             let mut fd = 0;
             File::from_arg(0, &mut fd)?;
-            let f = Cpus::myproc().unwrap().data_mut().ofile[fd].take().unwrap();
+            let f = Cpus::myproc().unwrap().set_ofile(fd, None).unwrap();
             if let Some((dev, inum)) = f.lock_key() {
                 let pid = Cpus::myproc().unwrap().pid();
                 fcntl::clear_locks(dev, inum, pid);
@@ -926,6 +1202,124 @@ impl SysCalls {
         }
     }
 
+    pub fn stat() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        path_stat(true)
+    }
+
+    pub fn lstat() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        path_stat(false)
+    }
+
+    pub fn readlink() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut path = [0; MAXPATH];
+            let path = Path::from_arg(0, &mut path)?;
+            let mut sbinfo: SBInfo = Default::default();
+            let sbinfo = SBInfo::from_arg(1, &mut sbinfo)?;
+
+            let (_, vn) = path.namei_nofollow()?;
+            vn.readlink(sbinfo.ptr.into(), sbinfo.len)
+        }
+    }
+
+    pub fn getdents() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut _fd = 0;
+            let mut sbinfo: SBInfo = Default::default();
+
+            let (f, _) = File::from_arg(0, &mut _fd)?;
+            let sbinfo = SBInfo::from_arg(1, &mut sbinfo)?;
+
+            f.getdents(sbinfo.ptr.into(), sbinfo.len)
+        }
+    }
+
+    pub fn lseek() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut _fd = 0;
+            let (f, _) = File::from_arg(0, &mut _fd)?;
+            let off = argraw(1) as isize;
+            let whence = argraw(2);
+
+            f.lseek(off, whence)
+        }
+    }
+
+    pub fn pread() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut _fd = 0;
+            let mut sbinfo: SBInfo = Default::default();
+
+            let (f, _) = File::from_arg(0, &mut _fd)?;
+            let sbinfo = SBInfo::from_arg(1, &mut sbinfo)?;
+            let off = argraw(2);
+
+            f.pread(sbinfo.ptr.into(), sbinfo.len, off)
+        }
+    }
+
+    pub fn pwrite() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut _fd = 0;
+            let mut sbinfo: SBInfo = Default::default();
+
+            let (f, _) = File::from_arg(0, &mut _fd)?;
+            let sbinfo = SBInfo::from_arg(1, &mut sbinfo)?;
+            let off = argraw(2);
+
+            f.pwrite(sbinfo.ptr.into(), sbinfo.len, off)
+        }
+    }
+
+    pub fn truncate() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut path = [0; MAXPATH];
+            let path = Path::from_arg(0, &mut path)?;
+            let len = argraw(1);
+
+            let (_, vn) = path.namei()?;
+            vn.access(mode::W_OK)?;
+            vn.truncate(len)
+        }
+    }
+
+    pub fn ftruncate() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut fd = 0;
+            let (f, _) = File::from_arg(0, &mut fd)?;
+            let len = argraw(1);
+
+            f.truncate(len)
+        }
+    }
+
     pub fn fsync() -> Result<()> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(());
@@ -959,22 +1353,76 @@ impl SysCalls {
             let old_path = Path::from_arg(0, &mut old)?;
             let new_path = Path::from_arg(1, &mut new)?;
 
-            let old_remote = dfs::is_remote_path(old_path);
-            let new_remote = dfs::is_remote_path(new_path);
-            if old_remote || new_remote {
-                if old_remote != new_remote {
-                    return Err(CrossesDevices);
-                }
-                return dfs::link(old_path, new_path);
-            }
+            vfs::link(old_path, new_path)
+        }
+    }
 
-            let res;
-            {
-                LOG.begin_op();
-                res = fs::link(old_path, new_path);
-                LOG.end_op();
-            }
-            res
+    pub fn rename() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut old = [0; MAXPATH];
+            let mut new = [0; MAXPATH];
+            let old_path = Path::from_arg(0, &mut old)?;
+            let new_path = Path::from_arg(1, &mut new)?;
+
+            vfs::rename(old_path, new_path)
+        }
+    }
+
+    pub fn chmod() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut path = [0; MAXPATH];
+            let path = Path::from_arg(0, &mut path)?;
+            let perm = argraw(1) as u32;
+
+            let (_, vn) = path.namei()?;
+            vn.chmod(perm)
+        }
+    }
+
+    pub fn fchmod() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut fd = 0;
+            let (f, _) = File::from_arg(0, &mut fd)?;
+            let perm = argraw(1) as u32;
+            f.vnode().ok_or(InvalidArgument)?.chmod(perm)
+        }
+    }
+
+    pub fn chown() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut path = [0; MAXPATH];
+            let path = Path::from_arg(0, &mut path)?;
+            let uid = argraw(1) as u32;
+            let gid = argraw(2) as u32;
+
+            let (_, vn) = path.namei()?;
+            vn.chown(uid, gid)
+        }
+    }
+
+    pub fn umask() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let p = Cpus::myproc().unwrap();
+            let _exported = p.exported.lock();
+            let data = p.data_mut();
+            let old = data.umask;
+            data.umask = argraw(0) as u32 & 0o777;
+            Ok(old as usize)
         }
     }
 
@@ -988,17 +1436,7 @@ impl SysCalls {
             let target = Path::from_arg(0, &mut target)?;
             let linkpath = Path::from_arg(1, &mut linkpath)?;
 
-            if dfs::is_remote_path(linkpath) {
-                return dfs::symlink(target.as_str(), linkpath);
-            }
-
-            let res;
-            {
-                LOG.begin_op();
-                res = fs::symlink(target, linkpath);
-                LOG.end_op();
-            }
-            res
+            vfs::symlink(target.as_str(), linkpath)
         }
     }
 
@@ -1065,6 +1503,78 @@ impl SysCalls {
         }
     }
 
+    pub fn getuid() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            Ok(Cpus::myproc().unwrap().data().cred.uid as usize)
+        }
+    }
+
+    pub fn setuid() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let uid = argraw(0) as u32;
+            setuid(uid)
+        }
+    }
+
+    pub fn getgid() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            Ok(Cpus::myproc().unwrap().data().cred.gid as usize)
+        }
+    }
+
+    pub fn setgid() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let gid = argraw(0) as u32;
+            setgid(gid)
+        }
+    }
+
+    pub fn getgroups() -> Result<usize> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(0);
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut sbinfo: SBInfo = Default::default();
+            let sbinfo = SBInfo::from_arg(0, &mut sbinfo)?;
+            let cred = Cpus::myproc().unwrap().data().cred;
+            let groups = &cred.groups[..cred.ngroups];
+
+            // An empty buffer just asks how many there are.
+            if sbinfo.len == 0 {
+                return Ok(groups.len());
+            }
+            if sbinfo.len < groups.len() {
+                return Err(InvalidArgument);
+            }
+            either_copyout(sbinfo.ptr.into(), groups)?;
+            Ok(groups.len())
+        }
+    }
+
+    pub fn setgroups() -> Result<()> {
+        #[cfg(not(all(target_os = "none", feature = "kernel")))]
+        return Ok(());
+        #[cfg(all(target_os = "none", feature = "kernel"))]
+        {
+            let mut groups = [0u32; NGROUPS];
+            let addr: UVAddr = argraw(0).into();
+            let len = fetch_slice(Slice::Ref(addr), &mut groups)?;
+            setgroups(&groups[..len.unwrap_or(0)])
+        }
+    }
+
     pub fn setpgid() -> Result<()> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(());
@@ -1152,17 +1662,7 @@ impl SysCalls {
             let mut path = [0; MAXPATH];
             let path = Path::from_arg(0, &mut path)?;
 
-            if dfs::is_remote_path(path) {
-                return dfs::unlink(path);
-            }
-
-            let res;
-            {
-                LOG.begin_op();
-                res = fs::unlink(path);
-                LOG.end_op();
-            }
-            res
+            vfs::unlink(path)
         }
     }
 
@@ -1175,25 +1675,9 @@ impl SysCalls {
             let omode = argraw(1);
             let path = Path::from_arg(0, &mut path)?;
 
-            if dfs::is_remote_path(path) {
-                let handle = dfs::open(path, omode)?;
-                return FTABLE
-                    .alloc(
-                        OMode::from_usize(omode),
-                        FType::Remote(RemoteFile::new(handle)),
-                    )
-                    .and_then(fdalloc);
-            }
-
-            let fd;
-            {
-                LOG.begin_op();
-                fd = FTABLE
-                    .alloc(OMode::from_usize(omode), FType::Node(path))
-                    .and_then(fdalloc);
-                LOG.end_op();
-            }
-            fd
+            FTABLE
+                .alloc(OMode::from_usize(omode), FType::Node(path))
+                .and_then(fdalloc)
         }
     }
 
@@ -1205,17 +1689,7 @@ impl SysCalls {
             let mut path = [0u8; MAXPATH];
             let path = Path::from_arg(0, &mut path)?;
 
-            if dfs::is_remote_path(path) {
-                return dfs::mkdir(path);
-            }
-
-            let res;
-            {
-                LOG.begin_op();
-                res = fs::create(path, FileType::Dir, 0, 0).and(Ok(()));
-                LOG.end_op();
-            }
-            res
+            vfs::create(path, FileType::Dir, 0, 0, mode::DIR).and(Ok(()))
         }
     }
 
@@ -1229,44 +1703,25 @@ impl SysCalls {
             let major = argraw(1) as u16;
             let minor = argraw(2) as u16;
 
-            let res;
-            {
-                LOG.begin_op();
-                res = fs::create(path, FileType::Device, major, minor).and(Ok(()));
-                LOG.end_op();
-            }
-            res
+            vfs::create(path, FileType::Device, major, minor, mode::FILE).and(Ok(()))
         }
     }
 
-    #[allow(clippy::redundant_closure_call)]
     pub fn chdir() -> Result<()> {
         #[cfg(not(all(target_os = "none", feature = "kernel")))]
         return Ok(());
         #[cfg(all(target_os = "none", feature = "kernel"))]
         {
             let mut path = [0u8; MAXPATH];
-            let data = Cpus::myproc().unwrap().data_mut();
             let path = Path::from_arg(0, &mut path)?;
 
-            let res;
-            {
-                LOG.begin_op();
-                let mut chidr = || -> Result<()> {
-                    let (_, ip) = path.namei()?;
-                    {
-                        let ip_guard = ip.lock();
-                        if ip_guard.itype() != FileType::Dir {
-                            return Err(NotADirectory);
-                        }
-                    }
-                    data.cwd.replace(ip);
-                    Ok(())
-                };
-                res = chidr();
-                LOG.end_op();
+            let (_, vn) = path.namei()?;
+            if vn.file_type() != FileType::Dir {
+                return Err(NotADirectory);
             }
-            res
+            vn.access(mode::X_OK)?;
+            drop(Cpus::myproc().unwrap().set_cwd(Some(vn)));
+            Ok(())
         }
     }
 
@@ -1305,7 +1760,7 @@ impl SysCalls {
             let fd1 = match fdalloc(wf) {
                 Ok(fd) => fd,
                 Err(err) => {
-                    Cpus::myproc().unwrap().data_mut().ofile[fd0].take();
+                    Cpus::myproc().unwrap().set_ofile(fd0, None);
                     return Err(err);
                 }
             };
@@ -1313,9 +1768,9 @@ impl SysCalls {
             if either_copyout(ptr.into(), &fd0).is_err()
                 || either_copyout((ptr + size_of::<usize>()).into(), &fd1).is_err()
             {
-                let p_data = Cpus::myproc().unwrap().data_mut();
-                p_data.ofile[fd0].take();
-                p_data.ofile[fd1].take();
+                let p = Cpus::myproc().unwrap();
+                p.set_ofile(fd0, None);
+                p.set_ofile(fd1, None);
                 return Err(BadVirtAddr);
             }
             Ok(())
@@ -1379,7 +1834,7 @@ impl SysCalls {
                             return Ok(0);
                         }
                     }
-                    if Cpus::myproc().unwrap().inner.lock().killed {
+                    if interrupted() {
                         return Err(Interrupted);
                     }
                     let mut ticks = TICKS.lock();
@@ -1408,7 +1863,7 @@ impl SysCalls {
                         return Ok(0);
                     }
                 }
-                if Cpus::myproc().unwrap().inner.lock().killed {
+                if interrupted() {
                     return Err(Interrupted);
                 }
                 let mut ticks = TICKS.lock();
@@ -1491,6 +1946,36 @@ impl SysCalls {
             60 => Self::Getnprocs,
             61 => Self::Getnprocsconf,
             62 => Self::Killpg,
+            63 => Self::Rename,
+            64 => Self::Chmod,
+            65 => Self::Fchmod,
+            66 => Self::Chown,
+            67 => Self::Umask,
+            68 => Self::Getuid,
+            69 => Self::Setuid,
+            70 => Self::Getgid,
+            71 => Self::Setgid,
+            72 => Self::Getgroups,
+            73 => Self::Setgroups,
+            74 => Self::Stat,
+            75 => Self::Lstat,
+            76 => Self::Readlink,
+            77 => Self::Getdents,
+            78 => Self::Lseek,
+            79 => Self::Pread,
+            80 => Self::Pwrite,
+            81 => Self::Truncate,
+            82 => Self::Ftruncate,
+            83 => Self::ClockGettime,
+            84 => Self::Utimens,
+            85 => Self::Mount,
+            86 => Self::Umount,
+            87 => Self::Mkfs,
+            88 => Self::Sigprocmask,
+            89 => Self::Sigpending,
+            90 => Self::Sigsuspend,
+            91 => Self::Futex,
+            92 => Self::Gettid,
             _ => Self::Invalid,
         }
     }
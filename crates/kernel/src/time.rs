@@ -0,0 +1,65 @@
+// Clocks and timestamps.
+//
+// File times and CLOCK_REALTIME are nanoseconds since the Unix epoch,
+// read from the goldfish RTC; CLOCK_MONOTONIC counts from boot.
+
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::memlayout::TIMEBASE_FREQ;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::riscv::registers::time;
+#[cfg(all(target_os = "none", feature = "kernel"))]
+use crate::rtc;
+
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Clocks for clock_gettime.
+pub mod clock {
+    pub const REALTIME: usize = 0; // wall-clock time, may jump
+    pub const MONOTONIC: usize = 1; // time since boot, never goes back
+}
+
+// Special nsec values for utimens.
+pub const UTIME_NOW: u64 = (1 << 30) - 1; // set to the current time
+pub const UTIME_OMIT: u64 = (1 << 30) - 2; // leave unchanged
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(C)]
+pub struct Timespec {
+    pub sec: u64,  // Whole seconds
+    pub nsec: u64, // Nanoseconds, below NSEC_PER_SEC
+}
+
+impl Timespec {
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self {
+            sec: nanos / NSEC_PER_SEC,
+            nsec: nanos % NSEC_PER_SEC,
+        }
+    }
+
+    pub const fn as_nanos(&self) -> u64 {
+        self.sec * NSEC_PER_SEC + self.nsec
+    }
+}
+
+// Nanoseconds since the Unix epoch.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn realtime() -> u64 {
+    rtc::read()
+}
+
+// Nanoseconds since boot.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn monotonic() -> u64 {
+    time::read() * (NSEC_PER_SEC / TIMEBASE_FREQ)
+}
+
+// Current time of clock id.
+#[cfg(all(target_os = "none", feature = "kernel"))]
+pub fn now(id: usize) -> Option<Timespec> {
+    match id {
+        clock::REALTIME => Some(Timespec::from_nanos(realtime())),
+        clock::MONOTONIC => Some(Timespec::from_nanos(monotonic())),
+        _ => None,
+    }
+}
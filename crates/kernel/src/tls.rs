@@ -0,0 +1,43 @@
+// Thread-local storage layout, shared by exec, which sets it up for the
+// first thread, and ulib, which sets it up for the rest.
+//
+// RISC-V puts a thread's TLS block right at tp, and a program addresses
+// its thread-locals at fixed offsets from there. The block starts as a
+// copy of the program's PT_TLS segment: .tdata copied, .tbss zeroed.
+// Just below tp sits a Tls header describing that segment, so a thread
+// can lay out a block for a new one without the ELF file at hand.
+//
+//   area: [ padding | Tls header | TLS block ]
+//                                ^ tp
+
+use core::mem::{align_of, size_of};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Tls {
+    pub image: usize,  // address of the .tdata template
+    pub filesz: usize, // bytes of it to copy
+    pub memsz: usize,  // size of the whole block
+    pub align: usize,
+}
+
+impl Tls {
+    pub fn align(&self) -> usize {
+        self.align.max(align_of::<Tls>())
+    }
+
+    // Bytes an area needs, with room to align tp wherever it starts.
+    pub fn area_size(&self) -> usize {
+        size_of::<Tls>() + self.align() - 1 + self.memsz
+    }
+
+    // Where tp goes in an area starting at base.
+    pub fn tp(&self, base: usize) -> usize {
+        (base + size_of::<Tls>() + self.align() - 1) & !(self.align() - 1)
+    }
+
+    // Where the header goes for a given tp.
+    pub fn header(tp: usize) -> usize {
+        tp - size_of::<Tls>()
+    }
+}
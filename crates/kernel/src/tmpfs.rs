@@ -0,0 +1,761 @@
+// In-memory file system.
+//
+// A tmpfs keeps its whole tree in kernel memory: directories map names
+// to inodes, file contents live in pages from kalloc, and symlinks hold
+// their target. Nothing survives a reboot and nothing goes through the
+// log. The pages of all files count against a per-mount cap; a write or
+// truncate that would exceed it fails with StorageFull.
+//
+// Every inode has a sleeplock. Namespace changes (create, link, unlink,
+// rename) also hold the tree lock, so no other operation ever holds two
+// inode locks and they may be taken in any order.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cmp::min;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::defs::AsBytes;
+use crate::error::{Error::*, Result};
+use crate::fcntl::OMode;
+use crate::file::Major;
+use crate::fs::{DIRSIZ, ROOTINO};
+use crate::param::{MAXPATH, TMPFS_PAGES};
+use crate::proc::{Cpus, either_copyin, either_copyout};
+use crate::riscv::PGSIZE;
+use crate::sleeplock::SleepLock;
+use crate::stat::{FileType, Stat, mode};
+use crate::time;
+use crate::vfs::{self, FileSystem, Node, Vnode, check_access, cred, put_dirent};
+use crate::vm::{Page, VirtAddr};
+
+// Pages a tmpfs may use, shared by its files.
+struct Budget {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl Budget {
+    fn take(&self, n: usize) -> Result<()> {
+        if self.used.fetch_add(n, Ordering::Relaxed) + n > self.max {
+            self.used.fetch_sub(n, Ordering::Relaxed);
+            return Err(StorageFull);
+        }
+        Ok(())
+    }
+
+    fn left(&self) -> usize {
+        self.max.saturating_sub(self.used.load(Ordering::Relaxed))
+    }
+
+    fn give(&self, n: usize) {
+        self.used.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+enum Content {
+    File(Vec<Box<Page>>),
+    Dir {
+        entries: BTreeMap<String, Arc<TmpInode>>,
+        parent: Weak<TmpInode>, // empty for the root
+    },
+    Symlink(String),
+    None, // devices and sockets
+}
+
+struct TmpData {
+    size: usize,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u16,
+    major: Major,
+    minor: u16,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
+    content: Content,
+}
+
+impl TmpData {
+    fn touch_mtime_ctime(&mut self) {
+        let now = time::realtime();
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    fn entries(&mut self) -> Result<&mut BTreeMap<String, Arc<TmpInode>>> {
+        match &mut self.content {
+            Content::Dir { entries, .. } => Ok(entries),
+            _ => Err(NotADirectory),
+        }
+    }
+
+    fn pages(&mut self) -> &mut Vec<Box<Page>> {
+        match &mut self.content {
+            Content::File(pages) => pages,
+            _ => panic!("tmpfs: not a file"),
+        }
+    }
+
+    fn access(&self, ftype: FileType, want: u32) -> Result<()> {
+        check_access(ftype, self.mode, self.uid, self.gid, want)
+    }
+
+    // Only the owner or root may change a node's attributes.
+    fn owner_or_root(&self) -> Result<()> {
+        let cred = cred();
+        if cred.is_root() || cred.euid == self.uid {
+            Ok(())
+        } else {
+            Err(PermissionDenied)
+        }
+    }
+}
+
+struct TmpInode {
+    ino: u32,
+    ftype: FileType,
+    budget: Arc<Budget>,
+    data: SleepLock<TmpData>,
+}
+
+impl Drop for TmpInode {
+    fn drop(&mut self) {
+        if let Content::File(pages) = &self.data.get_mut().content {
+            self.budget.give(pages.len());
+        }
+    }
+}
+
+impl TmpInode {
+    fn is_dir(&self) -> bool {
+        self.ftype == FileType::Dir
+    }
+
+    // The parent of directory ip; the root is its own.
+    fn parent(self: &Arc<Self>) -> Arc<Self> {
+        match &self.data.lock().content {
+            Content::Dir { parent, .. } => parent.upgrade().unwrap_or_else(|| Arc::clone(self)),
+            _ => Arc::clone(self),
+        }
+    }
+
+    // Resize file data to len bytes, zeroing anything past the old end.
+    fn resize(&self, data: &mut TmpData, len: usize) -> Result<()> {
+        let npages = len.div_ceil(PGSIZE);
+        let pages = data.pages();
+        if npages > pages.len() {
+            let more = npages - pages.len();
+            self.budget.take(more)?;
+            for _ in 0..more {
+                match Box::<Page>::try_new_zeroed() {
+                    Ok(page) => pages.push(unsafe { page.assume_init() }),
+                    Err(_) => {
+                        self.budget.give(npages - pages.len());
+                        return Err(OutOfMemory);
+                    }
+                }
+            }
+        } else {
+            self.budget.give(pages.len() - npages);
+            pages.truncate(npages);
+        }
+        // Bytes past the end of a page kept by a shrink must read back
+        // as zeros if the file grows again.
+        if len < data.size
+            && let Some(page) = data.pages().last_mut()
+            && !len.is_multiple_of(PGSIZE)
+        {
+            page.as_bytes_mut()[len % PGSIZE..].fill(0);
+        }
+        data.size = len;
+        Ok(())
+    }
+}
+
+pub struct TmpFs {
+    dev: u32,
+    next_ino: AtomicU32,
+    budget: Arc<Budget>,
+    root: Arc<TmpInode>,
+}
+
+impl TmpFs {
+    // A tmpfs of at most max_pages pages of file data, its root open to
+    // everyone.
+    pub fn new(max_pages: usize) -> Arc<Self> {
+        let budget = Arc::new(Budget {
+            max: max_pages,
+            used: AtomicUsize::new(0),
+        });
+        let root = new_inode(
+            ROOTINO,
+            FileType::Dir,
+            &budget,
+            mode::DIR,
+            0,
+            0,
+            Content::Dir {
+                entries: BTreeMap::new(),
+                parent: Weak::new(),
+            },
+        );
+        Arc::new(Self {
+            dev: vfs::anon_dev(),
+            next_ino: AtomicU32::new(ROOTINO + 1),
+            budget,
+            root,
+        })
+    }
+
+    // A tmpfs as asked for by mount: opts is the mount source, a comma
+    // separated list. "size=<bytes>[k|m]" sets the cap; words without
+    // an '=' (such as "tmpfs" or "none") are ignored.
+    pub fn from_options(opts: &str) -> Result<Arc<Self>> {
+        let mut pages = TMPFS_PAGES;
+        for opt in opts.split(',').filter(|opt| opt.contains('=')) {
+            let Some(size) = opt.strip_prefix("size=") else {
+                return Err(InvalidArgument);
+            };
+            {
+                let (num, unit) = match size.as_bytes().last() {
+                    Some(b'k') => (&size[..size.len() - 1], 1024),
+                    Some(b'm') => (&size[..size.len() - 1], 1024 * 1024),
+                    _ => (size, 1),
+                };
+                let bytes = num
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_mul(unit))
+                    .ok_or(InvalidArgument)?;
+                pages = bytes.div_ceil(PGSIZE);
+            }
+        }
+        Ok(Self::new(pages))
+    }
+}
+
+fn new_inode(
+    ino: u32,
+    ftype: FileType,
+    budget: &Arc<Budget>,
+    perm: u32,
+    uid: u32,
+    gid: u32,
+    content: Content,
+) -> Arc<TmpInode> {
+    let now = time::realtime();
+    Arc::new(TmpInode {
+        ino,
+        ftype,
+        budget: Arc::clone(budget),
+        data: SleepLock::new(
+            TmpData {
+                size: 0,
+                mode: perm,
+                uid,
+                gid,
+                nlink: if ftype == FileType::Dir { 2 } else { 1 },
+                major: Major::INVALID,
+                minor: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                content,
+            },
+            "tmpfs",
+        ),
+    })
+}
+
+impl FileSystem for TmpFs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn dev(&self) -> u32 {
+        self.dev
+    }
+
+    fn root(self: Arc<Self>) -> Result<Vnode> {
+        let ip = Arc::clone(&self.root);
+        Ok(Arc::new(TmpNode { fs: self, ip }))
+    }
+}
+
+// Serializes namespace changes, see the top of the file.
+static TREE_LOCK: SleepLock<()> = SleepLock::new((), "tmpfs_tree");
+
+pub struct TmpNode {
+    fs: Arc<TmpFs>,
+    ip: Arc<TmpInode>,
+}
+
+impl TmpNode {
+    fn node(&self, ip: Arc<TmpInode>) -> Vnode {
+        Arc::new(TmpNode {
+            fs: Arc::clone(&self.fs),
+            ip,
+        })
+    }
+
+    // ip of another vnode of the same tmpfs.
+    fn peer<'a>(&self, vn: &'a Vnode) -> Result<&'a Arc<TmpInode>> {
+        match vn.as_any().downcast_ref::<TmpNode>() {
+            Some(node) if Arc::ptr_eq(&node.fs, &self.fs) => Ok(&node.ip),
+            _ => Err(CrossesDevices),
+        }
+    }
+
+    // Directory ip and every directory above it.
+    fn ancestors(ip: &Arc<TmpInode>) -> Vec<Arc<TmpInode>> {
+        let mut chain = alloc::vec![Arc::clone(ip)];
+        loop {
+            let up = chain.last().unwrap().parent();
+            if Arc::ptr_eq(&up, chain.last().unwrap()) {
+                return chain;
+            }
+            chain.push(up);
+        }
+    }
+
+    // Add a new node name of type ftype to this directory.
+    // Caller must hold the tree lock.
+    fn add(
+        &self,
+        name: &str,
+        ftype: FileType,
+        perm: u32,
+        content: Content,
+    ) -> Result<Arc<TmpInode>> {
+        if name.len() > DIRSIZ {
+            return Err(FileNameTooLong);
+        }
+        let mut dp = self.ip.data.lock();
+        dp.access(self.ip.ftype, mode::W_OK | mode::X_OK)?;
+        if dp.nlink == 0 {
+            return Err(NotFound);
+        }
+        if dp.entries()?.contains_key(name) {
+            return Err(AlreadyExists);
+        }
+        let cred = cred();
+        let ino = self.fs.next_ino.fetch_add(1, Ordering::Relaxed);
+        let ip = new_inode(
+            ino,
+            ftype,
+            &self.fs.budget,
+            perm,
+            cred.euid,
+            cred.egid,
+            content,
+        );
+        dp.entries()?.insert(name.to_string(), Arc::clone(&ip));
+        if ftype == FileType::Dir {
+            dp.nlink += 1; // for ".."
+        }
+        dp.touch_mtime_ctime();
+        Ok(ip)
+    }
+}
+
+impl Node for TmpNode {
+    fn dev(&self) -> u32 {
+        self.fs.dev
+    }
+
+    fn ino(&self) -> u32 {
+        self.ip.ino
+    }
+
+    fn stat(&self, st: &mut Stat) -> Result<()> {
+        let data = self.ip.data.lock();
+        st.dev = self.fs.dev;
+        st.ino = self.ip.ino;
+        st.ftype = self.ip.ftype;
+        st.nlink = data.nlink;
+        st.mode = data.mode;
+        st.uid = data.uid;
+        st.gid = data.gid;
+        st.size = data.size;
+        st.atime = data.atime;
+        st.mtime = data.mtime;
+        st.ctime = data.ctime;
+        Ok(())
+    }
+
+    fn file_type(&self) -> FileType {
+        self.ip.ftype
+    }
+
+    fn major(&self) -> Major {
+        self.ip.data.lock().major
+    }
+
+    fn access(&self, want: u32) -> Result<()> {
+        self.ip.data.lock().access(self.ip.ftype, want)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Vnode> {
+        let ip = match name {
+            "." if self.ip.is_dir() => Arc::clone(&self.ip),
+            ".." if self.ip.is_dir() => self.ip.parent(),
+            _ => Arc::clone(self.ip.data.lock().entries()?.get(name).ok_or(NotFound)?),
+        };
+        Ok(self.node(ip))
+    }
+
+    fn create(
+        &self,
+        name: &str,
+        ftype: FileType,
+        major: u16,
+        minor: u16,
+        perm: u32,
+    ) -> Result<Vnode> {
+        let _tree = TREE_LOCK.lock();
+        if let Ok(vn) = self.lookup(name) {
+            return match (ftype, vn.file_type()) {
+                (FileType::File, FileType::File | FileType::Device) => Ok(vn),
+                _ => Err(AlreadyExists),
+            };
+        }
+        let content = match ftype {
+            FileType::File => Content::File(Vec::new()),
+            FileType::Dir => Content::Dir {
+                entries: BTreeMap::new(),
+                parent: Arc::downgrade(&self.ip),
+            },
+            FileType::Device | FileType::Socket => Content::None,
+            _ => return Err(InvalidArgument),
+        };
+        let umask = Cpus::myproc().map_or(mode::UMASK, |p| p.data().umask);
+        let ip = self.add(name, ftype, perm & !umask, content)?;
+        {
+            let mut data = ip.data.lock();
+            data.major = Major::from_u16(major);
+            data.minor = minor;
+        }
+        Ok(self.node(ip))
+    }
+
+    fn open(self: Arc<Self>, omode: &OMode) -> Result<Vnode> {
+        if omode.is_trunc() && self.ip.ftype == FileType::File {
+            let mut data = self.ip.data.lock();
+            self.ip.resize(&mut data, 0)?;
+            data.touch_mtime_ctime();
+        }
+        Ok(self)
+    }
+
+    fn read(&self, mut dst: VirtAddr, off: u64, n: usize) -> Result<usize> {
+        let mut data = self.ip.data.lock();
+        let off = off as usize;
+        let Content::File(pages) = &data.content else {
+            return Err(match self.ip.ftype {
+                FileType::Dir => IsADirectory,
+                _ => InvalidArgument,
+            });
+        };
+        if off >= data.size {
+            return Ok(0);
+        }
+        let n = min(n, data.size - off);
+        let mut tot = 0;
+        while tot < n {
+            let pos = off + tot;
+            let m = min(n - tot, PGSIZE - pos % PGSIZE);
+            let page = pages[pos / PGSIZE].as_bytes();
+            either_copyout(dst, &page[pos % PGSIZE..pos % PGSIZE + m])?;
+            tot += m;
+            dst += m;
+        }
+        if tot > 0 {
+            data.atime = time::realtime();
+        }
+        Ok(tot)
+    }
+
+    fn write(&self, mut src: VirtAddr, off: &mut u64, n: usize, append: bool) -> Result<usize> {
+        let mut data = self.ip.data.lock();
+        if self.ip.ftype != FileType::File {
+            return Err(InvalidArgument);
+        }
+        if append {
+            *off = data.size as u64;
+        }
+        let start = *off as usize;
+        let end = start.checked_add(n).ok_or(FileTooLarge)?;
+        if end > data.size
+            && let Err(err) = self.ip.resize(&mut data, end)
+        {
+            // Write what fits in the pages the cap leaves.
+            let room = min((data.pages().len() + self.ip.budget.left()) * PGSIZE, end);
+            if room <= start || self.ip.resize(&mut data, room).is_err() {
+                return Err(err);
+            }
+        }
+        let n = min(n, data.size - start);
+        let pages = data.pages();
+        let mut tot = 0;
+        while tot < n {
+            let pos = start + tot;
+            let m = min(n - tot, PGSIZE - pos % PGSIZE);
+            let page = pages[pos / PGSIZE].as_bytes_mut();
+            either_copyin(&mut page[pos % PGSIZE..pos % PGSIZE + m], src)?;
+            tot += m;
+            src += m;
+        }
+        data.touch_mtime_ctime();
+        *off += tot as u64;
+        Ok(tot)
+    }
+
+    fn readdir(&self, off: &mut u64, mut dst: VirtAddr, n: usize) -> Result<usize> {
+        let parent = self.ip.parent();
+        let mut data = self.ip.data.lock();
+        let entries = data.entries()?;
+        // The cookie counts records: ".", ".." and then the entries in
+        // name order.
+        let dots = [(".", &self.ip), ("..", &parent)];
+        let records = dots
+            .into_iter()
+            .chain(entries.iter().map(|(name, ip)| (name.as_str(), ip)))
+            .skip(*off as usize);
+        let mut tot = 0;
+        for (name, ip) in records {
+            if !put_dirent(&mut dst, &mut tot, n, ip.ino, ip.ftype, name)? {
+                break;
+            }
+            *off += 1;
+        }
+        Ok(tot)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        if name == "." || name == ".." {
+            return Err(PermissionDenied);
+        }
+        let _tree = TREE_LOCK.lock();
+        let mut dp = self.ip.data.lock();
+        dp.access(self.ip.ftype, mode::W_OK | mode::X_OK)?;
+        let ip = Arc::clone(dp.entries()?.get(name).ok_or(NotFound)?);
+        let mut data = ip.data.lock();
+        if ip.is_dir() {
+            if !data.entries()?.is_empty() {
+                return Err(DirectoryNotEmpty);
+            }
+            dp.nlink -= 1;
+            data.nlink = 0;
+        } else {
+            data.nlink -= 1;
+        }
+        data.ctime = time::realtime();
+        dp.entries()?.remove(name);
+        dp.touch_mtime_ctime();
+        Ok(())
+    }
+
+    fn link(&self, name: &str, target: &Vnode) -> Result<()> {
+        let ip = Arc::clone(self.peer(target)?);
+        if ip.is_dir() {
+            return Err(IsADirectory);
+        }
+        let _tree = TREE_LOCK.lock();
+        let mut dp = self.ip.data.lock();
+        dp.access(self.ip.ftype, mode::W_OK | mode::X_OK)?;
+        if dp.entries()?.contains_key(name) {
+            return Err(AlreadyExists);
+        }
+        {
+            let mut data = ip.data.lock();
+            data.nlink += 1;
+            data.ctime = time::realtime();
+        }
+        dp.entries()?.insert(name.to_string(), ip);
+        dp.touch_mtime_ctime();
+        Ok(())
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<()> {
+        if target.is_empty() || target.len() > MAXPATH {
+            return Err(InvalidArgument);
+        }
+        let _tree = TREE_LOCK.lock();
+        let ip = self.add(
+            name,
+            FileType::Symlink,
+            0o777,
+            Content::Symlink(target.to_string()),
+        )?;
+        ip.data.lock().size = target.len();
+        Ok(())
+    }
+
+    fn fsync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn readlink(&self, dst: VirtAddr, n: usize) -> Result<usize> {
+        let data = self.ip.data.lock();
+        let Content::Symlink(target) = &data.content else {
+            return Err(InvalidArgument);
+        };
+        let n = min(n, target.len());
+        either_copyout(dst, &target.as_bytes()[..n])?;
+        Ok(n)
+    }
+
+    fn rename(&self, name: &str, ndir: &Vnode, nname: &str) -> Result<()> {
+        for name in [name, nname] {
+            if name == "." || name == ".." || name == "/" {
+                return Err(InvalidArgument);
+            }
+        }
+        let ndp = Arc::clone(self.peer(ndir)?);
+        let odp = &self.ip;
+        let _tree = TREE_LOCK.lock();
+
+        let ip = Arc::clone(odp.data.lock().entries()?.get(name).ok_or(NotFound)?);
+        let tip = ndp.data.lock().entries()?.get(nname).cloned();
+
+        // Both names already refer to the same file.
+        if tip.as_ref().is_some_and(|tip| Arc::ptr_eq(tip, &ip)) {
+            return Ok(());
+        }
+        // A directory cannot move into its own subtree, and a target
+        // above the source still contains it.
+        if TmpNode::ancestors(&ndp)
+            .iter()
+            .any(|up| Arc::ptr_eq(up, &ip))
+        {
+            return Err(InvalidArgument);
+        }
+        if let Some(tip) = tip.as_ref() {
+            if TmpNode::ancestors(odp)
+                .iter()
+                .any(|up| Arc::ptr_eq(up, tip))
+            {
+                return Err(DirectoryNotEmpty);
+            }
+            match (ip.is_dir(), tip.is_dir()) {
+                (true, false) => return Err(NotADirectory),
+                (false, true) => return Err(IsADirectory),
+                (true, true) if !tip.data.lock().entries()?.is_empty() => {
+                    return Err(DirectoryNotEmpty);
+                }
+                _ => {}
+            }
+        }
+
+        let same_dir = Arc::ptr_eq(odp, &ndp);
+        let mut od = odp.data.lock();
+        od.access(odp.ftype, mode::W_OK | mode::X_OK)?;
+        let mut nd = if same_dir {
+            None
+        } else {
+            Some(ndp.data.lock())
+        };
+        if let Some(nd) = nd.as_ref() {
+            nd.access(ndp.ftype, mode::W_OK | mode::X_OK)?;
+        }
+        {
+            let nd = nd.as_mut().unwrap_or(&mut od);
+            if nd.nlink == 0 {
+                // new's parent was removed while we were looking.
+                return Err(NotFound);
+            }
+            if let Some(tip) = nd.entries()?.insert(nname.to_string(), Arc::clone(&ip)) {
+                let mut data = tip.data.lock();
+                if tip.is_dir() {
+                    nd.nlink -= 1; // for target's ".."
+                    data.nlink = 0;
+                } else {
+                    data.nlink -= 1;
+                }
+                data.ctime = time::realtime();
+            }
+            nd.touch_mtime_ctime();
+        }
+        od.entries()?.remove(name);
+        od.touch_mtime_ctime();
+
+        let mut data = ip.data.lock();
+        if ip.is_dir() && !same_dir {
+            if let Content::Dir { parent, .. } = &mut data.content {
+                *parent = Arc::downgrade(&ndp);
+            }
+            od.nlink -= 1;
+            nd.as_mut().unwrap().nlink += 1;
+        }
+        data.ctime = time::realtime();
+        Ok(())
+    }
+
+    fn truncate(&self, len: usize) -> Result<()> {
+        match self.ip.ftype {
+            FileType::File => {}
+            FileType::Dir => return Err(IsADirectory),
+            _ => return Err(InvalidArgument),
+        }
+        let mut data = self.ip.data.lock();
+        self.ip.resize(&mut data, len)?;
+        data.touch_mtime_ctime();
+        Ok(())
+    }
+
+    fn chmod(&self, perm: u32) -> Result<()> {
+        let mut data = self.ip.data.lock();
+        data.owner_or_root()?;
+        data.mode = perm & mode::ALL;
+        data.ctime = time::realtime();
+        Ok(())
+    }
+
+    // Same rules as fs::chown.
+    fn chown(&self, uid: u32, gid: u32) -> Result<()> {
+        let mut data = self.ip.data.lock();
+        let uid = if uid == u32::MAX { data.uid } else { uid };
+        let gid = if gid == u32::MAX { data.gid } else { gid };
+        let cred = cred();
+        if !cred.is_root()
+            && (cred.euid != data.uid
+                || uid != data.uid
+                || (gid != data.gid && !cred.in_group(gid)))
+        {
+            return Err(PermissionDenied);
+        }
+        data.uid = uid;
+        data.gid = gid;
+        data.ctime = time::realtime();
+        Ok(())
+    }
+
+    // Same rules as fs::utimens.
+    fn utimens(&self, atime: Option<u64>, mtime: Option<u64>, now: bool) -> Result<()> {
+        let mut data = self.ip.data.lock();
+        if data.owner_or_root().is_err() {
+            if !now {
+                return Err(PermissionDenied);
+            }
+            data.access(self.ip.ftype, mode::W_OK)?;
+        }
+        if let Some(atime) = atime {
+            data.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            data.mtime = mtime;
+        }
+        data.ctime = time::realtime();
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
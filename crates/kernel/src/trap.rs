@@ -2,23 +2,24 @@ use core::arch::asm;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::{
-    imsic,
+    aplic, imsic,
     kernelvec::kernelvec,
+    log::LOG,
     memlayout::{
-        STACK_PAGE_NUM, TRAMPOLINE, UART0_IRQ, VIRTIO0_IRQ, VIRTIO1_IRQ, VIRTIO2_IRQ, VIRTIO3_IRQ,
-        VIRTIO4_IRQ,
+        STACK_PAGE_NUM, TRAMPOLINE, UART0_IRQ, VIRTIO1_IRQ, VIRTIO2_IRQ, VIRTIO3_IRQ, VIRTIO4_IRQ,
     },
     proc::{self, Cpus, ProcState},
     riscv::{
         registers::{scause::*, *},
         *,
     },
+    signal::{SIGBUS, SIGILL, SIGSEGV},
     spinlock::Mutex,
     syscall::syscall,
     task,
     trampoline::trampoline,
     uart::UART,
-    virtio_disk::DISK,
+    virtio_disk,
     virtio_gpu::GPU,
     virtio_input::{KBD, MOUSE},
     virtio_net::NET,
@@ -103,7 +104,7 @@ pub extern "C" fn usertrap() -> ! {
                 if va.into_usize() < as_inner.sz {
                     let uvm = as_inner.uvm.as_mut().unwrap();
                     if uvm.resolve_cow(va).is_err() {
-                        p.inner.lock().killed = true;
+                        proc::fault(&p, SIGSEGV, fault);
                     }
                     did_cow = true;
                 }
@@ -112,7 +113,7 @@ pub extern "C" fn usertrap() -> ! {
                 // lazy mmap
                 intr_on();
                 if proc::handle_user_page_fault(fault, Exception::StorePageFault).is_err() {
-                    p.inner.lock().killed = true;
+                    proc::fault(&p, SIGSEGV, fault);
                 }
             }
         }
@@ -121,9 +122,18 @@ pub extern "C" fn usertrap() -> ! {
             intr_on();
             let fault = stval::read();
             if proc::handle_user_page_fault(fault, e).is_err() {
-                p.inner.lock().killed = true;
+                proc::fault(&p, SIGSEGV, fault);
             }
         }
+        Trap::Exception(Exception::IllegalInstruction) => proc::fault(&p, SIGILL, tf.epc),
+        Trap::Exception(
+            Exception::InstructionMisaligned
+            | Exception::LoadMisaligned
+            | Exception::StoreMisaligned,
+        ) => proc::fault(&p, SIGBUS, stval::read()),
+        Trap::Exception(
+            Exception::InstructionFault | Exception::LoadFault | Exception::StoreFault,
+        ) => proc::fault(&p, SIGSEGV, stval::read()),
         Trap::Interrupt(intr)
             if {
                 which_dev = devintr(intr);
@@ -153,6 +163,7 @@ pub extern "C" fn usertrap() -> ! {
 
     // give up the CPU if this is a timer interrupt.
     if Some(Intr::Timer) == which_dev {
+        LOG.commit_stale();
         proc::yielding()
     }
 
@@ -310,9 +321,10 @@ fn devintr(intr: Interrupt) -> Option<Intr> {
                     break;
                 }
                 EXT_IRQS.fetch_add(1, Ordering::Relaxed);
+                aplic::count(msg, unsafe { Cpus::cpu_id() });
                 match msg {
+                    _ if virtio_disk::intr(msg) => {}
                     UART0_IRQ => UART.intr(),
-                    VIRTIO0_IRQ => DISK.intr(),
                     VIRTIO1_IRQ => NET.intr(),
                     VIRTIO2_IRQ => GPU.lock().intr(),
                     VIRTIO3_IRQ => KBD.intr(),
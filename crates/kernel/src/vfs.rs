@@ -0,0 +1,578 @@
+// Virtual file system.
+//
+// Each file system implements FileSystem, which hands out its root
+// directory, and Node for every file, directory and symlink in it. A
+// vnode is a counted reference to a node. The mount table attaches the
+// root of one file system over a directory of another; path lookup
+// walks vnodes, stepping into a mounted root when it reaches a covered
+// directory and back out again when ".." leaves one.
+//
+// The inode file system on ROOTDEV (fs.rs) is mounted at "/", the dfs
+// client (dfs.rs) at /dfs, a tmpfs (tmpfs.rs) at /tmp and the process
+// file system (procfs.rs) at /proc by init().
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::defs::AsBytes;
+use crate::devfs::DevFs;
+use crate::dfs::{self, DFS_PREFIX};
+use crate::error::{Error::*, Result};
+use crate::fcntl::OMode;
+use crate::file::Major;
+use crate::fs::{DIRSIZ, InodeFs, Path};
+use crate::param::{MAXPATH, ROOTDEV, TMPFS_PAGES};
+use crate::proc::{Cpus, Cred, either_copyout};
+use crate::procfs::ProcFs;
+use crate::spinlock::Mutex;
+use crate::stat::{DirRecord, FileType, Stat, mode};
+use crate::tmpfs::TmpFs;
+use crate::virtio_disk;
+use crate::vm::VirtAddr;
+
+const SYMLINK_MAX_DEPTH: usize = 10;
+
+// Device numbers handed to file systems that have no disk.
+const ANON_DEV: u32 = 0x100;
+
+pub type Vnode = Arc<dyn Node>;
+
+// A file system that can be mounted.
+pub trait FileSystem: Send + Sync {
+    // Type name, as passed to mount.
+    fn name(&self) -> &'static str;
+    // Device number stat reports for its nodes.
+    fn dev(&self) -> u32;
+    fn root(self: Arc<Self>) -> Result<Vnode>;
+}
+
+// A file, directory or symlink of some file system.
+// Nodes hold a reference to their FileSystem, so that umount can tell
+// whether any of them is still in use.
+pub trait Node: Send + Sync {
+    // (dev, ino) identifies a node, e.g. as a mount point.
+    fn dev(&self) -> u32;
+    fn ino(&self) -> u32;
+    fn stat(&self, st: &mut Stat) -> Result<()>;
+
+    fn file_type(&self) -> FileType {
+        let mut st = Stat::default();
+        self.stat(&mut st).map_or(FileType::Empty, |_| st.ftype)
+    }
+
+    // Major number of a device node.
+    fn major(&self) -> Major {
+        Major::INVALID
+    }
+
+    // Check that the current process may access the node for want, a
+    // mask of mode::R_OK, W_OK and X_OK.
+    fn access(&self, _want: u32) -> Result<()> {
+        Ok(())
+    }
+
+    // Find name in this directory. "." and ".." never reach a file
+    // system's root, path lookup handles those.
+    fn lookup(&self, name: &str) -> Result<Vnode>;
+
+    // Create name in this directory with permission bits perm. Creating
+    // a File where a file or device already exists returns that one.
+    fn create(
+        &self,
+        name: &str,
+        ftype: FileType,
+        major: u16,
+        minor: u16,
+        perm: u32,
+    ) -> Result<Vnode>;
+
+    // The vnode an open file of this node reads and writes through,
+    // after applying omode's truncation.
+    fn open(self: Arc<Self>, omode: &OMode) -> Result<Vnode>;
+
+    fn read(&self, dst: VirtAddr, off: u64, n: usize) -> Result<usize>;
+
+    // Write n bytes at *off, or at the end of the file if append is
+    // set, and advance *off past them.
+    fn write(&self, src: VirtAddr, off: &mut u64, n: usize, append: bool) -> Result<usize>;
+
+    // Copy directory records from the cookie *off into dst, in getdents
+    // form, and advance it.
+    fn readdir(&self, off: &mut u64, dst: VirtAddr, n: usize) -> Result<usize>;
+
+    fn unlink(&self, name: &str) -> Result<()>;
+    // Add name in this directory for target.
+    fn link(&self, name: &str, target: &Vnode) -> Result<()>;
+    fn symlink(&self, name: &str, target: &str) -> Result<()>;
+    fn fsync(&self) -> Result<()>;
+
+    fn readlink(&self, _dst: VirtAddr, _n: usize) -> Result<usize> {
+        Err(InvalidArgument)
+    }
+
+    // Move name in this directory to nname in ndir.
+    fn rename(&self, _name: &str, _ndir: &Vnode, _nname: &str) -> Result<()> {
+        Err(CrossesDevices)
+    }
+
+    fn truncate(&self, _len: usize) -> Result<()> {
+        Err(InvalidArgument)
+    }
+
+    fn chmod(&self, _perm: u32) -> Result<()> {
+        Err(InvalidArgument)
+    }
+
+    fn chown(&self, _uid: u32, _gid: u32) -> Result<()> {
+        Err(InvalidArgument)
+    }
+
+    // Set the access and modification times, see fs::utimens.
+    fn utimens(&self, _atime: Option<u64>, _mtime: Option<u64>, _now: bool) -> Result<()> {
+        Err(InvalidArgument)
+    }
+
+    // The inode behind the node, for mmap.
+    fn inode(&self) -> Option<&crate::fs::Inode> {
+        None
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl core::fmt::Debug for dyn Node {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Vnode({}, {})", self.dev(), self.ino())
+    }
+}
+
+struct Mount {
+    fs: Arc<dyn FileSystem>,
+    source: String, // as given to mount, for /proc/mounts
+    root: Vnode,
+    covered: Option<Vnode>, // None for "/"
+}
+
+// Mounted file systems, "/" first.
+// Vnodes must not be dropped while holding the lock: dropping one may
+// sleep.
+static MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new(), "mounts");
+
+static NEXT_DEV: AtomicU32 = AtomicU32::new(ANON_DEV);
+
+// A device number for a file system without a disk.
+pub fn anon_dev() -> u32 {
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed)
+}
+
+// Credentials of the current process, or root's when a file system is
+// used outside of one.
+pub fn cred() -> Cred {
+    Cpus::myproc().map_or(Cred::default(), |p| p.data().cred)
+}
+
+// Check that the current process may access a node of type ftype with
+// permission bits perm, owned by uid and gid, for want.
+pub fn check_access(ftype: FileType, perm: u32, uid: u32, gid: u32, want: u32) -> Result<()> {
+    let cred = cred();
+    let granted = if cred.is_root() {
+        // root passes every check except executing a file that nobody
+        // may execute.
+        if ftype == FileType::Dir || perm & 0o111 != 0 {
+            0o7
+        } else {
+            0o6
+        }
+    } else if cred.euid == uid {
+        perm >> 6
+    } else if cred.in_group(gid) {
+        perm >> 3
+    } else {
+        perm
+    };
+    if granted & want == want {
+        Ok(())
+    } else {
+        Err(PermissionDenied)
+    }
+}
+
+fn same(a: &Vnode, b: &Vnode) -> bool {
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+// Mount the inode file system on ROOTDEV at "/", then devfs, dfs, tmpfs
+// and procfs.
+pub fn init() {
+    let fs = InodeFs::open(ROOTDEV).expect("vfs: cannot mount root");
+    let root = Arc::clone(&fs).root().expect("vfs: no root inode");
+    MOUNTS.lock().push(Mount {
+        fs,
+        source: "/dev/disk0".to_string(),
+        root,
+        covered: None,
+    });
+    let boot: [(Arc<dyn FileSystem>, &str, &str); 4] = [
+        (DevFs::new(), "devfs", "/dev"),
+        (dfs::DfsFs::new(), "none", DFS_PREFIX),
+        (TmpFs::new(TMPFS_PAGES), "tmpfs", "/tmp"),
+        (ProcFs::new(), "proc", "/proc"),
+    ];
+    for (fs, source, target) in boot {
+        let name = fs.name();
+        if let Err(err) = mount(fs, source, Path::new(target)) {
+            println!("vfs: cannot mount {} on {}: {}", name, target, err);
+        }
+    }
+}
+
+pub fn root() -> Vnode {
+    Arc::clone(&MOUNTS.lock()[0].root)
+}
+
+// A new file system of type fstype, for mount.
+pub fn new_fs(fstype: &str, source: &str) -> Result<Arc<dyn FileSystem>> {
+    match fstype {
+        "dfs" => Ok(dfs::DfsFs::new()),
+        "tmpfs" => Ok(TmpFs::from_options(source)?),
+        "proc" => Ok(ProcFs::new()),
+        "devfs" => Ok(DevFs::new()),
+        "xv6fs" => Ok(InodeFs::open(disk_of(source)?)?),
+        _ => Err(InvalidArgument),
+    }
+}
+
+// The device number of the disk whose node is at path source.
+pub fn disk_of(source: &str) -> Result<u32> {
+    let (_, vn) = Path::new(source).namei()?;
+    if vn.file_type() != FileType::Device {
+        return Err(InvalidArgument);
+    }
+    virtio_disk::dev_of(vn.major()).ok_or(InvalidArgument)
+}
+
+// Attach fs, made from source, over the directory at path.
+pub fn mount(fs: Arc<dyn FileSystem>, source: &str, path: &Path) -> Result<()> {
+    let (_, covered) = path.namei()?;
+    if covered.file_type() != FileType::Dir {
+        return Err(NotADirectory);
+    }
+    let root = Arc::clone(&fs).root()?;
+    let mut mounts = MOUNTS.lock();
+    // path resolves to a mounted root if something is mounted there.
+    if mounts.iter().any(|m| same(&m.root, &covered)) {
+        return Err(ResourceBusy);
+    }
+    mounts.push(Mount {
+        fs,
+        source: source.to_string(),
+        root,
+        covered: Some(covered),
+    });
+    Ok(())
+}
+
+// Detach the file system mounted at path. It must not be in use: no
+// vnode of it may be alive besides its root, held by the table and
+// by us.
+pub fn umount(path: &Path) -> Result<()> {
+    let (_, vn) = path.namei()?;
+    let mount = {
+        let mut mounts = MOUNTS.lock();
+        let i = mounts
+            .iter()
+            .position(|m| same(&m.root, &vn))
+            .ok_or(InvalidArgument)?;
+        let m = &mounts[i];
+        // The table and the root node each hold the file system.
+        if m.covered.is_none() || Arc::strong_count(&m.root) > 2 || Arc::strong_count(&m.fs) > 2 {
+            return Err(ResourceBusy);
+        }
+        mounts.remove(i)
+    };
+    drop(mount);
+    Ok(())
+}
+
+// The root mounted over vn, if any.
+fn mounted(vn: &Vnode) -> Option<Vnode> {
+    MOUNTS
+        .lock()
+        .iter()
+        .find(|m| m.covered.as_ref().is_some_and(|c| same(c, vn)))
+        .map(|m| Arc::clone(&m.root))
+}
+
+// The directory a mounted root vn covers, if it is one.
+fn covered(vn: &Vnode) -> Option<Vnode> {
+    MOUNTS
+        .lock()
+        .iter()
+        .find(|m| same(&m.root, vn))
+        .and_then(|m| m.covered.clone())
+}
+
+// Mounted file systems as (source, mount point, type), "/" first.
+pub fn mounts() -> Vec<(String, String, &'static str)> {
+    let table: Vec<_> = MOUNTS
+        .lock()
+        .iter()
+        .map(|m| (m.source.clone(), m.root.clone(), m.fs.name()))
+        .collect();
+    table
+        .into_iter()
+        .map(|(source, root, fstype)| {
+            let target = path_of(&root).unwrap_or_else(|_| "?".to_string());
+            (source, target, fstype)
+        })
+        .collect()
+}
+
+// The absolute path of directory dir: walk ".." up to "/", finding
+// each directory by number in its parent. A mounted root goes by the
+// name of the directory it covers.
+pub fn path_of(dir: &Vnode) -> Result<String> {
+    let root = root();
+    let mut names = Vec::new();
+    let mut cur = Arc::clone(dir);
+    while !same(&cur, &root) {
+        let parent = step(&cur, "..")?;
+        if same(&parent, &cur) {
+            return Err(NotFound); // a root that is no longer mounted
+        }
+        let ino = covered(&cur).map_or(cur.ino(), |c| c.ino());
+        names.push(name_of(&parent, ino)?);
+        cur = parent;
+    }
+    if names.is_empty() {
+        return Ok("/".to_string());
+    }
+    let mut path = String::new();
+    for name in names.iter().rev() {
+        path.push('/');
+        path.push_str(name);
+    }
+    Ok(path)
+}
+
+// The name of inode ino in directory dir.
+fn name_of(dir: &Vnode, ino: u32) -> Result<String> {
+    let hdr = size_of::<DirRecord>();
+    let mut buf = [0u8; 2 * DirRecord::rec_len(DIRSIZ)];
+    let mut off = 0;
+    loop {
+        let n = dir.readdir(
+            &mut off,
+            VirtAddr::Kernel(buf.as_mut_ptr() as usize),
+            buf.len(),
+        )?;
+        if n == 0 {
+            return Err(NotFound);
+        }
+        let mut pos = 0;
+        while pos < n {
+            let mut dr = DirRecord::default();
+            dr.as_bytes_mut().copy_from_slice(&buf[pos..pos + hdr]);
+            let name = &buf[pos + hdr..pos + hdr + dr.namelen as usize];
+            if dr.ino == ino && name != b"." && name != b".." {
+                return core::str::from_utf8(name)
+                    .map(|name| name.to_string())
+                    .or(Err(Utf8Error));
+            }
+            pos += dr.reclen as usize;
+        }
+    }
+}
+
+// Copy a directory record for name out to *dst for readdir, if it fits
+// in the n bytes asked for, and advance *dst and *tot past it. The first
+// record of a call must fit.
+pub fn put_dirent(
+    dst: &mut VirtAddr,
+    tot: &mut usize,
+    n: usize,
+    ino: u32,
+    ftype: FileType,
+    name: &str,
+) -> Result<bool> {
+    let hdr = size_of::<DirRecord>();
+    let reclen = DirRecord::rec_len(name.len());
+    if *tot + reclen > n {
+        return if *tot == 0 {
+            Err(InvalidArgument)
+        } else {
+            Ok(false)
+        };
+    }
+    let mut rec = [0u8; DirRecord::rec_len(DIRSIZ)];
+    let dr = DirRecord {
+        ino,
+        ftype,
+        namelen: name.len() as u16,
+        reclen: reclen as u16,
+        _pad: 0,
+    };
+    rec[..hdr].copy_from_slice(dr.as_bytes());
+    rec[hdr..hdr + name.len()].copy_from_slice(name.as_bytes());
+    either_copyout(*dst, &rec[..reclen])?;
+    *dst += reclen;
+    *tot += reclen;
+    Ok(true)
+}
+
+// Look name up in directory dir. ".." from a mounted root is looked up
+// in the directory it covers, and a covered result is replaced by the
+// root mounted over it.
+fn step(dir: &Vnode, name: &str) -> Result<Vnode> {
+    let next = match name {
+        "." => Arc::clone(dir),
+        ".." => match covered(dir) {
+            Some(covered) => covered.lookup("..")?,
+            None => dir.lookup("..")?,
+        },
+        _ => dir.lookup(name)?,
+    };
+    Ok(mounted(&next).unwrap_or(next))
+}
+
+// Mount points cannot be removed or renamed.
+fn not_mount_point(dir: &Vnode, name: &str) -> Result<()> {
+    match dir.lookup(name) {
+        Ok(vn) if mounted(&vn).is_some() => Err(ResourceBusy),
+        _ => Ok(()),
+    }
+}
+
+fn read_link(vn: &Vnode) -> Result<String> {
+    let mut buf = [0u8; MAXPATH];
+    let n = vn.readlink(VirtAddr::Kernel(buf.as_mut_ptr() as usize), MAXPATH)?;
+    if n == 0 {
+        return Err(InvalidArgument);
+    }
+    core::str::from_utf8(&buf[..n])
+        .map(String::from)
+        .or(Err(Utf8Error))
+}
+
+impl Path {
+    // Look up and return the vnode for a path name.
+    // If `parent` is true, return the vnode for the parent.
+    // If `follow` is false, a symlink in the final element is returned
+    // itself rather than resolved.
+    // Must not be called inside a transaction: dropping a vnode may
+    // start one.
+    pub fn namex(path: &Path, parent: bool, follow: bool) -> Result<(&str, Vnode)> {
+        if path.as_str().split('/').any(|name| name.len() > DIRSIZ) {
+            return Err(FileNameTooLong);
+        }
+        let trimmed = path.as_str().trim_matches('/');
+        let orig_name = if trimmed.is_empty() {
+            "/"
+        } else {
+            match trimmed.rsplit_once('/') {
+                Some((_, name)) => name,
+                None => trimmed,
+            }
+        };
+        let mut vn = match path.as_str().get(0..1) {
+            Some("/") => root(),
+            _ => Arc::clone(Cpus::myproc().unwrap().data().cwd.as_ref().unwrap()),
+        };
+
+        let mut path = path;
+        let mut link_paths: Vec<String> = Vec::new();
+        let mut link_depth = 0usize;
+        loop {
+            if vn.file_type() != FileType::Dir {
+                return Err(NotADirectory);
+            }
+            vn.access(mode::X_OK)?;
+            match path.skip_elem() {
+                (Some(name), rest) if rest.is_some() || !parent => {
+                    let next = step(&vn, name)?;
+                    if next.file_type() == FileType::Symlink && (rest.is_some() || follow) {
+                        if link_depth >= SYMLINK_MAX_DEPTH {
+                            return Err(TooManyLinks);
+                        }
+                        link_depth += 1;
+                        let mut new_path = read_link(&next)?;
+                        if let Some(rest) = rest {
+                            if !new_path.ends_with('/') {
+                                new_path.push('/');
+                            }
+                            new_path.push_str(rest.as_str());
+                        }
+                        link_paths.push(new_path);
+                        let path_str = link_paths.last().unwrap();
+                        if path_str.starts_with('/') {
+                            vn = root();
+                        }
+                        path = Path::new(path_str);
+                        continue;
+                    }
+                    match rest {
+                        Some(rest) => {
+                            vn = next;
+                            path = rest;
+                        }
+                        None => break Ok((orig_name, next)),
+                    }
+                }
+                (Some(_), None) => break Ok((orig_name, vn)),
+                _ if path.as_str() == "/" => break Ok(("/", vn)),
+                _ => break Err(NotFound),
+            }
+        }
+    }
+
+    pub fn namei(&self) -> Result<(&str, Vnode)> {
+        Self::namex(self, false, true)
+    }
+
+    // Like namei, but a final symlink is not followed.
+    pub fn namei_nofollow(&self) -> Result<(&str, Vnode)> {
+        Self::namex(self, false, false)
+    }
+
+    pub fn nameiparent(&self) -> Result<(&str, Vnode)> {
+        Self::namex(self, true, true)
+    }
+}
+
+// Create path; see Node::create.
+pub fn create(path: &Path, ftype: FileType, major: u16, minor: u16, perm: u32) -> Result<Vnode> {
+    let (name, dp) = path.nameiparent()?;
+    dp.create(name, ftype, major, minor, perm)
+}
+
+// Create the path new as a link to the same file as old.
+pub fn link(old: &Path, new: &Path) -> Result<()> {
+    let (_, vn) = old.namei()?;
+    let (name, dp) = new.nameiparent()?;
+    dp.link(name, &vn)
+}
+
+pub fn unlink(path: &Path) -> Result<()> {
+    let (name, dp) = path.nameiparent()?;
+    not_mount_point(&dp, name)?;
+    dp.unlink(name)
+}
+
+// Move old to new, replacing new if it exists. Both must be on the same
+// file system.
+pub fn rename(old: &Path, new: &Path) -> Result<()> {
+    let (oname, odp) = old.nameiparent()?;
+    let (nname, ndp) = new.nameiparent()?;
+    not_mount_point(&odp, oname)?;
+    not_mount_point(&ndp, nname)?;
+    odp.rename(oname, &ndp, nname)
+}
+
+pub fn symlink(target: &str, linkpath: &Path) -> Result<()> {
+    let (name, dp) = linkpath.nameiparent()?;
+    dp.symlink(name, target)
+}
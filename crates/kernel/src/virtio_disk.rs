@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use core::{
     convert::TryInto,
     sync::atomic::{Ordering, fence},
@@ -8,37 +9,69 @@ use crate::{
     bio::{BCACHE, Data},
     error::{Error::*, Result},
     file::{DEVSW, Device, Major},
-    fs::{BSIZE, SB},
-    memlayout::VIRTIO0,
-    param::ROOTDEV,
+    fs::BSIZE,
+    memlayout::VIRTIO_SLOTS,
+    param::{NDISK, ROOTDEV},
     proc::{self, either_copyin, either_copyout},
     sleeplock::SleepLockGuard,
-    spinlock::Mutex,
+    spinlock::{Mutex, MutexGuard},
+    sync::OnceLock,
     vm::VirtAddr,
 };
 
-// driver for qemu's virtio disk device.
+// driver for qemu's virtio disk devices.
 // uses qemu's mmio interface to virtio.
 //
+// init() probes every virtio mmio slot and drives each virtio-blk
+// device it finds as a disk, in slot order: disk0 has device number
+// ROOTDEV and holds the root file system, disk1 has ROOTDEV + 1, and
+// so on. Each disk gets a node in /dev, and the buffer cache, the
+// superblocks and the logs tell disks apart by device number.
+//
 // qemu ... -drive file=fs.img,if=none,format=raw,id=x0 -device
 // virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0
+// qemu ... -drive file=data.img,if=none,format=raw,id=x1 -device
+// virtio-blk-device,drive=x1,bus=virtio-mmio-bus.5
+
+static DISKS: [OnceLock<BlkDev>; NDISK] = [const { OnceLock::new() }; NDISK];
+const NAMES: [&str; NDISK] = ["disk0", "disk1", "disk2", "disk3"];
+
+// One disk: its queue, and its raw device file.
+pub struct BlkDev {
+    dev: u32,
+    irq: u32,
+    size: usize, // bytes
+    disk: Mutex<Disk>,
+}
 
-pub static DISK: Mutex<Disk> = Mutex::new(Disk::new(), "virtio_disk");
-pub static RAW_DISK: RawDisk = RawDisk;
+// The queue of disk dev.
+pub fn disk(dev: u32) -> &'static Mutex<Disk> {
+    &blkdev(dev).expect("virtio disk: no such disk").disk
+}
 
-pub struct RawDisk;
+fn blkdev(dev: u32) -> Option<&'static BlkDev> {
+    DISKS.get(dev.checked_sub(ROOTDEV)? as usize)?.get()
+}
 
-impl RawDisk {
-    fn disk_size_bytes(&self) -> Result<usize> {
-        let sb = SB.get().ok_or(InvalidArgument)?;
-        let blocks = usize::try_from(sb.size).map_err(|_| InvalidArgument)?;
-        blocks.checked_mul(BSIZE).ok_or(InvalidArgument)
-    }
+fn disks() -> impl Iterator<Item = &'static BlkDev> {
+    DISKS.iter().map_while(OnceLock::get)
+}
+
+// The device number of the disk behind device node major, if it is one.
+pub fn dev_of(major: Major) -> Option<u32> {
+    let name = DEVSW.entry(major)?.name;
+    let i = NAMES.iter().position(|&n| n == name)?;
+    Some(DISKS[i].get()?.dev)
 }
 
-impl Device for RawDisk {
+// Size of disk dev in blocks.
+pub fn blocks(dev: u32) -> Option<usize> {
+    Some(blkdev(dev)?.size / BSIZE)
+}
+
+impl Device for BlkDev {
     fn read(&self, mut dst: VirtAddr, mut n: usize, offset: usize) -> Result<usize> {
-        let size = self.disk_size_bytes()?;
+        let size = self.size;
         if offset >= size {
             return Ok(0);
         }
@@ -52,7 +85,7 @@ impl Device for RawDisk {
             let blockno = (off / BSIZE) as u32;
             let block_off = off % BSIZE;
             let chunk = core::cmp::min(n - done, BSIZE - block_off);
-            let bp = BCACHE.read(ROOTDEV, blockno);
+            let bp = BCACHE.read(self.dev, blockno);
             either_copyout(dst, &bp[block_off..(block_off + chunk)])?;
             done += chunk;
             off += chunk;
@@ -62,7 +95,7 @@ impl Device for RawDisk {
     }
 
     fn write(&self, mut src: VirtAddr, mut n: usize, offset: usize) -> Result<usize> {
-        let size = self.disk_size_bytes()?;
+        let size = self.size;
         if offset >= size {
             return Err(InvalidArgument);
         }
@@ -76,9 +109,9 @@ impl Device for RawDisk {
             let blockno = (off / BSIZE) as u32;
             let block_off = off % BSIZE;
             let chunk = core::cmp::min(n - done, BSIZE - block_off);
-            let mut bp = BCACHE.read(ROOTDEV, blockno);
+            let mut bp = BCACHE.read(self.dev, blockno);
             either_copyin(&mut bp[block_off..(block_off + chunk)], src)?;
-            bp.write();
+            bp.write_back();
             done += chunk;
             off += chunk;
             src += chunk;
@@ -86,8 +119,13 @@ impl Device for RawDisk {
         Ok(done)
     }
 
-    fn major(&self) -> Major {
-        Major::Disk
+    fn size(&self) -> Option<usize> {
+        Some(self.size)
+    }
+
+    fn sync(&self) -> Result<()> {
+        BCACHE.flush();
+        Ok(())
     }
 }
 
@@ -129,20 +167,31 @@ enum VirtioMMIO {
     // physical address for used ring, write-only
     DeviceDescLow = 0x0a0,
     DeviceDescHigh = 0x0a4,
+    // device config: capacity in 512-byte sectors, read-only
+    CapacityLow = 0x100,
+    CapacityHigh = 0x104,
 }
 
 impl VirtioMMIO {
-    fn read(self) -> u32 {
-        unsafe { core::ptr::read_volatile((VIRTIO0 + self as usize) as *const u32) }
+    fn read(self, base: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((base + self as usize) as *const u32) }
     }
 
-    unsafe fn write(self, data: u32) {
+    unsafe fn write(self, base: usize, data: u32) {
         unsafe {
-            core::ptr::write_volatile((VIRTIO0 + self as usize) as *mut u32, data);
+            core::ptr::write_volatile((base + self as usize) as *mut u32, data);
         }
     }
 }
 
+// Is there a virtio disk in the slot at base?
+fn probe(base: usize) -> bool {
+    VirtioMMIO::MagicValue.read(base) == 0x74726976
+        && VirtioMMIO::Version.read(base) == 2
+        && VirtioMMIO::DeviceId.read(base) == 2
+        && VirtioMMIO::VendorId.read(base) == 0x554d4551
+}
+
 type VirtioStatus = u32;
 // Status register bits, from qemu virtio_config.h
 mod virtio_status {
@@ -167,9 +216,10 @@ mod virtio_features {
     pub(crate) const RING_F_EVENT_IDX: u32 = 1 << 29;
 }
 
-// this many virtio descriptors.
+// this many virtio descriptors, room for a readahead batch
+// of READAHEAD + 1 requests of three each.
 // must be a power of 2.
-const NUM: usize = 8;
+const NUM: usize = 32;
 
 #[repr(C)]
 pub struct Disk {
@@ -203,6 +253,9 @@ pub struct Disk {
     // disk command handlers.
     // one-for-one with descriptors, for convenience.
     ops: [VirtioBlkReq; NUM],
+
+    // mmio registers of the slot.
+    base: usize,
 }
 
 // a single descriptor, from the spec
@@ -335,7 +388,7 @@ impl VirtioBlkReq {
 }
 
 impl Disk {
-    const fn new() -> Self {
+    const fn new(base: usize) -> Self {
         Self {
             desc: [VirtqDesc::new(); NUM],
             avail: VirtqAvail::new(),
@@ -344,34 +397,28 @@ impl Disk {
             used_idx: 0,
             info: array![Info::new(); NUM],
             ops: [VirtioBlkReq::new(); NUM],
+            base,
         }
     }
 
     unsafe fn init(&mut self) {
         unsafe {
+            let base = self.base;
             let mut status: VirtioStatus = 0;
 
-            if VirtioMMIO::MagicValue.read() != 0x74726976
-                || VirtioMMIO::Version.read() != 2
-                || VirtioMMIO::DeviceId.read() != 2
-                || VirtioMMIO::VendorId.read() != 0x554d4551
-            {
-                panic!("could not find virtio disk");
-            }
-
             // reset device
-            VirtioMMIO::Status.write(status);
+            VirtioMMIO::Status.write(base, status);
 
             // set ACKNOWLEDGE status bit
             status |= virtio_status::ACKNOWLEDGE;
-            VirtioMMIO::Status.write(status);
+            VirtioMMIO::Status.write(base, status);
 
             // set DRIVER status bit
             status |= virtio_status::DRIVER;
-            VirtioMMIO::Status.write(status);
+            VirtioMMIO::Status.write(base, status);
 
             // negotiate features
-            let mut features = VirtioMMIO::DeviceFeatures.read();
+            let mut features = VirtioMMIO::DeviceFeatures.read(base);
             features &= !(virtio_features::BLK_F_RO);
             features &= !(virtio_features::BLK_F_SCSI);
             features &= !(virtio_features::BLK_F_CONFIG_WCE);
@@ -379,55 +426,55 @@ impl Disk {
             features &= !(virtio_features::F_ANY_LAYOUT);
             features &= !(virtio_features::RING_F_EVENT_IDX);
             features &= !(virtio_features::RING_F_INDIRECT_DESC);
-            VirtioMMIO::DriverFeatures.write(features);
+            VirtioMMIO::DriverFeatures.write(base, features);
 
             // tell device that feature negotiation is complete.
             status |= virtio_status::FEATURES_OK;
-            VirtioMMIO::Status.write(status);
+            VirtioMMIO::Status.write(base, status);
 
             // re-read status to ensure FEATURES_OK is set.
-            status = VirtioMMIO::Status.read();
+            status = VirtioMMIO::Status.read(base);
             assert!(
                 status & virtio_status::FEATURES_OK != 0,
                 "virtio disk FEATURES_OK unset"
             );
 
             // initialize queue 0.
-            VirtioMMIO::QueueSel.write(0);
+            VirtioMMIO::QueueSel.write(base, 0);
 
             // ensure queue 0 is not in use
             assert!(
-                VirtioMMIO::QueueReady.read() == 0,
+                VirtioMMIO::QueueReady.read(base) == 0,
                 "virtio disk should not be ready"
             );
 
             // check maximum queue size.
-            let max = VirtioMMIO::QueueNumMax.read();
+            let max = VirtioMMIO::QueueNumMax.read(base);
             assert!(max != 0, "virtio disk has no queue 0");
             assert!(max >= NUM as u32, "virtio disk max queue too short");
 
             // set queue size.
-            VirtioMMIO::QueueNum.write(NUM as _);
+            VirtioMMIO::QueueNum.write(base, NUM as _);
 
             // write physical addresses.
-            VirtioMMIO::QueueDescLow.write(&self.desc as *const _ as u64 as u32);
-            VirtioMMIO::QueueDescHigh.write((&self.desc as *const _ as u64 >> 32) as u32);
-            VirtioMMIO::DriverDescLow.write(&self.avail as *const _ as u64 as u32);
-            VirtioMMIO::DriverDescHigh.write((&self.avail as *const _ as u64 >> 32) as u32);
-            VirtioMMIO::DeviceDescLow.write(&self.used as *const _ as u64 as u32);
-            VirtioMMIO::DeviceDescHigh.write((&self.used as *const _ as u64 >> 32) as u32);
+            VirtioMMIO::QueueDescLow.write(base, &self.desc as *const _ as u64 as u32);
+            VirtioMMIO::QueueDescHigh.write(base, (&self.desc as *const _ as u64 >> 32) as u32);
+            VirtioMMIO::DriverDescLow.write(base, &self.avail as *const _ as u64 as u32);
+            VirtioMMIO::DriverDescHigh.write(base, (&self.avail as *const _ as u64 >> 32) as u32);
+            VirtioMMIO::DeviceDescLow.write(base, &self.used as *const _ as u64 as u32);
+            VirtioMMIO::DeviceDescHigh.write(base, (&self.used as *const _ as u64 >> 32) as u32);
 
             // queue is ready.
-            VirtioMMIO::QueueReady.write(0x1);
+            VirtioMMIO::QueueReady.write(base, 0x1);
 
             // all NUM descriptors start out unused.
             self.free.iter_mut().for_each(|f| *f = true);
 
             // tell device we're completely ready.
             status |= virtio_status::DRIVER_OK;
-            VirtioMMIO::Status.write(status);
+            VirtioMMIO::Status.write(base, status);
 
-            // imsic.rs and trap.rs arrange for interrupts from VIRTIO0_IRQ.
+            // imsic.rs and trap.rs arrange for interrupts from the slot.
         }
     }
 
@@ -490,35 +537,16 @@ impl Disk {
     }
 }
 
-impl Mutex<Disk> {
-    pub fn rw(
-        &self,
-        b: Option<SleepLockGuard<'static, Data>>,
-        write: bool,
-    ) -> Option<SleepLockGuard<'static, Data>> {
-        let mut b = b.unwrap();
+impl Disk {
+    // Format the three descriptors in idx for a transfer of b and tell
+    // the device. The buffer stays in info until the request is reaped.
+    fn start(&mut self, idx: [usize; 3], mut b: SleepLockGuard<'static, Data>, write: bool) {
         let sector = b.blockno() as usize * (BSIZE / 512);
 
-        let mut guard = self.lock();
-        // let p = CPUS.my_proc().unwrap();
-
-        // the spec's Section 5.2 says that legacy block operations use
-        // three descriptors: one for type/reserved/sector, one for the
-        // data, one for a 1-byte status result.
-
-        // allocate the three descriptors.
-        let mut idx: [usize; 3] = [0; 3];
-        loop {
-            if guard.alloc3_desc(&mut idx).is_ok() {
-                break;
-            }
-            guard = proc::sleep(&guard.free[0] as *const _ as usize, guard);
-        }
-
         // format the three descriptors.
         // qemu's virtio-blk.c reads them.
 
-        let buf0 = guard.ops.get_mut(idx[0]).unwrap();
+        let buf0 = self.ops.get_mut(idx[0]).unwrap();
         buf0.type_ = if write {
             VIRTIO_BLK_T_OUT // write the disk
         } else {
@@ -527,57 +555,157 @@ impl Mutex<Disk> {
         buf0.reserved = 0;
         buf0.sector = sector as u64;
 
-        guard.desc[idx[0]].addr = buf0 as *mut _ as u64;
-        guard.desc[idx[0]].len = core::mem::size_of::<VirtioBlkReq>().try_into().unwrap();
-        guard.desc[idx[0]].flags = virtq_desc_flags::NEXT;
-        guard.desc[idx[0]].next = idx[1].try_into().unwrap();
+        self.desc[idx[0]].addr = buf0 as *mut _ as u64;
+        self.desc[idx[0]].len = core::mem::size_of::<VirtioBlkReq>().try_into().unwrap();
+        self.desc[idx[0]].flags = virtq_desc_flags::NEXT;
+        self.desc[idx[0]].next = idx[1].try_into().unwrap();
 
-        guard.desc[idx[1]].addr = &b.data as *const _ as u64;
-        guard.desc[idx[1]].len = BSIZE.try_into().unwrap();
-        guard.desc[idx[1]].flags = if write {
+        self.desc[idx[1]].addr = &b.data as *const _ as u64;
+        self.desc[idx[1]].len = BSIZE.try_into().unwrap();
+        self.desc[idx[1]].flags = if write {
             0
         } else {
             virtq_desc_flags::WRITE // device writes b->data
         };
-        guard.desc[idx[1]].flags |= virtq_desc_flags::NEXT;
-        guard.desc[idx[1]].next = idx[2].try_into().unwrap();
+        self.desc[idx[1]].flags |= virtq_desc_flags::NEXT;
+        self.desc[idx[1]].next = idx[2].try_into().unwrap();
 
-        guard.info[idx[0]].status = 0xff; // device writes 0 on success
-        guard.desc[idx[2]].addr = &mut guard.info[idx[0]].status as *mut _ as u64;
-        guard.desc[idx[2]].len = 1;
-        guard.desc[idx[2]].flags = virtq_desc_flags::WRITE; // device write the status
-        guard.desc[idx[2]].next = 0;
+        self.info[idx[0]].status = 0xff; // device writes 0 on success
+        self.desc[idx[2]].addr = &mut self.info[idx[0]].status as *mut _ as u64;
+        self.desc[idx[2]].len = 1;
+        self.desc[idx[2]].flags = virtq_desc_flags::WRITE; // device write the status
+        self.desc[idx[2]].next = 0;
 
         // record struct buf for intr()
         b.disk = true;
-        guard.info[idx[0]].buf.replace(b);
+        self.info[idx[0]].buf.replace(b);
 
         // tell the device the first index in our chain of descriptors.
-        let i = guard.avail.idx as usize % NUM;
-        guard.avail.ring[i] = idx[0].try_into().unwrap();
+        let i = self.avail.idx as usize % NUM;
+        self.avail.ring[i] = idx[0].try_into().unwrap();
 
         fence(Ordering::SeqCst);
 
         // tell the device another avail ring entry is available.
-        guard.avail.idx += 1; // not % NUM ...
+        self.avail.idx += 1; // not % NUM ...
 
         fence(Ordering::SeqCst);
 
         unsafe {
-            VirtioMMIO::QueueNotify.write(0); // value is queue number
+            VirtioMMIO::QueueNotify.write(self.base, 0); // value is queue number
         }
+    }
 
-        // wait for intr() to say request has finished.
-        while let Some(ref b) = guard.info[idx[0]].buf {
-            if b.disk {
-                guard = proc::sleep(&b.data as *const _ as usize, guard);
-            } else {
+    // Take back the buffer of request id if the device is done with it,
+    // and free its descriptors.
+    fn reap(&mut self, id: usize) -> Option<SleepLockGuard<'static, Data>> {
+        if self.info[id].buf.as_ref()?.disk {
+            return None;
+        }
+        self.free_chain(id);
+        self.info[id].buf.take()
+    }
+}
+
+impl Mutex<Disk> {
+    pub fn rw(
+        &self,
+        b: Option<SleepLockGuard<'static, Data>>,
+        write: bool,
+    ) -> Option<SleepLockGuard<'static, Data>> {
+        let b = b.unwrap();
+        let mut guard = self.lock();
+
+        // the spec's Section 5.2 says that legacy block operations use
+        // three descriptors: one for type/reserved/sector, one for the
+        // data, one for a 1-byte status result.
+
+        // allocate the three descriptors.
+        let mut idx: [usize; 3] = [0; 3];
+        loop {
+            if guard.alloc3_desc(&mut idx).is_ok() {
                 break;
             }
+            guard = proc::sleep(&guard.free[0] as *const _ as usize, guard);
+        }
+        guard.start(idx, b, write);
+        Some(self.wait(guard, idx[0]).0)
+    }
+
+    // Read several blocks in one round trip, for readahead. Only the
+    // first request may sleep for descriptors: later ones are skipped
+    // when the queue is full, since the descriptors they would wait for
+    // belong to this batch. Returns each buffer and whether it was read.
+    pub fn read_batch(
+        &self,
+        bufs: Vec<SleepLockGuard<'static, Data>>,
+    ) -> Vec<(SleepLockGuard<'static, Data>, bool)> {
+        let mut guard = self.lock();
+        let mut queued = Vec::with_capacity(bufs.len());
+        for b in bufs {
+            let mut idx: [usize; 3] = [0; 3];
+            loop {
+                if guard.alloc3_desc(&mut idx).is_ok() {
+                    guard.start(idx, b, false);
+                    queued.push(Ok(idx[0]));
+                    break;
+                }
+                if !queued.is_empty() {
+                    queued.push(Err(b));
+                    break;
+                }
+                guard = proc::sleep(&guard.free[0] as *const _ as usize, guard);
+            }
+        }
+        let mut done = Vec::with_capacity(queued.len());
+        for q in queued {
+            match q {
+                Ok(id) => {
+                    let b;
+                    (b, guard) = self.wait(guard, id);
+                    done.push((b, true));
+                }
+                Err(b) => done.push((b, false)),
+            }
+        }
+        done
+    }
+
+    // Queue a write of b without waiting, for kernel tasks, which cannot
+    // sleep. Returns the request id to pass to finish, or gives b back if
+    // the queue is full.
+    pub fn start_write(
+        &self,
+        b: SleepLockGuard<'static, Data>,
+    ) -> core::result::Result<usize, SleepLockGuard<'static, Data>> {
+        let mut guard = self.lock();
+        let mut idx: [usize; 3] = [0; 3];
+        if guard.alloc3_desc(&mut idx).is_err() {
+            return Err(b);
         }
+        guard.start(idx, b, true);
+        Ok(idx[0])
+    }
 
-        guard.free_chain(idx[0]);
-        guard.info[idx[0]].buf.take()
+    // The buffer of request id once it has finished, None while the
+    // device still has it.
+    pub fn finish(&self, id: usize) -> Option<SleepLockGuard<'static, Data>> {
+        self.lock().reap(id)
+    }
+
+    // wait for intr() to say request id has finished.
+    fn wait<'a>(
+        &'a self,
+        mut guard: MutexGuard<'a, Disk>,
+        id: usize,
+    ) -> (SleepLockGuard<'static, Data>, MutexGuard<'a, Disk>) {
+        loop {
+            if let Some(b) = guard.reap(id) {
+                return (b, guard);
+            }
+            let chan = &guard.info[id].buf.as_ref().unwrap().data as *const _ as usize;
+            guard = proc::sleep(chan, guard);
+        }
     }
 
     pub fn intr(&self) {
@@ -587,14 +715,12 @@ impl Mutex<Disk> {
         // the "used" ring, in which case we may process the new
         // completion entries in this interrupt, and have nothing to do
         // in the next interrupt, which is harmless.
-        let intr_stat = VirtioMMIO::InterruptStatus.read();
+        let mut guard = self.lock();
+        let intr_stat = VirtioMMIO::InterruptStatus.read(guard.base);
         unsafe {
-            VirtioMMIO::InterruptAck.write(intr_stat & 0x3);
+            VirtioMMIO::InterruptAck.write(guard.base, intr_stat & 0x3);
         }
 
-        fence(Ordering::SeqCst);
-
-        let mut guard = self.lock();
         fence(Ordering::SeqCst);
         while guard.used_idx != guard.used.idx {
             fence(Ordering::SeqCst);
@@ -611,11 +737,41 @@ impl Mutex<Disk> {
     }
 }
 
+// Drive every virtio disk found, up to NDISK of them.
 pub fn init() {
-    unsafe {
-        DISK.get_mut().init();
+    let mut n = 0;
+    for (base, irq) in VIRTIO_SLOTS {
+        if n == NDISK || !probe(base) {
+            continue;
+        }
+        let sectors = VirtioMMIO::CapacityLow.read(base) as usize
+            | (VirtioMMIO::CapacityHigh.read(base) as usize) << 32;
+        let blk = BlkDev {
+            dev: ROOTDEV + n as u32,
+            irq,
+            size: sectors * 512 / BSIZE * BSIZE,
+            disk: Mutex::new(Disk::new(base), "virtio_disk"),
+        };
+        assert!(DISKS[n].set(blk).is_ok(), "virtio disk: init twice");
+        let blk = DISKS[n].get().unwrap();
+        unsafe {
+            blk.disk.lock().init();
+        }
+        DEVSW.register(NAMES[n], 0o600, blk).unwrap();
+        n += 1;
+    }
+    assert!(n > 0, "could not find virtio disk");
+}
+
+// Handle an interrupt from irq if it belongs to a disk.
+pub fn intr(irq: u32) -> bool {
+    match disks().find(|d| d.irq == irq) {
+        Some(d) => {
+            d.disk.intr();
+            true
+        }
+        None => false,
     }
-    DEVSW.set(Major::Disk, &RAW_DISK).unwrap();
 }
 
 pub fn spawn_tasks() {
@@ -635,7 +791,7 @@ mod async_tasks {
         proc,
         spinlock::Mutex,
         task::{Task, spawn_on},
-        virtio_disk::{DISK, VirtioMMIO},
+        virtio_disk::{VirtioMMIO, disks},
     };
 
     static IRQ_SEQ: AtomicUsize = AtomicUsize::new(0);
@@ -685,9 +841,15 @@ mod async_tasks {
     }
 
     fn drain_completions() {
+        for d in disks() {
+            drain(&d.disk);
+        }
+    }
+
+    fn drain(disk: &Mutex<super::Disk>) {
         use core::sync::atomic::fence;
 
-        let mut guard = DISK.lock();
+        let mut guard = disk.lock();
 
         fence(Ordering::SeqCst);
         while guard.used_idx != guard.used.idx {
@@ -704,6 +866,6 @@ mod async_tasks {
             guard.used_idx += 1;
         }
 
-        let _ = VirtioMMIO::InterruptStatus.read();
+        let _ = VirtioMMIO::InterruptStatus.read(guard.base);
     }
 }
@@ -9,8 +9,8 @@ use crate::defs::AsBytes;
 use crate::error::{Error::*, Result};
 use crate::kalloc;
 use crate::memlayout::{
-    APLIC_M, APLIC_S, IMSIC_M, IMSIC_S, KERNBASE, PHYSTOP, SIFIVE_TEST, STACK_PAGE_NUM, TRAMPOLINE,
-    TRAPFRAME, UART0, VIRTIO0, VIRTIO1, VIRTIO2, VIRTIO3, VIRTIO4, trapframe_va, user_mem_top,
+    APLIC_M, APLIC_S, GOLDFISH_RTC, IMSIC_M, IMSIC_S, KERNBASE, PHYSTOP, SIFIVE_TEST,
+    STACK_PAGE_NUM, TRAMPOLINE, TRAPFRAME, UART0, VIRTIO_SLOTS, trapframe_va, user_mem_top,
 };
 use crate::param::NPROC;
 use crate::proc::PROCS;
@@ -215,6 +215,7 @@ pub unsafe trait PageAllocator: Sized {
 #[repr(C, align(4096))]
 pub struct Page([u8; 4096]);
 unsafe impl PageAllocator for Page {}
+unsafe impl AsBytes for Page {}
 
 #[allow(dead_code)]
 pub struct Stack([u8; PGSIZE * STACK_PAGE_NUM]);
@@ -783,18 +784,21 @@ impl Kvm {
             PTE_R | PTE_W,
         );
 
+        // goldfish real-time clock
+        self.map(
+            GOLDFISH_RTC.into(),
+            GOLDFISH_RTC.into(),
+            PGSIZE,
+            PTE_R | PTE_W,
+        );
+
         self.map(UART0.into(), UART0.into(), PGSIZE, PTE_R | PTE_W);
 
-        // virtio mmio disk interface
-        self.map(VIRTIO0.into(), VIRTIO0.into(), PGSIZE, PTE_R | PTE_W);
-        // virtio mmio net interface
-        self.map(VIRTIO1.into(), VIRTIO1.into(), PGSIZE, PTE_R | PTE_W);
-        // virtio mmio gpu interface
-        self.map(VIRTIO2.into(), VIRTIO2.into(), PGSIZE, PTE_R | PTE_W);
-        // virtio mmio keyboard interface
-        self.map(VIRTIO3.into(), VIRTIO3.into(), PGSIZE, PTE_R | PTE_W);
-        // virtio mmio mouse interface
-        self.map(VIRTIO4.into(), VIRTIO4.into(), PGSIZE, PTE_R | PTE_W);
+        // virtio mmio interfaces: disk, net, gpu, keyboard, mouse and
+        // the spare slots probed for more disks
+        for (base, _) in VIRTIO_SLOTS {
+            self.map(base.into(), base.into(), PGSIZE, PTE_R | PTE_W);
+        }
         // APLIC domains and IMSIC interrupt file regions
         self.map(APLIC_M.into(), APLIC_M.into(), 0x8000, PTE_R | PTE_W);
         self.map(APLIC_S.into(), APLIC_S.into(), 0x8000, PTE_R | PTE_W);
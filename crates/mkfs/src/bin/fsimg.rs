@@ -0,0 +1,213 @@
+// Look inside and edit an existing file system image from the host,
+// without booting it.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::{env, fmt, fs, process};
+
+use kernel::fsck;
+use kernel::stat::FileType;
+use mkfs::image::Image;
+
+const USAGE: &str = "usage: fsimg IMAGE COMMAND [ARGS]
+  ls [PATH]          list a directory
+  cat PATH           print a file
+  stat PATH          print an inode
+  get PATH [DEST]    copy a file out to the host
+  put SRC PATH       copy a host file in
+  rm PATH            remove a file or an empty directory
+  mkdir PATH         make a directory
+  tree [PATH]        list a directory and everything under it
+  check [-y]         run the fsck rules, repairing with -y";
+
+const DIR_MODE: u32 = 0o755;
+const FILE_MODE: u32 = 0o644;
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn other(msg: impl Into<String>) -> io::Error {
+    io::Error::other(msg.into())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let [image, cmd, rest @ ..] = &args[..] else {
+        usage();
+    };
+    if let Err(e) = run(image, cmd, rest) {
+        eprintln!("fsimg: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(image: &str, cmd: &str, args: &[&str]) -> io::Result<()> {
+    let write = matches!((cmd, args), ("put" | "rm" | "mkdir", _) | ("check", ["-y"]));
+    let img = Image::open(image, write)?;
+    if write && cmd != "check" {
+        img.check_log()?;
+    }
+    match (cmd, args) {
+        ("ls", []) => ls(&img, "/"),
+        ("ls", [path]) => ls(&img, path),
+        ("cat", [path]) => io::stdout().write_all(&img.read(regular(&img, path)?)?),
+        ("stat", [path]) => stat(&img, path),
+        ("get", [path]) => get(&img, path, basename(path)),
+        ("get", [path, dest]) => get(&img, path, dest),
+        ("put", [src, path]) => put(&img, src, path),
+        ("rm", [path]) => rm(&img, path),
+        ("mkdir", [path]) => mkdir(&img, path),
+        ("tree", []) => tree(&img, "/"),
+        ("tree", [path]) => tree(&img, path),
+        ("check", []) => check(&img, false),
+        ("check", ["-y"]) => check(&img, true),
+        _ => usage(),
+    }
+}
+
+fn basename(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+// The inode of the regular file at path.
+fn regular(img: &Image, path: &str) -> io::Result<u32> {
+    let inum = img.namei(path)?;
+    match img.inode(inum)?.itype {
+        FileType::Dir => Err(other(format!("{}: is a directory", path))),
+        _ => Ok(inum),
+    }
+}
+
+// Like the guest's ls: name, type, inode number and size.
+fn ls(img: &Image, path: &str) -> io::Result<()> {
+    let inum = img.namei(path)?;
+    let entries = match img.inode(inum)?.itype {
+        FileType::Dir => img.entries(inum)?,
+        _ => vec![(basename(path).to_string(), inum)],
+    };
+    for (name, inum) in entries {
+        if name == "." || name == ".." {
+            continue;
+        }
+        let din = img.inode(inum)?;
+        println!(
+            "{:14} {:6} {:3} {}",
+            name,
+            format!("{:?}", din.itype),
+            inum,
+            din.size
+        );
+    }
+    Ok(())
+}
+
+fn stat(img: &Image, path: &str) -> io::Result<()> {
+    let inum = img.namei(path)?;
+    let din = img.inode(inum)?;
+    println!("inode  {}", inum);
+    println!("type   {:?}", din.itype);
+    println!("nlink  {}", din.nlink);
+    println!("size   {}", din.size);
+    println!("mode   {:o}", din.mode);
+    println!("uid    {}", din.uid);
+    println!("gid    {}", din.gid);
+    if din.itype == FileType::Device {
+        println!("device {} {}", din.major.0, din.minor);
+    }
+    println!("atime  {}", din.atime);
+    println!("mtime  {}", din.mtime);
+    println!("ctime  {}", din.ctime);
+    println!("addrs  {:?}", din.addrs);
+    Ok(())
+}
+
+fn get(img: &Image, path: &str, dest: &str) -> io::Result<()> {
+    fs::write(dest, img.read(regular(img, path)?)?)
+}
+
+// Copy src into the file at path, or into a directory at path under
+// its own name, creating or replacing the file.
+fn put(img: &Image, src: &str, path: &str) -> io::Result<()> {
+    let data = fs::read(src)?;
+    let path = match img.namei(path) {
+        Ok(inum) if img.inode(inum)?.itype == FileType::Dir => {
+            format!("{}/{}", path.trim_end_matches('/'), basename(src))
+        }
+        _ => path.to_string(),
+    };
+    let (dir, name) = img.nameiparent(&path)?;
+    let inum = match img.lookup(dir, name)? {
+        Some(inum) if img.inode(inum)?.itype == FileType::File => inum,
+        Some(_) => return Err(other(format!("{}: not a regular file", path))),
+        None => {
+            let inum = img.ialloc(FileType::File, FILE_MODE)?;
+            img.link(dir, name, inum)?;
+            inum
+        }
+    };
+    img.write(inum, &data)
+}
+
+fn rm(img: &Image, path: &str) -> io::Result<()> {
+    let (dir, name) = img.nameiparent(path)?;
+    let inum = img
+        .lookup(dir, name)?
+        .ok_or_else(|| other(format!("{}: not found", path)))?;
+    if img.inode(inum)?.itype == FileType::Dir {
+        if img.entries(inum)?.len() > 2 {
+            return Err(other(format!("{}: directory not empty", path)));
+        }
+        let mut parent = img.inode(dir)?;
+        parent.nlink -= 1; // for its ".."
+        img.put_inode(dir, &parent)?;
+    }
+    img.unlink(dir, name)?;
+    img.drop_link(inum)
+}
+
+fn mkdir(img: &Image, path: &str) -> io::Result<()> {
+    let (dir, name) = img.nameiparent(path)?;
+    if img.lookup(dir, name)?.is_some() {
+        return Err(other(format!("{}: already exists", path)));
+    }
+    img.mkdir(dir, name, DIR_MODE).map(|_| ())
+}
+
+fn tree(img: &Image, path: &str) -> io::Result<()> {
+    let inum = img.namei(path)?;
+    println!("{}", path);
+    walk(img, inum, 1, &mut vec![inum])
+}
+
+// Print the entries below dir, indented by depth; seen guards against
+// a damaged image looping.
+fn walk(img: &Image, dir: u32, depth: usize, seen: &mut Vec<u32>) -> io::Result<()> {
+    for (name, inum) in img.entries(dir)? {
+        if name == "." || name == ".." {
+            continue;
+        }
+        let is_dir = img.inode(inum)?.itype == FileType::Dir;
+        let slash = if is_dir { "/" } else { "" };
+        println!("{:width$}{}{}", "", name, slash, width = depth * 2);
+        if is_dir && !seen.contains(&inum) {
+            seen.push(inum);
+            walk(img, inum, depth + 1, seen)?;
+        }
+    }
+    Ok(())
+}
+
+fn check(img: &Image, repair: bool) -> io::Result<()> {
+    let mut report = |fix: fmt::Arguments| println!("fsimg: {}", fix);
+    match fsck::fsck(&img.disk, repair, img.now, &mut report).map_err(other)? {
+        0 => println!("fsimg: ok"),
+        fixes => println!("fsimg: {} problems fixed", fixes),
+    }
+    Ok(())
+}
@@ -0,0 +1,400 @@
+// A file system image on the host, made empty or opened for reading
+// and editing. It speaks the same on-disk structures as the kernel but
+// none of its locking or logging: the image must not be in use.
+
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kernel::error::{Error, Result};
+use kernel::fs::{
+    BPB, BSIZE, DIRSIZ, DInode, DirEnt, IPB, MAXFILE, NDIRECT, NINDIRECT, ROOTINO, SuperBlock,
+};
+use kernel::fsck::{self, Disk};
+use kernel::stat::FileType;
+
+// The host's time, for stamping what is edited in an image.
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn other(msg: impl Into<String>) -> io::Error {
+    io::Error::other(msg.into())
+}
+
+// The image file, a block at a time.
+pub struct ImageFile(File);
+
+impl Disk for ImageFile {
+    fn read(&self, bno: u32, buf: &mut [u8; BSIZE]) -> Result<()> {
+        let mut file = &self.0;
+        file.seek(SeekFrom::Start(bno as u64 * BSIZE as u64))
+            .and_then(|_| file.read_exact(buf))
+            .or(Err(Error::Uncategorized))
+    }
+
+    fn write(&self, bno: u32, buf: &[u8; BSIZE]) -> Result<()> {
+        let mut file = &self.0;
+        file.seek(SeekFrom::Start(bno as u64 * BSIZE as u64))
+            .and_then(|_| file.write_all(buf))
+            .or(Err(Error::Uncategorized))
+    }
+}
+
+pub struct Image {
+    pub disk: ImageFile,
+    pub sb: SuperBlock,
+    pub now: u64,          // stamped on everything created or changed
+    next_inode: Cell<u32>, // where ialloc starts looking
+    next_block: Cell<u32>, // and balloc
+}
+
+impl Image {
+    fn new(disk: ImageFile, sb: SuperBlock, now: u64) -> Self {
+        Image {
+            disk,
+            sb,
+            now,
+            next_inode: Cell::new(ROOTINO + 1),
+            next_block: Cell::new(fsck::data_start(&sb)),
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, write: bool) -> io::Result<Self> {
+        let disk = ImageFile(OpenOptions::new().read(true).write(write).open(path)?);
+        let sb = fsck::superblock(&disk).map_err(other)?;
+        Ok(Image::new(disk, sb, now_ns()))
+    }
+
+    // Make an empty file system with the layout sb at path: the same one
+    // the kernel's mkfs writes to a disk.
+    pub fn create<P: AsRef<Path>>(path: P, sb: SuperBlock, now: u64) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(sb.size as u64 * BSIZE as u64)?;
+        let img = Image::new(ImageFile(file), sb, now);
+
+        let mut buf = [0; BSIZE];
+        unsafe { (buf.as_mut_ptr() as *mut SuperBlock).write_unaligned(sb) };
+        img.put_block(1, &buf)?;
+        let nmeta = fsck::data_start(&sb);
+        for bno in sb.bmapstart..nmeta {
+            let mut buf = [0; BSIZE];
+            let first = (bno - sb.bmapstart) * BPB;
+            for b in first..nmeta.min(first + BPB) {
+                buf[(b % BPB / 8) as usize] |= 1 << (b % 8);
+            }
+            img.put_block(bno, &buf)?;
+        }
+
+        img.put_inode(ROOTINO, &DInode::new(FileType::Dir, 0o755, now))?;
+        img.link(ROOTINO, ".", ROOTINO)?;
+        img.link(ROOTINO, "..", ROOTINO)?;
+        Ok(img)
+    }
+
+    // Editing under a commit the kernel has yet to install would be
+    // undone when it is.
+    pub fn check_log(&self) -> io::Result<()> {
+        match fsck::log_pending(&self.disk, &self.sb).map_err(other)? {
+            true => Err(other("log holds a commit, boot once to install it")),
+            false => Ok(()),
+        }
+    }
+
+    pub fn block(&self, bno: u32) -> io::Result<[u8; BSIZE]> {
+        let mut buf = [0; BSIZE];
+        self.disk
+            .read(bno, &mut buf)
+            .map_err(|_| other(format!("read block {}", bno)))?;
+        Ok(buf)
+    }
+
+    pub fn put_block(&self, bno: u32, buf: &[u8; BSIZE]) -> io::Result<()> {
+        self.disk
+            .write(bno, buf)
+            .map_err(|_| other(format!("write block {}", bno)))
+    }
+
+    pub fn inode(&self, inum: u32) -> io::Result<DInode> {
+        if inum >= self.sb.ninodes {
+            return Err(other(format!("inode {} out of range", inum)));
+        }
+        let buf = self.block(self.sb.iblock(inum))?;
+        DInode::from_block(&buf, inum as usize % IPB)
+            .ok_or_else(|| other(format!("inode {} has a bad type", inum)))
+    }
+
+    pub fn put_inode(&self, inum: u32, din: &DInode) -> io::Result<()> {
+        let bno = self.sb.iblock(inum);
+        let mut buf = self.block(bno)?;
+        din.to_block(&mut buf, inum as usize % IPB);
+        self.put_block(bno, &buf)
+    }
+
+    // Allocate a free inode of type itype.
+    pub fn ialloc(&self, itype: FileType, mode: u32) -> io::Result<u32> {
+        let hint = self.next_inode.get();
+        for inum in (hint..self.sb.ninodes).chain(ROOTINO + 1..hint) {
+            if self.inode(inum)?.itype == FileType::Empty {
+                self.put_inode(inum, &DInode::new(itype, mode, self.now))?;
+                self.next_inode.set(inum + 1);
+                return Ok(inum);
+            }
+        }
+        Err(other("out of inodes"))
+    }
+
+    // Allocate a zeroed data block.
+    fn balloc(&self) -> io::Result<u32> {
+        let start = fsck::data_start(&self.sb);
+        let hint = self.next_block.get();
+        for b in (hint..self.sb.size).chain(start..hint) {
+            let bno = self.sb.bblock(b);
+            let mut buf = self.block(bno)?;
+            let bit = (b % BPB) as usize;
+            if buf[bit / 8] & (1 << (bit % 8)) == 0 {
+                buf[bit / 8] |= 1 << (bit % 8);
+                self.put_block(bno, &buf)?;
+                self.put_block(b, &[0; BSIZE])?;
+                self.next_block.set(b + 1);
+                return Ok(b);
+            }
+        }
+        Err(other("out of blocks"))
+    }
+
+    fn bfree(&self, b: u32) -> io::Result<()> {
+        let bno = self.sb.bblock(b);
+        let mut buf = self.block(bno)?;
+        let bit = (b % BPB) as usize;
+        buf[bit / 8] &= !(1 << (bit % 8));
+        self.put_block(bno, &buf)
+    }
+
+    // The disk block holding block fbn of din, 0 if there is none and
+    // alloc is not set. Otherwise it and any indirect blocks on the way
+    // are allocated.
+    fn bmap(&self, din: &mut DInode, fbn: usize, alloc: bool) -> io::Result<u32> {
+        let entry = |addr: &mut u32| -> io::Result<u32> {
+            if *addr == 0 && alloc {
+                *addr = self.balloc()?;
+            }
+            Ok(*addr)
+        };
+        if fbn < NDIRECT {
+            return entry(&mut din.addrs[fbn]);
+        }
+        if fbn >= MAXFILE {
+            return Err(other("file too large"));
+        }
+        let mut fbn = fbn - NDIRECT;
+        let mut depth = 1;
+        while fbn >= NINDIRECT.pow(depth) {
+            fbn -= NINDIRECT.pow(depth);
+            depth += 1;
+        }
+        let mut x = entry(&mut din.addrs[NDIRECT + depth as usize - 1])?;
+        for level in (0..depth).rev() {
+            if x == 0 {
+                return Ok(0);
+            }
+            let mut buf = self.block(x)?;
+            let idx = fbn / NINDIRECT.pow(level) % NINDIRECT;
+            let off = idx * size_of::<u32>();
+            let mut addr = u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+            if addr == 0 && alloc {
+                entry(&mut addr)?;
+                buf[off..off + 4].copy_from_slice(&addr.to_le_bytes());
+                self.put_block(x, &buf)?;
+            }
+            x = addr;
+        }
+        Ok(x)
+    }
+
+    // Free the data and indirect blocks of din.
+    fn truncate(&self, din: &mut DInode) -> io::Result<()> {
+        for (i, addr) in din.addrs.iter_mut().enumerate() {
+            if *addr != 0 {
+                self.free_tree(*addr, (i + 1).saturating_sub(NDIRECT))?;
+                *addr = 0;
+            }
+        }
+        din.size = 0;
+        Ok(())
+    }
+
+    fn free_tree(&self, addr: u32, depth: usize) -> io::Result<()> {
+        if depth > 0 {
+            let buf = self.block(addr)?;
+            for entry in buf.as_chunks::<4>().0 {
+                let next = u32::from_le_bytes(*entry);
+                if next != 0 {
+                    self.free_tree(next, depth - 1)?;
+                }
+            }
+        }
+        self.bfree(addr)
+    }
+
+    pub fn read(&self, inum: u32) -> io::Result<Vec<u8>> {
+        let mut din = self.inode(inum)?;
+        let size = din.size as usize;
+        let mut data = Vec::with_capacity(size);
+        for fbn in 0..size.div_ceil(BSIZE) {
+            let n = (size - fbn * BSIZE).min(BSIZE);
+            match self.bmap(&mut din, fbn, false)? {
+                0 => data.resize(data.len() + n, 0),
+                bno => data.extend_from_slice(&self.block(bno)?[..n]),
+            }
+        }
+        Ok(data)
+    }
+
+    // Replace the contents of inum with data.
+    pub fn write(&self, inum: u32, data: &[u8]) -> io::Result<()> {
+        let mut din = self.inode(inum)?;
+        self.truncate(&mut din)?;
+        for (fbn, chunk) in data.chunks(BSIZE).enumerate() {
+            let bno = self.bmap(&mut din, fbn, true)?;
+            let mut buf = [0; BSIZE];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.put_block(bno, &buf)?;
+        }
+        din.size = data.len() as u64;
+        din.mtime = self.now;
+        din.ctime = din.mtime;
+        self.put_inode(inum, &din)
+    }
+
+    // The live entries of directory dir, "." and ".." included.
+    pub fn entries(&self, dir: u32) -> io::Result<Vec<(String, u32)>> {
+        let mut din = self.inode(dir)?;
+        if din.itype != FileType::Dir {
+            return Err(other("not a directory"));
+        }
+        let mut out = Vec::new();
+        for fbn in 0..din.size as usize / BSIZE {
+            let buf = self.block(self.bmap(&mut din, fbn, false)?)?;
+            let mut off = 0;
+            while off < BSIZE {
+                let (de, name) =
+                    DirEnt::at(&buf, off).ok_or_else(|| other("bad directory record"))?;
+                if de.inum != 0 {
+                    out.push((String::from_utf8_lossy(name).into_owned(), de.inum));
+                }
+                off += de.reclen as usize;
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn lookup(&self, dir: u32, name: &str) -> io::Result<Option<u32>> {
+        Ok(self
+            .entries(dir)?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, inum)| inum))
+    }
+
+    // The inode at an absolute path. Symlinks are not followed.
+    pub fn namei(&self, path: &str) -> io::Result<u32> {
+        let mut inum = ROOTINO;
+        for name in path.split('/').filter(|n| !n.is_empty()) {
+            inum = self
+                .lookup(inum, name)?
+                .ok_or_else(|| other(format!("{}: not found", path)))?;
+        }
+        Ok(inum)
+    }
+
+    // The directory holding path and the last name in it.
+    pub fn nameiparent<'a>(&self, path: &'a str) -> io::Result<(u32, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(other(format!("{}: bad name", path)));
+        }
+        Ok((self.namei(dir)?, name))
+    }
+
+    // Add an entry name for inum to dir, growing dir if it is full.
+    pub fn link(&self, dir: u32, name: &str, inum: u32) -> io::Result<()> {
+        if name.len() > DIRSIZ {
+            return Err(other(format!("{}: name too long", name)));
+        }
+        let mut din = self.inode(dir)?;
+        let nblocks = din.size as usize / BSIZE;
+        for fbn in 0..nblocks {
+            let bno = self.bmap(&mut din, fbn, false)?;
+            let mut buf = self.block(bno)?;
+            if DirEnt::insert(&mut buf, inum, name.as_bytes()).is_some() {
+                self.put_block(bno, &buf)?;
+                din.mtime = self.now;
+                return self.put_inode(dir, &din);
+            }
+        }
+        let bno = self.bmap(&mut din, nblocks, true)?;
+        let mut buf = [0; BSIZE];
+        DirEnt::put(&mut buf, 0, BSIZE, inum, name.as_bytes());
+        self.put_block(bno, &buf)?;
+        din.size += BSIZE as u64;
+        din.mtime = self.now;
+        self.put_inode(dir, &din)
+    }
+
+    // Remove the entry name from dir, returning the inode it named.
+    pub fn unlink(&self, dir: u32, name: &str) -> io::Result<u32> {
+        let mut din = self.inode(dir)?;
+        for fbn in 0..din.size as usize / BSIZE {
+            let bno = self.bmap(&mut din, fbn, false)?;
+            let mut buf = self.block(bno)?;
+            let mut off = 0;
+            while let Some((de, entry)) = DirEnt::at(&buf, off) {
+                if de.inum != 0 && entry == name.as_bytes() {
+                    DirEnt { inum: 0, ..de }.write(&mut buf, off);
+                    self.put_block(bno, &buf)?;
+                    din.mtime = self.now;
+                    self.put_inode(dir, &din)?;
+                    return Ok(de.inum);
+                }
+                off += de.reclen as usize;
+            }
+        }
+        Err(other(format!("{}: not found", name)))
+    }
+
+    // Make an empty directory called name in dir.
+    pub fn mkdir(&self, dir: u32, name: &str, mode: u32) -> io::Result<u32> {
+        let inum = self.ialloc(FileType::Dir, mode)?;
+        self.link(inum, ".", inum)?;
+        self.link(inum, "..", dir)?;
+        self.link(dir, name, inum)?;
+        let mut parent = self.inode(dir)?;
+        parent.nlink += 1; // for ".."
+        self.put_inode(dir, &parent)?;
+        Ok(inum)
+    }
+
+    // Drop a link to inum, freeing it with the last one.
+    pub fn drop_link(&self, inum: u32) -> io::Result<()> {
+        let mut din = self.inode(inum)?;
+        din.nlink = din.nlink.saturating_sub(1);
+        if din.nlink == 0 {
+            self.truncate(&mut din)?;
+            din = DInode::default();
+        } else {
+            din.ctime = self.now;
+        }
+        self.put_inode(inum, &din)
+    }
+}
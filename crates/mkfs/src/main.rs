@@ -1,359 +1,264 @@
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::process;
-use std::{env, io};
-
-use kernel::{defs::*, fs::*, param::*, stat::*};
-
-const NINODES: usize = 200;
-
-// Disk layout:
-// [ boot block | sb block | log | inode blocks | free bit map | data blocks ]
-
-const NBITMAP: usize = FSSIZE / (BSIZE * 8) + 1;
-const NINODEBLOCKS: usize = NINODES / IPB + 1;
-const NLOG: usize = LOGSIZE;
-const NMETA: usize = 2 + NLOG + NINODEBLOCKS + NBITMAP;
-const NBLOCKS: usize = FSSIZE - NMETA;
+// Build a file system image from a host directory tree, or from a
+// manifest that lists what goes where.
+//
+// A manifest has one entry per line, "PATH TYPE MODE [ARGS]", with the
+// mode in octal and blank lines and lines starting with # ignored:
+//
+//   /bin            dir      755
+//   /bin/sh         file     755  target/bin/_sh
+//   /etc/motd       file     644
+//   /bin/ls2        symlink  777  /bin/ls
+//   /dev/console    device   666  1 0
+//
+// A file's source is a host path, relative to the manifest; without one
+// the file is empty. A symlink takes its target and a device its major
+// and minor numbers. Missing parent directories are made with mode 755.
+//
+// Everything is owned by root and stamped with SOURCE_DATE_EPOCH, or
+// the epoch itself, so the same input always gives the same image.
+
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process};
+
+use kernel::file::Major;
+use kernel::fs::{ROOTINO, SuperBlock};
+use kernel::param::FSSIZE;
+use kernel::stat::FileType;
+use mkfs::image::Image;
+
+const USAGE: &str = "usage: mkfs [-s BLOCKS] [-i INODES] fs.img DIR
+       mkfs [-s BLOCKS] [-i INODES] -m MANIFEST fs.img";
+
+const NINODES: u32 = 200;
+const DIR_MODE: u32 = 0o755;
+const SYMLINK_MODE: u32 = 0o777;
+
+// What an entry is, and where its contents come from.
+enum Kind {
+    Dir,
+    File(Option<PathBuf>),
+    Symlink(String),
+    Device(u16, u16),
+}
 
-static ZEROS: [u8; BSIZE] = [0; BSIZE];
+struct Entry {
+    path: String, // absolute, in the image
+    kind: Kind,
+    mode: u32,
+}
 
-struct FsImg {
-    sb: SuperBlock,
-    img: File,
-    freeinode: usize,
-    freeblock: usize,
+fn other(msg: impl Into<String>) -> io::Error {
+    io::Error::other(msg.into())
 }
 
-impl FsImg {
-    fn new<P: AsRef<Path>>(sb: SuperBlock, path: P) -> Result<Self, std::io::Error> {
-        Ok(Self {
-            sb,
-            img: OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .read(true)
-                .open(path)?,
-            freeinode: 1,
-            freeblock: NMETA, // the first free block that we can allocate
-        })
-    }
+fn die(str: &str) -> ! {
+    println!("{}", str);
+    process::exit(1);
+}
 
-    fn wsect(&mut self, sec: u32, buf: &[u8]) -> Result<(), std::io::Error> {
-        let mut writer = BufWriter::new(&mut self.img);
-        if writer.seek(SeekFrom::Start((sec as usize * BSIZE) as u64))?
-            != (sec as usize * BSIZE) as u64
-        {
-            die("cargo:warning=mkfs:seek failed");
-        }
-        if writer.write(buf)? != buf.len() {
-            die("cargo:warning=mkfs:write failed");
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut size = FSSIZE as u32;
+    let mut ninodes = NINODES;
+    let mut manifest = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().map(String::as_str).unwrap_or_default();
+        match arg.as_str() {
+            "-s" => size = value().parse().unwrap_or_else(|_| usage()),
+            "-i" => ninodes = value().parse().unwrap_or_else(|_| usage()),
+            "-m" => manifest = Some(PathBuf::from(value())),
+            _ => rest.push(arg.as_str()),
         }
-        writer.flush()
     }
-
-    fn winode(&mut self, inum: u32, ip: &DInode) -> Result<(), std::io::Error> {
-        let mut buf = [0u8; BSIZE];
-        let bn = self.sb.iblock(inum);
-        self.rsect(bn, &mut buf)?;
-        let (head, dinode_slice, _tail) = unsafe { buf.align_to_mut::<DInode>() };
-        assert!(head.is_empty(), "cargo:warning=mkfs:Data was not aligned");
-        *dinode_slice.get_mut(inum as usize % IPB).unwrap() = *ip;
-        self.wsect(bn, &buf)
+    let entries = match (manifest, &rest[..]) {
+        (Some(manifest), [_]) => read_manifest(&manifest),
+        (None, [_, dir]) => read_tree(Path::new(dir)),
+        _ => usage(),
+    };
+    if let Err(e) = entries.and_then(|e| build(rest[0], size, ninodes, &e)) {
+        die(&format!("cargo:warning=mkfs: {}", e));
     }
+}
 
-    fn rinode(&self, inum: u32, ip: &mut DInode) -> Result<(), std::io::Error> {
-        let mut buf = [0u8; BSIZE];
-        let bn = self.sb.iblock(inum);
-        self.rsect(bn, &mut buf)?;
-        let (head, dinode_slice, _tail) = unsafe { buf.align_to::<DInode>() };
-        assert!(head.is_empty(), "cargo:warning=mkfs:Data was not aligned");
-        *ip = *dinode_slice.get(inum as usize % IPB).unwrap();
-        Ok(())
-    }
+fn usage() -> ! {
+    die(&format!("cargo:warning=mkfs: {}", USAGE));
+}
 
-    fn rsect(&self, sec: u32, buf: &mut [u8]) -> Result<(), std::io::Error> {
-        let mut reader = BufReader::new(&self.img);
-        if reader.seek(SeekFrom::Start((sec as usize * BSIZE) as u64))?
-            != (sec as usize * BSIZE) as u64
-        {
-            die("cargo:warning=mkfs:seek");
-        }
-        if reader.read(buf)? != BSIZE {
-            die("cargo:warning=mkfs:read");
-        }
-        Ok(())
-    }
+// The time to stamp on everything: SOURCE_DATE_EPOCH in nanoseconds,
+// never the host's clock.
+fn stamp() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map_or(0, |secs| secs * 1_000_000_000)
+}
 
-    fn ialloc(&mut self, itype: FileType) -> Result<u32, std::io::Error> {
-        let inum = self.freeinode as u32;
-        self.freeinode += 1;
-        let mut din: DInode = Default::default();
-        din.itype = (itype as u16).to_le();
-        din.nlink = 1u16.to_le();
-        din.size = 0;
-        self.winode(inum, &din)?;
-        Ok(inum)
+fn build(path: &str, size: u32, ninodes: u32, entries: &[Entry]) -> io::Result<()> {
+    let sb = SuperBlock::new(size, ninodes).ok_or_else(|| other("image too small"))?;
+    let img = Image::create(path, sb, stamp())?;
+    for entry in entries {
+        add(&img, entry).map_err(|e| other(format!("{}: {}", entry.path, e)))?;
     }
+    Ok(())
+}
 
-    fn balloc(&mut self, used: usize) -> Result<(), std::io::Error> {
-        let mut buf = vec![0u8; used];
-
-        for i in 0..used {
-            if let Some(elem) = buf.get_mut(i / 8) {
-                *elem = *elem | (0x1 << (i % 8));
-            }
+fn add(img: &Image, entry: &Entry) -> io::Result<()> {
+    let (dir, name) = parent(img, &entry.path)?;
+    if let Some(inum) = img.lookup(dir, name)? {
+        // A directory already made as a parent only needs its mode.
+        let mut din = img.inode(inum)?;
+        if !matches!(entry.kind, Kind::Dir) || din.itype != FileType::Dir {
+            return Err(other("already exists"));
         }
-        self.wsect(u32::from_le(self.sb.bmapstart), &buf)
+        din.mode = entry.mode;
+        return img.put_inode(inum, &din);
     }
-
-    fn iappend(&mut self, inum: u32, data: &[u8]) -> Result<(), std::io::Error> {
-        let mut din: DInode = Default::default();
-        let mut buf = [0u8; BSIZE];
-        let mut indirect = [0u32; NINDIRECT];
-        let mut indirect2 = [0u32; NINDIRECT];
-        let mut x: u32 = 0;
-        let mut p: usize = 0;
-        let mut n = data.len();
-
-        self.rinode(inum, &mut din)?;
-        let mut off = u32::from_le(din.size) as usize;
-
-        while n > 0 {
-            let o_fbn = off / BSIZE;
-            let mut fbn = o_fbn;
-            if fbn > MAXFILE {
-                die("cargo:warning=fbn < MAXFILE");
-            }
-            if fbn < NDIRECT {
-                if u32::from_le(din.addrs[fbn]) == 0 {
-                    din.addrs[fbn] = (self.freeblock as u32).to_le();
-                    self.freeblock += 1;
-                }
-                x = u32::from_le(din.addrs[fbn]);
-            }
-            fbn -= NDIRECT;
-
-            if fbn < NINDIRECT {
-                // fix here
-                if u32::from_le(din.addrs[NDIRECT]) == 0 {
-                    din.addrs[NDIRECT] = (self.freeblock as u32).to_le();
-                    self.freeblock += 1;
-                }
-                self.rsect(
-                    u32::from_le(din.addrs[NDIRECT]),
-                    mkfs_as_bytes_mut(&mut indirect),
-                )?;
-                if u32::from_le(indirect[fbn]) == 0 {
-                    indirect[fbn] = (self.freeblock as u32).to_le();
-                    self.freeblock += 1;
-                    self.wsect(u32::from_le(din.addrs[NDIRECT]), mkfs_as_bytes(&indirect))?;
-                }
-                x = u32::from_le(indirect[fbn]);
-            }
-            fbn -= NINDIRECT;
-
-            if fbn < NDINDIRECT {
-                if u32::from_le(din.addrs[NDIRECT + 1]) == 0 {
-                    din.addrs[NDIRECT + 1] = (self.freeblock as u32).to_le();
-                    self.freeblock += 1;
-                }
-                self.rsect(
-                    u32::from_le(din.addrs[NDIRECT + 1]),
-                    mkfs_as_bytes_mut(&mut indirect),
-                )?;
-                if u32::from_le(indirect[fbn / NINDIRECT]) == 0 {
-                    indirect[fbn / NINDIRECT] = (self.freeblock as u32).to_le();
-                    self.freeblock += 1;
-                    self.wsect(
-                        u32::from_le(din.addrs[NDIRECT + 1]),
-                        mkfs_as_bytes(&indirect),
-                    )?;
-                }
-                self.rsect(
-                    u32::from_le(indirect[fbn / NINDIRECT]),
-                    mkfs_as_bytes_mut(&mut indirect2),
-                )?;
-                if u32::from_le(indirect2[fbn % NINDIRECT]) == 0 {
-                    indirect2[fbn % NINDIRECT] = (self.freeblock as u32).to_le();
-                    self.freeblock += 1;
-                    self.wsect(
-                        u32::from_le(indirect[fbn / NINDIRECT]),
-                        mkfs_as_bytes(&indirect2),
-                    )?;
-                }
-                x = u32::from_le(indirect2[fbn % NINDIRECT]);
-            }
-
-            let n1 = std::cmp::min(n, (o_fbn + 1) * BSIZE - off);
-            self.rsect(x, &mut buf)?;
-            buf[off - (o_fbn * BSIZE)..(off - (o_fbn * BSIZE) + n1)]
-                .copy_from_slice(&data[p..(p + n1)]);
-            self.wsect(x, &buf)?;
-            n -= n1;
-            off += n1;
-            p += n1;
+    let itype = match entry.kind {
+        Kind::Dir => return img.mkdir(dir, name, entry.mode).map(|_| ()),
+        Kind::File(_) => FileType::File,
+        Kind::Symlink(_) => FileType::Symlink,
+        Kind::Device(..) => FileType::Device,
+    };
+    let inum = img.ialloc(itype, entry.mode)?;
+    img.link(dir, name, inum)?;
+    match &entry.kind {
+        Kind::File(Some(src)) => img.write(inum, &fs::read(src)?),
+        Kind::Symlink(target) => img.write(inum, target.as_bytes()),
+        Kind::Device(major, minor) => {
+            let mut din = img.inode(inum)?;
+            din.major = Major(*major);
+            din.minor = *minor;
+            img.put_inode(inum, &din)
         }
-        din.size = (off as u32).to_le();
-        self.winode(inum, &din)
+        _ => Ok(()),
     }
 }
 
-fn main() -> std::io::Result<()> {
-    let mut buf = [0u8; BSIZE];
-
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("cargo:warning=mkfs:Usage: mkfs fs.img files...");
-        process::exit(1);
+// The directory to hold path and the last name in it, making any
+// missing directories on the way.
+fn parent<'a>(img: &Image, path: &'a str) -> io::Result<(u32, &'a str)> {
+    let (dir, name) = path
+        .strip_prefix('/')
+        .map(|p| p.rsplit_once('/').unwrap_or(("", p)))
+        .ok_or_else(|| other("not an absolute path"))?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(other("bad name"));
     }
-
-    assert!(BSIZE % core::mem::size_of::<DInode>() == 0);
-    assert!(BSIZE % core::mem::size_of::<DirEnt>() == 0);
-
-    let sb = SuperBlock {
-        magic: FSMAGIC.to_le(),
-        size: (FSSIZE as u32).to_le(),
-        nblocks: (NBLOCKS as u32).to_le(),
-        ninodes: (NINODES as u32).to_le(),
-        nlog: (NLOG as u32).to_le(),
-        logstart: 2u32.to_le(),
-        inodestart: ((2 + NLOG) as u32).to_le(),
-        bmapstart: ((2 + NLOG + NINODEBLOCKS) as u32).to_le(),
-    };
-
-    let mut fsimg = FsImg::new(sb, &args[1])?;
-
-    for sec in 0..FSSIZE {
-        fsimg.wsect(sec as u32, &ZEROS)?;
+    let mut inum = ROOTINO;
+    for part in dir.split('/').filter(|p| !p.is_empty()) {
+        inum = match img.lookup(inum, part)? {
+            Some(inum) => inum,
+            None => img.mkdir(inum, part, DIR_MODE)?,
+        };
     }
+    Ok((inum, name))
+}
 
-    let (head, sb_slice, _tail) = unsafe { buf.align_to_mut::<SuperBlock>() };
-    assert!(head.is_empty(), "cargo:warning=mkfs:Data was not aligned");
-    *sb_slice.get_mut(0).unwrap() = sb;
-    fsimg.wsect(1, &buf)?;
-
-    let rootino = fsimg.ialloc(FileType::Dir)?;
-    assert!(rootino == ROOTINO);
-
-    let mut de: DirEnt = Default::default();
-    de.inum = (rootino as u16).to_le();
-    de.name[..".".len()].copy_from_slice(".".as_bytes());
-    fsimg.iappend(rootino, mkfs_as_bytes(&de))?;
-
-    let mut de: DirEnt = Default::default();
-    de.inum = (rootino as u16).to_le();
-    de.name[.."..".len()].copy_from_slice("..".as_bytes());
-    fsimg.iappend(rootino, mkfs_as_bytes(&de))?;
-
-    let mut create_dir = |name: &str| -> io::Result<u32> {
-        let result = fsimg.ialloc(FileType::Dir)?;
-
-        let mut de: DirEnt = Default::default();
-        de.inum = (result as u16).to_le();
-        de.name[..name.len()].copy_from_slice(name.as_bytes());
-        fsimg.iappend(rootino, mkfs_as_bytes(&de))?;
-        //
-        let mut de: DirEnt = Default::default();
-        de.inum = (result as u16).to_le();
-        de.name[..".".len()].copy_from_slice(".".as_bytes());
-        fsimg.iappend(result, mkfs_as_bytes(&de))?;
-        //
-        let mut de: DirEnt = Default::default();
-        de.inum = (rootino as u16).to_le();
-        de.name[.."..".len()].copy_from_slice("..".as_bytes());
-        fsimg.iappend(result, mkfs_as_bytes(&de))?;
-        Ok(result)
-    };
-    let _devino = create_dir("dev")?;
-    let binino = create_dir("bin")?;
-    let libino = create_dir("lib")?;
-    let etcino = create_dir("etc")?;
-
-    for path in args[2..]
-        .iter()
-        .map(|p| Path::new(p))
-        .filter(|p| p.exists())
-    {
-        // Skip leading _ in name when writing to file system.
-        // The binaries are named _rm, _cat, etc. to keep the
-        // build operating system from trying to execute them
-        // in place of system binaries like rm and cat.
-        let shortname = path
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .trim_start_matches("_");
-        assert!(shortname.len() < 14);
-        let parent_ino = path
-            .parent()
-            .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str())
-            .map(|parent_name| match parent_name {
-                "bin" if shortname.contains("init") => rootino,
-                "bin" => binino,
-                "lib" => libino,
-                "etc" => etcino,
-                _ => rootino,
-            })
-            .unwrap_or(rootino);
-
-        let mut fd = File::open(path)?;
-
-        let inum = fsimg.ialloc(FileType::File)?;
-
-        let mut de: DirEnt = Default::default();
-        de.inum = (inum as u16).to_le();
-        de.name[..shortname.len()].copy_from_slice(shortname.as_bytes());
-        fsimg.iappend(parent_ino, mkfs_as_bytes(&de))?;
-
-        loop {
-            match fd.read(&mut buf) {
-                Ok(n) if n > 0 => fsimg.iappend(inum, &buf[..n])?,
-                _ => break,
-            }
+fn read_manifest(path: &Path) -> io::Result<Vec<Entry>> {
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut entries = Vec::new();
+    for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
+        let bad = || other(format!("{}:{}: bad entry", path.display(), n + 1));
+        let ([path, itype, mode], rest) = fields(line).ok_or_else(bad)?;
+        let mode = u32::from_str_radix(mode, 8).map_err(|_| bad())?;
+        let kind = match (itype, rest) {
+            ("dir", "") => Kind::Dir,
+            ("file", "") => Kind::File(None),
+            ("file", src) => Kind::File(Some(base.join(src))),
+            ("symlink", target) if !target.is_empty() => Kind::Symlink(target.into()),
+            ("device", numbers) => match numbers.split_whitespace().collect::<Vec<_>>()[..] {
+                [major, minor] => Kind::Device(
+                    major.parse().map_err(|_| bad())?,
+                    minor.parse().map_err(|_| bad())?,
+                ),
+                _ => return Err(bad()),
+            },
+            _ => return Err(bad()),
+        };
+        entries.push(Entry {
+            path: path.into(),
+            kind,
+            mode,
+        });
     }
-
-    // fix size of root inode dir
-    let mut din: DInode = Default::default();
-    fsimg.rinode(rootino, &mut din)?;
-    let mut off = u32::from_le(din.size) as usize;
-    off = (off / BSIZE + 1) * BSIZE;
-    din.size = (off as u32).to_le();
-    fsimg.winode(rootino, &din)?;
-
-    fsimg.balloc(fsimg.freeblock)
+    Ok(entries)
 }
 
-fn die(str: &str) -> ! {
-    println!("{}", str);
-    std::process::exit(1);
+// The first three fields of a manifest line, and the rest of it, which
+// may hold spaces.
+fn fields(line: &str) -> Option<([&str; 3], &str)> {
+    let mut out = [""; 3];
+    let mut rest = line;
+    for field in out.iter_mut() {
+        let (f, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if f.is_empty() {
+            return None;
+        }
+        *field = f;
+        rest = tail.trim_start();
+    }
+    Some((out, rest))
 }
 
-// On-disk inode structure for mkfs
-#[repr(C)]
-#[derive(Debug, Clone, Copy, Default)]
-struct DInode {
-    itype: u16,                // File type
-    major: u16,                // Major Device Number (T_DEVICE only)
-    minor: u16,                // Minor Device Number (T_DEVICE only)
-    nlink: u16,                // Number of links to inode in file system
-    size: u32,                 // Size of data (bytes)
-    addrs: [u32; NDIRECT + 2], // Data block address
-    atime: u64,                // Ticks since boot
-    mtime: u64,                // Ticks since boot
-    ctime: u64,                // Ticks since boot
-    _padding: [u32; 10],       // Pad to 128B
+// Everything under dir, in name order, with the host's modes.
+fn read_tree(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    walk(dir, "", &mut entries)?;
+    Ok(entries)
 }
 
-fn mkfs_as_bytes<T: ?Sized>(refs: &T) -> &[u8] {
-    unsafe { as_bytes(refs) }
+fn walk(dir: &Path, prefix: &str, entries: &mut Vec<Entry>) -> io::Result<()> {
+    let mut names: Vec<_> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<io::Result<_>>()?;
+    names.sort();
+    for name in names {
+        let host = dir.join(&name);
+        let name = name
+            .into_string()
+            .map_err(|n| other(format!("{}: name is not UTF-8", n.display())))?;
+        let path = format!("{}/{}", prefix, name);
+        let md = fs::symlink_metadata(&host)?;
+        let ft = md.file_type();
+        let (kind, mode) = if ft.is_dir() {
+            (Kind::Dir, md.mode() & 0o7777)
+        } else if ft.is_file() {
+            (Kind::File(Some(host.clone())), md.mode() & 0o7777)
+        } else if ft.is_symlink() {
+            let target = fs::read_link(&host)?;
+            let target = target
+                .to_str()
+                .ok_or_else(|| other(format!("{}: target is not UTF-8", host.display())))?;
+            (Kind::Symlink(target.into()), SYMLINK_MODE)
+        } else if ft.is_char_device() || ft.is_block_device() {
+            let (major, minor) = dev_numbers(md.rdev());
+            (Kind::Device(major, minor), md.mode() & 0o7777)
+        } else {
+            return Err(other(format!("{}: unsupported file type", host.display())));
+        };
+        let is_dir = matches!(kind, Kind::Dir);
+        entries.push(Entry {
+            path: path.clone(),
+            kind,
+            mode,
+        });
+        if is_dir {
+            walk(&host, &path, entries)?;
+        }
+    }
+    Ok(())
 }
 
-fn mkfs_as_bytes_mut<T: ?Sized>(refs: &mut T) -> &mut [u8] {
-    unsafe { as_bytes_mut(refs) }
+// Split a Linux dev_t into its major and minor numbers.
+fn dev_numbers(rdev: u64) -> (u16, u16) {
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    (major as u16, minor as u16)
 }
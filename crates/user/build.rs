@@ -2,11 +2,13 @@ use std::{
     fs::{self, File},
     io::{self, Write},
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use kernel::syscall::*;
+use kernel::{sha256::crypt, syscall::*};
 
 fn main() {
+    println!("cargo:rerun-if-env-changed=ROOT_PASSWORD");
     let root_out_dir = std::env::var("ROOT_OUT_DIR").ok().map(PathBuf::from);
 
     if let Some(root_out_dir) = root_out_dir.as_ref() {
@@ -15,6 +17,7 @@ fn main() {
         let src_dir = manifest_dir.join("src").join("etc");
         let dst_dir = root_out_dir.join("etc");
         copy_files(&src_dir, &dst_dir, Some("_")).expect("failed to copy user etc");
+        set_root_password(&dst_dir.join("_passwd")).expect("failed to set root password");
 
         let src_dir = manifest_dir.join("src").join("lib");
         let dst_dir = root_out_dir.join("lib");
@@ -68,3 +71,38 @@ fn copy_files(src_dir: &Path, dst_dir: &Path, prefix: Option<&str>) -> io::Resul
     }
     Ok(())
 }
+
+// The image ships with root locked. ROOT_PASSWORD, if set, unlocks it
+// with the salted hash of that password.
+fn set_root_password(passwd: &Path) -> io::Result<()> {
+    let Ok(password) = std::env::var("ROOT_PASSWORD") else {
+        return Ok(());
+    };
+    assert!(
+        !password.is_empty() && !password.contains(['\n', ':']),
+        "ROOT_PASSWORD must be non-empty, without ':' or newlines"
+    );
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let salt = format!(
+        "{:016x}",
+        (nanos as u64) ^ u64::from(std::process::id()).rotate_left(32)
+    );
+    let hash = crypt(&salt, &password);
+    let entry = format!("{salt}${}", std::str::from_utf8(&hash).unwrap());
+
+    let contents = fs::read_to_string(passwd)?;
+    let lines: Vec<String> = contents
+        .lines()
+        .map(|line| match line.split_once(':') {
+            Some(("root", rest)) => {
+                let rest = rest.split_once(':').map_or("", |(_, rest)| rest);
+                format!("root:{entry}:{rest}")
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+    fs::write(passwd, lines.join("\n") + "\n")
+}
@@ -0,0 +1,24 @@
+#![no_std]
+use ulib::{
+    ExitCode, env, eprintln, println,
+    time::{DateTime, SystemTime, UNIX_EPOCH},
+};
+
+fn main() -> ExitCode {
+    let now = SystemTime::now();
+    match env::args().nth(1) {
+        None => println!("{}", DateTime::from(now)),
+        Some("+%s") => match now.duration_since(UNIX_EPOCH) {
+            Ok(since) => println!("{}", since.as_secs()),
+            Err(e) => {
+                eprintln!("date: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        Some(_) => {
+            eprintln!("usage: date [+%s]");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -7,7 +7,7 @@ use core::mem::size_of;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use kernel::defs::{AsBytes, FromBytes};
-use kernel::dfs::{DFS_ADDR, DFS_MAGIC, DfsOp, DfsReq, DfsResp};
+use kernel::dfs::{DFS_ADDR, DFS_MAGIC, DFS_OFF_NONE, DfsOp, DfsReq, DfsResp};
 use kernel::error::Error;
 use ulib::fs::File;
 use ulib::io::{Read, Write};
@@ -214,7 +214,12 @@ fn handle_conn(mut conn: File) -> sys::Result<()> {
                     continue;
                 };
                 let mut buf = alloc::vec![0u8; req.len as usize];
-                match file.read(&mut buf) {
+                let res = if req.off == DFS_OFF_NONE {
+                    file.read(&mut buf)
+                } else {
+                    file.read_at(&mut buf, req.off as usize)
+                };
+                match res {
                     Ok(n) => {
                         send_resp(&mut conn, n as i32, req.handle, Some(&buf[..n]))?;
                     }
@@ -229,7 +234,12 @@ fn handle_conn(mut conn: File) -> sys::Result<()> {
                     continue;
                 };
                 let data = read_bytes(&mut conn, req.len as usize)?;
-                match file.write(&data) {
+                let res = if req.off == DFS_OFF_NONE {
+                    file.write(&data)
+                } else {
+                    file.write_at(&data, req.off as usize)
+                };
+                match res {
                     Ok(n) => {
                         send_resp(&mut conn, n as i32, req.handle, None)?;
                     }
@@ -295,6 +305,21 @@ fn handle_conn(mut conn: File) -> sys::Result<()> {
                     Err(e) => send_resp(&mut conn, e as i32, 0, None)?,
                 }
             }
+            x if x == DfsOp::Readdir as u16 => {
+                let Some(file) = handles.get_mut(req.handle) else {
+                    send_resp(&mut conn, Error::BadFileDescriptor as i32, 0, None)?;
+                    continue;
+                };
+                let mut buf = alloc::vec![0u8; req.len as usize];
+                match sys::getdents(file.get_fd(), &mut buf) {
+                    Ok(n) => {
+                        send_resp(&mut conn, n as i32, req.handle, Some(&buf[..n]))?;
+                    }
+                    Err(e) => {
+                        send_resp(&mut conn, e as i32, 0, None)?;
+                    }
+                }
+            }
             x if x == DfsOp::Fsync as u16 => {
                 let Some(file) = handles.get_mut(req.handle) else {
                     send_resp(&mut conn, Error::BadFileDescriptor as i32, 0, None)?;
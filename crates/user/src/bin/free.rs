@@ -0,0 +1,39 @@
+#![no_std]
+
+use ulib::{ExitCode, eprintln, fs, println};
+
+// The kB count of field key in /proc/meminfo.
+fn field(meminfo: &str, key: &str) -> Option<usize> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn main() -> ExitCode {
+    let meminfo = match fs::read_to_string("/proc/meminfo") {
+        Ok(meminfo) => meminfo,
+        Err(e) => {
+            eprintln!("free: /proc/meminfo: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (Some(total), Some(free)) = (field(&meminfo, "MemTotal"), field(&meminfo, "MemFree"))
+    else {
+        eprintln!("free: bad /proc/meminfo");
+        return ExitCode::FAILURE;
+    };
+    println!("{:>8} {:>10} {:>10} {:>10}", "kB", "total", "used", "free");
+    println!(
+        "{:>8} {:>10} {:>10} {:>10}",
+        "Mem:",
+        total,
+        total - free,
+        free
+    );
+    ExitCode::SUCCESS
+}
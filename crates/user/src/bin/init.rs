@@ -4,11 +4,9 @@ use ulib::{
     eprintln,
     fs::{self, File, OpenOptions},
     io::Read,
-    path::Path,
     println,
     process::Command,
-    stdio,
-    sys::{self, Major},
+    stdio, sys,
 };
 
 fn journal_recover() {
@@ -29,7 +27,10 @@ fn journal_recover() {
     if n == DATA.len() && &buf[..n] == DATA {
         let _ = fs::remove_file(PATH);
         println!("init: journal recovered, removed {}", PATH);
-    } else if n > 0 {
+    } else if n == 0 {
+        let _ = fs::remove_file(PATH);
+        println!("init: journal discarded torn write, removed {}", PATH);
+    } else {
         let _ = fs::remove_file(PATH);
         eprintln!(
             "init: journal mismatch n={}, removed {}, rebuild fs.img or FORCE_MKFS=1",
@@ -39,49 +40,20 @@ fn journal_recover() {
 }
 
 fn main() -> sys::Result<()> {
-    loop {
-        match OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open("/dev/console")
-        {
-            Err(_) => {
-                if !Path::new("/dev").is_dir() {
-                    fs::create_dir("/dev")?;
-                }
-                sys::mknod("/dev/console", Major::Console as usize, 0)?;
-            }
-            Ok(stdin) => {
-                stdio::stdout().set(stdin.try_clone()?)?;
-                stdio::stderr().set(stdin.try_clone()?)?;
-                stdio::stdin().set(stdin)?;
-                break;
-            }
-        }
-    }
-    if File::open("/dev/null").is_err() {
-        match sys::mknod("/dev/null", Major::Null as usize, 0) {
-            Ok(()) => {}
-            Err(sys::Error::AlreadyExists) => {}
-            Err(e) => return Err(e),
-        }
-    }
-    if File::open("/dev/disk").is_err() {
-        match sys::mknod("/dev/disk", Major::Disk as usize, 0) {
-            Ok(()) => {}
-            Err(sys::Error::AlreadyExists) => {}
-            Err(e) => return Err(e),
-        }
-    }
-    if !Path::new("/tmp").is_dir() {
-        let _ = fs::create_dir("/tmp");
-    }
+    // devfs at /dev has the console node from boot.
+    let stdin = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/console")?;
+    stdio::stdout().set(stdin.try_clone()?)?;
+    stdio::stderr().set(stdin.try_clone()?)?;
+    stdio::stdin().set(stdin)?;
 
     journal_recover();
 
     loop {
-        println!("\ninit: starting sh\n");
-        let mut child = Command::new("/bin/sh").spawn().unwrap();
+        println!("\ninit: starting login\n");
+        let mut child = Command::new("/bin/login").spawn().unwrap();
         child.wait().unwrap();
     }
 }
@@ -0,0 +1,67 @@
+#![no_std]
+extern crate alloc;
+use alloc::string::String;
+
+use ulib::{
+    eprintln,
+    passwd::{self, User},
+    path::Path,
+    print, println,
+    process::Command,
+    stdio::stdin,
+    sys,
+};
+
+const ATTEMPTS: usize = 3;
+
+fn prompt(msg: &str) -> String {
+    print!("{}", msg);
+    let mut line = String::new();
+    loop {
+        match stdin().read_line(&mut line) {
+            Ok(0) => sys::exit(1),
+            Ok(_) => break,
+            Err(sys::Error::Interrupted) => continue,
+            Err(_) => sys::exit(1),
+        }
+    }
+    String::from(line.trim())
+}
+
+fn authenticate() -> Option<User> {
+    let name = prompt("login: ");
+    let user = passwd::user_by_name(&name).ok();
+    // Ask for a password even for unknown names, so a wrong name and a
+    // wrong password look the same.
+    if user.as_ref().is_none_or(|u| !u.password.is_empty()) {
+        let password = prompt("password: ");
+        return user.filter(|u| u.check_password(&password));
+    }
+    user
+}
+
+fn main() -> sys::Result<()> {
+    println!("log in as guest (no password)");
+    for _ in 0..ATTEMPTS {
+        let Some(user) = authenticate() else {
+            println!("login incorrect");
+            continue;
+        };
+        user.become_user()?;
+        let home = if Path::new(&user.home).is_dir() {
+            user.home.as_str()
+        } else {
+            "/"
+        };
+        let status = Command::new(&user.shell)
+            .current_dir(home)
+            .env("USER", &user.name)
+            .env("HOME", home)
+            .status();
+        if let Err(e) = status {
+            eprintln!("login: {}: {}", user.shell, e);
+        }
+        return Ok(());
+    }
+    sys::exit(1)
+}
@@ -1,26 +1,32 @@
 #![no_std]
-use alloc::format;
+use alloc::{format, string::String};
 
 use ulib::{
     ExitCode, env, eprintln,
-    fs::{self, File},
+    fs::{self, Metadata},
     path::Path,
-    println, sys,
+    println,
+    sys::{
+        self,
+        stat::{FileType, mode},
+    },
+    time::DateTime,
 };
 extern crate alloc;
 
 fn main() -> ExitCode {
-    let args = env::args();
+    let long = env::args().any(|arg| arg == "-l");
+    let mut paths = env::args().skip(1).filter(|arg| *arg != "-l").peekable();
     let mut failed = false;
-    if args.len() < 2 {
-        if let Err(e) = ls(".") {
+    if paths.peek().is_none() {
+        if let Err(e) = ls(".", long) {
             eprintln!("ls: .: {}", e);
             return ExitCode::FAILURE;
         }
         return ExitCode::SUCCESS;
     }
-    for arg in args.skip(1) {
-        if let Err(e) = ls(arg) {
+    for arg in paths {
+        if let Err(e) = ls(arg, long) {
             failed = true;
             eprintln!("ls: {}: {}", arg, e);
         }
@@ -32,34 +38,87 @@ fn main() -> ExitCode {
     }
 }
 
-fn ls(path: &str) -> sys::Result<()> {
+fn ls(path: &str, long: bool) -> sys::Result<()> {
     let path = Path::new(path);
     match fs::read_dir(path) {
         Err(sys::Error::NotADirectory) => {
-            let attr = File::open(path)?.metadata()?;
+            let attr = fs::symlink_metadata(path)?;
             let name = path.file_name().unwrap_or(path.to_str());
-            println!(
-                "{:14} {:6} {:3} {}",
-                name,
-                format!("{:?}", attr.file_type()),
-                attr.inum(),
-                attr.len()
-            );
+            print_entry(path, name, &attr, long);
         }
         Err(e) => return Err(e),
         Ok(entries) => {
             for entry in entries {
                 let entry = entry?;
                 let attr = entry.metadata()?;
-                println!(
-                    "{:14} {:6} {:3} {}",
-                    entry.file_name(),
-                    format!("{:?}", attr.file_type()),
-                    attr.inum(),
-                    attr.len()
-                );
+                print_entry(&entry.path(), &entry.file_name(), &attr, long);
             }
         }
     }
     Ok(())
 }
+
+fn print_entry(path: &Path, name: &str, attr: &Metadata, long: bool) {
+    // Show where a symlink points, as in "a.sym -> /tmp/a".
+    let name = match attr.is_symlink() {
+        true => match fs::read_link(path) {
+            Ok(target) => format!("{} -> {}", name, target.to_str()),
+            Err(_) => format!("{} -> ?", name),
+        },
+        false => String::from(name),
+    };
+    if long {
+        let mtime = DateTime::from(attr.modified());
+        println!(
+            "{} {:2} {:4} {:4} {:6} {} {:2} {:02}:{:02} {}",
+            mode_string(attr),
+            attr.nlink(),
+            attr.uid(),
+            attr.gid(),
+            attr.len(),
+            mtime.month_name(),
+            mtime.day,
+            mtime.hour,
+            mtime.minute,
+            name
+        );
+    } else {
+        println!(
+            "{:14} {:6} {:3} {}",
+            name,
+            format!("{:?}", attr.file_type()),
+            attr.inum(),
+            attr.len()
+        );
+    }
+}
+
+// Render the type and permission bits as in "drwxr-xr-x".
+fn mode_string(attr: &Metadata) -> String {
+    let mut s = String::new();
+    s.push(match attr.file_type() {
+        FileType::Dir => 'd',
+        FileType::Device => 'c',
+        FileType::Symlink => 'l',
+        FileType::Socket => 's',
+        _ => '-',
+    });
+    let perm = attr.mode();
+    for shift in [6, 3, 0] {
+        let bits = perm >> shift;
+        s.push(if bits & 4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 2 != 0 { 'w' } else { '-' });
+        let special = match shift {
+            6 => perm & mode::ISUID != 0,
+            3 => perm & mode::ISGID != 0,
+            _ => false,
+        };
+        s.push(match (bits & 1 != 0, special) {
+            (true, true) => 's',
+            (false, true) => 'S',
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    s
+}
@@ -0,0 +1,20 @@
+#![no_std]
+use ulib::{ExitCode, env, eprintln, sys};
+
+fn main() -> ExitCode {
+    let args: [Option<&str>; 5] = {
+        let mut args = env::args().skip(1);
+        core::array::from_fn(|_| args.next())
+    };
+    let [Some("-t"), Some(fstype), Some(source), Some(dir), None] = args else {
+        eprintln!("usage: mount -t fstype source dir");
+        return ExitCode::FAILURE;
+    };
+    match sys::mount(source, dir, fstype) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("mount: {}: {}", dir, e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,43 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use ulib::{
+    env, eprintln, fs,
+    path::Path,
+    sys::{self, Error::InvalidArgument},
+};
+
+fn main() {
+    let args: Vec<&str> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        panic!("Usage: mv source... dest");
+    }
+    let (sources, dest) = args.split_at(args.len() - 1);
+    let dest = Path::new(dest[0]);
+    let into_dir = dest.is_dir();
+    if sources.len() > 1 && !into_dir {
+        panic!("mv: {}: not a directory", dest.to_str());
+    }
+
+    let mut failed = false;
+    for &src in sources {
+        let src = Path::new(src);
+        let res = if into_dir {
+            match src.file_name() {
+                Some(name) => fs::rename(src, dest.join(name)),
+                None => Err(InvalidArgument),
+            }
+        } else {
+            fs::rename(src, dest)
+        };
+        if let Err(e) = res {
+            eprintln!("mv: {}: {}", src.to_str(), e);
+            failed = true;
+        }
+    }
+    if failed {
+        sys::exit(1);
+    }
+}
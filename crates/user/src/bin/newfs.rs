@@ -0,0 +1,23 @@
+#![no_std]
+use ulib::{ExitCode, env, eprintln, sys};
+
+const NINODES: usize = 200;
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let (Some(source), ninodes, None) = (args.next(), args.next(), args.next()) else {
+        eprintln!("usage: newfs /dev/diskN [ninodes]");
+        return ExitCode::FAILURE;
+    };
+    let Some(ninodes) = ninodes.map_or(Some(NINODES), |n| n.parse().ok()) else {
+        eprintln!("newfs: bad inode count");
+        return ExitCode::FAILURE;
+    };
+    match sys::mkfs(source, ninodes) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("newfs: {}: {}", source, e);
+            ExitCode::FAILURE
+        }
+    }
+}
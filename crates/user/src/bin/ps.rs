@@ -0,0 +1,56 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use ulib::{ExitCode, eprintln, fs, println};
+
+// The value of field key in a /proc/<pid>/status.
+fn field<'a>(status: &'a str, key: &str) -> &'a str {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .map_or("?", str::trim)
+}
+
+fn main() -> ExitCode {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("ps: /proc: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut pids: Vec<usize> = entries
+        .filter_map(|entry| entry.ok()?.file_name().parse().ok())
+        .collect();
+    pids.sort_unstable();
+
+    println!("{:>5} {:>5} {:>5} S CMD", "PID", "PPID", "UID");
+    for pid in pids {
+        // A process may exit between listing and reading.
+        let Ok(status) = fs::read_to_string(format!("/proc/{}/status", pid).as_str()) else {
+            continue;
+        };
+        let cmdline =
+            fs::read_to_string(format!("/proc/{}/cmdline", pid).as_str()).unwrap_or_default();
+        let cmd: String = if cmdline.is_empty() {
+            field(&status, "Name").into()
+        } else {
+            cmdline.trim_end_matches('\0').replace('\0', " ")
+        };
+        let uid = field(&status, "Uid").split('\t').nth(1).unwrap_or("?");
+        println!(
+            "{:>5} {:>5} {:>5} {} {}",
+            pid,
+            field(&status, "PPid"),
+            uid,
+            &field(&status, "State")[..1],
+            cmd
+        );
+    }
+    ExitCode::SUCCESS
+}
@@ -1,6 +1,7 @@
 #![no_std]
 extern crate alloc;
 use alloc::{format, string::String, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use ulib::{
     env, eprintln,
@@ -24,6 +25,14 @@ struct Job {
     stopped: bool,
 }
 
+// Set by SIGCHLD, so jobs that finish are reported while the shell
+// waits for input.
+static CHILD_CHANGED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigchld(_sig: usize) {
+    CHILD_CHANGED.store(true, Ordering::SeqCst);
+}
+
 fn init_job_control() -> usize {
     let _ = sys::setsid();
     let _ = sys::setpgid(0, 0);
@@ -57,7 +66,10 @@ fn parse_job_id(arg: Option<&str>, jobs: &[Job]) -> Option<usize> {
     }
 }
 
-fn reap_jobs(jobs: &mut Vec<Job>) {
+// Collect background jobs that have finished or stopped. Returns
+// whether any was reported.
+fn reap_jobs(jobs: &mut Vec<Job>) -> bool {
+    let mut reported = false;
     let mut i = 0;
     while i < jobs.len() {
         let mut stopped = false;
@@ -92,14 +104,17 @@ fn reap_jobs(jobs: &mut Vec<Job>) {
         if stopped {
             jobs[i].stopped = true;
             println!("[{}] stopped {}", jobs[i].id, jobs[i].cmd);
+            reported = true;
         }
         if jobs[i].pids.is_empty() {
             println!("[{}] done {}", jobs[i].id, jobs[i].cmd);
             jobs.remove(i);
+            reported = true;
         } else {
             i += 1;
         }
     }
+    reported
 }
 
 struct WaitResult {
@@ -844,11 +859,13 @@ fn main() {
     let _ = signal::signal(signal::SIGTSTP, signal::SIG_IGN);
     let _ = signal::signal(signal::SIGTTIN, signal::SIG_IGN);
     let _ = signal::signal(signal::SIGTTOU, signal::SIG_IGN);
+    let _ = signal::signal(signal::SIGCHLD, on_sigchld as *const () as usize);
     let mut jobs: Vec<Job> = Vec::new();
     let mut next_job_id = 1usize;
 
     // read and run input commands.
     'main: loop {
+        CHILD_CHANGED.store(false, Ordering::SeqCst);
         reap_jobs(&mut jobs);
         print!("$ ");
 
@@ -857,7 +874,12 @@ fn main() {
             match stdin().read_line(&mut input) {
                 Ok(0) => return,
                 Ok(_) => break,
-                Err(sys::Error::Interrupted) => continue,
+                Err(sys::Error::Interrupted) => {
+                    if CHILD_CHANGED.swap(false, Ordering::SeqCst) && reap_jobs(&mut jobs) {
+                        print!("$ ");
+                    }
+                    continue;
+                }
                 Err(e) => {
                     eprintln!("{}", e);
                     continue 'main;
@@ -0,0 +1,46 @@
+#![no_std]
+extern crate alloc;
+use alloc::string::String;
+
+use ulib::{env, eprintln, passwd, print, process::Command, stdio::stdin, sys};
+
+// Installed set-user-id root, so that it may switch to any user once
+// the target's password checks out.
+fn main() {
+    let name = env::args().nth(1).unwrap_or("root");
+    let user = match passwd::user_by_name(name) {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("su: {}: {}", name, e);
+            sys::exit(1);
+        }
+    };
+
+    // The real uid is still the caller's.
+    if sys::getuid().unwrap_or(0) != 0 && !user.password.is_empty() {
+        print!("password: ");
+        let mut password = String::new();
+        let _ = stdin().read_line(&mut password);
+        if !user.check_password(password.trim()) {
+            eprintln!("su: authentication failure");
+            sys::exit(1);
+        }
+    }
+
+    if let Err(e) = user.become_user() {
+        eprintln!("su: {}: {}", name, e);
+        sys::exit(1);
+    }
+    match Command::new(&user.shell)
+        .env("USER", &user.name)
+        .env("HOME", &user.home)
+        .status()
+    {
+        Ok(status) if status.0 == 0 => sys::exit(0),
+        Ok(_) => sys::exit(1),
+        Err(e) => {
+            eprintln!("su: {}: {}", user.shell, e);
+            sys::exit(1);
+        }
+    }
+}
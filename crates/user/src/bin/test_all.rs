@@ -2,28 +2,50 @@
 
 use ulib::{eprintln, println, process::Command, sys};
 
-const TESTS: [&str; 22] = [
+const TESTS: [&str; 44] = [
     "test_aplic",
+    "test_bcache",
+    "test_bigfile",
     "test_cow",
+    "test_cred",
     "test_dfs",
+    "test_dirent",
     "test_disk",
+    "test_disks",
     "fsck",
     "test_fcntl",
+    "test_fsck",
     "test_fsync",
     "test_ipc",
+    "test_journal",
     "test_kv",
     "test_ktask",
     "test_memc",
     "test_mr",
     "test_mmap",
+    "test_mount",
     "test_net",
+    "test_proc",
+    "test_devfs",
+    "test_rename",
     "test_reverse",
     "test_pdual",
+    "test_perm",
     "test_psort",
     "test_pzip",
     "test_poll",
+    "test_seek",
     "test_signal",
+    "test_sigmask",
+    "test_sigfault",
+    "test_sigaction",
+    "test_futex",
+    "test_tls",
+    "test_stat",
     "test_thread",
+    "test_time",
+    "test_tmpfs",
+    "test_trunc",
     "test_wserver",
 ];
 
@@ -0,0 +1,99 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec;
+
+use kernel::fs::BSIZE;
+use kernel::param::{NBUF, READAHEAD, WRITEBACK_TICKS};
+use ulib::{
+    eprintln,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    println,
+    sys::{self, Error},
+};
+
+const PATH: &str = "/t_bcache";
+const BLOCKS: usize = 48;
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_bcache: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// Buffers, hits, misses, readahead and writeback from /proc/bcache.
+fn counters() -> sys::Result<[usize; 5]> {
+    let text = fs::read_to_string("/proc/bcache")?;
+    let mut out = [0; 5];
+    for (slot, line) in out.iter_mut().zip(text.lines()) {
+        *slot = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|n| n.parse().ok())
+            .ok_or(Error::InvalidArgument)?;
+    }
+    Ok(out)
+}
+
+fn sequential() -> sys::Result<()> {
+    let mut data = vec![0u8; BLOCKS * BSIZE];
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = (i / BSIZE + i % 251) as u8;
+    }
+    File::create(PATH)?.write(&data)?;
+
+    let before = counters()?;
+    let mut file = File::open(PATH)?;
+    let mut buf = [0u8; BSIZE];
+    for i in 0..BLOCKS {
+        check("read", file.read(&mut buf)? == BSIZE)?;
+        check("data", buf[..] == data[i * BSIZE..(i + 1) * BSIZE])?;
+    }
+    let after = counters()?;
+    fs::remove_file(PATH)?;
+
+    let lookups = after[1] + after[2] - before[1] - before[2];
+    check("lookups", lookups >= BLOCKS)?;
+    // Cached or read ahead, at most one block in a window misses.
+    check(
+        "misses",
+        after[2] - before[2] <= BLOCKS.div_ceil(READAHEAD + 1),
+    )?;
+    println!("test_bcache: sequential ok");
+    Ok(())
+}
+
+// Raw disk writes are written back later by the kernel task. Block 0
+// is the unused boot block, so writing its own data back is harmless.
+fn writeback() -> sys::Result<()> {
+    let disk = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/disk0")?;
+    let mut block = [0u8; BSIZE];
+    check("disk read", disk.read_at(&mut block, 0)? == BSIZE)?;
+    let before = counters()?;
+    check("disk write", disk.write_at(&block, 0)? == BSIZE)?;
+    sys::sleep(WRITEBACK_TICKS * 2)?;
+    let after = counters()?;
+    check("writeback", after[4] > before[4])?;
+
+    check("disk rewrite", disk.write_at(&block, 0)? == BSIZE)?;
+    disk.sync()?;
+    println!("test_bcache: writeback ok");
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_bcache: start");
+    check("size", counters()?[0] >= NBUF)?;
+    sequential()?;
+    writeback()?;
+    println!("test_bcache: ok");
+    Ok(())
+}
@@ -0,0 +1,85 @@
+#![no_std]
+
+use kernel::fs::{BSIZE, NDINDIRECT, NDIRECT, NINDIRECT};
+use ulib::{
+    eprintln,
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    println,
+    sys::{self, Error},
+};
+
+// One block past what direct, indirect and double-indirect blocks
+// can map, so the tail lands behind the triple-indirect block.
+const NBLOCKS: usize = NDIRECT + NINDIRECT + NDINDIRECT + 1;
+const SIZE: usize = NBLOCKS * BSIZE + 100;
+
+// Every block holds its own number, repeated.
+fn fill(buf: &mut [u8; BSIZE], bn: usize) {
+    let (words, _) = buf.as_chunks_mut::<4>();
+    words.fill((bn as u32).to_le_bytes());
+}
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_bigfile: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_bigfile: start, {} bytes", SIZE);
+    let path = "/t_bigfile";
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    let mut buf = [0u8; BSIZE];
+    let mut off = 0;
+    while off < SIZE {
+        let n = core::cmp::min(BSIZE, SIZE - off);
+        fill(&mut buf, off / BSIZE);
+        file.write_all(&buf[..n])?;
+        off += n;
+    }
+    check("size", file.metadata()?.len() == SIZE)?;
+    println!("test_bigfile: write ok");
+
+    let mut expect = [0u8; BSIZE];
+    let mut reader = fs::File::open(path)?;
+    let mut off = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        fill(&mut expect, off / BSIZE);
+        if buf[..n] != expect[..n] {
+            eprintln!("test_bigfile: bad data at {}", off);
+            return Err(Error::InvalidArgument);
+        }
+        off += n;
+    }
+    check("read all", off == SIZE)?;
+
+    let last = NBLOCKS * BSIZE;
+    check("read tail", file.read_at(&mut buf, last)? == 100)?;
+    fill(&mut expect, NBLOCKS);
+    check("tail data", buf[..100] == expect[..100])?;
+    println!("test_bigfile: read ok");
+
+    // Shrinking back below the triple-indirect block frees it.
+    file.set_len(BSIZE)?;
+    check("shrunk", file.metadata()?.len() == BSIZE)?;
+    drop(file);
+    drop(reader);
+    fs::remove_file(path)?;
+
+    println!("test_bigfile: ok");
+    Ok(())
+}
@@ -0,0 +1,93 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::format;
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    passwd, println,
+    sys::{self, Error},
+};
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_cred: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// Passwords are kept as salt$hash; * or anything else locks the account.
+fn passwords() -> sys::Result<()> {
+    let hashed =
+        "18df02c26390713d$70735d00b6bf5c84884cc9e12d60c8e2b87527f85fcdfebe24d6d6f025cdcb78";
+    let parse = |password: &str| {
+        passwd::User::parse(&format!("u:{password}:5:5::/:/bin/sh")).ok_or(Error::InvalidArgument)
+    };
+    let user = parse(hashed)?;
+    check("password", user.check_password("hunter2"))?;
+    check("wrong password", !user.check_password("hunter3"))?;
+    check("hash as password", !user.check_password(hashed))?;
+    check("locked", !parse("*")?.check_password("*"))?;
+    check("no password", parse("")?.check_password("anything"))?;
+    Ok(())
+}
+
+// Runs in a forked child, which gives up root for good.
+fn drop_root() -> sys::Result<()> {
+    let guest = passwd::user_by_name("guest")?;
+    check("guest uid", guest.uid == 1000 && guest.gid == 1000)?;
+
+    sys::setgroups(&[10, 20])?;
+    let mut groups = [0u32; 4];
+    let n = sys::getgroups(&mut groups)?;
+    check("getgroups", groups[..n] == [10, 20])?;
+    check("getgroups count", sys::getgroups(&mut [])? == 2)?;
+
+    guest.become_user()?;
+    check("getuid", sys::getuid()? == 1000)?;
+    check("getgid", sys::getgid()? == 1000)?;
+    check(
+        "setuid back",
+        sys::setuid(0) == Err(Error::PermissionDenied),
+    )?;
+    check(
+        "setgroups",
+        sys::setgroups(&[0]) == Err(Error::PermissionDenied),
+    )?;
+
+    // "/" is root's and 0755, /tmp is open to everyone.
+    check(
+        "create in /",
+        File::create("/t_cred_root").err() == Some(Error::PermissionDenied),
+    )?;
+    File::create("/tmp/t_cred")?;
+    let attr = fs::metadata("/tmp/t_cred")?;
+    check("owner", attr.uid() == 1000 && attr.gid() == 1000)?;
+    check(
+        "chown away",
+        fs::chown("/tmp/t_cred", Some(0), None) == Err(Error::PermissionDenied),
+    )?;
+    fs::remove_file("/tmp/t_cred")?;
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_cred: start");
+    check("root", sys::getuid()? == 0)?;
+    passwords()?;
+
+    match sys::fork()? {
+        0 => sys::exit(if drop_root().is_ok() { 0 } else { 1 }),
+        _ => {
+            let mut status = 0;
+            sys::wait(&mut status)?;
+            check("child", status == 0)?;
+        }
+    }
+    check("parent still root", sys::getuid()? == 0)?;
+    println!("test_cred: ok");
+    Ok(())
+}
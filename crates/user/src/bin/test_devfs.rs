@@ -0,0 +1,140 @@
+#![no_std]
+
+extern crate alloc;
+
+use ulib::{
+    eprintln,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    println,
+    sys::{self, Error, stat::FileType},
+};
+
+const NODES: [&str; 7] = ["null", "zero", "full", "urandom", "tty", "console", "disk0"];
+const MKNOD: &str = "/tmp/t_devfs_zero";
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_devfs: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn listing() -> sys::Result<()> {
+    let mut seen = [false; NODES.len()];
+    for entry in fs::read_dir("/dev")? {
+        let entry = entry?;
+        if let Some(i) = NODES.iter().position(|&n| n == entry.file_name()) {
+            seen[i] = true;
+        }
+    }
+    check("read_dir", seen.iter().all(|&s| s))?;
+    for name in NODES {
+        let meta = fs::metadata(alloc::format!("/dev/{}", name).as_str())?;
+        check(name, meta.file_type() == FileType::Device)?;
+    }
+    check("null mode", fs::metadata("/dev/null")?.mode() == 0o666)?;
+    check("disk0 mode", fs::metadata("/dev/disk0")?.mode() == 0o600)?;
+    check(
+        "create",
+        File::create("/dev/t_devfs") == Err(Error::PermissionDenied),
+    )?;
+    check(
+        "unlink",
+        fs::remove_file("/dev/null") == Err(Error::PermissionDenied),
+    )?;
+    println!("test_devfs: listing ok");
+    Ok(())
+}
+
+fn memory() -> sys::Result<()> {
+    let mut buf = [1u8; 1000];
+    let mut null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    check("null read", null.read(&mut buf)? == 0)?;
+    check("null write", null.write(&buf)? == buf.len())?;
+
+    let mut zero = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/zero")?;
+    check("zero read", zero.read(&mut buf)? == buf.len())?;
+    check("zero data", buf.iter().all(|&b| b == 0))?;
+    check("zero write", zero.write(&buf)? == buf.len())?;
+
+    let mut full = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/full")?;
+    buf.fill(1);
+    check("full read", full.read(&mut buf)? == buf.len())?;
+    check("full data", buf.iter().all(|&b| b == 0))?;
+    check("full write", full.write(&buf) == Err(Error::StorageFull))?;
+    println!("test_devfs: memory ok");
+    Ok(())
+}
+
+fn urandom() -> sys::Result<()> {
+    let mut file = File::open("/dev/urandom")?;
+    let mut a = [0u8; 64];
+    let mut b = [0u8; 64];
+    check("urandom read", file.read(&mut a)? == a.len())?;
+    check("urandom read again", file.read(&mut b)? == b.len())?;
+    check("urandom zero", a.iter().any(|&x| x != 0))?;
+    check("urandom repeat", a != b)?;
+    let mut seeder = OpenOptions::new().write(true).open("/dev/urandom")?;
+    check("urandom write", seeder.write(&a)? == a.len())?;
+    check("urandom read mixed", file.read(&mut b)? == b.len())?;
+    check("urandom mixed", a != b)?;
+    println!("test_devfs: urandom ok");
+    Ok(())
+}
+
+fn tty() -> sys::Result<()> {
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    check("tty write", tty.write(b"test_devfs: tty ok\n")? == 19)?;
+    Ok(())
+}
+
+// /proc/devices gives the major numbers for mknod elsewhere.
+fn mknod() -> sys::Result<()> {
+    let devices = fs::read_to_string("/proc/devices")?;
+    for name in NODES {
+        check(
+            "proc devices",
+            devices
+                .lines()
+                .any(|l| l.split_whitespace().nth(1) == Some(name)),
+        )?;
+    }
+    let major = devices
+        .lines()
+        .find(|l| l.split_whitespace().nth(1) == Some("zero"))
+        .and_then(|l| l.split_whitespace().next()?.parse::<usize>().ok())
+        .ok_or(Error::NotFound)?;
+    sys::mknod(MKNOD, major, 0)?;
+    let mut buf = [1u8; 100];
+    let res = File::open(MKNOD).and_then(|mut f| f.read(&mut buf));
+    fs::remove_file(MKNOD)?;
+    check(
+        "mknod read",
+        res? == buf.len() && buf.iter().all(|&b| b == 0),
+    )?;
+    println!("test_devfs: mknod ok");
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_devfs: start");
+    listing()?;
+    memory()?;
+    urandom()?;
+    tty()?;
+    mknod()?;
+    println!("test_devfs: ok");
+    Ok(())
+}
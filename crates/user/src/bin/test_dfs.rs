@@ -9,7 +9,8 @@ use ulib::{
     io::{Read, Write},
     println,
     process::Command,
-    signal, sys,
+    signal,
+    sys::{self, stat::FileType},
 };
 
 const TEST_DIR_PREFIX: &str = "/dfs/dfstest";
@@ -116,6 +117,20 @@ fn run_client(idx: usize) -> sys::Result<()> {
         return Err(sys::Error::Uncategorized);
     }
 
+    // The server lists the directory with getdents on our behalf.
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir.as_str())? {
+        let entry = entry?;
+        if entry.file_type()? != FileType::File {
+            return Err(sys::Error::Uncategorized);
+        }
+        names.push(entry.file_name());
+    }
+    if names != [TEST_FILE_NAME] {
+        eprintln!("test_dfs: readdir idx={} got={:?}", idx, names);
+        return Err(sys::Error::Uncategorized);
+    }
+
     let _ = fs::remove_file(path.as_str());
     let _ = fs::remove_file(dir.as_str());
     Ok(())
@@ -0,0 +1,111 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    io::{Read, Write},
+    println,
+    sys::{self, Error, stat::FileType},
+};
+
+// On the root file system, not /tmp, to exercise xv6fs records.
+const DIR: &str = "/t_dirent";
+const NFILES: usize = 40;
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_dirent: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// Names long enough that the directory spans several blocks.
+fn name(i: usize) -> String {
+    format!("{}_{:03}", "long_file_name_".repeat(3), i)
+}
+
+fn listing() -> sys::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(DIR)? {
+        names.push(entry?.file_name());
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_dirent: start");
+    fs::create_dir(DIR)?;
+
+    let longest = "n".repeat(255);
+    let path = format!("{}/{}", DIR, longest);
+    File::create(path.as_str())?.write(b"255")?;
+    let mut buf = String::new();
+    File::open(path.as_str())?.read_to_string(&mut buf)?;
+    check("longest name", buf == "255")?;
+    check(
+        "name too long",
+        File::create(format!("{}/{}", DIR, "n".repeat(256)).as_str()).err()
+            == Some(Error::FileNameTooLong),
+    )?;
+    fs::remove_file(path.as_str())?;
+    println!("test_dirent: long names ok");
+
+    for i in 0..NFILES {
+        File::create(format!("{}/{}", DIR, name(i)).as_str())?;
+    }
+    let want: Vec<String> = (0..NFILES).map(name).collect();
+    check("listing", listing()? == want)?;
+
+    // getdents returns whole records only, and needs room for one.
+    let dir = File::open(DIR)?;
+    let mut small = [0u8; 8];
+    check(
+        "getdents small",
+        sys::getdents(dir.get_fd(), &mut small) == Err(Error::InvalidArgument),
+    )?;
+    for entry in fs::read_dir(DIR)? {
+        let entry = entry?;
+        check("file type", entry.file_type()? == FileType::File)?;
+        check(
+            "ino",
+            entry.ino() == fs::metadata(entry.path())?.inum() as u32,
+        )?;
+    }
+    println!("test_dirent: getdents ok");
+
+    // Free every other record, then fill the holes with other names.
+    for i in (0..NFILES).step_by(2) {
+        fs::remove_file(format!("{}/{}", DIR, name(i)).as_str())?;
+    }
+    for i in (0..NFILES).step_by(2) {
+        File::create(format!("{}/x{}", DIR, i).as_str())?;
+    }
+    let mut want: Vec<String> = (0..NFILES)
+        .map(|i| {
+            if i % 2 == 0 {
+                format!("x{}", i)
+            } else {
+                name(i)
+            }
+        })
+        .collect();
+    want.sort();
+    check("refill", listing()? == want)?;
+    let attr = fs::metadata(DIR)?;
+    check("dir size", attr.len() % 1024 == 0)?;
+    println!("test_dirent: reuse ok");
+
+    for entry in want {
+        fs::remove_file(format!("{}/{}", DIR, entry).as_str())?;
+    }
+    check("empty", listing()?.is_empty())?;
+    sys::unlink(DIR)?;
+    println!("test_dirent: ok");
+    Ok(())
+}
@@ -8,10 +8,10 @@ use ulib::{eprintln, fs::File, io::Read, println};
 fn main() {
     println!("test_disk: raw disk superblock");
 
-    let mut disk = match File::open("/dev/disk") {
+    let mut disk = match File::open("/dev/disk0") {
         Ok(file) => file,
         Err(e) => {
-            eprintln!("test_disk: open /dev/disk failed err={}", e);
+            eprintln!("test_disk: open /dev/disk0 failed err={}", e);
             return;
         }
     };
@@ -0,0 +1,84 @@
+#![no_std]
+
+extern crate alloc;
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    io::{Read, Write},
+    println,
+    sys::{self, Error},
+};
+
+const DATA: &str = "/dev/disk1";
+const MNT: &str = "/t_disks";
+const FILE: &str = "/t_disks/hello";
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_disks: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn listing() -> sys::Result<()> {
+    let devices = fs::read_to_string("/proc/devices")?;
+    check(
+        "proc devices",
+        devices
+            .lines()
+            .any(|l| l.split_whitespace().nth(1) == Some("disk0")),
+    )?;
+    check(
+        "root busy",
+        sys::mkfs("/dev/disk0", 200) == Err(Error::ResourceBusy),
+    )?;
+    check("not a disk", sys::mkfs("/dev/null", 200).is_err())?;
+    println!("test_disks: listing ok");
+    Ok(())
+}
+
+// Format the second disk, mount it, and check it is a tree of its own
+// that keeps its data across a remount.
+fn data() -> sys::Result<()> {
+    sys::mkfs(DATA, 200)?;
+    fs::create_dir(MNT)?;
+    sys::mount(DATA, MNT, "xv6fs")?;
+    check(
+        "mkfs mounted",
+        sys::mkfs(DATA, 200) == Err(Error::ResourceBusy),
+    )?;
+    File::create(FILE)?.write(b"second disk")?;
+    let root = File::open("/")?.stat()?.dev;
+    let mnt = File::open(MNT)?.stat()?.dev;
+    check(
+        "st_dev",
+        root != mnt && File::open(FILE)?.stat()?.dev == mnt,
+    )?;
+    sys::umount(MNT)?;
+    check("unmounted", File::open(FILE) == Err(Error::NotFound))?;
+
+    sys::mount(DATA, MNT, "xv6fs")?;
+    let mut buf = [0u8; 32];
+    let n = File::open(FILE)?.read(&mut buf)?;
+    fs::remove_file(FILE)?;
+    sys::umount(MNT)?;
+    fs::remove_file(MNT)?;
+    check("remount data", &buf[..n] == b"second disk")?;
+    println!("test_disks: data ok");
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_disks: start");
+    listing()?;
+    if fs::metadata(DATA).is_ok() {
+        data()?;
+    } else {
+        println!("test_disks: no {}, skipping data disk", DATA);
+    }
+    println!("test_disks: ok");
+    Ok(())
+}
@@ -0,0 +1,149 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use core::mem::size_of;
+
+use kernel::fs::{BSIZE, DirEnt, IPB, ROOTINO, SuperBlock};
+use ulib::{
+    eprintln,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    println,
+    process::Command,
+    sys::{self, Error},
+};
+
+const DISK: &str = "/dev/disk1";
+const MNT: &str = "/t_fsck";
+
+// Offsets into an on-disk inode.
+const INODE_SIZE: usize = BSIZE / IPB;
+const ITYPE: usize = 0;
+const NLINK: usize = 6;
+const ADDRS: usize = 16;
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_fsck: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn fsck(args: &[&str]) -> sys::Result<bool> {
+    Ok(Command::new("fsck").args(args).status()?.0 == 0)
+}
+
+fn read_block(disk: &File, bno: u32) -> sys::Result<[u8; BSIZE]> {
+    let mut buf = [0u8; BSIZE];
+    disk.read_at(&mut buf, bno as usize * BSIZE)?;
+    Ok(buf)
+}
+
+fn write_block(disk: &File, bno: u32, buf: &[u8; BSIZE]) -> sys::Result<()> {
+    disk.write_at(buf, bno as usize * BSIZE)?;
+    Ok(())
+}
+
+// Change bytes of inode inum at offset off within it.
+fn poke_inode(
+    disk: &File,
+    sb: &SuperBlock,
+    inum: u32,
+    off: usize,
+    bytes: &[u8],
+) -> sys::Result<()> {
+    let bno = sb.iblock(inum);
+    let mut buf = read_block(disk, bno)?;
+    let start = inum as usize % IPB * INODE_SIZE + off;
+    buf[start..start + bytes.len()].copy_from_slice(bytes);
+    write_block(disk, bno, &buf)
+}
+
+// Point the root directory's entry called name at inode 0.
+fn drop_entry(disk: &File, sb: &SuperBlock, name: &str) -> sys::Result<()> {
+    let root = read_block(disk, sb.iblock(ROOTINO))?;
+    let start = ROOTINO as usize % IPB * INODE_SIZE + ADDRS;
+    let bno = u32::from_le_bytes(root[start..start + 4].try_into().unwrap());
+    let mut buf = read_block(disk, bno)?;
+    let mut off = 0;
+    while off < BSIZE {
+        let de = unsafe { (buf.as_ptr().add(off) as *const DirEnt).read_unaligned() };
+        let at = off + size_of::<DirEnt>();
+        if de.inum != 0 && &buf[at..at + de.namelen as usize] == name.as_bytes() {
+            buf[off..off + 4].fill(0);
+            return write_block(disk, bno, &buf);
+        }
+        off += de.reclen as usize;
+    }
+    Err(Error::NotFound)
+}
+
+fn populate() -> sys::Result<[u32; 3]> {
+    sys::mkfs(DISK, 200)?;
+    fs::create_dir(MNT)?;
+    sys::mount(DISK, MNT, "xv6fs")?;
+    let mut inums = [0; 3];
+    for (inum, name) in inums.iter_mut().zip(["keep", "orphan", "gone"]) {
+        let path = format!("{}/{}", MNT, name);
+        File::create(path.as_str())?.write(name.as_bytes())?;
+        *inum = File::open(path.as_str())?.stat()?.ino;
+    }
+    sys::umount(MNT)?;
+    Ok(inums)
+}
+
+// A wrong link count, an orphan, and an entry naming a freed inode
+// whose block is still marked in use.
+fn corrupt([keep, orphan, gone]: [u32; 3]) -> sys::Result<()> {
+    let disk = OpenOptions::new().read(true).write(true).open(DISK)?;
+    let sb = read_block(&disk, 1)?;
+    let sb = unsafe { (sb.as_ptr() as *const SuperBlock).read_unaligned() };
+    poke_inode(&disk, &sb, keep, NLINK, &5u16.to_le_bytes())?;
+    drop_entry(&disk, &sb, "orphan")?;
+    poke_inode(&disk, &sb, gone, ITYPE, &0u16.to_le_bytes())?;
+    disk.sync()?;
+    println!("test_fsck: corrupted inodes {} {} {}", keep, orphan, gone);
+    Ok(())
+}
+
+fn verify(orphan: u32) -> sys::Result<()> {
+    sys::mount(DISK, MNT, "xv6fs")?;
+    let res = (|| {
+        check(
+            "nlink",
+            fs::metadata(format!("{}/keep", MNT).as_str())?.nlink() == 1,
+        )?;
+        check(
+            "dangling",
+            fs::metadata(format!("{}/gone", MNT).as_str()).err() == Some(Error::NotFound),
+        )?;
+        let mut data = [0u8; 16];
+        let path = format!("{}/lost+found/#{}", MNT, orphan);
+        let n = File::open(path.as_str())?.read(&mut data)?;
+        check("lost+found", &data[..n] == b"orphan")
+    })();
+    sys::umount(MNT)?;
+    res
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_fsck: start");
+    if fs::metadata(DISK).is_err() {
+        println!("test_fsck: no {}, skipping", DISK);
+        return Ok(());
+    }
+    let inums = populate()?;
+    check("clean", fsck(&[DISK])?)?;
+    corrupt(inums)?;
+    check("detect", !fsck(&[DISK])?)?;
+    check("repair", fsck(&["-y", DISK])?)?;
+    check("repaired", fsck(&[DISK])?)?;
+    verify(inums[1])?;
+    fs::remove_file(MNT)?;
+    println!("test_fsck: ok");
+    Ok(())
+}
@@ -18,7 +18,11 @@ fn print_meta(label: &str, meta: &ulib::fs::Metadata) {
     );
 }
 
-fn journal_demo() -> sys::Result<()> {
+// Arm a crash at the given commit stage and write a file; reboot and
+// run again to check. Stages 1 and 4 crash after the commit, so the
+// data survives; 2 and 3 crash before it or tear the header, so the
+// write is lost and the file stays empty.
+fn journal_demo(stage: usize) -> sys::Result<()> {
     const PATH: &str = "/t_journal.txt";
     const DATA: &[u8] = b"journal-data";
 
@@ -30,8 +34,10 @@ fn journal_demo() -> sys::Result<()> {
         if n == DATA.len() && &buf[..n] == DATA {
             let _ = remove_file(PATH);
             println!("test_fsync: journal recovered ok, removed {}", PATH);
-        }
-        if n > 0 {
+        } else if n == 0 {
+            let _ = remove_file(PATH);
+            println!("test_fsync: journal discarded ok, removed {}", PATH);
+        } else {
             eprintln!("test_fsync: journal mismatch n={}", n);
             return Err(sys::Error::InvalidArgument);
         }
@@ -42,8 +48,9 @@ fn journal_demo() -> sys::Result<()> {
         .create(true)
         .truncate(true)
         .open(PATH)?;
-    println!("test_fsync: journal phase1 arming crash");
-    sys::logcrash(1)?;
+    file.sync()?;
+    println!("test_fsync: journal phase1 arming crash at stage {}", stage);
+    sys::logcrash(stage)?;
     let n = file.write(DATA)?;
     println!("test_fsync: journal wrote n={}", n);
     file.sync()?;
@@ -52,8 +59,10 @@ fn journal_demo() -> sys::Result<()> {
 }
 
 fn main() -> sys::Result<()> {
-    if env::args().any(|arg| arg == "--journal") {
-        return journal_demo();
+    let mut args = env::args().skip(1);
+    if args.next() == Some("--journal") {
+        let stage = args.next().map_or(Ok(1), |s| s.parse());
+        return journal_demo(stage.or(Err(sys::Error::InvalidArgument))?);
     }
 
     println!("test_fsync: start");
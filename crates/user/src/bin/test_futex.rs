@@ -0,0 +1,199 @@
+#![no_std]
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use ulib::{
+    barrier::Barrier,
+    eprintln, futex,
+    ipc::{self, PROT_READ, PROT_WRITE},
+    mutex::{Condvar, Mutex},
+    once::Once,
+    println,
+    rwlock::RwLock,
+    sys::{self, Error},
+    thread,
+};
+
+const NTHREADS: usize = 4;
+const ITERS: usize = 500;
+
+static WORD: AtomicU32 = AtomicU32::new(0);
+static FORKED: AtomicU32 = AtomicU32::new(0);
+static COUNT: Mutex<usize> = Mutex::new(0);
+static READY: Mutex<usize> = Mutex::new(0);
+static READY_CV: Condvar = Condvar::new();
+static PAIR: RwLock<(usize, usize)> = RwLock::new((0, 0));
+static TORN: AtomicUsize = AtomicUsize::new(0);
+static ONCE: Once = Once::new();
+static RAN: AtomicUsize = AtomicUsize::new(0);
+static BARRIER: Barrier = Barrier::new(NTHREADS);
+static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+static EARLY: AtomicUsize = AtomicUsize::new(0);
+static LEADERS: AtomicUsize = AtomicUsize::new(0);
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_futex: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn spawn(f: extern "C" fn(usize, usize)) -> sys::Result<()> {
+    for i in 0..NTHREADS {
+        thread::thread_create(f, i, 0)?;
+    }
+    Ok(())
+}
+
+fn join() -> sys::Result<()> {
+    for _ in 0..NTHREADS {
+        thread::thread_join()?;
+    }
+    Ok(())
+}
+
+fn run(f: extern "C" fn(usize, usize)) -> sys::Result<()> {
+    spawn(f)?;
+    join()
+}
+
+extern "C" fn count(_i: usize, _unused: usize) {
+    for _ in 0..ITERS {
+        *COUNT.lock() += 1;
+    }
+    *READY.lock() += 1;
+    READY_CV.notify_one();
+}
+
+// Writers keep both halves equal; readers must never see them differ.
+extern "C" fn read_write(i: usize, _unused: usize) {
+    for _ in 0..ITERS {
+        if i % 2 == 0 {
+            let mut pair = PAIR.write();
+            pair.0 += 1;
+            pair.1 += 1;
+        } else {
+            let pair = PAIR.read();
+            if pair.0 != pair.1 {
+                TORN.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+extern "C" fn once(_i: usize, _unused: usize) {
+    ONCE.call_once(|| {
+        let _ = sys::sleep(2);
+        RAN.fetch_add(1, Ordering::SeqCst);
+    });
+    if RAN.load(Ordering::SeqCst) != 1 {
+        EARLY.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+extern "C" fn barrier(_i: usize, _unused: usize) {
+    for round in 1..=3 {
+        ARRIVED.fetch_add(1, Ordering::SeqCst);
+        if BARRIER.wait() {
+            LEADERS.fetch_add(1, Ordering::SeqCst);
+        }
+        if ARRIVED.load(Ordering::SeqCst) < round * NTHREADS {
+            EARLY.fetch_add(1, Ordering::SeqCst);
+        }
+        // Nobody starts the next round until all have checked this one.
+        BARRIER.wait();
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_futex: start");
+
+    check(
+        "changed word",
+        futex::wait(&WORD, 1, 0) == Err(Error::WouldBlock),
+    )?;
+    check("timeout", futex::wait(&WORD, 0, 2) == Err(Error::TimedOut))?;
+    check("no waiters", futex::wake(&WORD, 1)? == 0)?;
+    check("unaligned", sys::futex(3, futex::FUTEX_WAKE, 1, 0).is_err())?;
+    println!("test_futex: futex ok");
+
+    spawn(count)?;
+    let ready = READY_CV.wait_while(READY.lock(), |n| *n < NTHREADS);
+    check("condvar", *ready == NTHREADS)?;
+    drop(ready);
+    join()?;
+    check("count", *COUNT.lock() == NTHREADS * ITERS)?;
+    let guard = COUNT.lock();
+    check("try_lock", COUNT.try_lock().is_none())?;
+    drop(guard);
+    check("try_lock free", COUNT.try_lock().is_some())?;
+    let (_guard, timed_out) = READY_CV.wait_timeout(READY.lock(), 2);
+    check("condvar timeout", timed_out)?;
+    println!("test_futex: mutex ok");
+
+    run(read_write)?;
+    check("torn read", TORN.load(Ordering::SeqCst) == 0)?;
+    let pair = *PAIR.read();
+    check(
+        "writes",
+        pair == (NTHREADS / 2 * ITERS, NTHREADS / 2 * ITERS),
+    )?;
+    run(once)?;
+    check(
+        "once",
+        RAN.load(Ordering::SeqCst) == 1 && ONCE.is_completed(),
+    )?;
+    run(barrier)?;
+    check("barrier leaders", LEADERS.load(Ordering::SeqCst) == 3)?;
+    check("barrier early", EARLY.load(Ordering::SeqCst) == 0)?;
+    println!("test_futex: sync ok");
+
+    // A word in a shared segment is the same futex in both processes,
+    // wherever each has it mapped.
+    let id = ipc::shm_create(4096)?;
+    let child = sys::fork()?;
+    if child == 0 {
+        let ok = ipc::shm_attach(id, PROT_READ | PROT_WRITE).is_ok_and(|addr| {
+            let word = unsafe { &*(addr as *const AtomicU32) };
+            let _ = sys::sleep(2);
+            word.store(1, Ordering::SeqCst);
+            futex::wake(word, 1).is_ok()
+        });
+        sys::exit(if ok { 0 } else { 1 });
+    }
+    let addr = ipc::shm_attach(id, PROT_READ | PROT_WRITE)?;
+    let word = unsafe { &*(addr as *const AtomicU32) };
+    let res = futex::wait(word, 0, 200);
+    check("shared wake", res.is_ok() || res == Err(Error::WouldBlock))?;
+    check("shared word", word.load(Ordering::SeqCst) == 1)?;
+    let mut status = 0;
+    sys::waitpid(child as isize, &mut status, 0)?;
+    check("shared child", status == 0)?;
+    ipc::shm_detach(addr)?;
+    ipc::shm_destroy(id)?;
+    println!("test_futex: shm ok");
+
+    // A fork while a thread waits makes the word's page copy-on-write
+    // again, so the store below moves it to a new frame. The waiter
+    // must still be found.
+    let waiter = thread::spawn(|| futex::wait(&FORKED, 0, 200))?;
+    let _ = sys::sleep(2);
+    let child = sys::fork()?;
+    if child == 0 {
+        sys::exit(0);
+    }
+    FORKED.store(1, Ordering::SeqCst);
+    futex::wake(&FORKED, 1)?;
+    let res = waiter.join()?;
+    check(
+        "wake after fork",
+        res.is_ok() || res == Err(Error::WouldBlock),
+    )?;
+    sys::waitpid(child as isize, &mut status, 0)?;
+    println!("test_futex: fork ok");
+
+    println!("test_futex: ok");
+    Ok(())
+}
@@ -2,7 +2,7 @@
 
 use kernel::mmap::{PROT_READ, PROT_WRITE};
 use ulib::io::{Read, Write};
-use ulib::{eprintln, ipc, println, socket, sys};
+use ulib::{eprintln, fs, ipc, println, socket, sys};
 
 const PGSIZE: usize = 4096;
 
@@ -102,7 +102,7 @@ fn main() {
 }
 
 fn test_unix_socket() {
-    const PATH: &str = "/sock-ipc";
+    const PATH: &str = "/tmp/sock-ipc";
     println!("test_ipc: af_unix socket");
 
     let server = match socket::socket(socket::AF_UNIX, socket::SOCK_STREAM, 0) {
@@ -180,4 +180,5 @@ fn test_unix_socket() {
     let _ = conn.write(&buf[..n]);
     let mut status: i32 = 0;
     let _ = sys::wait(&mut status);
+    let _ = fs::remove_file(PATH);
 }
@@ -0,0 +1,119 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+
+use kernel::param::LOGSIZE;
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    io::Write,
+    println,
+    sys::{self, Error},
+};
+
+const WRITERS: usize = 4;
+const WRITES: usize = 16;
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_journal: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// Size, seq, commits and transactions from /proc/log.
+fn counters() -> sys::Result<[usize; 4]> {
+    let text = fs::read_to_string("/proc/log")?;
+    let mut out = [0; 4];
+    for (slot, line) in out.iter_mut().zip(text.lines()) {
+        *slot = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|n| n.parse().ok())
+            .ok_or(Error::InvalidArgument)?;
+    }
+    Ok(out)
+}
+
+fn path(i: usize) -> alloc::string::String {
+    format!("/t_journal_{}", i)
+}
+
+fn writer(i: usize) -> sys::Result<()> {
+    let mut file = File::create(path(i).as_str())?;
+    for _ in 0..WRITES {
+        file.write(&[i as u8; 100])?;
+    }
+    Ok(())
+}
+
+// fsync commits the open group and advances the sequence number.
+fn fsync() -> sys::Result<()> {
+    let before = counters()?;
+    let mut file = File::create("/t_journal")?;
+    file.write(b"journal")?;
+    file.sync()?;
+    let after = counters()?;
+    fs::remove_file("/t_journal")?;
+    check("seq", after[1] > before[1])?;
+    check("commits", after[2] > before[2])?;
+    println!("test_journal: fsync ok");
+    Ok(())
+}
+
+// Concurrent writers share commits; every system call lands in one.
+fn group() -> sys::Result<()> {
+    let before = counters()?;
+    for i in 0..WRITERS {
+        if sys::fork()? == 0 {
+            sys::exit(if writer(i).is_ok() { 0 } else { 1 });
+        }
+    }
+    for _ in 0..WRITERS {
+        let mut status: i32 = 0;
+        sys::wait(&mut status)?;
+        check("writer", status == 0)?;
+    }
+    File::open("/")?.sync()?;
+    let after = counters()?;
+    for i in 0..WRITERS {
+        let data = fs::read_to_string(path(i).as_str())?;
+        fs::remove_file(path(i).as_str())?;
+        check(
+            "data",
+            data.len() == WRITES * 100 && data.bytes().all(|b| b == i as u8),
+        )?;
+    }
+    let commits = after[2] - before[2];
+    let transactions = after[3] - before[3];
+    check("grouped", commits >= 1 && transactions >= WRITERS * WRITES)?;
+    println!(
+        "test_journal: group ok, {} transactions in {} commits",
+        transactions, commits
+    );
+    Ok(())
+}
+
+fn crash_stages() -> sys::Result<()> {
+    check(
+        "stage range",
+        sys::logcrash(5) == Err(Error::InvalidArgument),
+    )?;
+    sys::logcrash(0)?;
+    println!("test_journal: stages ok");
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_journal: start");
+    check("size", counters()?[0] == LOGSIZE)?;
+    fsync()?;
+    group()?;
+    crash_stages()?;
+    println!("test_journal: ok");
+    Ok(())
+}
@@ -0,0 +1,88 @@
+#![no_std]
+
+use ulib::{
+    env, eprintln,
+    fs::{self, File},
+    println,
+    sys::{self, Error},
+};
+
+const MNT: &str = "/tmp/t_mnt";
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_mount: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// None of this talks to a dfs server: the root of a dfs mount is known
+// without asking it.
+fn main() -> sys::Result<()> {
+    println!("test_mount: start");
+    let _ = fs::create_dir(MNT);
+    let tmp = fs::metadata("/tmp")?.inum();
+
+    sys::mount("none", MNT, "dfs")?;
+    check(
+        "mount twice",
+        sys::mount("none", MNT, "dfs") == Err(Error::ResourceBusy),
+    )?;
+    check(
+        "remove mount point",
+        fs::remove_file(MNT) == Err(Error::ResourceBusy),
+    )?;
+    check(
+        "rename mount point",
+        fs::rename(MNT, "/tmp/t_mnt2") == Err(Error::ResourceBusy),
+    )?;
+    println!("test_mount: mount ok");
+
+    // ".." of a mounted root leads out of the mount.
+    env::set_current_dir(MNT)?;
+    check(
+        "umount in use",
+        sys::umount(MNT) == Err(Error::ResourceBusy),
+    )?;
+    env::set_current_dir("..")?;
+    check("dotdot", fs::metadata(".")?.inum() == tmp)?;
+    env::set_current_dir("/")?;
+
+    sys::umount(MNT)?;
+    check(
+        "umount twice",
+        sys::umount(MNT) == Err(Error::InvalidArgument),
+    )?;
+    check("umount /", sys::umount("/") == Err(Error::ResourceBusy))?;
+    println!("test_mount: umount ok");
+
+    check(
+        "fstype",
+        sys::mount("none", MNT, "nofs") == Err(Error::InvalidArgument),
+    )?;
+    File::create("/tmp/t_mnt_file")?;
+    check(
+        "mount on file",
+        sys::mount("none", "/tmp/t_mnt_file", "dfs") == Err(Error::NotADirectory),
+    )?;
+    fs::remove_file("/tmp/t_mnt_file")?;
+
+    match sys::fork()? {
+        0 => {
+            let ok = sys::setuid(1000).is_ok()
+                && sys::mount("none", MNT, "dfs") == Err(Error::PermissionDenied);
+            sys::exit(if ok { 0 } else { 1 })
+        }
+        _ => {
+            let mut status = 0;
+            sys::wait(&mut status)?;
+            check("mount as user", status == 0)?;
+        }
+    }
+
+    fs::remove_file(MNT)?;
+    println!("test_mount: ok");
+    Ok(())
+}
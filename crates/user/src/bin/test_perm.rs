@@ -0,0 +1,80 @@
+#![no_std]
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    io::Write,
+    println,
+    sys::{self, Error},
+};
+
+fn check_mode(path: &str, want: u32) -> sys::Result<()> {
+    let got = fs::metadata(path)?.mode();
+    if got != want {
+        eprintln!("test_perm: {} mode={:o} want={:o}", path, got, want);
+        return Err(Error::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_perm: start");
+    let a = "/t_perm_a";
+    let b = "/t_perm_b";
+    let d = "/t_perm_d";
+
+    let old = sys::umask(0o022)?;
+    File::create(a)?.write(b"not an elf")?;
+    check_mode(a, 0o644)?;
+    fs::create_dir(d)?;
+    check_mode(d, 0o755)?;
+    sys::umask(0o077)?;
+    File::create(b)?;
+    check_mode(b, 0o600)?;
+    sys::umask(old)?;
+    println!("test_perm: umask ok");
+
+    fs::set_permissions(a, 0o600)?;
+    check_mode(a, 0o600)?;
+    File::open(a)?.set_permissions(0o640)?;
+    check_mode(a, 0o640)?;
+    println!("test_perm: chmod ok");
+
+    // Even root may not execute a file without any execute bit.
+    match sys::exec(a, &["t_perm_a"], None) {
+        Err(Error::PermissionDenied) => {}
+        res => {
+            eprintln!("test_perm: exec without x = {:?}", res);
+            return Err(Error::InvalidArgument);
+        }
+    }
+    fs::set_permissions(a, 0o750)?;
+    match sys::exec(a, &["t_perm_a"], None) {
+        Err(Error::ExecFileFormatError) => {}
+        res => {
+            eprintln!("test_perm: exec with x = {:?}", res);
+            return Err(Error::InvalidArgument);
+        }
+    }
+    println!("test_perm: exec ok");
+
+    fs::chown(a, Some(7), Some(8))?;
+    let attr = fs::metadata(a)?;
+    if attr.uid() != 7 || attr.gid() != 8 {
+        eprintln!("test_perm: owner {}:{}", attr.uid(), attr.gid());
+        return Err(Error::InvalidArgument);
+    }
+    fs::chown(a, None, Some(0))?;
+    let attr = fs::metadata(a)?;
+    if attr.uid() != 7 || attr.gid() != 0 {
+        eprintln!("test_perm: owner {}:{}", attr.uid(), attr.gid());
+        return Err(Error::InvalidArgument);
+    }
+    println!("test_perm: chown ok");
+
+    fs::remove_file(a)?;
+    fs::remove_file(b)?;
+    sys::unlink(d)?;
+    println!("test_perm: ok");
+    Ok(())
+}
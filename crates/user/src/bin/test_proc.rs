@@ -0,0 +1,107 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+
+use ulib::{
+    env, eprintln,
+    fs::{self, File, OpenOptions},
+    println,
+    sys::{self, Error},
+};
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_proc: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_proc: start");
+    let pid = sys::getpid()?;
+
+    let status = fs::read_to_string("/proc/self/status")?;
+    check(
+        "status pid",
+        status.lines().any(|l| l == format!("Pid:\t{}", pid)),
+    )?;
+    check(
+        "status name",
+        status.lines().any(|l| l == "Name:\ttest_proc"),
+    )?;
+    check(
+        "self",
+        fs::read_link("/proc/self")?.to_str() == format!("{}", pid),
+    )?;
+    let cmdline = fs::read_to_string(format!("/proc/{}/cmdline", pid).as_str())?;
+    check("cmdline", cmdline.starts_with("test_proc\0"))?;
+    println!("test_proc: status ok");
+
+    env::set_current_dir("/tmp")?;
+    check("cwd", fs::read_link("/proc/self/cwd")?.to_str() == "/tmp")?;
+    env::set_current_dir("/")?;
+    check("cwd root", fs::read_link("/proc/self/cwd")?.to_str() == "/")?;
+    let dir = File::open("/proc")?;
+    let fd = format!("/proc/self/fd/{}", dir.get_fd());
+    check("fd", fs::read_link(fd.as_str())?.to_str() == "/proc")?;
+    drop(dir);
+    check("fd closed", fs::read_link(fd.as_str()).is_err())?;
+    println!("test_proc: links ok");
+
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    check("meminfo", meminfo.starts_with("MemTotal:"))?;
+    let mounts = fs::read_to_string("/proc/mounts")?;
+    check(
+        "mounts",
+        mounts.lines().any(|l| l == "tmpfs /tmp tmpfs")
+            && mounts.lines().any(|l| l == "proc /proc proc"),
+    )?;
+    check(
+        "interrupts",
+        fs::read_to_string("/proc/interrupts")?.contains("virtio-disk"),
+    )?;
+    check(
+        "uptime",
+        fs::read_to_string("/proc/uptime")?
+            .trim()
+            .parse::<f64>()
+            .is_ok(),
+    )?;
+    check(
+        "read only",
+        OpenOptions::new()
+            .write(true)
+            .open("/proc/meminfo")
+            .is_err()
+            && File::create("/proc/new").is_err(),
+    )?;
+    println!("test_proc: files ok");
+
+    // A child shows up while it lives and is gone once reaped.
+    let child = match sys::fork()? {
+        0 => {
+            sys::sleep(5)?;
+            sys::exit(0)
+        }
+        child => child,
+    };
+    let listed = fs::read_dir("/proc")?
+        .filter_map(|e| e.ok())
+        .any(|e| e.file_name() == format!("{}", child));
+    let ppid = fs::read_to_string(format!("/proc/{}/status", child).as_str())?
+        .lines()
+        .any(|l| l == format!("PPid:\t{}", pid));
+    let mut st = 0;
+    sys::wait(&mut st)?;
+    check("child listed", listed && ppid)?;
+    check(
+        "child reaped",
+        fs::metadata(format!("/proc/{}", child).as_str()).err() == Some(Error::NotFound),
+    )?;
+    println!("test_proc: ok");
+    Ok(())
+}
@@ -0,0 +1,120 @@
+#![no_std]
+
+use ulib::{
+    env, eprintln,
+    fs::{self, File, remove_file},
+    io::{Read, Write},
+    println, sys,
+};
+
+fn read_all(path: &str, buf: &mut [u8]) -> sys::Result<usize> {
+    let mut file = File::open(path)?;
+    file.read(buf)
+}
+
+fn write_file(path: &str, data: &[u8]) -> sys::Result<()> {
+    let mut file = File::create(path)?;
+    file.write(data)?;
+    file.sync()
+}
+
+fn expect_err(label: &str, res: sys::Result<()>, want: sys::Error) -> sys::Result<()> {
+    match res {
+        Err(e) if e == want => {
+            println!("test_rename: {} -> {}", label, e);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("test_rename: {} err={}", label, e);
+            Err(e)
+        }
+        Ok(()) => {
+            eprintln!("test_rename: {} unexpected ok", label);
+            Err(sys::Error::InvalidArgument)
+        }
+    }
+}
+
+fn journal_demo() -> sys::Result<()> {
+    const OLD: &str = "/t_jren_old";
+    const NEW: &str = "/t_jren_new";
+    const DATA: &[u8] = b"rename-data";
+
+    println!("test_rename: journal demo");
+
+    let mut buf = [0u8; 32];
+    if let Ok(n) = read_all(NEW, &mut buf) {
+        if n == DATA.len() && &buf[..n] == DATA && fs::metadata(OLD).is_err() {
+            let _ = remove_file(NEW);
+            println!("test_rename: journal recovered ok, removed {}", NEW);
+            return Ok(());
+        }
+        eprintln!("test_rename: journal mismatch n={}", n);
+        return Err(sys::Error::InvalidArgument);
+    }
+
+    let _ = remove_file(OLD);
+    write_file(OLD, DATA)?;
+    println!("test_rename: journal phase1 arming crash");
+    sys::logcrash(1)?;
+    fs::rename(OLD, NEW)?;
+    eprintln!("test_rename: crash did not trigger");
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    if env::args().any(|arg| arg == "--journal") {
+        return journal_demo();
+    }
+
+    println!("test_rename: start");
+    let a = "/t_ren_a";
+    let b = "/t_ren_b";
+    let d1 = "/t_ren_d1";
+    let d2 = "/t_ren_d2";
+
+    write_file(a, b"old")?;
+    write_file(b, b"new")?;
+    fs::rename(a, b)?;
+    let mut buf = [0u8; 8];
+    let n = read_all(b, &mut buf)?;
+    if &buf[..n] != b"old" || fs::metadata(a).is_ok() {
+        eprintln!("test_rename: replace mismatch n={}", n);
+        return Err(sys::Error::InvalidArgument);
+    }
+    println!("test_rename: replace ok");
+
+    fs::create_dir(d1)?;
+    fs::create_dir(d2)?;
+    fs::create_dir("/t_ren_d1/sub")?;
+    fs::rename("/t_ren_d1/sub", "/t_ren_d2/sub")?;
+    let parent = fs::metadata("/t_ren_d2/sub/..")?.inum();
+    if parent != fs::metadata(d2)?.inum() {
+        eprintln!("test_rename: dotdot={} not updated", parent);
+        return Err(sys::Error::InvalidArgument);
+    }
+    println!("test_rename: move dir ok");
+
+    expect_err(
+        "into subtree",
+        fs::rename(d2, "/t_ren_d2/sub/d2"),
+        sys::Error::InvalidArgument,
+    )?;
+    expect_err(
+        "over non-empty dir",
+        fs::rename(d1, d2),
+        sys::Error::DirectoryNotEmpty,
+    )?;
+    expect_err(
+        "to dfs",
+        fs::rename(b, "/dfs/t_ren_b"),
+        sys::Error::CrossesDevices,
+    )?;
+
+    sys::unlink("/t_ren_d2/sub")?;
+    sys::unlink(d2)?;
+    sys::unlink(d1)?;
+    remove_file(b)?;
+    println!("test_rename: ok");
+    Ok(())
+}
@@ -0,0 +1,98 @@
+#![no_std]
+
+use core::mem::size_of;
+
+use kernel::fs::{BSIZE, FSMAGIC, SuperBlock};
+use ulib::{
+    eprintln,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    pipe, println,
+    sys::{self, Error},
+};
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_seek: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_seek: start");
+    let path = "/tmp/t_seek";
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(b"0123456789")?;
+
+    let mut buf = [0u8; 4];
+    check("seek set", file.seek(SeekFrom::Start(2))? == 2)?;
+    file.read(&mut buf)?;
+    check("read after set", &buf == b"2345")?;
+    check("seek cur", file.seek(SeekFrom::Current(-3))? == 3)?;
+    file.read(&mut buf)?;
+    check("read after cur", &buf == b"3456")?;
+    check("seek end", file.seek(SeekFrom::End(-2))? == 8)?;
+    check(
+        "short read at end",
+        file.read(&mut buf)? == 2 && &buf[..2] == b"89",
+    )?;
+    check(
+        "negative offset",
+        file.seek(SeekFrom::Current(-100)).err() == Some(Error::InvalidArgument),
+    )?;
+    check("position kept", file.stream_position()? == 10)?;
+    println!("test_seek: lseek ok");
+
+    check("pread", file.read_at(&mut buf, 4)? == 4 && &buf == b"4567")?;
+    check("pwrite", file.write_at(b"ab", 0)? == 2)?;
+    check("offset untouched", file.stream_position()? == 10)?;
+    file.rewind()?;
+    file.read(&mut buf)?;
+    check("read pwrite data", &buf == b"ab23")?;
+    println!("test_seek: pread/pwrite ok");
+
+    // Writing past the end fills the gap with zeros.
+    file.seek(SeekFrom::End(BSIZE as isize))?;
+    file.write_all(b"z")?;
+    check("grown", file.metadata()?.len() == 10 + BSIZE + 1)?;
+    let mut gap = [0xffu8; BSIZE];
+    check("gap read", file.read_at(&mut gap, 10)? == BSIZE)?;
+    check("gap zeroed", gap.iter().all(|&b| b == 0))?;
+    println!("test_seek: gap ok");
+    drop(file);
+    fs::remove_file(path)?;
+
+    let (mut reader, _writer) = pipe::pipe()?;
+    check(
+        "pipe seek",
+        reader.seek(SeekFrom::Start(0)).err() == Some(Error::IllegalSeek),
+    )?;
+    check(
+        "pipe pread",
+        reader.read_at(&mut buf, 0).err() == Some(Error::IllegalSeek),
+    )?;
+
+    let mut disk = File::open("/dev/disk0")?;
+    let mut block = [0u8; BSIZE];
+    check(
+        "disk pread",
+        disk.read_at(&mut block, BSIZE)? >= size_of::<SuperBlock>(),
+    )?;
+    let sb = unsafe { (block.as_ptr() as *const SuperBlock).read_unaligned() };
+    check("disk magic", sb.magic == FSMAGIC)?;
+    check(
+        "disk size",
+        disk.seek(SeekFrom::End(0))? == sb.size as usize * BSIZE,
+    )?;
+    println!("test_seek: disk ok");
+
+    println!("test_seek: ok");
+    Ok(())
+}
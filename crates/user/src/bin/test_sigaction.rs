@@ -0,0 +1,170 @@
+#![no_std]
+
+use core::sync::atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering};
+
+use ulib::{
+    eprintln,
+    io::{Read, Write},
+    pipe, println,
+    signal::{
+        self, SA_NODEFER, SA_RESETHAND, SA_RESTART, SA_SIGINFO, SIG_BLOCK, SIG_DFL, SIGALRM,
+        SIGCHLD, SIGSEGV, SIGUSR1, SIGUSR2, SigAction, SigInfo, sig_mask,
+    },
+    sys::{self, Error},
+};
+
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+static MASK: AtomicU32 = AtomicU32::new(0);
+static SIGNO: AtomicU32 = AtomicU32::new(0);
+static PID: AtomicUsize = AtomicUsize::new(0);
+static STATUS: AtomicI32 = AtomicI32::new(0);
+static ADDR: AtomicUsize = AtomicUsize::new(0);
+static NAPPED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn count(_sig: usize) {
+    CAUGHT.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mask) = signal::sigprocmask(SIG_BLOCK, 0) {
+        MASK.store(mask, Ordering::SeqCst);
+    }
+}
+
+extern "C" fn record(_sig: usize, info: &SigInfo, _ctx: usize) {
+    SIGNO.store(info.signo, Ordering::SeqCst);
+    PID.store(info.pid, Ordering::SeqCst);
+    STATUS.store(info.status, Ordering::SeqCst);
+}
+
+// Leaves with 0 if the fault was at the address the child touched.
+extern "C" fn segv(_sig: usize, info: &SigInfo, _ctx: usize) {
+    sys::exit(if info.addr == ADDR.load(Ordering::SeqCst) {
+        0
+    } else {
+        1
+    });
+}
+
+// Another signal arriving while this handler runs waits for its
+// sigreturn, so it must not cut the sleep short.
+extern "C" fn nap(_sig: usize) {
+    let _ = sys::getpid().and_then(|me| sys::kill(me, SIGUSR2));
+    let napped = if sys::sleep(2).is_ok() { 1 } else { 2 };
+    NAPPED.store(napped, Ordering::SeqCst);
+}
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_sigaction: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn act(handler: usize, flags: u32) -> SigAction {
+    SigAction {
+        flags,
+        ..SigAction::new(handler)
+    }
+}
+
+// Read from a pipe a child writes to only after SIGALRM has gone off
+// in the parent, and return what the read returned.
+fn read_through_alarm(flags: u32) -> sys::Result<sys::Result<usize>> {
+    signal::sigaction(SIGALRM, &act(count as *const () as usize, flags))?;
+    let (mut rx, mut tx) = pipe::pipe()?;
+    let pid = sys::fork()?;
+    if pid == 0 {
+        let _ = sys::sleep(5);
+        let _ = tx.write(b"x");
+        sys::exit(0);
+    }
+    let before = CAUGHT.load(Ordering::SeqCst);
+    signal::setitimer(1, 0)?;
+    let mut buf = [0u8; 1];
+    let res = rx.read(&mut buf);
+    check("alarm caught", CAUGHT.load(Ordering::SeqCst) == before + 1)?;
+    let mut status = 0;
+    sys::waitpid(pid as isize, &mut status, 0)?;
+    signal::signal(SIGALRM, SIG_DFL)?;
+    Ok(res)
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_sigaction: start");
+    let me = sys::getpid()?;
+
+    // SA_SIGINFO handlers learn who sent the signal.
+    signal::sigaction(SIGUSR1, &act(record as *const () as usize, SA_SIGINFO))?;
+    sys::kill(me, SIGUSR1)?;
+    check("info signo", SIGNO.load(Ordering::SeqCst) == SIGUSR1 as u32)?;
+    check("info pid", PID.load(Ordering::SeqCst) == me)?;
+
+    // SIGCHLD tells which child and how it ended.
+    signal::sigaction(SIGCHLD, &act(record as *const () as usize, SA_SIGINFO))?;
+    let child = sys::fork()?;
+    if child == 0 {
+        sys::exit(5);
+    }
+    let mut status = 0;
+    sys::waitpid(child as isize, &mut status, 0)?;
+    check("chld signo", SIGNO.load(Ordering::SeqCst) == SIGCHLD as u32)?;
+    check("chld pid", PID.load(Ordering::SeqCst) == child)?;
+    check("chld status", STATUS.load(Ordering::SeqCst) == 5)?;
+    signal::signal(SIGCHLD, SIG_DFL)?;
+
+    // A fault handler gets the faulting address.
+    let child = sys::fork()?;
+    if child == 0 {
+        let _ = signal::sigaction(SIGSEGV, &act(segv as *const () as usize, SA_SIGINFO));
+        let addr = sys::sbrk(0).unwrap_or(0) + 16 * 4096;
+        ADDR.store(addr, Ordering::SeqCst);
+        unsafe { core::ptr::read_volatile(addr as *const u8) };
+        sys::exit(2);
+    }
+    sys::waitpid(child as isize, &mut status, 0)?;
+    check("fault addr", status == 0)?;
+    println!("test_sigaction: siginfo ok");
+
+    // The handler runs with its mask blocked, and with the signal
+    // itself blocked unless SA_NODEFER.
+    let usr1 = sig_mask(SIGUSR1);
+    let usr2 = sig_mask(SIGUSR2);
+    let masked = SigAction {
+        mask: usr2,
+        ..act(count as *const () as usize, 0)
+    };
+    signal::sigaction(SIGUSR1, &masked)?;
+    sys::kill(me, SIGUSR1)?;
+    check("mask", MASK.load(Ordering::SeqCst) == usr1 | usr2)?;
+    signal::sigaction(SIGUSR1, &act(count as *const () as usize, SA_NODEFER))?;
+    sys::kill(me, SIGUSR1)?;
+    check("nodefer", MASK.load(Ordering::SeqCst) == 0)?;
+
+    // SA_RESETHAND puts the default back once the handler is called.
+    signal::sigaction(SIGUSR1, &act(count as *const () as usize, SA_RESETHAND))?;
+    sys::kill(me, SIGUSR1)?;
+    check("resethand", signal::signal(SIGUSR1, SIG_DFL)? == SIG_DFL)?;
+    println!("test_sigaction: flags ok");
+
+    // A read cut short by a handler restarts with SA_RESTART and
+    // returns Interrupted without.
+    check("restart", read_through_alarm(SA_RESTART)? == Ok(1))?;
+    check(
+        "interrupted",
+        read_through_alarm(0)? == Err(Error::Interrupted),
+    )?;
+    println!("test_sigaction: restart ok");
+
+    signal::sigaction(SIGUSR2, &act(count as *const () as usize, 0))?;
+    signal::sigaction(SIGUSR1, &act(nap as *const () as usize, 0))?;
+    let before = CAUGHT.load(Ordering::SeqCst);
+    sys::kill(me, SIGUSR1)?;
+    check("sleep in handler", NAPPED.load(Ordering::SeqCst) == 1)?;
+    check("held signal", CAUGHT.load(Ordering::SeqCst) == before + 1)?;
+    signal::signal(SIGUSR1, SIG_DFL)?;
+    signal::signal(SIGUSR2, SIG_DFL)?;
+    println!("test_sigaction: nested ok");
+
+    println!("test_sigaction: ok");
+    Ok(())
+}
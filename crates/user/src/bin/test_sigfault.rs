@@ -0,0 +1,110 @@
+#![no_std]
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ulib::{
+    eprintln,
+    io::Write,
+    pipe, println,
+    signal::{self, SIG_BLOCK, SIG_IGN, SIGCHLD, SIGILL, SIGPIPE, SIGSEGV, sig_mask},
+    sys::{self, Error},
+};
+
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn count(_sig: usize) {
+    CAUGHT.fetch_add(1, Ordering::SeqCst);
+}
+
+// Returning would run the faulting instruction again, so leave instead,
+// with the signal as the exit status.
+extern "C" fn leave(sig: usize) {
+    sys::exit(sig as i32);
+}
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_sigfault: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// Run f in a child and return its exit status: -1 if it was killed.
+fn child(f: fn()) -> sys::Result<i32> {
+    let pid = sys::fork()?;
+    if pid == 0 {
+        f();
+        sys::exit(0);
+    }
+    let mut status = 0;
+    sys::waitpid(pid as isize, &mut status, 0)?;
+    Ok(status)
+}
+
+// Read well past the break, where nothing is mapped.
+fn segv() {
+    if let Ok(brk) = sys::sbrk(0) {
+        let addr = (brk + 16 * 4096) as *const u8;
+        unsafe { core::ptr::read_volatile(addr) };
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_sigfault: start");
+
+    let status = child(|| {
+        let _ = signal::signal(SIGSEGV, leave as *const () as usize);
+        segv();
+    })?;
+    check("segv caught", status == SIGSEGV as i32)?;
+    check("segv default", child(segv)? == -1)?;
+    let status = child(|| {
+        let _ = signal::signal(SIGSEGV, leave as *const () as usize);
+        let _ = signal::sigprocmask(SIG_BLOCK, sig_mask(SIGSEGV));
+        segv();
+    })?;
+    check("segv blocked", status == -1)?;
+    let status = child(|| {
+        let _ = signal::signal(SIGILL, leave as *const () as usize);
+        unsafe { asm!("unimp") };
+    })?;
+    check("ill caught", status == SIGILL as i32)?;
+    println!("test_sigfault: faults ok");
+
+    // A write with no reader fails with BrokenPipe and raises SIGPIPE,
+    // which kills by default.
+    let (rx, mut tx) = pipe::pipe()?;
+    drop(rx);
+    signal::signal(SIGPIPE, count as *const () as usize)?;
+    let res = tx.write(b"x");
+    check("pipe error", res.err() == Some(Error::BrokenPipe))?;
+    check("pipe caught", CAUGHT.load(Ordering::SeqCst) == 1)?;
+    signal::signal(SIGPIPE, SIG_IGN)?;
+    let res = tx.write(b"x");
+    check("pipe ignored", res.err() == Some(Error::BrokenPipe))?;
+    check("pipe not caught", CAUGHT.load(Ordering::SeqCst) == 1)?;
+    let status = child(|| {
+        let _ = signal::signal(SIGPIPE, signal::SIG_DFL);
+        if let Ok((rx, mut tx)) = pipe::pipe() {
+            drop(rx);
+            let _ = tx.write(b"x");
+        }
+    })?;
+    check("pipe default", status == -1)?;
+    signal::signal(SIGPIPE, signal::SIG_DFL)?;
+    println!("test_sigfault: pipe ok");
+
+    // The parent hears about each child that exits.
+    signal::signal(SIGCHLD, count as *const () as usize)?;
+    let before = CAUGHT.load(Ordering::SeqCst);
+    check("child exit", child(|| {})? == 0)?;
+    check("chld caught", CAUGHT.load(Ordering::SeqCst) == before + 1)?;
+    signal::signal(SIGCHLD, signal::SIG_DFL)?;
+    println!("test_sigfault: chld ok");
+
+    println!("test_sigfault: ok");
+    Ok(())
+}
@@ -0,0 +1,101 @@
+#![no_std]
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use ulib::{
+    eprintln, println,
+    signal::{
+        self, SIG_BLOCK, SIG_DFL, SIG_SETMASK, SIG_UNBLOCK, SIGALRM, SIGKILL, SIGTERM, SIGUSR1,
+        sig_mask,
+    },
+    sys::{self, Error},
+};
+
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+static HANDLER_MASK: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn handler(_sig: usize) {
+    CAUGHT.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mask) = blocked() {
+        HANDLER_MASK.store(mask, Ordering::SeqCst);
+    }
+}
+
+fn blocked() -> sys::Result<u32> {
+    signal::sigprocmask(SIG_BLOCK, 0)
+}
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_sigmask: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_sigmask: start");
+    let pid = sys::getpid()?;
+
+    // A blocked signal stays pending until it is unblocked, and the
+    // handler runs with it blocked.
+    let usr1 = sig_mask(SIGUSR1);
+    signal::signal(SIGUSR1, handler as *const () as usize)?;
+    signal::sigprocmask(SIG_BLOCK, usr1)?;
+    sys::kill(pid, SIGUSR1)?;
+    check("held", CAUGHT.load(Ordering::SeqCst) == 0)?;
+    check("pending", signal::sigpending()? & usr1 != 0)?;
+    signal::sigprocmask(SIG_UNBLOCK, usr1)?;
+    check("delivered", CAUGHT.load(Ordering::SeqCst) == 1)?;
+    check(
+        "handler mask",
+        HANDLER_MASK.load(Ordering::SeqCst) & usr1 != 0,
+    )?;
+    check("mask restored", blocked()? & usr1 == 0)?;
+    check("not pending", signal::sigpending()? & usr1 == 0)?;
+    println!("test_sigmask: block ok");
+
+    let old = signal::sigprocmask(SIG_SETMASK, !0)?;
+    check("kill unblockable", blocked()? & sig_mask(SIGKILL) == 0)?;
+    signal::sigprocmask(SIG_SETMASK, old)?;
+
+    // The child inherits the mask, so SIGTERM neither kills it nor
+    // cuts its sleep short.
+    let term = sig_mask(SIGTERM);
+    signal::sigprocmask(SIG_BLOCK, term)?;
+    let child = sys::fork()?;
+    if child == 0 {
+        let inherited = blocked().is_ok_and(|m| m & term != 0);
+        let _ = sys::kill(sys::getpid().unwrap_or(0), SIGTERM);
+        let slept = sys::sleep(2).is_ok();
+        sys::exit(if inherited && slept { 7 } else { 1 });
+    }
+    let mut status = 0;
+    sys::waitpid(child as isize, &mut status, 0)?;
+    check("fork mask", status == 7)?;
+    signal::sigprocmask(SIG_UNBLOCK, term)?;
+    println!("test_sigmask: fork ok");
+
+    // sigsuspend takes the alarm that arrived while it was blocked,
+    // then puts the mask back.
+    let alrm = sig_mask(SIGALRM);
+    signal::signal(SIGALRM, handler as *const () as usize)?;
+    signal::sigprocmask(SIG_BLOCK, alrm)?;
+    signal::setitimer(1, 0)?;
+    let before = CAUGHT.load(Ordering::SeqCst);
+    let res = signal::sigsuspend(0);
+    check("suspend interrupted", res.err() == Some(Error::Interrupted))?;
+    check(
+        "suspend caught",
+        CAUGHT.load(Ordering::SeqCst) == before + 1,
+    )?;
+    check("suspend mask", blocked()? == alrm)?;
+    signal::sigprocmask(SIG_UNBLOCK, alrm)?;
+    println!("test_sigmask: suspend ok");
+
+    signal::signal(SIGALRM, SIG_DFL)?;
+    signal::signal(SIGUSR1, SIG_DFL)?;
+    println!("test_sigmask: ok");
+    Ok(())
+}
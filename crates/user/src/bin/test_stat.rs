@@ -0,0 +1,65 @@
+#![no_std]
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    io::Write,
+    println,
+    sys::{self, Error, stat::FileType},
+};
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_stat: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_stat: start");
+    let file = "/tmp/t_stat";
+    let link = "/tmp/t_stat.sym";
+    let dangling = "/tmp/t_stat.bad";
+
+    File::create(file)?.write(b"hello")?;
+    fs::symlink(file, link)?;
+    fs::symlink("/tmp/t_stat.none", dangling)?;
+
+    let attr = fs::metadata(link)?;
+    check("stat follows", attr.is_file() && attr.len() == 5)?;
+    let attr = fs::symlink_metadata(link)?;
+    check("lstat", attr.is_symlink() && attr.len() == file.len())?;
+    check("lstat file", fs::symlink_metadata(file)?.is_file())?;
+    println!("test_stat: stat ok");
+
+    check("read_link", fs::read_link(link)?.to_str() == file)?;
+    let mut buf = [0u8; 4];
+    check("short readlink", sys::readlink(link, &mut buf)? == 4)?;
+    check("readlink prefix", &buf == b"/tmp")?;
+    check(
+        "readlink file",
+        fs::read_link(file).err() == Some(Error::InvalidArgument),
+    )?;
+    println!("test_stat: readlink ok");
+
+    check(
+        "stat dangling",
+        fs::metadata(dangling).err() == Some(Error::NotFound),
+    )?;
+    check(
+        "lstat dangling",
+        fs::symlink_metadata(dangling)?.file_type() == FileType::Symlink,
+    )?;
+
+    // No read permission is needed to stat a file.
+    fs::set_permissions(file, 0)?;
+    check("stat unreadable", fs::metadata(file)?.mode() == 0)?;
+
+    fs::remove_file(file)?;
+    fs::remove_file(link)?;
+    fs::remove_file(dangling)?;
+    println!("test_stat: ok");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::format;
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    println,
+    sys::{
+        self, Error,
+        time::{Timespec, UTIME_NOW, UTIME_OMIT, clock},
+    },
+    time::{DateTime, Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_time: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_time: start");
+
+    // The RTC follows the host clock, which is well past 2020.
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+    check("realtime", secs > 1_577_836_800)?;
+    let mut ts = Timespec::default();
+    check(
+        "bad clock",
+        sys::clockgettime(7, &mut ts).err() == Some(Error::InvalidArgument),
+    )?;
+
+    let start = Instant::now();
+    sys::sleep(2)?;
+    let slept = start.elapsed();
+    check("monotonic", slept >= Duration::from_millis(100))?;
+    check("monotonic order", Instant::now() >= start + slept)?;
+    sys::clockgettime(clock::MONOTONIC, &mut ts)?;
+    check("monotonic nsec", ts.nsec < 1_000_000_000)?;
+    println!("test_time: clocks ok, slept {:?}", slept);
+
+    check(
+        "epoch",
+        format!("{}", DateTime::from(UNIX_EPOCH)) == "Thu Jan  1 00:00:00 UTC 1970",
+    )?;
+    let leap = UNIX_EPOCH + Duration::from_secs(951_827_696);
+    check(
+        "leap day",
+        format!("{}", DateTime::from(leap)) == "Tue Feb 29 12:34:56 UTC 2000",
+    )?;
+    println!("test_time: {}", DateTime::from(now));
+
+    let path = "/tmp/t_time";
+    File::create(path)?;
+    let created = fs::metadata(path)?.modified();
+    check(
+        "file mtime",
+        created
+            .duration_since(now - Duration::from_secs(60))
+            .is_ok(),
+    )?;
+
+    let then = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::set_times(path, Some(then), None)?;
+    let attr = fs::metadata(path)?;
+    check("set atime", attr.accessed() == then)?;
+    check("kept mtime", attr.modified() == created)?;
+
+    let omit = Timespec {
+        sec: 0,
+        nsec: UTIME_OMIT,
+    };
+    let stamp = Timespec { sec: 42, nsec: 5 };
+    sys::utimens(path, &omit, &stamp)?;
+    let attr = fs::metadata(path)?;
+    check("explicit mtime", attr.mtime() == 42_000_000_005)?;
+    check("omitted atime", attr.accessed() == then)?;
+
+    let now_ts = Timespec {
+        sec: 0,
+        nsec: UTIME_NOW,
+    };
+    sys::utimens(path, &now_ts, &now_ts)?;
+    check("now mtime", fs::metadata(path)?.modified() >= created)?;
+    let bad = Timespec {
+        sec: 0,
+        nsec: 1_000_000_000,
+    };
+    check(
+        "bad nsec",
+        sys::utimens(path, &bad, &omit).err() == Some(Error::InvalidArgument),
+    )?;
+    fs::remove_file(path)?;
+    println!("test_time: utimens ok");
+
+    println!("test_time: ok");
+    Ok(())
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![feature(thread_local)]
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use ulib::{
+    eprintln, println,
+    sys::{self, Error},
+    thread,
+};
+
+const NTHREADS: usize = 4;
+
+#[thread_local]
+static SEED: Cell<usize> = Cell::new(7); // .tdata
+#[thread_local]
+static COUNT: Cell<usize> = Cell::new(0); // .tbss
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_tls: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+// Every thread starts from the template and only sees its own changes.
+fn bump(n: usize) -> [(usize, usize); 2] {
+    let start = (SEED.get(), COUNT.get());
+    for _ in 0..n {
+        COUNT.set(COUNT.get() + 1);
+    }
+    SEED.set(SEED.get() * 10 + n);
+    [start, (SEED.get(), COUNT.get())]
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_tls: start");
+    let pid = sys::getpid()?;
+    check("main tid", sys::gettid()? == pid)?;
+
+    let handles = (1..=NTHREADS)
+        .map(|n| thread::spawn(move || (bump(n * 10), sys::getpid(), sys::gettid())))
+        .collect::<sys::Result<Vec<_>>>()?;
+    let tids: Vec<usize> = handles.iter().map(|h| h.tid()).collect();
+    // Join in reverse, so each join waits for that thread alone.
+    for (n, handle) in (1..NTHREADS + 1).zip(handles).rev() {
+        let tid = handle.tid();
+        let (locals, tpid, ttid) = handle.join()?;
+        check("thread locals", locals == [(7, 0), (70 + n * 10, n * 10)])?;
+        check("thread pid", tpid == Ok(pid))?;
+        check("thread tid", ttid == Ok(tid) && tid != pid)?;
+    }
+    check(
+        "distinct tids",
+        (1..tids.len()).all(|i| !tids[..i].contains(&tids[i])),
+    )?;
+    check("main locals", SEED.get() == 7 && COUNT.get() == 0)?;
+    check(
+        "joined",
+        sys::join(tids[0], &mut 0) == Err(Error::NoChildProcesses),
+    )?;
+    println!("test_tls: spawn ok");
+
+    // A bigger stack than the default holds a bigger frame.
+    let big = thread::Builder::new().stack_size(128 * 1024).spawn(|| {
+        let buf = [1u8; 64 * 1024];
+        core::hint::black_box(&buf)
+            .iter()
+            .map(|&b| b as usize)
+            .sum::<usize>()
+    })?;
+    check("stack size", big.join()? == 64 * 1024)?;
+    let left = thread::spawn(|| -> usize { sys::exit(0) })?;
+    check("exited", left.join() == Err(Error::Uncategorized))?;
+    println!("test_tls: builder ok");
+
+    println!("test_tls: ok");
+    Ok(())
+}
@@ -0,0 +1,201 @@
+#![no_std]
+
+use ulib::{
+    eprintln,
+    fs::{self, File},
+    io::{Read, Write},
+    println, socket,
+    sys::{self, Error, stat::FileType},
+};
+
+const DIR: &str = "/tmp/t_tmpfs";
+const CAP: &str = "/tmp/t_tmpfs_cap";
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_tmpfs: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn files() -> sys::Result<()> {
+    let path = "/tmp/t_tmpfs/a";
+    let mut file = File::create(path)?;
+    let mut data = [0u8; 5000];
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = (i % 251) as u8;
+    }
+    check("write", file.write(&data)? == data.len())?;
+    check("size", fs::metadata(path)?.len() == data.len())?;
+
+    let mut buf = [0u8; 5000];
+    let mut file = File::open(path)?;
+    check("read", file.read(&mut buf)? == buf.len() && buf == data)?;
+    check("read at end", file.read(&mut buf)? == 0)?;
+
+    // Shrinking and growing again reads back zeros.
+    fs::truncate(path, 100)?;
+    fs::truncate(path, 4200)?;
+    let file = File::open(path)?;
+    check("truncate", file.read_at(&mut buf, 0)? == 4200)?;
+    check("truncate keep", buf[..100] == data[..100])?;
+    check("truncate zero", buf[100..4200].iter().all(|&b| b == 0))?;
+
+    // A hole past the end also reads as zeros.
+    let file = File::options().write(true).open(path)?;
+    file.write_at(b"end", 9000)?;
+    let file = File::open(path)?;
+    check("hole", file.read_at(&mut buf[..10], 8990)? == 10)?;
+    check("hole data", buf[..10] == *b"\0\0\0\0\0\0\0\0\0\0")?;
+    check("hole end", fs::metadata(path)?.len() == 9003)?;
+    println!("test_tmpfs: files ok");
+    Ok(())
+}
+
+fn dirs() -> sys::Result<()> {
+    fs::create_dir("/tmp/t_tmpfs/d")?;
+    File::create("/tmp/t_tmpfs/d/f")?;
+    let dir = fs::metadata("/tmp/t_tmpfs/d")?;
+    check("dir", dir.is_dir())?;
+    check(
+        "rmdir nonempty",
+        fs::remove_file("/tmp/t_tmpfs/d") == Err(Error::DirectoryNotEmpty),
+    )?;
+    check(
+        "mkdir twice",
+        fs::create_dir("/tmp/t_tmpfs/d") == Err(Error::AlreadyExists),
+    )?;
+    check("nlink", fs::metadata(DIR)?.nlink() == 3)?;
+
+    let mut names = [false; 2];
+    for entry in fs::read_dir(DIR)? {
+        match entry?.file_name().as_str() {
+            "a" => names[0] = true,
+            "d" => names[1] = true,
+            _ => {}
+        }
+    }
+    check("read_dir", names == [true, true])?;
+
+    fs::rename("/tmp/t_tmpfs/d/f", "/tmp/t_tmpfs/d/g")?;
+    check("rename", fs::metadata("/tmp/t_tmpfs/d/g").is_ok())?;
+    check(
+        "rename into self",
+        fs::rename("/tmp/t_tmpfs/d", "/tmp/t_tmpfs/d/e") == Err(Error::InvalidArgument),
+    )?;
+    fs::hard_link("/tmp/t_tmpfs/d/g", "/tmp/t_tmpfs/g")?;
+    check("link", fs::metadata("/tmp/t_tmpfs/g")?.nlink() == 2)?;
+    check(
+        "link across",
+        fs::hard_link("/tmp/t_tmpfs/g", "/t_tmpfs_g") == Err(Error::CrossesDevices),
+    )?;
+    fs::remove_file("/tmp/t_tmpfs/d/g")?;
+    fs::remove_file("/tmp/t_tmpfs/d")?;
+    check("rmdir nlink", fs::metadata(DIR)?.nlink() == 2)?;
+
+    fs::symlink("g", "/tmp/t_tmpfs/s")?;
+    check(
+        "symlink",
+        fs::symlink_metadata("/tmp/t_tmpfs/s")?.is_symlink()
+            && fs::metadata("/tmp/t_tmpfs/s")?.is_file(),
+    )?;
+    check("readlink", fs::read_link("/tmp/t_tmpfs/s")?.to_str() == "g")?;
+    println!("test_tmpfs: dirs ok");
+    Ok(())
+}
+
+fn sockets() -> sys::Result<()> {
+    let path = "/tmp/t_tmpfs/sock";
+    let server = socket::socket(socket::AF_UNIX, socket::SOCK_STREAM, 0)?;
+    socket::bind(&server, path)?;
+    check(
+        "socket node",
+        fs::metadata(path)?.file_type() == FileType::Socket,
+    )?;
+    let other = socket::socket(socket::AF_UNIX, socket::SOCK_STREAM, 0)?;
+    check(
+        "bind twice",
+        socket::bind(&other, path) == Err(Error::AlreadyExists),
+    )?;
+    check("open socket", File::open(path).is_err())?;
+    check(
+        "connect to file",
+        socket::connect(&other, "/tmp/t_tmpfs/a") == Err(Error::NotConnected),
+    )?;
+    fs::remove_file(path)?;
+    println!("test_tmpfs: sockets ok");
+    Ok(())
+}
+
+// A tmpfs capped at four pages fills up.
+fn cap() -> sys::Result<()> {
+    let _ = fs::create_dir(CAP);
+    sys::mount("size=16k", CAP, "tmpfs")?;
+    let res = (|| {
+        let mut file = File::create("/tmp/t_tmpfs_cap/f")?;
+        let data = [7u8; 4096];
+        for _ in 0..4 {
+            check("fill", file.write(&data)? == data.len())?;
+        }
+        check("full", file.write(&data) == Err(Error::StorageFull))?;
+        // Freeing the file gives its pages back.
+        fs::remove_file("/tmp/t_tmpfs_cap/f")?;
+        let mut file = File::create("/tmp/t_tmpfs_cap/f")?;
+        check("refill", file.write(&data)? == data.len())?;
+        fs::remove_file("/tmp/t_tmpfs_cap/f")
+    })();
+    sys::umount(CAP)?;
+    fs::remove_file(CAP)?;
+    res?;
+    check(
+        "bad option",
+        sys::mount("mode=1", DIR, "tmpfs") == Err(Error::InvalidArgument),
+    )?;
+    println!("test_tmpfs: cap ok");
+    Ok(())
+}
+
+fn perms() -> sys::Result<()> {
+    fs::create_dir("/tmp/t_tmpfs/ro")?;
+    fs::set_permissions("/tmp/t_tmpfs/ro", 0o755)?;
+    match sys::fork()? {
+        0 => {
+            let ok = sys::setuid(1000).is_ok()
+                && File::create("/tmp/t_tmpfs/ro/f") == Err(Error::PermissionDenied)
+                && File::create("/tmp/t_tmpfs_user").is_ok()
+                && fs::metadata("/tmp/t_tmpfs_user").is_ok_and(|m| m.uid() == 1000)
+                && fs::remove_file("/tmp/t_tmpfs_user").is_ok();
+            sys::exit(if ok { 0 } else { 1 })
+        }
+        _ => {
+            let mut status = 0;
+            sys::wait(&mut status)?;
+            check("user", status == 0)?;
+        }
+    }
+    fs::remove_file("/tmp/t_tmpfs/ro")?;
+    println!("test_tmpfs: perms ok");
+    Ok(())
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_tmpfs: start");
+    check(
+        "mounted",
+        fs::metadata("/tmp")?.inum() == 1 && fs::metadata("/tmp")?.mode() == 0o777,
+    )?;
+    fs::create_dir(DIR)?;
+    files()?;
+    dirs()?;
+    sockets()?;
+    cap()?;
+    perms()?;
+    for path in ["/tmp/t_tmpfs/a", "/tmp/t_tmpfs/g", "/tmp/t_tmpfs/s"] {
+        fs::remove_file(path)?;
+    }
+    fs::remove_file(DIR)?;
+    println!("test_tmpfs: ok");
+    Ok(())
+}
@@ -0,0 +1,91 @@
+#![no_std]
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use kernel::fs::{BSIZE, NDIRECT};
+use ulib::{
+    eprintln,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    println,
+    sys::{self, Error},
+};
+
+fn check(label: &str, ok: bool) -> sys::Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        eprintln!("test_trunc: {} failed", label);
+        Err(Error::InvalidArgument)
+    }
+}
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8 + 1
+}
+
+fn main() -> sys::Result<()> {
+    println!("test_trunc: start");
+    let path = "/t_trunc";
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    // Reach into the indirect block so shrinking frees part of it.
+    let big = (NDIRECT + 5) * BSIZE + 100;
+    let data: Vec<u8> = (0..big).map(pattern).collect();
+    file.write_all(&data)?;
+    check("size", file.metadata()?.len() == big)?;
+
+    let short = (NDIRECT + 2) * BSIZE + 10;
+    file.set_len(short)?;
+    check("shrunk", file.metadata()?.len() == short)?;
+    let mut buf = vec![0u8; big];
+    check("read shrunk", file.read_at(&mut buf, 0)? == short)?;
+    check("kept data", buf[..short] == data[..short])?;
+    println!("test_trunc: shrink ok");
+
+    // Growing again reads zeros past the old length, also in the
+    // tail of the block that was cut.
+    file.set_len(big)?;
+    check("grown", file.metadata()?.len() == big)?;
+    check("read grown", file.read_at(&mut buf, 0)? == big)?;
+    check("grown data", buf[..short] == data[..short])?;
+    check("grown zeros", buf[short..].iter().all(|&b| b == 0))?;
+    // Far more than one transaction holds.
+    let far = (NDIRECT + 40) * BSIZE + 7;
+    file.set_len(far)?;
+    let mut tail = vec![1u8; far - short];
+    check("grown far", file.metadata()?.len() == far)?;
+    check("read far", file.read_at(&mut tail, short)? == tail.len())?;
+    check("far zeros", tail.iter().all(|&b| b == 0))?;
+    println!("test_trunc: grow ok");
+
+    fs::truncate(path, 3)?;
+    let mut small = [0u8; 8];
+    let mut reader = File::open(path)?;
+    check("path truncate", reader.read(&mut small)? == 3)?;
+    check("path data", small[..3] == data[..3])?;
+    check(
+        "read-only fd",
+        reader.set_len(0).err() == Some(Error::InvalidArgument),
+    )?;
+    check(
+        "directory",
+        fs::truncate("/tmp", 0).err() == Some(Error::IsADirectory),
+    )?;
+
+    file.set_len(0)?;
+    check("empty", file.metadata()?.len() == 0)?;
+    drop(file);
+    drop(reader);
+    fs::remove_file(path)?;
+
+    println!("test_trunc: ok");
+    Ok(())
+}
@@ -0,0 +1,17 @@
+#![no_std]
+use ulib::{ExitCode, env, eprintln, sys};
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let (Some(dir), None) = (args.next(), args.next()) else {
+        eprintln!("usage: umount dir");
+        return ExitCode::FAILURE;
+    };
+    match sys::umount(dir) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("umount: {}: {}", dir, e);
+            ExitCode::FAILURE
+        }
+    }
+}
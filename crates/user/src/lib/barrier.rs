@@ -0,0 +1,46 @@
+// Hold threads until n of them have arrived, then let them all go.
+
+use crate::mutex::{Condvar, Mutex};
+
+#[derive(Debug)]
+struct BarrierState {
+    count: usize,
+    generation: usize,
+}
+
+#[derive(Debug)]
+pub struct Barrier {
+    state: Mutex<BarrierState>,
+    cvar: Condvar,
+    n: usize,
+}
+
+impl Barrier {
+    pub const fn new(n: usize) -> Self {
+        Self {
+            state: Mutex::new(BarrierState {
+                count: 0,
+                generation: 0,
+            }),
+            cvar: Condvar::new(),
+            n,
+        }
+    }
+
+    // Returns true in exactly one of the threads released together, the
+    // last to arrive. The barrier can then be used again.
+    pub fn wait(&self) -> bool {
+        let mut state = self.state.lock();
+        let generation = state.generation;
+        state.count += 1;
+        if state.count < self.n {
+            let _state = self.cvar.wait_while(state, |s| s.generation == generation);
+            false
+        } else {
+            state.count = 0;
+            state.generation = state.generation.wrapping_add(1);
+            self.cvar.notify_all();
+            true
+        }
+    }
+}
@@ -1,17 +1,21 @@
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
 
-use crate::io::{Read, Write};
+use crate::io::{Read, Seek, SeekFrom, Write};
 use crate::path::{Path, PathBuf};
 use crate::sys::{
     self,
     Error::*,
     defs::AsBytes,
-    fcntl::{FcntlCmd, fd, omode},
-    fs::DirEnt,
-    stat::FileType,
-    stat::Stat,
+    fcntl::{FcntlCmd, fd, omode, seek},
+    fs::BSIZE,
+    param::MAXPATH,
+    stat::{DirRecord, FileType, Stat},
+    time::{Timespec, UTIME_OMIT},
 };
+use crate::time::SystemTime;
 pub type Fd = usize;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -78,6 +82,22 @@ impl File {
     pub fn sync(&self) -> sys::Result<()> {
         sys::fsync(self.0)
     }
+
+    pub fn set_permissions(&self, mode: u32) -> sys::Result<()> {
+        sys::fchmod(self.0, mode as usize)
+    }
+
+    pub fn set_len(&self, size: usize) -> sys::Result<()> {
+        sys::ftruncate(self.0, size)
+    }
+
+    pub fn read_at(&self, buf: &mut [u8], offset: usize) -> sys::Result<usize> {
+        sys::pread(self.0, buf, offset)
+    }
+
+    pub fn write_at(&self, buf: &[u8], offset: usize) -> sys::Result<usize> {
+        sys::pwrite(self.0, buf, offset)
+    }
 }
 
 impl Drop for File {
@@ -98,6 +118,16 @@ impl Write for File {
     }
 }
 
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> sys::Result<usize> {
+        match pos {
+            SeekFrom::Start(off) => sys::lseek(self.0, off as isize, seek::SET),
+            SeekFrom::End(off) => sys::lseek(self.0, off, seek::END),
+            SeekFrom::Current(off) => sys::lseek(self.0, off, seek::CUR),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct OpenOptions {
     read: bool,
@@ -197,7 +227,23 @@ impl OpenOptions {
 pub struct Metadata(Stat);
 
 pub fn metadata<P: AsRef<Path>>(path: P) -> sys::Result<Metadata> {
-    File::open(path)?.metadata()
+    let mut stat: Stat = Default::default();
+    sys::stat(path.as_ref().to_str(), &mut stat)?;
+    Ok(Metadata(stat))
+}
+
+// Like metadata, but describes a symlink itself rather than its target.
+pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> sys::Result<Metadata> {
+    let mut stat: Stat = Default::default();
+    sys::lstat(path.as_ref().to_str(), &mut stat)?;
+    Ok(Metadata(stat))
+}
+
+pub fn read_link<P: AsRef<Path>>(path: P) -> sys::Result<PathBuf> {
+    let mut buf = [0u8; MAXPATH];
+    let n = sys::readlink(path.as_ref().to_str(), &mut buf)?;
+    let target = core::str::from_utf8(&buf[..n]).or(Err(Utf8Error))?;
+    Ok(PathBuf::from(target.to_string()))
 }
 
 impl Metadata {
@@ -213,6 +259,10 @@ impl Metadata {
         self.file_type() == FileType::File
     }
 
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == FileType::Symlink
+    }
+
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.0.size
@@ -230,20 +280,80 @@ impl Metadata {
         self.0.ctime
     }
 
+    pub fn accessed(&self) -> SystemTime {
+        SystemTime::from_nanos(self.0.atime)
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        SystemTime::from_nanos(self.0.mtime)
+    }
+
     pub fn inum(&self) -> u32 {
         self.0.ino
     }
+
+    pub fn nlink(&self) -> u16 {
+        self.0.nlink
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.0.mode
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.0.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.0.gid
+    }
+}
+
+pub fn set_permissions<P: AsRef<Path>>(path: P, mode: u32) -> sys::Result<()> {
+    sys::chmod(path.as_ref().to_str(), mode as usize)
+}
+
+// None leaves the access or modification time unchanged.
+pub fn set_times<P: AsRef<Path>>(
+    path: P,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> sys::Result<()> {
+    let ts = |time: Option<SystemTime>| {
+        time.map_or(
+            Timespec {
+                sec: 0,
+                nsec: UTIME_OMIT,
+            },
+            SystemTime::to_timespec,
+        )
+    };
+    sys::utimens(path.as_ref().to_str(), &ts(accessed), &ts(modified))
+}
+
+pub fn truncate<P: AsRef<Path>>(path: P, size: usize) -> sys::Result<()> {
+    sys::truncate(path.as_ref().to_str(), size)
+}
+
+// None leaves the owner or group unchanged.
+pub fn chown<P: AsRef<Path>>(path: P, uid: Option<u32>, gid: Option<u32>) -> sys::Result<()> {
+    let id = |id: Option<u32>| id.map_or(usize::MAX, |id| id as usize);
+    sys::chown(path.as_ref().to_str(), id(uid), id(gid))
 }
 
 pub struct ReadDir {
     fd: File,
     root: Arc<PathBuf>,
+    buf: Vec<u8>, // DirRecords from the last getdents
+    pos: usize,
     end_of_stream: bool,
 }
 
 pub struct DirEntry {
     dir: Arc<PathBuf>,
     name: String,
+    ino: u32,
+    ftype: FileType,
 }
 
 pub fn read_dir<P: AsRef<Path>>(path: P) -> sys::Result<ReadDir> {
@@ -253,6 +363,8 @@ pub fn read_dir<P: AsRef<Path>>(path: P) -> sys::Result<ReadDir> {
         Ok(ReadDir {
             fd,
             root,
+            buf: Vec::new(),
+            pos: 0,
             end_of_stream: false,
         })
     } else {
@@ -264,29 +376,44 @@ impl Iterator for ReadDir {
     type Item = sys::Result<DirEntry>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut de: DirEnt = Default::default();
-        if self.end_of_stream {
-            return None;
-        }
+        let hdr = size_of::<DirRecord>();
         loop {
-            if let Ok(0) = self.fd.read(de.as_bytes_mut()) {
-                self.end_of_stream = true;
-                break None;
+            if self.end_of_stream {
+                return None;
             }
-
-            if de.inum == 0 {
-                continue;
+            if self.pos >= self.buf.len() {
+                self.buf.resize(BSIZE, 0);
+                self.pos = 0;
+                match sys::getdents(self.fd.get_fd(), &mut self.buf) {
+                    Ok(0) => {
+                        self.end_of_stream = true;
+                        return None;
+                    }
+                    Ok(n) => self.buf.truncate(n),
+                    Err(e) => {
+                        self.buf.clear();
+                        return Some(Err(e));
+                    }
+                }
             }
-            let name = core::str::from_utf8(&de.name)
-                .unwrap()
-                .trim_end_matches(char::from(0))
-                .to_string();
-            if name == "." || name == ".." {
+
+            let rest = &self.buf[self.pos..];
+            let mut dr: DirRecord = Default::default();
+            dr.as_bytes_mut().copy_from_slice(&rest[..hdr]);
+            let name = &rest[hdr..hdr + dr.namelen as usize];
+            self.pos += dr.reclen as usize;
+            if name == b"." || name == b".." {
                 continue;
             }
-            break Some(Ok(DirEntry {
+            let name = match core::str::from_utf8(name) {
+                Ok(name) => name.to_string(),
+                Err(_) => return Some(Err(Utf8Error)),
+            };
+            return Some(Ok(DirEntry {
                 dir: Arc::clone(&self.root),
                 name,
+                ino: dr.ino,
+                ftype: dr.ftype,
             }));
         }
     }
@@ -301,12 +428,18 @@ impl DirEntry {
         self.name.clone()
     }
 
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    // Like std, this does not follow a symlink entry.
     pub fn metadata(&self) -> sys::Result<Metadata> {
-        File::open(self.path())?.metadata()
+        symlink_metadata(self.path())
     }
 
+    // Comes with the entry, without another syscall.
     pub fn file_type(&self) -> sys::Result<FileType> {
-        self.metadata().map(|m| m.file_type())
+        Ok(self.ftype)
     }
 }
 
@@ -373,6 +506,10 @@ pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> sys::R
     sys::link(original.as_ref().to_str(), link.as_ref().to_str())
 }
 
+pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> sys::Result<()> {
+    sys::rename(from.as_ref().to_str(), to.as_ref().to_str())
+}
+
 pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(target: P, link: Q) -> sys::Result<()> {
     sys::symlink(target.as_ref().to_str(), link.as_ref().to_str())
 }
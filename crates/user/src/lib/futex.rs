@@ -0,0 +1,16 @@
+use core::sync::atomic::AtomicU32;
+
+pub use kernel::futex::{FUTEX_WAIT, FUTEX_WAKE};
+
+use crate::sys;
+
+// Sleep while word holds val, for at most timeout ticks, or for ever if
+// timeout is 0. Returns WouldBlock at once if word holds something else.
+pub fn wait(word: &AtomicU32, val: u32, timeout: usize) -> sys::Result<()> {
+    sys::futex(word.as_ptr() as usize, FUTEX_WAIT, val as usize, timeout).map(|_| ())
+}
+
+// Wake up to n threads waiting on word, and return how many woke.
+pub fn wake(word: &AtomicU32, n: usize) -> sys::Result<usize> {
+    sys::futex(word.as_ptr() as usize, FUTEX_WAKE, n, 0)
+}
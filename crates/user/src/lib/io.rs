@@ -139,6 +139,24 @@ impl<B: BufRead> Iterator for Lines<B> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(usize),
+    End(isize),
+    Current(isize),
+}
+
+pub trait Seek {
+    fn seek(&mut self, pos: SeekFrom) -> sys::Result<usize>;
+    fn rewind(&mut self) -> sys::Result<()> {
+        self.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+    fn stream_position(&mut self) -> sys::Result<usize> {
+        self.seek(SeekFrom::Current(0))
+    }
+}
+
 pub trait BufRead: Read {
     fn lines(self) -> Lines<Self>
     where
@@ -13,29 +13,39 @@ pub mod sys {
     pub use kernel::fcntl;
     pub use kernel::file::Major;
     pub use kernel::fs;
+    pub use kernel::param;
     pub use kernel::poll;
     pub use kernel::signal;
     pub use kernel::stat;
     pub use kernel::sync;
+    pub use kernel::time;
+    use signal::SigAction;
     use stat::Stat;
+    use time::Timespec;
     include!(concat!(env!("OUT_DIR"), "/usys.rs"));
 }
 pub extern crate alloc;
 #[macro_use]
 pub mod stdio;
+pub mod barrier;
 pub mod env;
 pub mod fs;
+pub mod futex;
 pub mod io;
 pub mod ipc;
 pub mod mapreduce;
 pub mod mutex;
+pub mod once;
+pub mod passwd;
 pub mod path;
 pub mod pipe;
 pub mod process;
+pub mod rwlock;
 pub mod signal;
 pub mod socket;
 pub mod sysinfo;
 pub mod thread;
+pub mod time;
 pub mod umalloc;
 // pub mod regex;
 
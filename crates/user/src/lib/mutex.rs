@@ -1,15 +1,20 @@
-// implement simple mutex as spinlock
-// later: we need to implement futex_mutex with implementing kernel futex.
+// A sleeping mutex and a condition variable, both built on futex.
 
 use core::{
     cell::UnsafeCell,
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicU32, Ordering},
 };
 
+use crate::futex;
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2; // locked, and someone may be asleep on it
+
 #[derive(Debug)]
 pub struct Mutex<T> {
-    locked: AtomicBool,
+    state: AtomicU32,
     value: UnsafeCell<T>,
 }
 
@@ -18,18 +23,44 @@ unsafe impl<T> Sync for Mutex<T> where T: Send {}
 impl<T> Mutex<T> {
     pub const fn new(value: T) -> Self {
         Self {
-            locked: AtomicBool::new(false),
+            state: AtomicU32::new(UNLOCKED),
             value: UnsafeCell::new(value),
         }
     }
 
     pub fn lock(&self) -> MutexGuard<'_, T> {
-        while self.locked.swap(true, Ordering::Acquire) {
-            core::hint::spin_loop();
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
         }
         MutexGuard { lock: self }
     }
 
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { lock: self })
+    }
+
+    // Mark the lock contended so the holder wakes us, then sleep until
+    // it is free. Having slept, we can't tell whether others still wait,
+    // so we take it contended too.
+    fn lock_contended(&self) {
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            let _ = futex::wait(&self.state, CONTENDED, 0);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = futex::wake(&self.state, 1);
+        }
+    }
+
     pub fn into_inner(self) -> T {
         self.value.into_inner()
     }
@@ -60,6 +91,60 @@ impl<T> DerefMut for MutexGuard<'_, T> {
 
 impl<T> Drop for MutexGuard<'_, T> {
     fn drop(&mut self) {
-        self.lock.locked.store(false, Ordering::Release)
+        self.lock.unlock()
+    }
+}
+
+// Waiters sleep on a counter that every notify bumps, so a notify that
+// comes between unlocking the mutex and going to sleep is not lost.
+#[derive(Debug, Default)]
+pub struct Condvar {
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, 0).0
+    }
+
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    // Wait for at most ticks clock ticks, or for ever if ticks is 0.
+    // Also returns true if the wait timed out.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        ticks: usize,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let lock = guard.lock;
+        drop(guard);
+        let res = futex::wait(&self.seq, seq, ticks);
+        (lock.lock(), res == Err(crate::sys::Error::TimedOut))
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        let _ = futex::wake(&self.seq, 1);
+    }
+
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        let _ = futex::wake(&self.seq, usize::MAX);
     }
 }
@@ -0,0 +1,61 @@
+// Run something exactly once, however many threads ask. Late callers
+// sleep on the state word until the first one is done.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::futex;
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const WAITED: u32 = 2; // running, and someone is asleep on it
+const COMPLETE: u32 = 3;
+
+#[derive(Debug, Default)]
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn call_once(&self, f: impl FnOnce()) {
+        let mut f = Some(f);
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    if let Some(f) = f.take() {
+                        f();
+                    }
+                    if self.state.swap(COMPLETE, Ordering::Release) == WAITED {
+                        let _ = futex::wake(&self.state, usize::MAX);
+                    }
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(state) => {
+                    if state == WAITED
+                        || self
+                            .state
+                            .compare_exchange(RUNNING, WAITED, Ordering::Relaxed, Ordering::Relaxed)
+                            .is_ok()
+                    {
+                        let _ = futex::wait(&self.state, WAITED, 0);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
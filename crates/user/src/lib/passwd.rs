@@ -0,0 +1,147 @@
+// User and group databases in /etc/passwd and /etc/group.
+//
+// Each line of /etc/passwd is
+//   name:password:uid:gid:gecos:home:shell
+// and each line of /etc/group is
+//   name:password:gid:member,member,...
+// An empty password means none is asked for. Otherwise it is salt$hash,
+// with hash from kernel::sha256::crypt, and anything else, like *,
+// locks the account. There is no shadow file, so /etc/passwd is only
+// readable by root; login runs as root and su is set-user-id. Blank
+// lines and lines starting with '#' are skipped.
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use kernel::sha256::crypt;
+
+use crate::{
+    fs::File,
+    io::Read,
+    sys::{self, Error::NotFound, param::NGROUPS},
+};
+
+pub const PASSWD: &str = "/etc/passwd";
+pub const GROUP: &str = "/etc/group";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub password: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub gecos: String,
+    pub home: String,
+    pub shell: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub password: String,
+    pub gid: u32,
+    pub members: Vec<String>,
+}
+
+impl User {
+    pub fn parse(line: &str) -> Option<User> {
+        let mut fields = line.split(':');
+        let user = User {
+            name: fields.next()?.to_string(),
+            password: fields.next()?.to_string(),
+            uid: fields.next()?.parse().ok()?,
+            gid: fields.next()?.parse().ok()?,
+            gecos: fields.next()?.to_string(),
+            home: fields.next()?.to_string(),
+            shell: fields.next()?.to_string(),
+        };
+        if user.name.is_empty() || fields.next().is_some() {
+            return None;
+        }
+        Some(user)
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        if self.password.is_empty() {
+            return true;
+        }
+        self.password
+            .split_once('$')
+            .is_some_and(|(salt, hash)| crypt(salt, password) == hash.as_bytes())
+    }
+
+    // Supplementary groups listing this user as a member.
+    pub fn groups(&self) -> sys::Result<Vec<u32>> {
+        Ok(groups()?
+            .into_iter()
+            .filter(|g| g.gid != self.gid && g.members.contains(&self.name))
+            .map(|g| g.gid)
+            .take(NGROUPS)
+            .collect())
+    }
+
+    // Take on this user's identity: supplementary groups first, then
+    // the group and user ids, since setuid() gives up the right to
+    // change the others.
+    pub fn become_user(&self) -> sys::Result<()> {
+        sys::setgroups(&self.groups()?)?;
+        sys::setgid(self.gid as usize)?;
+        sys::setuid(self.uid as usize)
+    }
+}
+
+impl Group {
+    pub fn parse(line: &str) -> Option<Group> {
+        let mut fields = line.split(':');
+        let group = Group {
+            name: fields.next()?.to_string(),
+            password: fields.next()?.to_string(),
+            gid: fields.next()?.parse().ok()?,
+            members: fields
+                .next()?
+                .split(',')
+                .filter(|m| !m.is_empty())
+                .map(String::from)
+                .collect(),
+        };
+        if group.name.is_empty() || fields.next().is_some() {
+            return None;
+        }
+        Some(group)
+    }
+}
+
+fn entries<T>(path: &str, parse: fn(&str) -> Option<T>) -> sys::Result<Vec<T>> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse)
+        .collect())
+}
+
+pub fn users() -> sys::Result<Vec<User>> {
+    entries(PASSWD, User::parse)
+}
+
+pub fn groups() -> sys::Result<Vec<Group>> {
+    entries(GROUP, Group::parse)
+}
+
+pub fn user_by_name(name: &str) -> sys::Result<User> {
+    users()?
+        .into_iter()
+        .find(|u| u.name == name)
+        .ok_or(NotFound)
+}
+
+pub fn user_by_uid(uid: u32) -> sys::Result<User> {
+    users()?.into_iter().find(|u| u.uid == uid).ok_or(NotFound)
+}
+
+pub fn group_by_gid(gid: u32) -> sys::Result<Group> {
+    groups()?.into_iter().find(|g| g.gid == gid).ok_or(NotFound)
+}
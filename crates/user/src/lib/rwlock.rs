@@ -0,0 +1,143 @@
+// A reader-writer lock built on futex. The state word counts readers,
+// holds WRITE_LOCKED for a writer, and has WAITING set while anyone
+// sleeps on it. Whoever releases the lock with WAITING set wakes every
+// sleeper to try again.
+
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::futex;
+
+const MASK: u32 = (1 << 30) - 1;
+const WRITE_LOCKED: u32 = MASK;
+const MAX_READERS: u32 = MASK - 1;
+const WAITING: u32 = 1 << 31;
+
+#[derive(Debug)]
+pub struct RwLock<T> {
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for RwLock<T> where T: Send + Sync {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & MASK < MAX_READERS {
+                if self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return RwLockReadGuard { lock: self };
+                }
+            } else {
+                self.sleep(state);
+            }
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & MASK == 0 {
+                if self
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state | WRITE_LOCKED,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return RwLockWriteGuard { lock: self };
+                }
+            } else {
+                self.sleep(state);
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    // Set WAITING and sleep, unless the state has moved on meanwhile.
+    fn sleep(&self, state: u32) {
+        let waiting = state | WAITING;
+        if state == waiting
+            || self
+                .state
+                .compare_exchange(state, waiting, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            let _ = futex::wait(&self.state, waiting, 0);
+        }
+    }
+
+    fn wake_all(&self) {
+        self.state.fetch_and(!WAITING, Ordering::Relaxed);
+        let _ = futex::wake(&self.state, usize::MAX);
+    }
+}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        let prev = self.lock.state.fetch_sub(1, Ordering::Release);
+        if prev & MASK == 1 && prev & WAITING != 0 {
+            self.lock.wake_all();
+        }
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        let prev = self.lock.state.fetch_sub(WRITE_LOCKED, Ordering::Release);
+        if prev & WAITING != 0 {
+            self.lock.wake_all();
+        }
+    }
+}
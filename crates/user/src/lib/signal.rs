@@ -1,8 +1,10 @@
 use core::arch::asm;
 
 pub use kernel::signal::{
-    NSIG, SIG_DFL, SIG_IGN, SIGALRM, SIGCONT, SIGINT, SIGKILL, SIGTERM, SIGTSTP, SIGTTIN, SIGTTOU,
-    SIGUSR1, SIGUSR2, WCONTINUED, WNOHANG, WUNTRACED,
+    NSIG, SA_NODEFER, SA_RESETHAND, SA_RESTART, SA_SIGINFO, SIG_BLOCK, SIG_DFL, SIG_IGN,
+    SIG_SETMASK, SIG_UNBLOCK, SIGALRM, SIGBUS, SIGCHLD, SIGCONT, SIGFPE, SIGHUP, SIGILL, SIGINT,
+    SIGKILL, SIGPIPE, SIGSEGV, SIGTERM, SIGTSTP, SIGTTIN, SIGTTOU, SIGUSR1, SIGUSR2, SigAction,
+    SigInfo, WCONTINUED, WNOHANG, WUNTRACED, sig_mask,
 };
 use kernel::syscall::SysCalls;
 
@@ -20,16 +22,44 @@ extern "C" fn sigrestorer() -> ! {
     }
 }
 
-pub fn signal(signum: usize, handler: usize) -> sys::Result<usize> {
-    let prev = sys::sigaction(signum, handler, sigrestorer as *const () as usize)?;
-    let prev = match prev {
-        0 => SIG_DFL,
-        1 => SIG_IGN,
-        _ => prev,
+// Set the action for signum, returning through sigreturn, and return
+// the old one.
+pub fn sigaction(signum: usize, act: &SigAction) -> sys::Result<SigAction> {
+    let act = SigAction {
+        restorer: sigrestorer as *const () as usize,
+        ..*act
     };
-    Ok(prev)
+    let mut old = SigAction::default();
+    sys::sigaction(signum, &act, &mut old)?;
+    Ok(old)
+}
+
+// Set a plain handler, or SIG_DFL or SIG_IGN, and return the old one.
+// System calls it interrupts return Interrupted.
+pub fn signal(signum: usize, handler: usize) -> sys::Result<usize> {
+    Ok(sigaction(signum, &SigAction::new(handler))?.handler)
 }
 
 pub fn setitimer(initial: usize, interval: usize) -> sys::Result<usize> {
     sys::setitimer(initial, interval)
 }
+
+// Block, unblock or set the blocked signals, a mask of sig_mask bits.
+// Returns the old mask; SIG_BLOCK with an empty set just reads it.
+pub fn sigprocmask(how: usize, set: u32) -> sys::Result<u32> {
+    let mut old = 0;
+    sys::sigprocmask(how, &set, &mut old)?;
+    Ok(old)
+}
+
+pub fn sigpending() -> sys::Result<u32> {
+    let mut set = 0;
+    sys::sigpending(&mut set)?;
+    Ok(set)
+}
+
+// Wait for a signal with mask blocked instead. Returns Interrupted
+// once one has been handled, with the old mask back in place.
+pub fn sigsuspend(mask: u32) -> sys::Result<()> {
+    sys::sigsuspend(&mask)
+}
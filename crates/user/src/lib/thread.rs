@@ -1,48 +1,155 @@
-use alloc::boxed::Box;
-use core::alloc::Layout;
-use core::mem::size_of;
+// Threads share the address space. Each one gets a single allocation
+// holding its stack and, above that, its TLS area (see kernel::tls):
+//
+//   [ stack size | stack ... | TLS area ]
+//                ^ base      ^ sp
+//
+// join hands base back, so whoever joins can free the allocation.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{alloc::Layout, arch::asm, cell::UnsafeCell, ptr};
+
+pub use kernel::tls::Tls;
 
 use crate::sys;
 
-const PGSIZE: usize = 4096;
-const STACK_ALLOC_BYTES: usize = PGSIZE * 2 + size_of::<usize>();
+pub const DEFAULT_STACK_SIZE: usize = 16 * 1024;
+const MIN_STACK_SIZE: usize = 4096;
+const PREFIX: usize = 16;
 
-#[repr(C)]
-struct Start {
-    f: extern "C" fn(usize, usize),
-    arg1: usize,
-    arg2: usize,
+// The TLS template, from the header just below this thread's tp.
+fn template() -> Tls {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+        ptr::read(Tls::header(tp) as *const Tls)
+    }
 }
 
-extern "C" fn thread_entry(start_ptr: usize, _unused: usize) -> ! {
-    let start = unsafe { Box::from_raw(start_ptr as *mut Start) };
-    (start.f)(start.arg1, start.arg2);
-    sys::exit(0)
+fn layout(stack_size: usize, tls: &Tls) -> Layout {
+    let size = PREFIX + stack_size + tls.area_size();
+    Layout::from_size_align(size, tls.align().max(PREFIX)).unwrap()
 }
 
-fn alloc_stack_page() -> sys::Result<usize> {
-    // Allocate an aligned stack base for clone().
-    let layout = Layout::from_size_align(STACK_ALLOC_BYTES, size_of::<usize>()).unwrap();
-    let raw = unsafe { alloc::alloc::alloc(layout) };
+// Allocate a stack and a TLS block; returns the stack base and tp.
+fn alloc_area(stack_size: usize) -> sys::Result<(usize, usize)> {
+    let tls = template();
+    let raw = unsafe { alloc::alloc::alloc(layout(stack_size, &tls)) };
     if raw.is_null() {
         return Err(sys::Error::OutOfMemory);
     }
-    let mut aligned = raw as usize + size_of::<usize>();
-    aligned = (aligned + PGSIZE - 1) & !(PGSIZE - 1);
+    let base = raw as usize + PREFIX;
+    let tp = tls.tp(base + stack_size);
     unsafe {
-        *((aligned - size_of::<usize>()) as *mut usize) = raw as usize;
+        ptr::write(raw as *mut usize, stack_size);
+        ptr::write(Tls::header(tp) as *mut Tls, tls);
+        ptr::copy_nonoverlapping(tls.image as *const u8, tp as *mut u8, tls.filesz);
+        ptr::write_bytes((tp + tls.filesz) as *mut u8, 0, tls.memsz - tls.filesz);
     }
-    Ok(aligned)
+    Ok((base, tp))
 }
 
-fn free_stack_page(aligned: usize) {
-    if aligned == 0 {
+fn free_area(base: usize) {
+    if base < PREFIX {
         return;
     }
-    let layout = Layout::from_size_align(STACK_ALLOC_BYTES, size_of::<usize>()).unwrap();
+    let raw = (base - PREFIX) as *mut u8;
     unsafe {
-        let raw = *((aligned - size_of::<usize>()) as *const usize) as *mut u8;
-        alloc::alloc::dealloc(raw, layout);
+        let stack_size = ptr::read(raw as *const usize);
+        alloc::alloc::dealloc(raw, layout(stack_size, &template()));
+    }
+}
+
+extern "C" fn start<F: FnOnce()>(main: usize, _unused: usize) -> ! {
+    let main = unsafe { Box::from_raw(main as *mut F) };
+    main();
+    sys::exit(0)
+}
+
+// Start a thread running main, and return its tid.
+fn clone<F: FnOnce() + Send + 'static>(main: F, stack_size: usize) -> sys::Result<usize> {
+    let stack_size = (stack_size.max(MIN_STACK_SIZE) + 15) & !15;
+    let (base, tp) = alloc_area(stack_size)?;
+    let main = Box::into_raw(Box::new(main));
+    let entry = start::<F> as *const () as usize;
+    sys::clone(entry, main as usize, 0, base, stack_size, tp).inspect_err(|_| {
+        drop(unsafe { Box::from_raw(main) });
+        free_area(base);
+    })
+}
+
+// Where a thread leaves what it returned, for join to collect.
+struct Packet<T>(UnsafeCell<Option<T>>);
+
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Builder {
+    stack_size: usize,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub const fn new() -> Self {
+        Self {
+            stack_size: DEFAULT_STACK_SIZE,
+        }
+    }
+
+    pub const fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    pub fn spawn<F, T>(self, f: F) -> sys::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let packet = Arc::new(Packet(UnsafeCell::new(None)));
+        let theirs = Arc::clone(&packet);
+        let main = move || {
+            let result = f();
+            unsafe { *theirs.0.get() = Some(result) };
+        };
+        let tid = clone(main, self.stack_size)?;
+        Ok(JoinHandle { tid, packet })
+    }
+}
+
+// Run f in a new thread with the default stack size.
+pub fn spawn<F, T>(f: F) -> sys::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().spawn(f)
+}
+
+// A thread that is never joined stays a zombie, with its stack, until
+// the process exits.
+pub struct JoinHandle<T> {
+    tid: usize,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    // Wait for the thread to finish and take what it returned. Fails
+    // with Uncategorized if it left through sys::exit instead.
+    pub fn join(self) -> sys::Result<T> {
+        let mut stack = 0;
+        sys::join(self.tid, &mut stack)?;
+        free_area(stack);
+        unsafe { (*self.packet.0.get()).take() }.ok_or(sys::Error::Uncategorized)
     }
 }
 
@@ -51,15 +158,13 @@ pub fn thread_create(
     arg1: usize,
     arg2: usize,
 ) -> sys::Result<usize> {
-    let start = Box::new(Start { f, arg1, arg2 });
-    let start_ptr = Box::into_raw(start) as usize;
-    let stack = alloc_stack_page()?;
-    sys::clone(thread_entry as *const () as usize, start_ptr, 0, stack)
+    clone(move || f(arg1, arg2), DEFAULT_STACK_SIZE)
 }
 
+// Join whichever thread finishes first.
 pub fn thread_join() -> sys::Result<usize> {
     let mut stack: usize = 0;
-    let pid = sys::join(&mut stack)?;
-    free_stack_page(stack);
-    Ok(pid)
+    let tid = sys::join(0, &mut stack)?;
+    free_area(stack);
+    Ok(tid)
 }
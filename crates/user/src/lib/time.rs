@@ -0,0 +1,182 @@
+use core::fmt;
+use core::ops::{Add, Sub};
+pub use core::time::Duration;
+
+use crate::sys::{
+    self,
+    Error::*,
+    time::{Timespec, clock},
+};
+
+fn now(id: usize) -> u64 {
+    let mut ts = Timespec::default();
+    sys::clockgettime(id, &mut ts).expect("clockgettime");
+    ts.as_nanos()
+}
+
+// A point in wall-clock time, as nanoseconds since the Unix epoch.
+// It may go back when the host clock does; use Instant to measure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(u64);
+
+pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    pub fn now() -> SystemTime {
+        SystemTime(now(clock::REALTIME))
+    }
+
+    pub fn duration_since(&self, earlier: SystemTime) -> sys::Result<Duration> {
+        self.0
+            .checked_sub(earlier.0)
+            .map(Duration::from_nanos)
+            .ok_or(InvalidArgument)
+    }
+
+    pub fn elapsed(&self) -> sys::Result<Duration> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub(crate) fn from_nanos(nanos: u64) -> SystemTime {
+        SystemTime(nanos)
+    }
+
+    pub(crate) fn to_timespec(self) -> Timespec {
+        Timespec::from_nanos(self.0)
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, dur: Duration) -> SystemTime {
+        SystemTime(self.0 + dur.as_nanos() as u64)
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, dur: Duration) -> SystemTime {
+        SystemTime(self.0 - dur.as_nanos() as u64)
+    }
+}
+
+// A reading of the monotonic clock, for measuring how long things take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(now(clock::MONOTONIC))
+    }
+
+    // Zero if earlier is in fact later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, dur: Duration) -> Instant {
+        Instant(self.0 + dur.as_nanos() as u64)
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, dur: Duration) -> Instant {
+        Instant(self.0 - dur.as_nanos() as u64)
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, other: Instant) -> Duration {
+        self.duration_since(other)
+    }
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// A SystemTime broken down into its UTC calendar date and time of day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u64,
+    pub month: u32, // 1..=12
+    pub day: u32,   // 1..=31
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub weekday: u32, // 0 is Sunday
+}
+
+impl DateTime {
+    pub fn month_name(&self) -> &'static str {
+        MONTHS[self.month as usize - 1]
+    }
+
+    pub fn weekday_name(&self) -> &'static str {
+        DAYS[self.weekday as usize]
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    // Days to civil date after Howard Hinnant's civil_from_days,
+    // with years starting in March so the leap day comes last.
+    fn from(time: SystemTime) -> DateTime {
+        let secs = time.0 / 1_000_000_000;
+        let days = secs / 86400;
+        let rem = (secs % 86400) as u32;
+
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z % 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: rem / 3600,
+            minute: rem / 60 % 60,
+            second: rem % 60,
+            weekday: ((days + 4) % 7) as u32, // 1970-01-01 was a Thursday
+        }
+    }
+}
+
+// As date(1) prints it, e.g. "Thu Jan  1 00:00:00 UTC 1970".
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {:2} {:02}:{:02}:{:02} UTC {}",
+            self.weekday_name(),
+            self.month_name(),
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.year
+        )
+    }
+}